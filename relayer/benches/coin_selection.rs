@@ -0,0 +1,75 @@
+//! Coin selection of a vault holding 5,000 UTXOs, with every strategy.
+
+#![allow(missing_docs)]
+
+// The relayer is a binary crate, so the coin selection is included directly with its types.
+#[allow(dead_code)]
+#[path = "../src/chain/btc/coin_selection.rs"]
+mod coin_selection;
+
+mod types {
+	pub type Satoshi = u64;
+
+	// The outpoints are irrelevant to the selection.
+	#[derive(Debug)]
+	pub struct Utxo {
+		pub value: Satoshi,
+	}
+}
+
+// crates.io
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+// self
+use coin_selection::*;
+use types::*;
+
+fn vault(size: usize) -> Vec<Utxo> {
+	let mut rng = StdRng::seed_from_u64(0);
+
+	(0..size)
+		.map(|_| Utxo {
+			// Mostly small deposits, and a few large ones.
+			value: if rng.gen_ratio(1, 20) {
+				rng.gen_range(1_000_000..10_000_000)
+			} else {
+				rng.gen_range(10_000..200_000)
+			},
+		})
+		.collect()
+}
+
+fn select_from_5000_utxos(c: &mut Criterion) {
+	let utxos = vault(5_000);
+	let mut group = c.benchmark_group("select_from_5000_utxos");
+
+	for (name, target) in [("small", 150_000), ("large", 20_000_000)] {
+		let params = SelectionParams {
+			target,
+			fee_rate: 5,
+			input_vsize: 58,
+			change_vsize: Some(43),
+			min_change: 330,
+		};
+
+		for strategy in [
+			SelectionStrategy::LeastWaste,
+			SelectionStrategy::BranchAndBound,
+			SelectionStrategy::Knapsack,
+			SelectionStrategy::SingleRandomDraw,
+		] {
+			let coin_selection = CoinSelection { strategy, long_term_fee_rate: 10 };
+
+			group.bench_with_input(
+				BenchmarkId::new(format!("{strategy:?}"), name),
+				&params,
+				|b, params| b.iter(|| select(&utxos, params, coin_selection).unwrap()),
+			);
+		}
+	}
+
+	group.finish();
+}
+
+criterion_group!(benches, select_from_5000_utxos);
+criterion_main!(benches);
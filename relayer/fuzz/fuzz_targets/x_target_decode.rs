@@ -0,0 +1,43 @@
+//! Decode arbitrary `OP_RETURN` payloads as `XTarget`, the decoding must never panic and every
+//! successfully decoded target must roundtrip.
+
+#![no_main]
+
+// The relayer is a binary crate, so the codec is included directly with a minimal prelude.
+#[allow(dead_code, missing_docs)]
+#[path = "../../src/x.rs"]
+mod x;
+#[allow(dead_code, missing_docs)]
+#[path = "../../src/error/x.rs"]
+mod x_error;
+
+mod prelude {
+	pub use crate::x_error::*;
+
+	pub type Result<T> = std::result::Result<T, Error>;
+
+	#[derive(Debug, thiserror::Error)]
+	pub enum Error {
+		#[error("{0:?}")]
+		ArrayBytes(array_bytes::Error),
+		#[error(transparent)]
+		X(#[from] XError),
+	}
+}
+
+// crates.io
+use libfuzzer_sys::fuzz_target;
+// self
+use x::XTarget;
+
+fuzz_target!(|data: (bool, &[u8])| {
+	let (accept_legacy, s) = data;
+
+	if let Ok(xt) = XTarget::decode(s, accept_legacy) {
+		let encoded = xt.encode().unwrap();
+		let decoded = XTarget::decode(encoded.as_bytes(), false).unwrap();
+
+		assert_eq!(xt.id.0, decoded.id.0);
+		assert_eq!(xt.entity.as_bytes(), decoded.entity.as_bytes());
+	}
+});
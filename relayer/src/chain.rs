@@ -1 +1,2 @@
 pub mod btc;
+pub mod evm;
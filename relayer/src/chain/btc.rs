@@ -1,23 +1,50 @@
 pub mod api;
 
+pub mod coin_selection;
+use coin_selection::CoinSelection;
+
+pub mod consolidation;
+
+pub mod deposit;
+
+#[cfg(test)] pub mod fixture;
+
+pub mod frost;
+
+pub mod header;
+
+pub mod payout;
+
+pub mod policy;
+
+pub mod psbt;
+
+pub mod recovery;
+
+pub mod signer;
+use signer::*;
+
+pub mod spv;
+
 pub mod types;
 use types::*;
 
 mod util;
 
+mod weight;
+
 // crates.io
 use bitcoin::{
+	bip32::KeySource,
 	blockdata::{
 		locktime::absolute::LockTime,
 		transaction::{Transaction, Version},
 	},
-	consensus,
-	key::{Keypair, TapTweak},
+	key::{Keypair, XOnlyPublicKey},
 	opcodes::all::OP_RETURN,
-	secp256k1::{All, Message, Secp256k1},
-	sighash::{Prevouts, SighashCache},
-	taproot::Signature,
-	Address, Amount, Network, Script, ScriptBuf, TapSighashType, TxIn, TxOut, Witness,
+	psbt::Psbt,
+	secp256k1::{schnorr, All, Secp256k1},
+	Address, Amount, Network, Script, ScriptBuf, TapNodeHash, TapSighashType, TxIn, TxOut,
 };
 use once_cell::sync::Lazy;
 // self
@@ -25,22 +52,75 @@ use crate::{prelude::*, x::*};
 
 static SECP256K1: Lazy<Secp256k1<All>> = Lazy::new(Secp256k1::new);
 
+/// Signer of a vault's taproot key path, whose public key is the internal key.
+pub trait KeySpendSigner
+where
+	Self: Signer,
+{
+	fn script_public_key(&self) -> &ScriptBuf;
+
+	/// Root of the tap tree committed to by the output key.
+	fn merkle_root(&self) -> Option<TapNodeHash>;
+}
+
 #[derive(Debug)]
-pub struct XTxBuilder<'a> {
+pub struct XTxBuilder<'a, K> {
 	pub network: Network,
 	pub fee_rate: Satoshi,
-	pub sender: &'a TaprootKey,
+	pub sender: &'a K,
 	pub utxos: &'a [Utxo],
+	pub coin_selection: CoinSelection,
+	pub sighash_type: TapSighashType,
 	pub recipient: &'a str,
 	pub x_target: XTarget,
 	pub amount: Satoshi,
 }
-impl XTxBuilder<'_> {
+impl<K> XTxBuilder<'_, K>
+where
+	K: KeySpendSigner,
+{
 	const LOCK_TIME: LockTime = LockTime::ZERO;
 	const VERSION: Version = Version::TWO;
 
-	pub fn build(self) -> Result<String> {
-		let Self { network, fee_rate, sender, utxos, recipient, x_target, amount } = self;
+	/// Build the unsigned PSBT for an offline signer, see [`psbt`].
+	///
+	/// `key_origin` is the BIP-32 origin of the internal key, which hardware signers require to
+	/// recognize their key.
+	pub fn build_psbt(self, key_origin: Option<KeySource>) -> Result<Psbt> {
+		let Self { sender, sighash_type, .. } = self;
+		let (unsigned_tx, prevouts) = self.build_unsigned()?;
+		let mut psbt = Psbt::from_unsigned_tx(unsigned_tx).map_err(BitcoinError::Psbt)?;
+		let internal_key = sender.public_key();
+
+		for (input, prevout) in psbt.inputs.iter_mut().zip(prevouts) {
+			input.witness_utxo = Some(prevout);
+			input.sighash_type = Some(sighash_type.into());
+			input.tap_internal_key = Some(internal_key);
+			input.tap_merkle_root = sender.merkle_root();
+
+			if let Some(origin) = &key_origin {
+				input.tap_key_origins.insert(internal_key, (Vec::new(), origin.to_owned()));
+			}
+		}
+
+		tracing::info!("xtx psbt: {psbt}");
+
+		Ok(psbt)
+	}
+
+	// Select the UTXOs and build the unsigned transaction, along with the spent outputs.
+	fn build_unsigned(self) -> Result<(Transaction, Vec<TxOut>)> {
+		let Self {
+			network,
+			fee_rate,
+			sender,
+			utxos,
+			coin_selection,
+			sighash_type,
+			recipient,
+			x_target,
+			amount,
+		} = self;
 		let recipient_spk = util::addr_from_str(recipient, network)?.script_pubkey();
 		let op_return = TxOut {
 			script_pubkey: Script::builder()
@@ -49,112 +129,120 @@ impl XTxBuilder<'_> {
 				.into_script(),
 			value: Amount::ZERO,
 		};
-		let mut input_count = 1;
-		let (utxos, input, output, fee) = loop {
-			// Assume there is always a transfer output, a charge output, and a mark output.
-			let (tx_size, v_size) = util::estimate_tx_size(input_count, 2, op_return.size() as _);
-
-			tracing::info!("estimated tx size: {tx_size}");
-			tracing::info!("estimated tx virtual size: {v_size}");
-
-			let fee = (v_size.ceil() as Satoshi) * fee_rate;
-			let spent = amount + fee;
-			let (utxos_amount, utxos) =
-				util::select_utxos(utxos, spent).ok_or(ChainError::InsufficientFunds {
-					required: spent as _,
-					available: utxos.iter().map(|u| u.value).sum::<Satoshi>() as _,
-				})?;
-
-			if utxos.len() as Satoshi == input_count {
-				let charge = utxos_amount - spent;
-				let input = utxos
-					.iter()
-					.map(|u| TxIn { previous_output: u.outpoint, ..Default::default() })
-					.collect::<Vec<_>>();
-				let mut output = vec![
-					TxOut { script_pubkey: recipient_spk, value: Amount::from_sat(amount) },
-					op_return,
-				];
-
-				if charge != 0 {
-					output.push(TxOut {
-						script_pubkey: sender.script_public_key.clone(),
-						value: Amount::from_sat(charge),
-					});
-				};
-
-				break (utxos, input, output, fee);
-			} else {
-				input_count = utxos.len() as _;
-			}
-		};
+		let charge =
+			TxOut { script_pubkey: sender.script_public_key().clone(), value: Amount::ZERO };
+		let spend = weight::Spend::Key(sighash_type);
+		let mut output = vec![
+			TxOut { script_pubkey: recipient_spk, value: Amount::from_sat(amount) },
+			op_return,
+		];
+		// The charge output is counted by the selection.
+		let selection = util::select_utxos(
+			utxos,
+			amount,
+			weight::base_vsize(&output),
+			Some(&charge),
+			fee_rate,
+			&spend,
+			coin_selection,
+		)?;
+		let utxos = selection.utxos;
+		let input = utxos
+			.iter()
+			.map(|u| TxIn { previous_output: u.outpoint, ..Default::default() })
+			.collect::<Vec<_>>();
+
+		// Otherwise the excess is lower than the cost of the charge, and goes to the fee.
+		if selection.change {
+			output.push(charge);
+		}
 
-		tracing::info!("fee: {fee}");
+		let mut unsigned_tx =
+			Transaction { version: Self::VERSION, lock_time: Self::LOCK_TIME, input, output };
+		let v_size = weight::signed_vsize(&unsigned_tx, &spend);
+		let mut fee = selection.amount - amount;
 
-		let unsigned_tx = Transaction {
-			version: Self::VERSION,
-			lock_time: Self::LOCK_TIME,
-			input,
-			output: output.clone(),
-		};
+		if selection.change {
+			unsigned_tx.output[2].value = Amount::from_sat(fee - v_size * fee_rate);
 
-		let sighash_type = TapSighashType::AllPlusAnyoneCanPay;
-		let mut hasher = SighashCache::new(unsigned_tx);
-
-		for (i, utxo) in utxos.into_iter().enumerate() {
-			let sighash = hasher
-				.taproot_key_spend_signature_hash(
-					i,
-					&Prevouts::One(
-						i,
-						TxOut {
-							script_pubkey: sender.script_public_key.clone(),
-							value: Amount::from_sat(utxo.value),
-						},
-					),
-					sighash_type,
-				)
-				.map_err(BitcoinError::SigHashTapRoot)?;
-			let msg = Message::from_digest_slice(sighash.as_ref())?;
-			let sig = SECP256K1.sign_schnorr(&msg, &sender.keypair);
-			let sig = Signature { signature: sig, sighash_type };
-
-			*hasher.witness_mut(i).unwrap() = Witness::p2tr_key_spend(&sig);
+			// A sub-dust charge goes to the fee too.
+			if policy::is_dust(&unsigned_tx.output[2]) {
+				unsigned_tx.output.pop();
+			} else {
+				fee = v_size * fee_rate;
+			}
 		}
 
-		let tx = hasher.into_transaction();
-
-		tracing::debug!("xtx: {tx:?}");
-
-		// dbg!(tx.total_size());
-		// tx.input.iter().for_each(|i| {
-		// 	dbg!(i.witness.size(), i.base_size());
-		// });
-		// tx.output.iter().for_each(|o| {
-		// 	dbg!(o.size());
-		// });
+		tracing::info!("tx virtual size: {v_size}");
+		tracing::info!("fee: {fee}");
 
-		let tx_hex = array_bytes::bytes2hex("", consensus::serialize(&tx));
+		policy::check(&weight::dummy_signed(&unsigned_tx, &spend), fee)?;
 
-		tracing::info!("xtx hex: {tx_hex}");
+		let prevouts = utxos
+			.iter()
+			.map(|u| TxOut {
+				script_pubkey: sender.script_public_key().clone(),
+				value: Amount::from_sat(u.value),
+			})
+			.collect();
 
-		Ok(tx_hex)
+		Ok((unsigned_tx, prevouts))
 	}
 }
 
+/// Vault address of a single key.
 #[derive(Debug)]
-pub struct TaprootKey {
-	pub keypair: Keypair,
+pub struct TaprootKey<S = LocalSigner> {
+	pub signer: S,
+	pub merkle_root: Option<TapNodeHash>,
 	pub script_public_key: ScriptBuf,
 	pub address: String,
 }
 impl TaprootKey {
-	pub fn from_untweaked_keypair(keypair: Keypair, network: Network) -> Self {
-		let (x_only_public_key, _) = keypair.x_only_public_key();
-		let address = Address::p2tr(&SECP256K1, x_only_public_key, None, network);
-		let keypair = keypair.tap_tweak(&SECP256K1, None).to_inner();
-		let script_public_key = address.script_pubkey();
+	pub fn from_untweaked_keypair(
+		keypair: Keypair,
+		merkle_root: Option<TapNodeHash>,
+		network: Network,
+	) -> Self {
+		Self::new(LocalSigner(keypair), merkle_root, network)
+	}
+}
+impl<S> TaprootKey<S>
+where
+	S: Signer,
+{
+	pub fn new(signer: S, merkle_root: Option<TapNodeHash>, network: Network) -> Self {
+		let address = Address::p2tr(&SECP256K1, signer.public_key(), merkle_root, network);
+
+		Self {
+			signer,
+			merkle_root,
+			script_public_key: address.script_pubkey(),
+			address: address.to_string(),
+		}
+	}
+}
+impl<S> Signer for TaprootKey<S>
+where
+	S: Signer,
+{
+	fn public_key(&self) -> XOnlyPublicKey {
+		self.signer.public_key()
+	}
+
+	async fn sign(&self, request: &SignRequest) -> Result<Vec<schnorr::Signature>> {
+		self.signer.sign(request).await
+	}
+}
+impl<S> KeySpendSigner for TaprootKey<S>
+where
+	S: Signer,
+{
+	fn script_public_key(&self) -> &ScriptBuf {
+		&self.script_public_key
+	}
 
-		Self { keypair, script_public_key, address: address.to_string() }
+	fn merkle_root(&self) -> Option<TapNodeHash> {
+		self.merkle_root
 	}
 }
@@ -1,2 +1,121 @@
+pub mod esplora;
 pub mod mempool;
+#[cfg(test)] pub mod mock;
 pub mod node;
+
+// std
+use std::fmt::Debug;
+// crates.io
+use bitcoin::{block::Header, consensus, Transaction};
+use serde::Deserialize;
+// self
+use super::{policy, spv::SpvProof, types::*};
+use crate::prelude::*;
+
+/// Chain data source of the relayer.
+///
+/// Implementations normalize the backend specific responses, so the relayer logic can be written
+/// once and tested against an in-memory chain.
+pub trait BtcSource
+where
+	Self: Debug + Send + Sync,
+{
+	/// Maximum number of transactions returned by [`BtcSource::get_addr_txs_chain`] at once.
+	const PAGE_SIZE: usize;
+
+	/// Get the confirmed transactions of the address, sorted with newest first.
+	///
+	/// Returns at most [`BtcSource::PAGE_SIZE`] transactions, more can be requested by specifying
+	/// the last `txid` seen by the previous query.
+	async fn get_addr_txs_chain<S, S1>(&self, address: S, after: Option<S1>) -> Result<Vec<Tx>>
+	where
+		S: AsRef<str>,
+		S1: AsRef<str>;
+
+	/// Same as [`BtcSource::get_addr_txs_chain`], but the history below the block at `since` may
+	/// be left out.
+	///
+	/// Backends able to skip it, e.g. the node wallet, do so, the others leave it to the caller.
+	async fn get_addr_txs_chain_since<S, S1>(
+		&self,
+		address: S,
+		since: BlockNumber,
+		after: Option<S1>,
+	) -> Result<Vec<Tx>>
+	where
+		S: AsRef<str>,
+		S1: AsRef<str>,
+	{
+		let _ = since;
+
+		self.get_addr_txs_chain(address, after).await
+	}
+
+	/// Get the unspent transaction outputs of the address, including the unconfirmed ones.
+	async fn get_utxos<S>(&self, address: S) -> Result<Vec<Utxo>>
+	where
+		S: AsRef<str>;
+
+	/// Get the height of the best chain's tip.
+	async fn get_tip_height(&self) -> Result<BlockNumber>;
+
+	/// Get the hash of the best chain's block at `height`.
+	async fn get_block_hash(&self, height: BlockNumber) -> Result<String>;
+
+	/// Get the header of the block.
+	async fn get_block_header(&self, hash: &str) -> Result<Header>;
+
+	/// Get the height and hash of the block including the transaction, `None` if it's unconfirmed
+	/// or unknown.
+	async fn get_tx_block(&self, txid: &str) -> Result<Option<(BlockNumber, String)>>;
+
+	/// Get the `txid` and the block height of the confirmed transaction double spending any input
+	/// of the unconfirmed transaction, `None` if there is none.
+	async fn get_double_spend(&self, tx: &Transaction) -> Result<Option<(String, BlockNumber)>>;
+
+	/// Get the proof of the transaction's inclusion in the block.
+	async fn get_spv_proof(&self, txid: &str, block_hash: &str) -> Result<SpvProof>;
+
+	/// Get the recommended fee rates (in satoshis per virtual byte).
+	async fn get_recommended_fee(&self) -> Result<Fees>;
+
+	/// Broadcast a raw transaction in hex format, returns the `txid`.
+	async fn broadcast<S>(&self, tx_hex: S) -> Result<String>
+	where
+		S: Into<String>;
+
+	/// Broadcast the signed transaction paying the fee once it passes the standardness policy,
+	/// returns the `txid`.
+	async fn broadcast_standard(&self, tx: &Transaction, fee: Satoshi) -> Result<String> {
+		policy::check(tx, fee)?;
+
+		self.broadcast(consensus::encode::serialize_hex(tx)).await
+	}
+
+	/// Submit the raw transactions in hex format as a package, the parents before their child, so
+	/// that the child's fee counts for its parents too.
+	///
+	/// Backends without package relay broadcast them one by one.
+	async fn submit_package(&self, txs_hex: Vec<String>) -> Result<()> {
+		for tx_hex in txs_hex {
+			self.broadcast(tx_hex).await?;
+		}
+
+		Ok(())
+	}
+}
+
+// Result of the `submitpackage` call.
+#[derive(Debug, Deserialize)]
+struct PackageResult {
+	package_msg: String,
+}
+impl PackageResult {
+	fn into_result(self) -> Result<()> {
+		if self.package_msg == "success" {
+			Ok(())
+		} else {
+			Err(ApiError::PackageRejected { message: self.package_msg })?
+		}
+	}
+}
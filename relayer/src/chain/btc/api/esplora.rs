@@ -0,0 +1,96 @@
+//! Rust bindings for the [Esplora API](https://github.com/Blockstream/esplora/blob/master/API.md).
+//!
+//! The Mempool API is a superset of the Esplora API, the differences used by the relayer are the
+//! fee recommendation and the package submission, which falls back to broadcasting one by one.
+
+// std
+use std::{collections::HashMap, fmt::Debug};
+// crates.io
+use bitcoin::{block::Header, Transaction};
+// self
+use super::{
+	super::{spv::SpvProof, types::*},
+	mempool, BtcSource,
+};
+use crate::{http::*, prelude::*};
+
+#[derive(Debug)]
+pub struct Api<H>(pub mempool::Api<H>)
+where
+	H: Http;
+impl<H> BtcSource for Api<H>
+where
+	H: Debug + Http + Send + Sync,
+{
+	const PAGE_SIZE: usize = mempool::Api::<H>::PAGE_SIZE;
+
+	async fn get_addr_txs_chain<S, S1>(&self, address: S, after: Option<S1>) -> Result<Vec<Tx>>
+	where
+		S: AsRef<str>,
+		S1: AsRef<str>,
+	{
+		self.0.get_addr_txs_chain(address, after).await
+	}
+
+	async fn get_utxos<S>(&self, address: S) -> Result<Vec<Utxo>>
+	where
+		S: AsRef<str>,
+	{
+		self.0.get_utxos(address).await
+	}
+
+	async fn get_tip_height(&self) -> Result<BlockNumber> {
+		self.0.get_tip_height().await
+	}
+
+	async fn get_block_hash(&self, height: BlockNumber) -> Result<String> {
+		self.0.get_block_hash(height).await
+	}
+
+	async fn get_block_header(&self, hash: &str) -> Result<Header> {
+		self.0.get_block_header(hash).await
+	}
+
+	async fn get_tx_block(&self, txid: &str) -> Result<Option<(BlockNumber, String)>> {
+		self.0.get_tx_block(txid).await
+	}
+
+	async fn get_double_spend(&self, tx: &Transaction) -> Result<Option<(String, BlockNumber)>> {
+		self.0.get_double_spend(tx).await
+	}
+
+	async fn get_spv_proof(&self, txid: &str, block_hash: &str) -> Result<SpvProof> {
+		self.0.get_spv_proof(txid, block_hash).await
+	}
+
+	// Get an object where the key is the confirmation target (in number of blocks) and the value is
+	// the estimated fee rate (in sat/vB). The targets are mapped to the Mempool API's
+	// recommendation levels.
+	async fn get_recommended_fee(&self) -> Result<Fees> {
+		let estimates = self
+			.0
+			.http
+			.get_with_reties(format!("{}/fee-estimates", self.0.base_uri), 3, 1_000)
+			.await?
+			.json::<HashMap<String, f64>>()?;
+		let of = |target: &str| estimates.get(target).map_or(1, |f| f.ceil() as Satoshi).max(1);
+		let fees = Fees {
+			fastest_fee: of("1"),
+			half_hour_fee: of("3"),
+			hour_fee: of("6"),
+			economy_fee: of("144"),
+			minimum_fee: of("1008"),
+		};
+
+		tracing::debug!("get_fees\n{fees:?}");
+
+		Ok(fees)
+	}
+
+	async fn broadcast<S>(&self, tx_hex: S) -> Result<String>
+	where
+		S: Into<String>,
+	{
+		self.0.broadcast(tx_hex).await
+	}
+}
@@ -1,9 +1,18 @@
 //! Rust bindings for the [Mempool API](https://mempool.space/testnet/docs/api/rest).
 
+// std
+use std::fmt::Debug;
 // crates.io
-use serde::{Deserialize, Serialize};
+use bitcoin::{block::Header, consensus, Transaction, TxMerkleNode};
+use serde::Deserialize;
 // self
-use super::super::types::*;
+use super::{
+	super::{
+		spv::SpvProof,
+		types::{self, *},
+	},
+	BtcSource, PackageResult,
+};
 use crate::{http::*, prelude::*};
 
 #[derive(Debug)]
@@ -12,16 +21,61 @@ where
 	H: Http,
 {
 	pub http: H,
-	pub base_uri: &'static str,
+	pub base_uri: String,
 }
 impl<H> Api<H>
 where
 	H: Http,
 {
+	// Get the list of unspent transaction outputs associated with the address/scripthash. Available
+	// fields: `txid`, `vout`, `value`, and `status` (with the status of the funding tx).
+	pub async fn get_raw_utxos<S>(&self, address: S) -> Result<Vec<Utxo>>
+	where
+		S: AsRef<str>,
+	{
+		let utxos = self
+			.http
+			.get_with_reties(
+				format!("{}/address/{}/utxo", self.base_uri, address.as_ref()),
+				3,
+				1_000,
+			)
+			.await?
+			.json::<Vec<Utxo>>()?;
+
+		tracing::debug!("get_utxos\n{utxos:?}");
+
+		Ok(utxos)
+	}
+
+	// TODO
+	#[allow(unused)]
+	pub async fn get_utxos_confirmed<S>(&self, address: S) -> Result<Vec<Utxo>>
+	where
+		S: AsRef<str>,
+	{
+		let utxos =
+			self.get_raw_utxos(address).await?.into_iter().filter(|u| u.status.confirmed).collect();
+
+		tracing::debug!("get_utxos_confirmed\n{utxos:?}");
+
+		Ok(utxos)
+	}
+}
+impl<H> BtcSource for Api<H>
+where
+	H: Debug + Http + Send + Sync,
+{
+	const PAGE_SIZE: usize = 25;
+
 	// Get confirmed transaction history for the specified address/scripthash, sorted with newest
 	// first. Returns 25 transactions per page. More can be requested by specifying the last `txid`
 	// seen by the previous query.
-	pub async fn get_addr_txs_chain<S, S1>(&self, address: S, after: Option<S1>) -> Result<Vec<Tx>>
+	async fn get_addr_txs_chain<S, S1>(
+		&self,
+		address: S,
+		after: Option<S1>,
+	) -> Result<Vec<types::Tx>>
 	where
 		S: AsRef<str>,
 		S1: AsRef<str>,
@@ -43,42 +97,102 @@ where
 
 		tracing::debug!("{txs:?}");
 
-		Ok(txs)
+		Ok(txs.into_iter().map(Into::into).collect())
 	}
 
-	// Get the list of unspent transaction outputs associated with the address/scripthash. Available
-	// fields: `txid`, `vout`, `value`, and `status` (with the status of the funding tx).
-	pub async fn get_utxos<S>(&self, address: S) -> Result<Vec<Utxo>>
+	async fn get_utxos<S>(&self, address: S) -> Result<Vec<types::Utxo>>
 	where
 		S: AsRef<str>,
 	{
-		let utxos = self
+		self.get_raw_utxos(address).await?.into_iter().map(TryInto::try_into).collect()
+	}
+
+	// Returns the height of the last block.
+	async fn get_tip_height(&self) -> Result<BlockNumber> {
+		Ok(self
 			.http
-			.get_with_reties(format!("{}/address/{}/utxo", self.base_uri, address.as_ref()), 3, 1_000)
+			.get_with_reties(format!("{}/blocks/tip/height", self.base_uri), 3, 1_000)
 			.await?
-			.json::<Vec<Utxo>>()?;
+			.text()
+			.parse()?)
+	}
 
-		tracing::debug!("get_utxos\n{utxos:?}");
+	// Returns the hash of the block currently at `height`.
+	async fn get_block_hash(&self, height: BlockNumber) -> Result<String> {
+		Ok(self
+			.http
+			.get_with_reties(format!("{}/block-height/{height}", self.base_uri), 3, 1_000)
+			.await?
+			.text())
+	}
 
-		Ok(utxos)
+	// Returns the hex-encoded block header.
+	async fn get_block_header(&self, hash: &str) -> Result<Header> {
+		let header = self
+			.http
+			.get_with_reties(format!("{}/block/{hash}/header", self.base_uri), 3, 1_000)
+			.await?
+			.text();
+
+		Ok(consensus::encode::deserialize_hex(&header).map_err(BitcoinError::from)?)
 	}
 
-	// TODO
-	#[allow(unused)]
-	pub async fn get_utxos_confirmed<S>(&self, address: S) -> Result<Vec<Utxo>>
-	where
-		S: AsRef<str>,
-	{
-		let utxos =
-			self.get_utxos(address).await?.into_iter().filter(|u| u.status.confirmed).collect();
+	// Returns the transaction confirmation status. An unknown transaction is responded with a
+	// plain text error, taken as unconfirmed.
+	async fn get_tx_block(&self, txid: &str) -> Result<Option<(BlockNumber, String)>> {
+		let status = self
+			.http
+			.get_with_reties(format!("{}/tx/{txid}/status", self.base_uri), 3, 1_000)
+			.await?;
 
-		tracing::debug!("get_utxos_confirmed\n{utxos:?}");
+		Ok(serde_json::from_slice::<Status>(&status)
+			.ok()
+			.filter(|s| s.confirmed)
+			.map(|s| (s.block_height, s.block_hash)))
+	}
 
-		Ok(utxos)
+	// Returns the spending status of each input through `/tx/:txid/outspend/:vout`, the first one
+	// spent by another confirmed transaction is the double spend.
+	async fn get_double_spend(&self, tx: &Transaction) -> Result<Option<(String, BlockNumber)>> {
+		let txid = tx.compute_txid().to_string();
+
+		for i in &tx.input {
+			let outspend = self
+				.http
+				.get_with_reties(
+					format!(
+						"{}/tx/{}/outspend/{}",
+						self.base_uri, i.previous_output.txid, i.previous_output.vout
+					),
+					3,
+					1_000,
+				)
+				.await?
+				.json::<Outspend>()?;
+
+			if let Outspend { txid: Some(t), status: Some(s) } = outspend {
+				if s.confirmed && t != txid {
+					return Ok(Some((t, s.block_height)));
+				}
+			}
+		}
+
+		Ok(None)
+	}
+
+	// Returns a merkle inclusion proof for the transaction.
+	async fn get_spv_proof(&self, txid: &str, block_hash: &str) -> Result<SpvProof> {
+		let MerkleProof { merkle, pos } = self
+			.http
+			.get_with_reties(format!("{}/tx/{txid}/merkle-proof", self.base_uri), 3, 1_000)
+			.await?
+			.json()?;
+
+		Ok(SpvProof { header: self.get_block_header(block_hash).await?, index: pos, merkle })
 	}
 
 	// Returns our currently suggested fees for new transactions.
-	pub async fn get_recommended_fee(&self) -> Result<Fees> {
+	async fn get_recommended_fee(&self) -> Result<Fees> {
 		let fees = self
 			.http
 			.get_with_reties(format!("{}/v1/fees/recommended", self.base_uri), 3, 1_000)
@@ -92,7 +206,7 @@ where
 
 	// Broadcast a raw transaction to the network. The transaction should be provided as hex in the
 	// request body. The `txid` will be returned on success.
-	pub async fn broadcast<S>(&self, tx_hex: S) -> Result<String>
+	async fn broadcast<S>(&self, tx_hex: S) -> Result<String>
 	where
 		S: Into<String>,
 	{
@@ -102,6 +216,20 @@ where
 			.await?
 			.text())
 	}
+
+	// Submit the package as a JSON array, the result is the node's `submitpackage` one.
+	async fn submit_package(&self, txs_hex: Vec<String>) -> Result<()> {
+		self.http
+			.post_with_retries(
+				format!("{}/txs/package", self.base_uri),
+				serde_json::to_string(&txs_hex)?,
+				3,
+				50,
+			)
+			.await?
+			.json::<PackageResult>()?
+			.into_result()
+	}
 }
 
 #[derive(Debug, Deserialize)]
@@ -109,7 +237,7 @@ pub struct Tx {
 	pub txid: String,
 	// pub version: u8,
 	// pub locktime: BlockNumber,
-	// pub vin: Vec<Vin>,
+	pub vin: Vec<Vin>,
 	pub vout: Vec<Vout>,
 	// pub size: u32,
 	// pub weight: u32,
@@ -117,23 +245,23 @@ pub struct Tx {
 	// pub fee: Satoshi,
 	pub status: Status,
 }
-// #[derive(Debug, Deserialize)]
-// pub struct Vin {
-// 	pub txid: String,
-// 	pub vout: Index,
-// 	pub prevout: Vout,
-// 	pub scriptsig: String,
-// 	pub scriptsig_asm: String,
-// 	pub witness: Vec<String>,
-// 	pub is_coinbase: bool,
-// 	pub sequence: u32,
-// }
+#[derive(Debug, Deserialize)]
+pub struct Vin {
+	// pub txid: String,
+	// pub vout: Index,
+	// `null` for the coinbase.
+	pub prevout: Option<Vout>,
+	// pub scriptsig: String,
+	// pub scriptsig_asm: String,
+	// pub witness: Vec<String>,
+	// pub is_coinbase: bool,
+	// pub sequence: u32,
+}
 #[derive(Debug, Deserialize)]
 pub struct Vout {
 	// pub scriptpubkey: String,
 	pub scriptpubkey_asm: String,
-	// TODO: use enum type.
-	pub scriptpubkey_type: String,
+	pub scriptpubkey_type: ScriptType,
 	pub scriptpubkey_address: Option<String>,
 	pub value: Satoshi,
 }
@@ -148,43 +276,54 @@ pub struct Utxo {
 #[derive(Debug, Deserialize)]
 pub struct Status {
 	pub confirmed: bool,
+	// Unconfirmed transactions don't have the block fields.
+	#[serde(default)]
 	pub block_height: BlockNumber,
-	// 	pub block_hash: String,
+	#[serde(default)]
+	pub block_hash: String,
 	// 	pub block_time: u64,
 }
 
 #[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct Fees {
-	pub fastest_fee: Satoshi,
-	pub half_hour_fee: Satoshi,
-	pub hour_fee: Satoshi,
-	pub economy_fee: Satoshi,
-	pub minimum_fee: Satoshi,
-}
-impl Fees {
-	pub fn of(&self, strategy: FeeType) -> Satoshi {
-		match strategy {
-			FeeType::Fastest => self.fastest_fee,
-			FeeType::HalfHour => self.half_hour_fee,
-			FeeType::Hour => self.hour_fee,
-			FeeType::Economy => self.economy_fee,
-			FeeType::Minimum => self.minimum_fee,
-		}
-	}
+struct Outspend {
+	// Absent if the output is unspent.
+	txid: Option<String>,
+	status: Option<Status>,
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case")]
-pub enum FeeType {
-	Fastest,
-	HalfHour,
-	Hour,
-	Economy,
-	Minimum,
+#[derive(Debug, Deserialize)]
+struct MerkleProof {
+	// Sibling hashes from the transaction up to the merkle root.
+	merkle: Vec<TxMerkleNode>,
+	pos: u32,
 }
-impl Default for FeeType {
-	fn default() -> Self {
-		Self::Fastest
-	}
+#[test]
+fn script_types_should_work() {
+	let txs = serde_json::from_str::<Vec<Tx>>(include_str!(concat!(
+		env!("CARGO_MANIFEST_DIR"),
+		"/fixtures/mempool/address-txs-chain.json"
+	)))
+	.unwrap()
+	.into_iter()
+	.map(types::Tx::from)
+	.collect::<Vec<_>>();
+
+	assert_eq!(
+		txs.iter().flat_map(|t| t.vout.iter().map(|v| v.scriptpubkey_type)).collect::<Vec<_>>(),
+		[
+			ScriptType::V0P2wpkh,
+			ScriptType::OpReturn,
+			ScriptType::V1P2tr,
+			ScriptType::V1P2tr,
+			ScriptType::V1P2tr,
+			ScriptType::P2pkh,
+			ScriptType::P2sh,
+			ScriptType::V0P2wsh,
+			ScriptType::V1P2tr,
+			ScriptType::Unknown,
+			ScriptType::OpReturn,
+		]
+	);
+	assert_eq!(txs[0].block_height, 2_903_102);
+	assert_eq!(txs[1].vout[6].scriptpubkey_address, None);
 }
@@ -0,0 +1,246 @@
+//! In-memory chain data source for testing.
+
+// std
+use std::sync::{
+	atomic::{AtomicU64, Ordering},
+	Mutex,
+};
+// crates.io
+use bitcoin::{
+	block::{Header, Version},
+	consensus,
+	hashes::Hash,
+	merkle_tree, Address, BlockHash, CompactTarget, Network, OutPoint, Transaction, TxMerkleNode,
+	Txid,
+};
+// self
+use super::{
+	super::{spv::SpvProof, types::*},
+	BtcSource,
+};
+use crate::prelude::*;
+
+#[derive(Debug, Default)]
+pub struct Api {
+	// Best chain, indexed by the block height.
+	pub blocks: Mutex<Vec<Block>>,
+	pub utxos: Mutex<Vec<Utxo>>,
+	// Outputs spent by the mined transactions, along with the spending `txid` and block height.
+	pub spends: Mutex<Vec<(OutPoint, String, BlockNumber)>>,
+	pub fee_rate: Satoshi,
+	pub broadcasted: Mutex<Vec<String>>,
+}
+impl Api {
+	/// Append a new block containing the transactions to the best chain, returns its hash.
+	///
+	/// The block is mined with the regtest difficulty, the `txid`s must be valid.
+	pub fn mine(&self, txs: Vec<Tx>) -> String {
+		// Make the blocks unique, even with the same parent and transactions.
+		static TIME: AtomicU64 = AtomicU64::new(0);
+
+		let mut blocks = self.blocks.lock().unwrap();
+		let txids = txs.iter().map(|t| t.txid.parse::<Txid>().unwrap()).collect::<Vec<_>>();
+		let mut header = Header {
+			version: Version::TWO,
+			prev_blockhash: blocks.last().map_or(BlockHash::all_zeros(), |b| b.header.block_hash()),
+			merkle_root: merkle_tree::calculate_root(txids.iter().map(|t| t.to_raw_hash()))
+				.map_or(TxMerkleNode::all_zeros(), TxMerkleNode::from_raw_hash),
+			time: TIME.fetch_add(1, Ordering::Relaxed) as _,
+			bits: CompactTarget::from_consensus(0x207fffff),
+			nonce: 0,
+		};
+
+		while header.validate_pow(header.target()).is_err() {
+			header.nonce += 1;
+		}
+
+		let hash = header.block_hash().to_string();
+		let height = blocks.len() as _;
+		let txs = txs
+			.into_iter()
+			.map(|t| Tx { block_height: height, block_hash: hash.clone(), ..t })
+			.collect();
+
+		blocks.push(Block { hash: hash.clone(), header, txs });
+
+		hash
+	}
+
+	/// Mine the broadcasted transactions into a new block, their inputs are removed from the
+	/// UTXOs.
+	pub fn mine_broadcasted(&self, network: Network) -> String {
+		let height = self.blocks.lock().unwrap().len() as BlockNumber;
+		let mut txs = Vec::<Tx>::new();
+
+		for tx_hex in self.broadcasted.lock().unwrap().drain(..) {
+			let tx = consensus::encode::deserialize_hex::<Transaction>(&tx_hex).unwrap();
+			let txid = tx.compute_txid().to_string();
+
+			// Broadcasted again.
+			if txs.iter().any(|t| t.txid == txid) {
+				continue;
+			}
+
+			self.utxos
+				.lock()
+				.unwrap()
+				.retain(|u| tx.input.iter().all(|i| i.previous_output != u.outpoint));
+			self.spends
+				.lock()
+				.unwrap()
+				.extend(tx.input.iter().map(|i| (i.previous_output, txid.clone(), height)));
+			txs.push(Tx {
+				txid,
+				// The spent outputs of the mined transactions.
+				vin: tx
+					.input
+					.iter()
+					.map(|i| {
+						let prevout_address = self
+							.blocks
+							.lock()
+							.unwrap()
+							.iter()
+							.flat_map(|b| b.txs.iter())
+							.chain(&txs)
+							.find(|t| t.txid == i.previous_output.txid.to_string())
+							.and_then(|t| t.vout.get(i.previous_output.vout as usize))
+							.and_then(|v| v.scriptpubkey_address.clone());
+
+						Vin { prevout_address }
+					})
+					.collect(),
+				vout: tx
+					.output
+					.iter()
+					.map(|o| Vout {
+						scriptpubkey_asm: o.script_pubkey.to_asm_string(),
+						scriptpubkey_type: if o.script_pubkey.is_op_return() {
+							ScriptType::OpReturn
+						} else if o.script_pubkey.is_p2tr() {
+							ScriptType::V1P2tr
+						} else {
+							ScriptType::Unknown
+						},
+						scriptpubkey_address: Address::from_script(&o.script_pubkey, network)
+							.ok()
+							.map(|a| a.to_string()),
+						value: o.value.to_sat(),
+					})
+					.collect(),
+				block_height: 0,
+				block_hash: Default::default(),
+			});
+		}
+
+		self.mine(txs)
+	}
+
+	/// Disconnect the blocks above `height` from the best chain.
+	pub fn reorg(&self, height: BlockNumber) {
+		self.blocks.lock().unwrap().truncate(height as usize + 1);
+		self.spends.lock().unwrap().retain(|(_, _, h)| *h <= height);
+	}
+}
+impl BtcSource for Api {
+	// Small enough to exercise the pagination.
+	const PAGE_SIZE: usize = 2;
+
+	async fn get_addr_txs_chain<S, S1>(&self, address: S, after: Option<S1>) -> Result<Vec<Tx>>
+	where
+		S: AsRef<str>,
+		S1: AsRef<str>,
+	{
+		let address = address.as_ref();
+		let blocks = self.blocks.lock().unwrap();
+		let txs = blocks
+			.iter()
+			.flat_map(|b| b.txs.iter())
+			.rev()
+			.filter(|t| t.vout.iter().any(|v| v.scriptpubkey_address.as_deref() == Some(address)))
+			.skip_while(|t| after.as_ref().map(|a| t.txid != a.as_ref()).unwrap_or_default())
+			.skip(after.is_some() as _)
+			.take(Self::PAGE_SIZE)
+			.cloned()
+			.collect();
+
+		Ok(txs)
+	}
+
+	async fn get_utxos<S>(&self, _: S) -> Result<Vec<Utxo>>
+	where
+		S: AsRef<str>,
+	{
+		Ok(self.utxos.lock().unwrap().clone())
+	}
+
+	async fn get_tip_height(&self) -> Result<BlockNumber> {
+		Ok(self.blocks.lock().unwrap().len().saturating_sub(1) as _)
+	}
+
+	async fn get_block_hash(&self, height: BlockNumber) -> Result<String> {
+		Ok(self.blocks.lock().unwrap()[height as usize].hash.clone())
+	}
+
+	async fn get_block_header(&self, hash: &str) -> Result<Header> {
+		Ok(self.blocks.lock().unwrap().iter().find(|b| b.hash == hash).unwrap().header)
+	}
+
+	async fn get_tx_block(&self, txid: &str) -> Result<Option<(BlockNumber, String)>> {
+		let blocks = self.blocks.lock().unwrap();
+
+		Ok(blocks
+			.iter()
+			.flat_map(|b| b.txs.iter())
+			.find(|t| t.txid == txid)
+			.map(|t| (t.block_height, t.block_hash.clone())))
+	}
+
+	async fn get_double_spend(&self, tx: &Transaction) -> Result<Option<(String, BlockNumber)>> {
+		let txid = tx.compute_txid().to_string();
+
+		Ok(self
+			.spends
+			.lock()
+			.unwrap()
+			.iter()
+			.find(|(o, t, _)| *t != txid && tx.input.iter().any(|i| i.previous_output == *o))
+			.map(|(_, t, h)| (t.clone(), *h)))
+	}
+
+	async fn get_spv_proof(&self, txid: &str, block_hash: &str) -> Result<SpvProof> {
+		let blocks = self.blocks.lock().unwrap();
+		let b = blocks.iter().find(|b| b.hash == block_hash).unwrap();
+		let txids = b.txs.iter().map(|t| t.txid.parse().unwrap()).collect::<Vec<_>>();
+
+		SpvProof::from_txids(b.header, &txids, &txid.parse().unwrap())
+	}
+
+	async fn get_recommended_fee(&self) -> Result<Fees> {
+		let r = self.fee_rate;
+
+		Ok(Fees { fastest_fee: r, half_hour_fee: r, hour_fee: r, economy_fee: r, minimum_fee: r })
+	}
+
+	async fn broadcast<S>(&self, tx_hex: S) -> Result<String>
+	where
+		S: Into<String>,
+	{
+		let tx_hex = tx_hex.into();
+		let tx = consensus::deserialize::<Transaction>(
+			&array_bytes::hex2bytes(&tx_hex).map_err(Error::ArrayBytes)?,
+		)
+		.unwrap();
+
+		self.broadcasted.lock().unwrap().push(tx_hex);
+
+		Ok(tx.compute_txid().to_string())
+	}
+}
+
+#[derive(Debug)]
+pub struct Block {
+	pub hash: String,
+	pub header: Header,
+	pub txs: Vec<Tx>,
+}
@@ -1 +1,534 @@
+//! Rust bindings for the [Bitcoin Core JSON-RPC API](https://developer.bitcoin.org/reference/rpc).
+//!
+//! Bitcoin Core doesn't index transactions by address, so the vault address must be imported into
+//! a (watch-only) descriptor wallet, e.g. `importdescriptors '[{"desc": "addr(..)#..", "timestamp":
+//! "now"}]'`. The wallet is selected through `Api::uri`, e.g. `http://127.0.0.1:8332/wallet/helix`.
+//! The history of an address missing from the wallet is refused rather than reported as empty.
+//!
+//! The spent outputs of the transactions are decoded by `getrawtransaction` with verbosity 2,
+//! available since Bitcoin Core v25, so the node must not prune the blocks of the vault history.
 
+// std
+use std::fmt::Debug;
+// crates.io
+use bitcoin::{block::Header, consensus, Amount, OutPoint, Transaction, Txid};
+use serde::Deserialize;
+use serde_json::json;
+// self
+use super::{
+	super::{spv::SpvProof, types::*},
+	BtcSource, PackageResult,
+};
+use crate::{http::*, prelude::*};
+
+#[derive(Debug)]
+pub struct Api<H>
+where
+	H: Http,
+{
+	pub http: H,
+	pub uri: String,
+}
+impl<H> Api<H>
+where
+	H: Http,
+{
+	// Get the confirmed transaction along with its spent outputs, decoded into the normalized
+	// format.
+	//
+	// The block hash lets `getrawtransaction` find the transaction without `-txindex`, the spent
+	// outputs come from the block's undo data.
+	async fn get_tx(
+		&self,
+		txid: &str,
+		block_height: BlockNumber,
+		block_hash: String,
+	) -> Result<Tx> {
+		let tx = self
+			.http
+			.call::<_, _, DecodedTx>(&self.uri, "getrawtransaction", json!([txid, 2, block_hash]))
+			.await?;
+
+		Ok(Tx {
+			txid: tx.txid,
+			vin: tx
+				.vin
+				.into_iter()
+				.map(|v| Vin { prevout_address: v.prevout.and_then(|p| p.script_pub_key.address) })
+				.collect(),
+			vout: tx
+				.vout
+				.into_iter()
+				.map(|v| Vout {
+					scriptpubkey_asm: v.script_pub_key.asm,
+					scriptpubkey_type: script_type(&v.script_pub_key.r#type),
+					scriptpubkey_address: v.script_pub_key.address,
+					value: v.value.to_sat(),
+				})
+				.collect(),
+			block_height,
+			block_hash,
+		})
+	}
+
+	// The node might not have enough data to estimate the fee, e.g. on regtest. Fallback to the
+	// minimum fee in that case.
+	async fn estimate_smart_fee(&self, conf_target: u16, fallback: Satoshi) -> Result<Satoshi> {
+		let fee = self
+			.http
+			.call::<_, _, SmartFee>(&self.uri, "estimatesmartfee", json!([conf_target]))
+			.await?
+			.feerate
+			.map(sat_per_vb)
+			.unwrap_or(fallback);
+
+		Ok(fee.max(fallback))
+	}
+}
+impl<H> BtcSource for Api<H>
+where
+	H: Debug + Http + Send + Sync,
+{
+	// Same page size as the Mempool API.
+	const PAGE_SIZE: usize = 25;
+
+	// Get confirmed transaction history for the specified address, sorted with newest first.
+	// Returns 25 transactions per page. More can be requested by specifying the last `txid` seen by
+	// the previous query.
+	async fn get_addr_txs_chain<S, S1>(&self, address: S, after: Option<S1>) -> Result<Vec<Tx>>
+	where
+		S: AsRef<str>,
+		S1: AsRef<str>,
+	{
+		self.get_addr_txs_chain_since(address, 0, after).await
+	}
+
+	// Only scan the wallet history from the block at `since` through `listsinceblock`, instead of
+	// listing the whole of it on every page.
+	async fn get_addr_txs_chain_since<S, S1>(
+		&self,
+		address: S,
+		since: BlockNumber,
+		after: Option<S1>,
+	) -> Result<Vec<Tx>>
+	where
+		S: AsRef<str>,
+		S1: AsRef<str>,
+	{
+		let address = address.as_ref();
+
+		// A wallet without the address returns an empty history instead of an error.
+		if after.is_none() {
+			let info = self
+				.http
+				.call::<_, _, AddressInfo>(&self.uri, "getaddressinfo", json!([address]))
+				.await?;
+
+			if !info.ismine && !info.iswatchonly {
+				Err(ApiError::Unwatched { address: address.into() })?;
+			}
+		}
+
+		// The transactions of the block at `since` are included, the blocks after the given one
+		// are listed.
+		let params = match since.checked_sub(1) {
+			Some(h) => json!([self.get_block_hash(h).await?, 1, true]),
+			None => json!([]),
+		};
+		let mut entries = self
+			.http
+			.call::<_, _, ListSinceBlock>(&self.uri, "listsinceblock", params)
+			.await?
+			.transactions
+			.into_iter()
+			.filter(|e| {
+				e.confirmations > 0 && e.address.as_ref().map(|a| a == address).unwrap_or_default()
+			})
+			.collect::<Vec<_>>();
+
+		entries.sort_by_key(|e| std::cmp::Reverse((e.blockheight, e.blockindex)));
+		// A transaction has an entry for each of its outputs paying to the wallet.
+		entries.dedup_by(|a, b| a.txid == b.txid);
+
+		let skip = match after {
+			Some(a) => entries.iter().position(|e| e.txid == a.as_ref()).map_or(0, |i| i + 1),
+			None => 0,
+		};
+		let mut txs = Vec::new();
+
+		for e in entries.into_iter().skip(skip).take(Self::PAGE_SIZE) {
+			txs.push(self.get_tx(&e.txid, e.blockheight, e.blockhash).await?);
+		}
+
+		tracing::debug!("{txs:?}");
+
+		Ok(txs)
+	}
+
+	// Get the list of unspent transaction outputs associated with the address, including the
+	// unconfirmed ones.
+	async fn get_utxos<S>(&self, address: S) -> Result<Vec<Utxo>>
+	where
+		S: AsRef<str>,
+	{
+		let utxos = self
+			.http
+			.call::<_, _, Vec<ListUnspentEntry>>(
+				&self.uri,
+				"listunspent",
+				json!([0, 9_999_999, [address.as_ref()], true]),
+			)
+			.await?
+			.into_iter()
+			.map(|u| {
+				Ok(Utxo {
+					outpoint: OutPoint {
+						txid: u.txid.parse().map_err(BitcoinError::HexToArray)?,
+						vout: u.vout,
+					},
+					value: u.amount.to_sat(),
+					confirmed: u.confirmations > 0,
+				})
+			})
+			.collect::<Result<Vec<_>>>()?;
+
+		tracing::debug!("get_utxos\n{utxos:?}");
+
+		Ok(utxos)
+	}
+
+	// Get the height of the most-work fully-validated chain.
+	async fn get_tip_height(&self) -> Result<BlockNumber> {
+		self.http.call(&self.uri, "getblockcount", json!([])).await
+	}
+
+	// Get the hash of block in best-block-chain at height provided.
+	async fn get_block_hash(&self, height: BlockNumber) -> Result<String> {
+		self.http.call(&self.uri, "getblockhash", json!([height])).await
+	}
+
+	// Get the serialized, hex-encoded header of the block.
+	async fn get_block_header(&self, hash: &str) -> Result<Header> {
+		let header = self
+			.http
+			.call::<_, _, String>(&self.uri, "getblockheader", json!([hash, false]))
+			.await?;
+
+		Ok(consensus::encode::deserialize_hex(&header).map_err(BitcoinError::from)?)
+	}
+
+	// Get the block of the wallet transaction, the payouts spend the vault so they are always
+	// known by the wallet.
+	async fn get_tx_block(&self, txid: &str) -> Result<Option<(BlockNumber, String)>> {
+		match self
+			.http
+			.call::<_, _, WalletTxStatus>(&self.uri, "gettransaction", json!([txid, true]))
+			.await
+		{
+			Ok(WalletTxStatus {
+				confirmations, blockheight: Some(h), blockhash: Some(b), ..
+			}) if confirmations > 0 => Ok(Some((h, b))),
+			Ok(_) => Ok(None),
+			// Invalid or non-wallet transaction id.
+			Err(Error::Api(ApiError::JsonRpc { code: -5, .. })) => Ok(None),
+			Err(e) => Err(e),
+		}
+	}
+
+	// The wallet reports a transaction double spent by a confirmed one with negative
+	// confirmations, the confirmed one is among its conflicts.
+	async fn get_double_spend(&self, tx: &Transaction) -> Result<Option<(String, BlockNumber)>> {
+		let conflicts = match self
+			.http
+			.call::<_, _, WalletTxStatus>(
+				&self.uri,
+				"gettransaction",
+				json!([tx.compute_txid().to_string(), true]),
+			)
+			.await
+		{
+			Ok(WalletTxStatus { confirmations, walletconflicts, .. }) if confirmations < 0 =>
+				walletconflicts,
+			Ok(_) => return Ok(None),
+			// Never reached the node.
+			Err(Error::Api(ApiError::JsonRpc { code: -5, .. })) => return Ok(None),
+			Err(e) => Err(e)?,
+		};
+
+		for c in conflicts {
+			if let Some((h, _)) = self.get_tx_block(&c).await? {
+				return Ok(Some((c, h)));
+			}
+		}
+
+		Ok(None)
+	}
+
+	// Build the proof from the block's transaction ids, `gettxoutproof` returns a partial merkle
+	// tree instead of a branch.
+	async fn get_spv_proof(&self, txid: &str, block_hash: &str) -> Result<SpvProof> {
+		let txids = self
+			.http
+			.call::<_, _, BlockTxids>(&self.uri, "getblock", json!([block_hash, 1]))
+			.await?
+			.tx;
+
+		SpvProof::from_txids(
+			self.get_block_header(block_hash).await?,
+			&txids,
+			&txid.parse::<Txid>().map_err(BitcoinError::HexToArray)?,
+		)
+	}
+
+	// Estimate the fees through `estimatesmartfee`, the targets are mapped to the Mempool API's
+	// recommendation levels. The minimum fee is taken from the node's mempool policy.
+	async fn get_recommended_fee(&self) -> Result<Fees> {
+		let minimum_fee = sat_per_vb(
+			self.http
+				.call::<_, _, MempoolInfo>(&self.uri, "getmempoolinfo", json!([]))
+				.await?
+				.mempoolminfee,
+		);
+		let fees = Fees {
+			fastest_fee: self.estimate_smart_fee(1, minimum_fee).await?,
+			half_hour_fee: self.estimate_smart_fee(3, minimum_fee).await?,
+			hour_fee: self.estimate_smart_fee(6, minimum_fee).await?,
+			economy_fee: self.estimate_smart_fee(144, minimum_fee).await?,
+			minimum_fee,
+		};
+
+		tracing::debug!("get_fees\n{fees:?}");
+
+		Ok(fees)
+	}
+
+	// Broadcast a raw transaction to the network. The transaction should be provided as hex. The
+	// `txid` will be returned on success.
+	async fn broadcast<S>(&self, tx_hex: S) -> Result<String>
+	where
+		S: Into<String>,
+	{
+		self.http.call(&self.uri, "sendrawtransaction", json!([tx_hex.into()])).await
+	}
+
+	// Submit the package through `submitpackage`, available since Bitcoin Core v26.
+	async fn submit_package(&self, txs_hex: Vec<String>) -> Result<()> {
+		self.http
+			.call::<_, _, PackageResult>(&self.uri, "submitpackage", json!([txs_hex]))
+			.await?
+			.into_result()
+	}
+}
+
+#[derive(Debug, Deserialize)]
+struct AddressInfo {
+	ismine: bool,
+	#[serde(default)]
+	iswatchonly: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListSinceBlock {
+	transactions: Vec<ListTransactionsEntry>,
+}
+#[derive(Debug, Deserialize)]
+struct ListTransactionsEntry {
+	address: Option<String>,
+	confirmations: i64,
+	#[serde(default)]
+	blockheight: BlockNumber,
+	#[serde(default)]
+	blockhash: String,
+	#[serde(default)]
+	blockindex: u32,
+	txid: String,
+}
+
+// Conflicted transactions have negative confirmations.
+#[derive(Debug, Deserialize)]
+struct WalletTxStatus {
+	confirmations: i64,
+	blockheight: Option<BlockNumber>,
+	blockhash: Option<String>,
+	#[serde(default)]
+	walletconflicts: Vec<String>,
+}
+#[derive(Debug, Deserialize)]
+struct DecodedTx {
+	txid: String,
+	vin: Vec<DecodedVin>,
+	vout: Vec<DecodedVout>,
+}
+#[derive(Debug, Deserialize)]
+struct DecodedVin {
+	// Absent for the coinbase.
+	prevout: Option<DecodedPrevout>,
+}
+#[derive(Debug, Deserialize)]
+struct DecodedPrevout {
+	#[serde(rename = "scriptPubKey")]
+	script_pub_key: ScriptPubKey,
+}
+#[derive(Debug, Deserialize)]
+struct DecodedVout {
+	#[serde(with = "bitcoin::amount::serde::as_btc")]
+	value: Amount,
+	#[serde(rename = "scriptPubKey")]
+	script_pub_key: ScriptPubKey,
+}
+#[derive(Debug, Deserialize)]
+struct ScriptPubKey {
+	asm: String,
+	r#type: String,
+	address: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BlockTxids {
+	tx: Vec<Txid>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListUnspentEntry {
+	txid: String,
+	vout: Index,
+	#[serde(with = "bitcoin::amount::serde::as_btc")]
+	amount: Amount,
+	confirmations: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct MempoolInfo {
+	#[serde(with = "bitcoin::amount::serde::as_btc")]
+	mempoolminfee: Amount,
+}
+
+#[derive(Debug, Deserialize)]
+struct SmartFee {
+	#[serde(default, with = "bitcoin::amount::serde::as_btc::opt")]
+	feerate: Option<Amount>,
+}
+
+// Convert a fee rate in BTC/kvB into sat/vB, rounding up.
+fn sat_per_vb(fee_rate: Amount) -> Satoshi {
+	fee_rate.to_sat().div_ceil(1_000).max(1)
+}
+
+// Map the Bitcoin Core script type names.
+fn script_type(r#type: &str) -> ScriptType {
+	match r#type {
+		"pubkeyhash" => ScriptType::P2pkh,
+		"scripthash" => ScriptType::P2sh,
+		"witness_v0_keyhash" => ScriptType::V0P2wpkh,
+		"witness_v0_scripthash" => ScriptType::V0P2wsh,
+		"witness_v1_taproot" => ScriptType::V1P2tr,
+		"nulldata" => ScriptType::OpReturn,
+		_ => ScriptType::Unknown,
+	}
+}
+
+#[tokio::test]
+async fn node_api_should_work() {
+	// crates.io
+	use bitcoin::{absolute::LockTime, transaction::Version};
+
+	let tx = |lock_time| Transaction {
+		version: Version::TWO,
+		lock_time: LockTime::from_consensus(lock_time),
+		input: Vec::new(),
+		output: Vec::new(),
+	};
+	let double_spent = tx(1).compute_txid().to_string();
+	let api = Api {
+		http: MockRpc::new(move |method, params| match method {
+			"getaddressinfo" => json!({ "ismine": false, "iswatchonly": params[0] == "vault" }),
+			"listsinceblock" if params.as_array().unwrap().is_empty() => json!({ "transactions": [
+				{ "address": "vault", "confirmations": 2, "blockheight": 99, "blockindex": 1, "txid": "a" },
+				{ "address": "vault", "confirmations": 1, "blockheight": 100, "blockhash": "00".repeat(32), "blockindex": 3, "txid": "b" },
+				{ "address": "vault", "confirmations": 1, "blockheight": 100, "blockhash": "00".repeat(32), "blockindex": 3, "txid": "b" },
+				{ "address": "vault", "confirmations": 0, "txid": "c" },
+				{ "address": "other", "confirmations": 1, "blockheight": 100, "blockindex": 4, "txid": "d" },
+			]}),
+			"listsinceblock" => json!({ "transactions": [
+				{ "address": "vault", "confirmations": 1, "blockheight": 100, "blockhash": "00".repeat(32), "blockindex": 3, "txid": "b" },
+			]}),
+			"getrawtransaction" => json!({
+				"txid": "b",
+				"vin": [
+					{ "prevout": { "value": 0.0002, "scriptPubKey": { "asm": "1 00", "type": "witness_v1_taproot", "address": "vault" } } },
+					{ "prevout": { "value": 0.0001, "scriptPubKey": { "asm": "", "type": "nonstandard" } } },
+				],
+				"vout": [
+					{ "value": 0.0001, "scriptPubKey": { "asm": "1 00", "type": "witness_v1_taproot", "address": "vault" } },
+					{ "value": 0, "scriptPubKey": { "asm": "OP_RETURN 00", "type": "nulldata" } },
+				],
+			}),
+			// Double spent by `b`.
+			"gettransaction" if params[0] == double_spent => json!({
+				"confirmations": -1,
+				"walletconflicts": ["b"],
+			}),
+			"gettransaction" => json!({
+				"confirmations": 1,
+				"blockheight": 100,
+				"blockhash": "00".repeat(32),
+			}),
+			"listunspent" => json!([
+				{ "txid": "aa".repeat(32), "vout": 0, "amount": 0.0001, "confirmations": 2 },
+				{ "txid": "cc".repeat(32), "vout": 1, "amount": 0.00000546, "confirmations": 0 },
+			]),
+			"getblockcount" => json!(100),
+			"getblockhash" => json!("00".repeat(32)),
+			"getmempoolinfo" => json!({ "mempoolminfee": 0.00001 }),
+			"estimatesmartfee" => json!({ "feerate": 0.00012345, "blocks": 2 }),
+			"sendrawtransaction" => json!("b"),
+			"submitpackage" if params[0].as_array().unwrap().len() == 2 =>
+				json!({ "package_msg": "success", "tx-results": {} }),
+			"submitpackage" => json!({ "package_msg": "transaction failed", "tx-results": {} }),
+			_ => unreachable!(),
+		}),
+		uri: "http://127.0.0.1:18443/wallet/helix".into(),
+	};
+
+	let txs = api.get_addr_txs_chain("vault", None::<&str>).await.unwrap();
+
+	assert_eq!(txs.len(), 2);
+	assert_eq!(txs[0].vout[0].scriptpubkey_type, ScriptType::V1P2tr);
+	assert_eq!(txs[0].vout[0].value, 10_000);
+	assert_eq!(txs[0].vout[1].scriptpubkey_type, ScriptType::OpReturn);
+	assert_eq!(txs[0].vin[0].prevout_address.as_deref(), Some("vault"));
+	assert_eq!(txs[0].vin[1].prevout_address, None);
+	assert_eq!(txs[0].block_height, 100);
+	assert_eq!(txs[0].block_hash, "00".repeat(32));
+	assert_eq!(api.get_addr_txs_chain("vault", Some("b")).await.unwrap().len(), 1);
+	assert_eq!(api.get_addr_txs_chain_since("vault", 100, None::<&str>).await.unwrap().len(), 1);
+	assert!(matches!(
+		api.get_addr_txs_chain("other", None::<&str>).await,
+		Err(Error::Api(ApiError::Unwatched { .. }))
+	));
+
+	let utxos = api.get_utxos("vault").await.unwrap();
+
+	assert_eq!(utxos[0].value, 10_000);
+	assert_eq!(utxos[1].outpoint.vout, 1);
+	assert_eq!(utxos[1].value, 546);
+	assert!(utxos[0].confirmed && !utxos[1].confirmed);
+
+	assert_eq!(api.get_tip_height().await.unwrap(), 100);
+	assert_eq!(api.get_block_hash(100).await.unwrap(), "00".repeat(32));
+	assert_eq!(api.get_tx_block("b").await.unwrap(), Some((100, "00".repeat(32))));
+	assert_eq!(api.get_double_spend(&tx(1)).await.unwrap(), Some(("b".into(), 100)));
+	assert_eq!(api.get_double_spend(&tx(0)).await.unwrap(), None);
+
+	let fees = api.get_recommended_fee().await.unwrap();
+
+	assert_eq!(fees.fastest_fee, 13);
+	assert_eq!(fees.minimum_fee, 1);
+	assert_eq!(api.broadcast("00").await.unwrap(), "b");
+	assert!(api.submit_package(vec!["00".into(), "01".into()]).await.is_ok());
+	assert!(matches!(
+		api.submit_package(vec!["00".into()]).await,
+		Err(Error::Api(ApiError::PackageRejected { .. }))
+	));
+	assert!(api.http.get("http://127.0.0.1:18443/rest/chaininfo.json").await.is_err());
+}
@@ -0,0 +1,417 @@
+//! Coin selection of the vault UTXOs, following Bitcoin Core.
+//!
+//! UTXOs are selected by their effective values, which are their values minus the fees of
+//! spending them, so that adding an input is never free. The candidates are scored by their
+//! waste, the fee paid now over the fee of spending the same inputs at the long-term fee rate,
+//! plus either the cost of the change or the excess dropped to the fee if there is no change:
+//!
+//! - Branch-and-bound searches for a changeless selection, whose excess is lower than the cost of
+//!   the change.
+//! - Knapsack approximates the smallest selection over the target plus a minimum change.
+//! - Single random draw picks UTXOs randomly until the target plus a minimum change is reached,
+//!   which breaks the patterns of the others.
+
+// std
+use std::cmp::Reverse;
+// crates.io
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+// self
+use super::types::{Satoshi, Utxo};
+
+// Explored branches of branch-and-bound before giving up, as Bitcoin Core does.
+const BNB_TOTAL_TRIES: usize = 100_000;
+// Random subsets tried by knapsack.
+const KNAPSACK_ITERATIONS: usize = 1_000;
+
+/// Coin selection algorithm of the vault.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SelectionStrategy {
+	/// Run every algorithm and keep the selection of the least waste.
+	#[default]
+	LeastWaste,
+	/// Branch-and-bound, falling back to knapsack if there is no changeless selection.
+	BranchAndBound,
+	Knapsack,
+	SingleRandomDraw,
+}
+
+/// Coin selection policy of the vault.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CoinSelection {
+	#[serde(default)]
+	pub strategy: SelectionStrategy,
+	/// Fee rate the vault expects to spend its UTXOs at in the long run, the inputs are cheaper
+	/// to spend now below it.
+	#[serde(default = "default_long_term_fee_rate")]
+	pub long_term_fee_rate: Satoshi,
+}
+impl Default for CoinSelection {
+	fn default() -> Self {
+		Self { strategy: Default::default(), long_term_fee_rate: default_long_term_fee_rate() }
+	}
+}
+fn default_long_term_fee_rate() -> Satoshi {
+	10
+}
+
+/// Costs of the transaction the UTXOs are selected for, in virtual bytes and satoshis per virtual
+/// byte.
+#[derive(Clone, Copy, Debug)]
+pub struct SelectionParams {
+	/// Amount to spend, including the fee of the transaction without the inputs and the change.
+	pub target: Satoshi,
+	pub fee_rate: Satoshi,
+	/// Size of an input, which is also the size of spending the change later.
+	pub input_vsize: Satoshi,
+	/// Size of the change output, `None` if an output of the transaction takes the excess anyway.
+	pub change_vsize: Option<Satoshi>,
+	/// Smallest change worth creating.
+	pub min_change: Satoshi,
+}
+impl SelectionParams {
+	fn input_fee(&self) -> Satoshi {
+		self.fee_rate * self.input_vsize
+	}
+
+	fn change_fee(&self) -> Satoshi {
+		self.fee_rate * self.change_vsize.unwrap_or_default()
+	}
+
+	// Target of the selections with change.
+	fn target_with_change(&self) -> Satoshi {
+		self.target + self.change_fee() + self.min_change
+	}
+}
+
+/// Selected UTXOs, whose effective values cover the target.
+#[derive(Debug)]
+pub struct Selection<'a> {
+	pub utxos: Vec<&'a Utxo>,
+	/// Total value of the UTXOs.
+	pub amount: Satoshi,
+	/// Whether the transaction needs a change output, or the excess is dropped to the fee.
+	pub change: bool,
+	pub waste: i64,
+}
+
+// UTXO spendable at a positive effective value.
+#[derive(Debug)]
+struct Candidate<'a> {
+	utxo: &'a Utxo,
+	effective_value: Satoshi,
+}
+
+/// Select the UTXOs paying the target with the strategy.
+pub fn select<'a>(
+	utxos: &'a [Utxo],
+	params: &SelectionParams,
+	coin_selection: CoinSelection,
+) -> Option<Selection<'a>> {
+	let input_fee = params.input_fee();
+	let mut candidates = utxos
+		.iter()
+		.filter(|u| u.value > input_fee)
+		.map(|u| Candidate { utxo: u, effective_value: u.value - input_fee })
+		.collect::<Vec<_>>();
+
+	candidates.sort_by_key(|c| Reverse(c.effective_value));
+
+	let CoinSelection { strategy, long_term_fee_rate } = coin_selection;
+	// Waste of each input, negative if spending it now is cheaper than in the long run.
+	let input_waste =
+		(params.fee_rate as i64 - long_term_fee_rate as i64) * params.input_vsize as i64;
+	// Nothing is lost to the excess without change.
+	let cost_of_change = params
+		.change_vsize
+		.map_or(0, |_| params.change_fee() + long_term_fee_rate * params.input_vsize);
+	let mut rng = rand::thread_rng();
+	let changeless = || {
+		branch_and_bound(&candidates, params.target, cost_of_change, input_waste)
+			.map(|s| selection(&candidates, s, params, false, input_waste, cost_of_change))
+	};
+	let knapsack = |rng: &mut _| {
+		knapsack(&candidates, params.target_with_change(), rng)
+			.map(|s| selection(&candidates, s, params, true, input_waste, cost_of_change))
+	};
+	let single_random_draw = |rng: &mut _| {
+		single_random_draw(&candidates, params.target_with_change(), rng)
+			.map(|s| selection(&candidates, s, params, true, input_waste, cost_of_change))
+	};
+
+	match strategy {
+		SelectionStrategy::LeastWaste =>
+			[changeless(), knapsack(&mut rng), single_random_draw(&mut rng)]
+				.into_iter()
+				.flatten()
+				// Fewer inputs on ties, the vault keeps the others for later.
+				.min_by_key(|s| (s.waste, s.utxos.len())),
+		SelectionStrategy::BranchAndBound => changeless().or_else(|| knapsack(&mut rng)),
+		SelectionStrategy::Knapsack => knapsack(&mut rng),
+		SelectionStrategy::SingleRandomDraw => single_random_draw(&mut rng),
+	}
+}
+
+fn selection<'a>(
+	candidates: &[Candidate<'a>],
+	selected: Vec<usize>,
+	params: &SelectionParams,
+	change: bool,
+	input_waste: i64,
+	cost_of_change: Satoshi,
+) -> Selection<'a> {
+	let effective_value = selected.iter().map(|&i| candidates[i].effective_value).sum::<Satoshi>();
+	let utxos = selected.into_iter().map(|i| candidates[i].utxo).collect::<Vec<_>>();
+	let amount = utxos.iter().map(|u| u.value).sum();
+	let waste = input_waste * utxos.len() as i64
+		+ if change { cost_of_change } else { effective_value - params.target } as i64;
+
+	Selection { utxos, amount, change, waste }
+}
+
+// Depth-first search for the changeless selection of the least waste, whose effective value is
+// between the target and the target plus the cost of change.
+//
+// The candidates are sorted by their effective values in descending order. Each one is included
+// first and then excluded, the branches which can't reach the target, overshoot it, or waste more
+// than the best selection are cut.
+fn branch_and_bound(
+	candidates: &[Candidate],
+	target: Satoshi,
+	cost_of_change: Satoshi,
+	input_waste: i64,
+) -> Option<Vec<usize>> {
+	let mut available = candidates.iter().map(|c| c.effective_value).sum::<Satoshi>();
+
+	if available < target {
+		return None;
+	}
+
+	let mut value = 0;
+	let mut waste = 0;
+	let mut selected = Vec::<usize>::new();
+	let mut best = None;
+	let mut best_waste = i64::MAX;
+	let mut i = 0;
+
+	for _ in 0..BNB_TOTAL_TRIES {
+		let backtrack = if value + available < target
+			|| value > target + cost_of_change
+			// More inputs only waste more when the fee rate is higher than the long-term one.
+			|| (waste > best_waste && input_waste > 0)
+		{
+			true
+		} else if value >= target {
+			let excess = (value - target) as i64;
+
+			if waste + excess <= best_waste {
+				best = Some(selected.clone());
+				best_waste = waste + excess;
+			}
+
+			true
+		} else {
+			false
+		};
+
+		if backtrack {
+			let Some(&last) = selected.last() else {
+				break;
+			};
+
+			// The candidates after the last included one are available again to its exclusion
+			// branch.
+			i -= 1;
+
+			while i > last {
+				available += candidates[i].effective_value;
+				i -= 1;
+			}
+
+			value -= candidates[i].effective_value;
+			waste -= input_waste;
+			selected.pop();
+		} else {
+			let c = &candidates[i];
+
+			available -= c.effective_value;
+
+			// Including a candidate equal to the excluded previous one leads to the branches
+			// explored already.
+			if selected.last().is_none_or(|&last| last == i - 1)
+				|| c.effective_value != candidates[i - 1].effective_value
+			{
+				selected.push(i);
+				value += c.effective_value;
+				waste += input_waste;
+			}
+		}
+
+		i += 1;
+	}
+
+	best
+}
+
+// Approximate the smallest selection reaching the target by random subsets of the candidates
+// smaller than the target, or the smallest larger candidate if it's closer.
+fn knapsack<R>(candidates: &[Candidate], target: Satoshi, rng: &mut R) -> Option<Vec<usize>>
+where
+	R: Rng,
+{
+	// Sorted in descending order, the larger ones come first.
+	let larger = candidates.iter().rposition(|c| c.effective_value >= target);
+
+	if let Some(i) = larger {
+		if candidates[i].effective_value == target {
+			return Some(vec![i]);
+		}
+	}
+
+	let lower = larger.map_or(0, |i| i + 1);
+	let values = candidates[lower..].iter().map(|c| c.effective_value).collect::<Vec<_>>();
+	let total_lower = values.iter().sum::<Satoshi>();
+
+	if total_lower < target {
+		return larger.map(|i| vec![i]);
+	}
+
+	let mut best = vec![true; values.len()];
+	let mut best_value = total_lower;
+
+	for _ in 0..KNAPSACK_ITERATIONS {
+		if best_value == target {
+			break;
+		}
+
+		let mut included = vec![false; values.len()];
+		let mut value = 0;
+		let mut reached = false;
+
+		// Random inclusions first, then the rest until the target is reached.
+		for pass in 0..2 {
+			if reached {
+				break;
+			}
+
+			for (j, &v) in values.iter().enumerate() {
+				if if pass == 0 { rng.gen_bool(0.5) } else { !included[j] } {
+					value += v;
+					included[j] = true;
+
+					if value >= target {
+						reached = true;
+
+						if value < best_value {
+							best_value = value;
+							best.clone_from(&included);
+						}
+
+						value -= v;
+						included[j] = false;
+					}
+				}
+			}
+		}
+	}
+
+	match larger {
+		Some(i) if candidates[i].effective_value <= best_value => Some(vec![i]),
+		_ =>
+			Some(best.into_iter().enumerate().filter(|(_, b)| *b).map(|(j, _)| lower + j).collect()),
+	}
+}
+
+// Pick the candidates randomly until the target is reached.
+fn single_random_draw<R>(
+	candidates: &[Candidate],
+	target: Satoshi,
+	rng: &mut R,
+) -> Option<Vec<usize>>
+where
+	R: Rng,
+{
+	let mut order = (0..candidates.len()).collect::<Vec<_>>();
+	let mut value = 0;
+
+	order.shuffle(rng);
+
+	for (n, &i) in order.iter().enumerate() {
+		value += candidates[i].effective_value;
+
+		if value >= target {
+			order.truncate(n + 1);
+
+			return Some(order);
+		}
+	}
+
+	None
+}
+#[test]
+fn branch_and_bound_should_work() {
+	let utxos = [1, 2, 3, 4, 5].map(|v| Utxo::new(v * 1_000));
+	let candidates = utxos
+		.iter()
+		.rev()
+		.map(|u| Candidate { utxo: u, effective_value: u.value })
+		.collect::<Vec<_>>();
+	// Total value and the input count.
+	let summary =
+		|s: Vec<usize>| (s.iter().map(|&i| candidates[i].utxo.value).sum::<Satoshi>(), s.len());
+
+	// Exact match of the fewest inputs, when inputs waste.
+	assert_eq!(branch_and_bound(&candidates, 9_000, 0, 1).map(summary), Some((9_000, 2)));
+	// Within the cost of change.
+	assert_eq!(branch_and_bound(&candidates, 9_500, 600, 1).map(summary), Some((10_000, 3)));
+	assert!(branch_and_bound(&candidates, 9_500, 400, 1).is_none());
+	// More inputs save on waste when the fee rate is lower than the long-term one.
+	assert_eq!(branch_and_bound(&candidates, 9_000, 0, -1).map(summary), Some((9_000, 3)));
+	assert!(branch_and_bound(&candidates, 16_000, 1_000, 1).is_none());
+}
+#[test]
+fn select_should_work() {
+	let params = SelectionParams {
+		target: 10_000,
+		fee_rate: 2,
+		input_vsize: 58,
+		change_vsize: Some(43),
+		min_change: 330,
+	};
+	let coin_selection = |strategy| CoinSelection { strategy, long_term_fee_rate: 10 };
+	// Effective values of 9_884 and 116.
+	let utxos = [Utxo::new(10_000), Utxo::new(232), Utxo::new(50_000), Utxo::new(100)];
+
+	for strategy in [SelectionStrategy::LeastWaste, SelectionStrategy::BranchAndBound] {
+		let s = select(&utxos, &params, coin_selection(strategy)).unwrap();
+
+		assert_eq!(s.utxos, [&utxos[0], &utxos[1]]);
+		assert_eq!((s.amount, s.change), (10_232, false));
+		assert_eq!(s.waste, (2 - 10) * 58 * 2);
+	}
+
+	let s = select(&utxos, &params, coin_selection(SelectionStrategy::Knapsack)).unwrap();
+
+	// The only selection with a large enough change.
+	assert_eq!(s.utxos, [&utxos[2]]);
+	assert!(s.change);
+	assert_eq!(s.waste, (2 - 10) * 58 + 2 * 43 + 10 * 58);
+
+	for _ in 0..10 {
+		let s =
+			select(&utxos, &params, coin_selection(SelectionStrategy::SingleRandomDraw)).unwrap();
+
+		assert!(s.change);
+		assert!(s.amount - 116 * s.utxos.len() as Satoshi >= params.target_with_change());
+	}
+
+	// Not enough after the fees of spending the UTXOs.
+	assert!(select(
+		&utxos,
+		&SelectionParams { target: 60_000, ..params },
+		coin_selection(SelectionStrategy::LeastWaste)
+	)
+	.is_none());
+}
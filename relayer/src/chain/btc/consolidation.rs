@@ -0,0 +1,137 @@
+//! Consolidation of the small vault UTXOs while the fees are low.
+//!
+//! Every deposit adds a UTXO to the vault, which the later spends pay for at whatever the fee rate
+//! is then. Merging the smallest ones into a few larger outputs at a low fee rate keeps the vault
+//! cheap to spend from, while several outputs still allow payouts in parallel.
+
+// std
+use std::iter;
+// crates.io
+use bitcoin::{
+	absolute::LockTime, transaction::Version, Amount, Sequence, TapSighashType, Transaction, TxIn,
+	TxOut,
+};
+// self
+use super::{
+	payout, policy,
+	types::*,
+	weight::{self, Spend},
+	KeySpendSigner,
+};
+use crate::prelude::*;
+
+/// Whether the UTXO is worth more than the fee of spending it at the fee rate.
+pub fn is_worth_spending(utxo: &Utxo, fee_rate: Satoshi) -> bool {
+	utxo.value > Spend::Key(TapSighashType::Default).input_vsize() * fee_rate
+}
+
+/// Consolidation spending all the UTXOs to `outputs` equal outputs to the vault.
+#[derive(Debug)]
+pub struct ConsolidationTxBuilder<'a, K> {
+	pub fee_rate: Satoshi,
+	pub sender: &'a K,
+	pub utxos: &'a [Utxo],
+	pub outputs: usize,
+}
+impl<K> ConsolidationTxBuilder<'_, K>
+where
+	K: KeySpendSigner,
+{
+	const LOCK_TIME: LockTime = LockTime::ZERO;
+	const SIGHASH_TYPE: TapSighashType = TapSighashType::Default;
+	const VERSION: Version = Version::TWO;
+
+	/// Build the signed transaction, with fewer outputs if the UTXOs can't fill them all above
+	/// the dust limit.
+	pub async fn build(self) -> Result<Transaction> {
+		let Self { fee_rate, sender, utxos, outputs } = self;
+		let output =
+			TxOut { script_pubkey: sender.script_public_key().clone(), value: Amount::ZERO };
+		let dust = output.script_pubkey.minimal_non_dust().to_sat();
+		let amount = utxos.iter().map(|u| u.value).sum::<Satoshi>();
+		let mut outputs = outputs.max(1);
+		let fee = loop {
+			let v_size = weight::vsize(
+				utxos.len(),
+				iter::repeat_n(&output, outputs),
+				&Spend::Key(Self::SIGHASH_TYPE),
+			);
+			let required = v_size * fee_rate + dust * outputs as Satoshi;
+
+			if amount >= required {
+				break v_size * fee_rate;
+			}
+			if outputs == 1 {
+				Err(ChainError::InsufficientFunds {
+					required: required as _,
+					available: amount as _,
+				})?;
+			}
+
+			outputs -= 1;
+		};
+		let value = (amount - fee) / outputs as Satoshi;
+
+		tracing::info!("consolidating {} UTXOs into {outputs}, fee: {fee}", utxos.len());
+
+		let input = utxos
+			.iter()
+			.map(|u| TxIn {
+				previous_output: u.outpoint,
+				sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+				..Default::default()
+			})
+			.collect();
+		let prevouts = utxos
+			.iter()
+			.map(|u| TxOut { value: Amount::from_sat(u.value), ..output.clone() })
+			.collect();
+		// The first output takes the remainder of the division.
+		let output = (0..outputs)
+			.map(|i| TxOut {
+				value: Amount::from_sat(
+					value + if i == 0 { (amount - fee) % outputs as Satoshi } else { 0 },
+				),
+				..output.clone()
+			})
+			.collect();
+		let tx = Transaction { version: Self::VERSION, lock_time: Self::LOCK_TIME, input, output };
+
+		policy::check(&weight::dummy_signed(&tx, &Spend::Key(Self::SIGHASH_TYPE)), fee)?;
+
+		let tx = payout::sign_key_spends(sender, tx, prevouts, Self::SIGHASH_TYPE).await?;
+
+		tracing::debug!("consolidation: {tx:?}");
+
+		Ok(tx)
+	}
+}
+#[test]
+fn consolidation_should_work() {
+	// self
+	use super::fixture::*;
+
+	let rt = tokio::runtime::Runtime::new().unwrap();
+	let vault = vault();
+	let utxos = (1..=10).map(|i| utxo(i, 1_000)).collect::<Vec<_>>();
+	let build = |fee_rate, outputs| {
+		rt.block_on(
+			ConsolidationTxBuilder { fee_rate, sender: &vault, utxos: &utxos, outputs }.build(),
+		)
+	};
+	let tx = build(1, 3).unwrap();
+	let fee = 10_000 - tx.output.iter().map(|o| o.value.to_sat()).sum::<Satoshi>();
+
+	assert_eq!(tx.input.len(), 10);
+	assert_eq!(tx.output.len(), 3);
+	assert!(tx.output.iter().all(|o| o.script_pubkey == vault.script_public_key));
+	// The estimation is exact for the signed transaction.
+	assert_eq!(fee, tx.vsize() as Satoshi);
+
+	// The outputs would be dust.
+	let tx = build(15, 3).unwrap();
+
+	assert_eq!(tx.output.len(), 1);
+	assert!(tx.output[0].value.to_sat() >= 330);
+	assert!(matches!(build(20, 3), Err(Error::Chain(ChainError::InsufficientFunds { .. }))));
+}
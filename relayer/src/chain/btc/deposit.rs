@@ -0,0 +1,244 @@
+//! Per-user deposit addresses, derived from the vault's extended public key.
+//!
+//! Every address is bound to an [`XTarget`](crate::x::XTarget) once issued, so that the deposits
+//! are credited without an `OP_RETURN` output. The addresses are BIP-86 key path only taproot
+//! addresses of the receive chain `xpub/0/index`, whose funds are swept into the vault with
+//! [`SweepTxBuilder`].
+
+// crates.io
+use bitcoin::{
+	absolute::LockTime,
+	bip32::{ChildNumber, KeySource, Xpub},
+	key::XOnlyPublicKey,
+	psbt::Psbt,
+	transaction::Version,
+	Address, Amount, Network, NetworkKind, TapSighashType, Transaction, TxIn, TxOut,
+};
+// self
+use super::{policy, types::*, weight, KeySpendSigner, SECP256K1};
+use crate::prelude::*;
+
+/// Keys of the deposit addresses.
+#[derive(Clone, Debug)]
+pub struct DepositKeys {
+	pub xpub: Xpub,
+	/// BIP-32 origin of the `xpub`, which hardware signers require to recognize their keys.
+	pub key_origin: Option<KeySource>,
+	pub network: Network,
+}
+impl DepositKeys {
+	pub fn new(xpub: Xpub, key_origin: Option<KeySource>, network: Network) -> Result<Self> {
+		if xpub.network != NetworkKind::from(network) {
+			Err(ChainError::InvalidDepositKey { reason: "xpub of another network" })?;
+		}
+		if let Some((fingerprint, path)) = &key_origin {
+			if path.len() != xpub.depth as usize
+				|| path.as_ref().last().is_some_and(|c| *c != xpub.child_number)
+				|| (xpub.depth == 1 && *fingerprint != xpub.parent_fingerprint)
+			{
+				Err(ChainError::InvalidDepositKey { reason: "key origin mismatches the xpub" })?;
+			}
+		}
+
+		Ok(Self { xpub, key_origin, network })
+	}
+
+	pub fn public_key(&self, index: Index) -> Result<XOnlyPublicKey> {
+		Ok(self
+			.xpub
+			.derive_pub(&SECP256K1, &Self::path(index)?)
+			.map_err(BitcoinError::Bip32)?
+			.to_x_only_pub())
+	}
+
+	pub fn address(&self, index: Index) -> Result<Address> {
+		Ok(Address::p2tr(&SECP256K1, self.public_key(index)?, None, self.network))
+	}
+
+	/// BIP-32 origin of the key at `index`, if the origin of the `xpub` is known.
+	pub fn key_origin(&self, index: Index) -> Result<Option<KeySource>> {
+		let path = Self::path(index)?;
+
+		Ok(self.key_origin.as_ref().map(|(f, p)| (*f, p.extend(path))))
+	}
+
+	fn path(index: Index) -> Result<[ChildNumber; 2]> {
+		Ok([
+			ChildNumber::from_normal_idx(0).map_err(BitcoinError::Bip32)?,
+			ChildNumber::from_normal_idx(index).map_err(BitcoinError::Bip32)?,
+		])
+	}
+}
+
+/// Sweep of the deposit addresses into the vault, signed offline by the holder of the `xpriv`.
+#[derive(Debug)]
+pub struct SweepTxBuilder<'a, K> {
+	pub fee_rate: Satoshi,
+	pub keys: &'a DepositKeys,
+	/// UTXOs of the deposit addresses, by their index.
+	pub deposits: &'a [(Index, Vec<Utxo>)],
+	pub vault: &'a K,
+}
+impl<K> SweepTxBuilder<'_, K>
+where
+	K: KeySpendSigner,
+{
+	const LOCK_TIME: LockTime = LockTime::ZERO;
+	const SIGHASH_TYPE: TapSighashType = TapSighashType::Default;
+	const VERSION: Version = Version::TWO;
+
+	/// Build the unsigned PSBT paying everything but the fee to the vault.
+	pub fn build_psbt(self) -> Result<Psbt> {
+		let Self { fee_rate, keys, deposits, vault } = self;
+		let mut input = Vec::new();
+		let mut prevouts = Vec::new();
+
+		for (index, utxos) in deposits {
+			let script_pubkey = keys.address(*index)?.script_pubkey();
+
+			for u in utxos {
+				input.push(TxIn { previous_output: u.outpoint, ..Default::default() });
+				prevouts.push((
+					*index,
+					TxOut {
+						script_pubkey: script_pubkey.clone(),
+						value: Amount::from_sat(u.value),
+					},
+				));
+			}
+		}
+
+		let mut unsigned_tx = Transaction {
+			version: Self::VERSION,
+			lock_time: Self::LOCK_TIME,
+			input,
+			output: vec![TxOut {
+				script_pubkey: vault.script_public_key().clone(),
+				value: Amount::ZERO,
+			}],
+		};
+		let spend = weight::Spend::Key(Self::SIGHASH_TYPE);
+		let fee = weight::signed_vsize(&unsigned_tx, &spend) * fee_rate;
+		let available = prevouts.iter().map(|(_, p)| p.value.to_sat()).sum::<Satoshi>();
+
+		unsigned_tx.output[0].value = Amount::from_sat(
+			available.checked_sub(fee).filter(|v| *v > 0).ok_or(ChainError::InsufficientFunds {
+				required: fee as u128 + 1,
+				available: available as _,
+			})?,
+		);
+
+		tracing::info!("sweeping {} UTXOs, fee: {fee}", unsigned_tx.input.len());
+
+		policy::check(&weight::dummy_signed(&unsigned_tx, &spend), fee)?;
+
+		let mut psbt = Psbt::from_unsigned_tx(unsigned_tx).map_err(BitcoinError::Psbt)?;
+
+		for (input, (index, prevout)) in psbt.inputs.iter_mut().zip(prevouts) {
+			let internal_key = keys.public_key(index)?;
+
+			input.witness_utxo = Some(prevout);
+			input.sighash_type = Some(Self::SIGHASH_TYPE.into());
+			input.tap_internal_key = Some(internal_key);
+
+			if let Some(origin) = keys.key_origin(index)? {
+				input.tap_key_origins.insert(internal_key, (Vec::new(), origin));
+			}
+		}
+
+		Ok(psbt)
+	}
+}
+#[test]
+fn deposit_keys_should_work() {
+	// std
+	use std::str::FromStr;
+	// crates.io
+	use bitcoin::bip32::{DerivationPath, Xpriv};
+
+	// Test vectors of BIP-86, with the seed of the "abandon ... about" mnemonic.
+	let seed = array_bytes::hex2bytes_unchecked(
+		"5eb00bbddcf069084889a8ab9155568165f5c453ccb85e70811aaed6f6da5fc1\
+		9a5ac40b389cd370d086206dec8aa6c43daea6690f20ad3d8d48b2d2ce9e38e4",
+	);
+	let master = Xpriv::new_master(Network::Bitcoin, &seed).unwrap();
+	let path = DerivationPath::from_str("m/86'/0'/0'").unwrap();
+	let xpub = Xpub::from_priv(&SECP256K1, &master.derive_priv(&SECP256K1, &path).unwrap());
+
+	assert_eq!(
+		xpub.to_string(),
+		"xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ"
+	);
+
+	let origin = (master.fingerprint(&SECP256K1), path);
+	let keys = DepositKeys::new(xpub, Some(origin.clone()), Network::Bitcoin).unwrap();
+
+	assert_eq!(
+		keys.public_key(0).unwrap().to_string(),
+		"cc8a4bc64d897bddc5fbc2f670f7a8ba0b386779106cf1223c6fc5d7cd6fc115"
+	);
+	assert_eq!(
+		keys.address(0).unwrap().to_string(),
+		"bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"
+	);
+	assert_eq!(
+		keys.address(1).unwrap().to_string(),
+		"bc1p4qhjn9zdvkux4e44uhx8tc55attvtyu358kutcqkudyccelu0was9fqzwh"
+	);
+	assert_eq!(
+		keys.key_origin(1).unwrap().unwrap().1,
+		DerivationPath::from_str("m/86'/0'/0'/0/1").unwrap()
+	);
+	assert!(DepositKeys::new(xpub, None, Network::Testnet).is_err());
+	assert!(DepositKeys::new(
+		xpub,
+		Some((origin.0, DerivationPath::from_str("m/86'/0'/1'").unwrap())),
+		Network::Bitcoin
+	)
+	.is_err());
+}
+#[test]
+fn sweep_should_work() {
+	// std
+	use std::str::FromStr;
+	// crates.io
+	use bitcoin::bip32::{DerivationPath, Xpriv};
+	// self
+	use super::fixture::utxo;
+
+	let master = Xpriv::new_master(Network::Testnet, &[1; 32]).unwrap();
+	let path = DerivationPath::from_str("m/86'/1'/0'").unwrap();
+	let xpub = Xpub::from_priv(&SECP256K1, &master.derive_priv(&SECP256K1, &path).unwrap());
+	let keys =
+		DepositKeys::new(xpub, Some((master.fingerprint(&SECP256K1), path)), Network::Testnet)
+			.unwrap();
+	let vault = super::TaprootKey::new(
+		super::signer::WatchOnly(keys.public_key(100).unwrap()),
+		None,
+		Network::Testnet,
+	);
+	let deposits = [(0, vec![utxo(1, 10_000), utxo(2, 20_000)]), (3, vec![utxo(3, 30_000)])];
+	let sweep = |fee_rate| {
+		SweepTxBuilder { fee_rate, keys: &keys, deposits: &deposits, vault: &vault }.build_psbt()
+	};
+	let exported = sweep(10).unwrap();
+	let fee = exported.fee().unwrap().to_sat();
+
+	assert_eq!(exported.unsigned_tx.input.len(), 3);
+	assert_eq!(exported.unsigned_tx.output.len(), 1);
+	assert_eq!(exported.unsigned_tx.output[0].script_pubkey, vault.script_public_key);
+	assert_eq!(exported.unsigned_tx.output[0].value.to_sat(), 60_000 - fee);
+	assert_eq!(
+		exported.inputs[2].witness_utxo.as_ref().unwrap().script_pubkey,
+		keys.address(3).unwrap().script_pubkey()
+	);
+
+	let mut signed = exported.clone();
+
+	signed.sign(&master, &SECP256K1).unwrap();
+
+	let tx = super::psbt::finalize(&exported, signed).unwrap();
+
+	assert!(tx.input.iter().all(|i| i.witness.len() == 1));
+	assert!(matches!(sweep(1_000), Err(Error::Chain(ChainError::InsufficientFunds { .. }))));
+}
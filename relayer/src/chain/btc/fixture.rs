@@ -0,0 +1,28 @@
+//! Vault, UTXOs and recipient shared by the tests building the vault transactions.
+
+// crates.io
+use bitcoin::{hashes::Hash, key::Keypair, Network, OutPoint, Txid};
+// self
+use super::{types::*, TaprootKey, SECP256K1};
+
+/// Testnet taproot address of an unrelated key.
+pub const RECIPIENT: &str = "tb1pedlrf67ss52md29qqkzr2avma6ghyrt4jx9ecp9457qsl75x247shsh6th";
+
+/// Key of the test vault.
+pub fn keypair() -> Keypair {
+	Keypair::from_seckey_slice(&SECP256K1, &[1; 32]).unwrap()
+}
+
+/// Testnet vault of [`keypair`], without a recovery script path.
+pub fn vault() -> TaprootKey {
+	TaprootKey::from_untweaked_keypair(keypair(), None, Network::Testnet)
+}
+
+/// First output of the `i`-th funding transaction.
+pub fn utxo(i: u8, value: Satoshi) -> Utxo {
+	Utxo {
+		outpoint: OutPoint { txid: Txid::from_byte_array([i; 32]), vout: 0 },
+		value,
+		confirmed: true,
+	}
+}
@@ -0,0 +1,966 @@
+//! FROST threshold signing of the vault's taproot key spends.
+//!
+//! The vault key is split among `n` relayers with a `t`-of-`n` Shamir sharing. Any `t` of them
+//! produce a BIP-340 signature for the tweaked output key in two rounds:
+//!
+//! 1. Each signer commits to a pair of nonces per message.
+//! 2. Given all the commitments, each signer returns its signature shares, which are verified and
+//!    aggregated by the coordinator.
+//!
+//! Nonces only live in memory and are consumed by the second round whatever its result. Signers are
+//! given the spend along with the signing package, and only sign the sighashes they computed from
+//! it once it passes their policy.
+
+pub mod transport;
+pub use transport::*;
+
+// std
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	fmt,
+	fs::{self, OpenOptions},
+	io::Write,
+	iter,
+	os::unix::fs::OpenOptionsExt,
+	path::Path,
+	sync::Mutex,
+};
+// crates.io
+use bitcoin::{
+	hashes::{sha256, Hash, HashEngine},
+	key::{TweakedPublicKey, XOnlyPublicKey},
+	secp256k1::{rand, schnorr, Message},
+	Address, Network, ScriptBuf, TapNodeHash, TapTweakHash,
+};
+use k256::{
+	elliptic_curve::{
+		ops::Reduce, point::AffineCoordinates, sec1::ToEncodedPoint, Field, PrimeField,
+	},
+	AffinePoint, FieldBytes, ProjectivePoint, Scalar, U256,
+};
+use serde::{Deserialize, Serialize};
+// self
+use super::{
+	signer::{Policy, SignRequest, Signer, SpendPath},
+	KeySpendSigner, SECP256K1,
+};
+use crate::prelude::*;
+
+/// Index of a signer, starting from 1.
+pub type SignerIndex = u16;
+
+// Hiding and binding nonces of a message.
+type Nonces = (Scalar, Scalar);
+
+/// Public data of the group.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PublicKeyPackage {
+	pub threshold: u16,
+	// Untweaked group key, which is the taproot internal key.
+	pub group_key: AffinePoint,
+	// Public key of each signer's share, to verify the signature shares.
+	pub verifying_shares: BTreeMap<SignerIndex, AffinePoint>,
+}
+impl PublicKeyPackage {
+	fn from_commitments(
+		commitments: &[AffinePoint],
+		signers: impl Iterator<Item = SignerIndex>,
+	) -> Self {
+		Self {
+			threshold: commitments.len() as _,
+			group_key: commitments[0],
+			verifying_shares: signers.map(|i| (i, evaluate_commitments(commitments, i))).collect(),
+		}
+	}
+
+	pub fn internal_key(&self) -> Result<XOnlyPublicKey> {
+		x_only(&self.group_key)
+	}
+
+	fn output_key(&self, merkle_root: Option<TapNodeHash>) -> Result<OutputKey> {
+		let p = ProjectivePoint::from(self.group_key);
+		let p_negated = bool::from(self.group_key.y_is_odd());
+		let p = if p_negated { -p } else { p };
+		let tweak = Option::<Scalar>::from(Scalar::from_repr(FieldBytes::from(
+			TapTweakHash::from_key_and_tweak(self.internal_key()?, merkle_root).to_byte_array(),
+		)))
+		.ok_or(ChainError::Frost { reason: "invalid tweak" })?;
+		let q = (p + ProjectivePoint::GENERATOR * tweak).to_affine();
+		let q_negated = bool::from(q.y_is_odd());
+
+		Ok(OutputKey {
+			key: x_only(&q)?,
+			tweak,
+			q_sign: sign(q_negated),
+			secret_sign: sign(p_negated ^ q_negated),
+		})
+	}
+}
+
+// The tweaked taproot output key, and how the shares map to its even-y secret key:
+// `secret_sign * x + q_sign * tweak`.
+struct OutputKey {
+	key: XOnlyPublicKey,
+	tweak: Scalar,
+	q_sign: Scalar,
+	secret_sign: Scalar,
+}
+
+/// Secret share of a signer.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct KeyShare {
+	pub index: SignerIndex,
+	secret: Scalar,
+	pub public: PublicKeyPackage,
+}
+impl KeyShare {
+	pub fn load(path: &Path) -> Result<Self> {
+		let s = serde_json::from_str::<Self>(&fs::read_to_string(path)?)?;
+
+		if s.public.verifying_shares.get(&s.index)
+			!= Some(&(ProjectivePoint::GENERATOR * s.secret).to_affine())
+		{
+			Err(ChainError::Frost { reason: "key share mismatches its verifying share" })?;
+		}
+
+		Ok(s)
+	}
+
+	/// Save the share readable by the owner only, an existing file is never overwritten.
+	pub fn save(&self, path: &Path) -> Result<()> {
+		save_private(path, self)
+	}
+
+	/// Deal this share to a new group.
+	///
+	/// Each dealer deals on its own host, and only delivers to each new signer its share of the
+	/// dealing, split out with [`Dealing::to`]. The dealings of at least `threshold` current
+	/// signers, all dealing with the same `dealers`, are combined by each new signer with
+	/// [`KeyShare::from_dealings`].
+	pub fn reshare(
+		&self,
+		dealers: &[SignerIndex],
+		threshold: u16,
+		signers: u16,
+	) -> Result<Dealing> {
+		if dealers.len() < self.public.threshold as usize || !dealers.contains(&self.index) {
+			Err(ChainError::Frost { reason: "insufficient dealers" })?;
+		}
+
+		Dealing::new(
+			lagrange(self.index, dealers.iter().copied())? * self.secret,
+			threshold,
+			signers,
+		)
+	}
+
+	/// Combine the dealings of the current signers into the share of `index` in the new group,
+	/// which keeps the vault's `internal_key`.
+	pub fn from_dealings(
+		index: SignerIndex,
+		internal_key: XOnlyPublicKey,
+		dealings: &[Dealing],
+	) -> Result<Self> {
+		let first = dealings.first().ok_or(ChainError::Frost { reason: "no dealing" })?;
+		let mut commitments = vec![ProjectivePoint::IDENTITY; first.commitments.len()];
+		let mut secret = Scalar::ZERO;
+
+		for d in dealings {
+			if d.commitments.len() != commitments.len() || d.signers != first.signers {
+				Err(ChainError::Frost { reason: "inconsistent dealings" })?;
+			}
+
+			d.commitments.iter().zip(&mut commitments).for_each(|(c, s)| *s += c);
+			secret += d.verify(index)?;
+		}
+
+		let commitments = commitments.iter().map(|c| c.to_affine()).collect::<Vec<_>>();
+
+		// The secret is preserved only if the dealers' shares interpolate to it.
+		if x_only(&commitments[0])? != internal_key {
+			Err(ChainError::Frost { reason: "group key mismatch" })?;
+		}
+
+		Ok(Self {
+			index,
+			secret,
+			public: PublicKeyPackage::from_commitments(&commitments, 1..=first.signers),
+		})
+	}
+
+	fn sign(&self, package: &SigningPackage, nonces: &[Nonces]) -> Result<Vec<Scalar>> {
+		let output_key = self.public.output_key(package.merkle_root)?;
+		let lambda = lagrange(self.index, package.commitments.keys().copied())?;
+
+		nonces
+			.iter()
+			.enumerate()
+			.map(|(i, (hiding, binding))| {
+				let round = Round::new(&self.public, &output_key, package, i)?;
+				let rho = round.binding_factors[&self.index];
+
+				Ok(round.nonce_sign * (*hiding + rho * binding)
+					+ round.challenge * lambda * output_key.secret_sign * self.secret)
+			})
+			.collect()
+	}
+}
+impl fmt::Debug for KeyShare {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("KeyShare")
+			.field("index", &self.index)
+			.field("public", &self.public)
+			.finish_non_exhaustive()
+	}
+}
+
+/// Split a freshly generated key among `signers` with a trusted dealer.
+///
+/// Returns the shares of the signers `1..=signers`.
+pub fn keygen(threshold: u16, signers: u16) -> Result<Vec<KeyShare>> {
+	let dealing = Dealing::new(random_scalar(), threshold, signers)?;
+	let public = PublicKeyPackage::from_commitments(&dealing.commitments, 1..=signers);
+
+	Ok(dealing
+		.shares
+		.into_iter()
+		.map(|(index, secret)| KeyShare { index, secret, public: public.clone() })
+		.collect())
+}
+
+/// Shares of a secret dealt to the signers, with the commitments to the sharing polynomial.
+///
+/// The shares must be delivered to their signers privately.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Dealing {
+	pub commitments: Vec<AffinePoint>,
+	// Number of signers dealt to, the shares of the others might be split out.
+	signers: u16,
+	shares: BTreeMap<SignerIndex, Scalar>,
+}
+impl Dealing {
+	pub fn load(path: &Path) -> Result<Self> {
+		Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+	}
+
+	/// Save the dealing readable by the owner only, an existing file is never overwritten.
+	pub fn save(&self, path: &Path) -> Result<()> {
+		save_private(path, self)
+	}
+
+	/// Split out the dealing of the signer `index`, without the shares of the others.
+	pub fn to(&self, index: SignerIndex) -> Result<Self> {
+		let share =
+			*self.shares.get(&index).ok_or(ChainError::Frost { reason: "signer not dealt" })?;
+
+		Ok(Self {
+			commitments: self.commitments.clone(),
+			signers: self.signers,
+			shares: BTreeMap::from([(index, share)]),
+		})
+	}
+
+	fn new(secret: Scalar, threshold: u16, signers: u16) -> Result<Self> {
+		if threshold == 0 || threshold > signers {
+			Err(ChainError::Frost { reason: "invalid threshold" })?;
+		}
+
+		let coefficients =
+			iter::once(secret).chain((1..threshold).map(|_| random_scalar())).collect::<Vec<_>>();
+		let commitments =
+			coefficients.iter().map(|c| (ProjectivePoint::GENERATOR * c).to_affine()).collect();
+		let shares = (1..=signers)
+			.map(|i| {
+				let x = Scalar::from(i as u64);
+
+				(i, coefficients.iter().rev().fold(Scalar::ZERO, |acc, c| acc * x + c))
+			})
+			.collect();
+
+		Ok(Self { commitments, signers, shares })
+	}
+
+	// Verify the share of `index` against the commitments.
+	fn verify(&self, index: SignerIndex) -> Result<Scalar> {
+		let share =
+			*self.shares.get(&index).ok_or(ChainError::Frost { reason: "signer not dealt" })?;
+
+		if (ProjectivePoint::GENERATOR * share).to_affine()
+			!= evaluate_commitments(&self.commitments, index)
+		{
+			Err(ChainError::Frost { reason: "share mismatches the commitments" })?;
+		}
+
+		Ok(share)
+	}
+}
+
+/// Commitments to a signer's nonces.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Commitment {
+	pub hiding: AffinePoint,
+	pub binding: AffinePoint,
+}
+
+/// Messages to sign and the commitments of the participating signers, one per message.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SigningPackage {
+	pub messages: Vec<[u8; 32]>,
+	// Root of the vault's tap tree, which the output key commits to.
+	pub merkle_root: Option<TapNodeHash>,
+	pub commitments: BTreeMap<SignerIndex, Vec<Commitment>>,
+}
+
+// Values of a message shared by the signers and the coordinator.
+struct Round {
+	binding_factors: BTreeMap<SignerIndex, Scalar>,
+	// Even-y group commitment.
+	r: AffinePoint,
+	// Negates the nonces if the group commitment has an odd y.
+	nonce_sign: Scalar,
+	challenge: Scalar,
+}
+impl Round {
+	fn new(
+		public: &PublicKeyPackage,
+		output_key: &OutputKey,
+		package: &SigningPackage,
+		i: usize,
+	) -> Result<Self> {
+		let message = package.messages.get(i).ok_or(ChainError::Frost { reason: "no message" })?;
+		let mut commitments = Vec::new();
+
+		if package.commitments.len() < public.threshold as usize {
+			Err(ChainError::Frost { reason: "insufficient signers" })?;
+		}
+
+		for (j, c) in &package.commitments {
+			let c = c.get(i).ok_or(ChainError::Frost { reason: "missing commitment" })?;
+
+			commitments.extend_from_slice(&j.to_be_bytes());
+			commitments.extend_from_slice(c.hiding.to_encoded_point(true).as_bytes());
+			commitments.extend_from_slice(c.binding.to_encoded_point(true).as_bytes());
+		}
+
+		let binding_factors = package
+			.commitments
+			.keys()
+			.map(|j| {
+				let h = tagged_hash(
+					"FROST/rho",
+					&[
+						&j.to_be_bytes(),
+						output_key.key.serialize().as_slice(),
+						message,
+						&commitments,
+					],
+				);
+
+				(*j, <Scalar as Reduce<U256>>::reduce_bytes(&FieldBytes::from(h)))
+			})
+			.collect::<BTreeMap<_, _>>();
+		let r = package
+			.commitments
+			.iter()
+			.fold(ProjectivePoint::IDENTITY, |r, (j, c)| {
+				r + c[i].hiding + ProjectivePoint::from(c[i].binding) * binding_factors[j]
+			})
+			.to_affine();
+
+		if r == AffinePoint::IDENTITY {
+			Err(ChainError::Frost { reason: "identity group commitment" })?;
+		}
+
+		let r_negated = bool::from(r.y_is_odd());
+		let r = if r_negated { (-ProjectivePoint::from(r)).to_affine() } else { r };
+		let challenge = <Scalar as Reduce<U256>>::reduce_bytes(&FieldBytes::from(tagged_hash(
+			"BIP0340/challenge",
+			&[&r.x(), output_key.key.serialize().as_slice(), message],
+		)));
+
+		Ok(Self { binding_factors, r, nonce_sign: sign(r_negated), challenge })
+	}
+}
+
+/// A signer holding a key share, answering the coordinator's requests.
+#[derive(Debug)]
+pub struct Participant {
+	share: KeyShare,
+	policy: Policy,
+	// Commitments and nonces of the pending round.
+	pending: Mutex<Option<(Vec<Commitment>, Vec<Nonces>)>>,
+}
+impl Participant {
+	pub fn new(share: KeyShare, policy: Policy) -> Self {
+		Self { share, policy, pending: Mutex::new(None) }
+	}
+
+	/// Start a round of `count` messages, a pending round is abandoned.
+	pub fn commit(&self, count: usize) -> Vec<Commitment> {
+		let nonces = (0..count).map(|_| (self.nonce(), self.nonce())).collect::<Vec<_>>();
+		let commitments = nonces
+			.iter()
+			.map(|(h, b)| Commitment {
+				hiding: (ProjectivePoint::GENERATOR * h).to_affine(),
+				binding: (ProjectivePoint::GENERATOR * b).to_affine(),
+			})
+			.collect::<Vec<_>>();
+
+		*self.pending.lock().unwrap() = Some((commitments.clone(), nonces));
+
+		commitments
+	}
+
+	/// Sign the spend in the pending round, the nonces are consumed whatever the result.
+	///
+	/// The messages of the package must be the sighashes of the spend, which must pass the policy.
+	pub fn sign(&self, request: &SignRequest, package: &SigningPackage) -> Result<Vec<Scalar>> {
+		let (commitments, nonces) = self
+			.pending
+			.lock()
+			.unwrap()
+			.take()
+			.ok_or(ChainError::Frost { reason: "no pending round" })?;
+
+		if package.commitments.get(&self.share.index) != Some(&commitments)
+			|| package.messages.len() != nonces.len()
+		{
+			Err(ChainError::Frost { reason: "signing package mismatches the round" })?;
+		}
+		if request.path != (SpendPath::Key { merkle_root: package.merkle_root })
+			|| request
+				.sighashes()?
+				.iter()
+				.map(|h| h.to_byte_array())
+				.ne(package.messages.iter().copied())
+		{
+			Err(ChainError::Frost { reason: "signing package mismatches the spend" })?;
+		}
+
+		self.policy.check(self.share.public.internal_key()?, request)?;
+		self.share.sign(package, &nonces)
+	}
+
+	// Hedged against a weak RNG by the secret share.
+	fn nonce(&self) -> Scalar {
+		let mut random = [0; 32];
+
+		rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut random);
+
+		<Scalar as Reduce<U256>>::reduce_bytes(&FieldBytes::from(tagged_hash(
+			"FROST/nonce",
+			&[&random, &self.share.secret.to_bytes()],
+		)))
+	}
+}
+
+/// Coordinator of the signing rounds, the vault's key spend signer.
+#[derive(Debug)]
+pub struct Coordinator {
+	public: PublicKeyPackage,
+	internal_key: XOnlyPublicKey,
+	merkle_root: Option<TapNodeHash>,
+	signers: Vec<Transport>,
+	pub script_public_key: ScriptBuf,
+	pub address: String,
+}
+impl Coordinator {
+	pub fn new(
+		public: PublicKeyPackage,
+		merkle_root: Option<TapNodeHash>,
+		signers: Vec<Transport>,
+		network: Network,
+	) -> Result<Self> {
+		let key = TweakedPublicKey::dangerous_assume_tweaked(public.output_key(merkle_root)?.key);
+		let address = Address::p2tr_tweaked(key, network);
+
+		Ok(Self {
+			internal_key: public.internal_key()?,
+			public,
+			merkle_root,
+			signers,
+			script_public_key: address.script_pubkey(),
+			address: address.to_string(),
+		})
+	}
+
+	fn aggregate(
+		&self,
+		package: &SigningPackage,
+		shares: &BTreeMap<SignerIndex, Vec<Scalar>>,
+	) -> Result<Vec<schnorr::Signature>> {
+		let output_key = self.public.output_key(package.merkle_root)?;
+		let lambdas = package
+			.commitments
+			.keys()
+			.map(|&j| Ok((j, lagrange(j, package.commitments.keys().copied())?)))
+			.collect::<Result<BTreeMap<_, _>>>()?;
+
+		(0..package.messages.len())
+			.map(|i| {
+				let round = Round::new(&self.public, &output_key, package, i)?;
+				let mut z = round.challenge * output_key.q_sign * output_key.tweak;
+
+				for (j, c) in &package.commitments {
+					let z_j = *shares
+						.get(j)
+						.and_then(|s| s.get(i))
+						.ok_or(ChainError::InvalidSignatureShare { index: *j })?;
+					let y_j = self
+						.public
+						.verifying_shares
+						.get(j)
+						.ok_or(ChainError::InvalidSignatureShare { index: *j })?;
+					let expected = (ProjectivePoint::from(c[i].hiding)
+						+ ProjectivePoint::from(c[i].binding) * round.binding_factors[j])
+						* round.nonce_sign
+						+ ProjectivePoint::from(*y_j)
+							* (round.challenge * lambdas[j] * output_key.secret_sign);
+
+					if ProjectivePoint::GENERATOR * z_j != expected {
+						Err(ChainError::InvalidSignatureShare { index: *j })?;
+					}
+
+					z += z_j;
+				}
+
+				let mut sig = [0; 64];
+
+				sig[..32].copy_from_slice(&round.r.x());
+				sig[32..].copy_from_slice(&z.to_bytes());
+
+				let sig = schnorr::Signature::from_slice(&sig)?;
+
+				SECP256K1.verify_schnorr(
+					&sig,
+					&Message::from_digest(package.messages[i]),
+					&output_key.key,
+				)?;
+
+				Ok(sig)
+			})
+			.collect()
+	}
+}
+impl KeySpendSigner for Coordinator {
+	fn script_public_key(&self) -> &ScriptBuf {
+		&self.script_public_key
+	}
+
+	fn merkle_root(&self) -> Option<TapNodeHash> {
+		self.merkle_root
+	}
+}
+impl Signer for Coordinator {
+	fn public_key(&self) -> XOnlyPublicKey {
+		self.internal_key
+	}
+
+	async fn sign(&self, request: &SignRequest) -> Result<Vec<schnorr::Signature>> {
+		let SpendPath::Key { merkle_root } = request.path else {
+			Err(ChainError::Frost { reason: "script path unsupported" })?
+		};
+		let sighashes = request.sighashes()?;
+		let threshold = self.public.threshold as usize;
+		// Signers which failed a round, left out of the next ones.
+		let mut excluded = BTreeSet::new();
+
+		'round: loop {
+			let mut participants = BTreeMap::new();
+			let mut commitments = BTreeMap::new();
+
+			// Take the first `threshold` available signers.
+			for (k, s) in self.signers.iter().enumerate().filter(|(k, _)| !excluded.contains(k)) {
+				if participants.len() == threshold {
+					break;
+				}
+
+				match s.request(&Request::Commit { count: sighashes.len() }).await {
+					Ok(Response::Commit { index, commitments: c })
+						if !commitments.contains_key(&index) && c.len() == sighashes.len() =>
+					{
+						commitments.insert(index, c);
+						participants.insert(index, k);
+					},
+					Ok(r) => tracing::warn!("unexpected response from signer {s:?}: {r:?}"),
+					Err(e) => tracing::warn!("signer {s:?} is unavailable: {e}"),
+				}
+			}
+
+			if participants.len() < threshold {
+				Err(ChainError::Frost { reason: "insufficient signers" })?;
+			}
+
+			let package = SigningPackage {
+				messages: sighashes.iter().map(|h| h.to_byte_array()).collect(),
+				merkle_root,
+				commitments,
+			};
+			let sign =
+				Request::Sign { request: Box::new(request.to_owned()), package: package.clone() };
+			let mut shares = BTreeMap::new();
+
+			for (&index, &k) in &participants {
+				let s = &self.signers[k];
+
+				match s.request(&sign).await {
+					Ok(Response::Sign { index: i, shares: z }) if i == index => {
+						shares.insert(index, z);
+					},
+					r => {
+						tracing::warn!("signer {s:?} fails to sign, retrying without it: {r:?}");
+
+						excluded.insert(k);
+
+						continue 'round;
+					},
+				}
+			}
+
+			match self.aggregate(&package, &shares) {
+				Err(Error::Chain(ChainError::InvalidSignatureShare { index })) => {
+					tracing::warn!("signer {index} returns an invalid share, retrying without it");
+
+					excluded.insert(participants[&index]);
+				},
+				r => return r,
+			}
+		}
+	}
+}
+
+// Save the secret readable by the owner only, an existing file is never overwritten.
+fn save_private<T>(path: &Path, secret: &T) -> Result<()>
+where
+	T: Serialize,
+{
+	OpenOptions::new()
+		.write(true)
+		.create_new(true)
+		.mode(0o600)
+		.open(path)?
+		.write_all(serde_json::to_string_pretty(secret)?.as_bytes())?;
+
+	Ok(())
+}
+
+// `λ_i` of the Lagrange interpolation at zero.
+fn lagrange(i: SignerIndex, signers: impl Iterator<Item = SignerIndex>) -> Result<Scalar> {
+	let x_i = Scalar::from(i as u64);
+	let (num, den) = signers.filter(|&j| j != i).fold((Scalar::ONE, Scalar::ONE), |(n, d), j| {
+		let x_j = Scalar::from(j as u64);
+
+		(n * x_j, d * (x_j - x_i))
+	});
+
+	Option::<Scalar>::from(den.invert())
+		.map(|d| num * d)
+		.ok_or(ChainError::Frost { reason: "invalid signer index" }.into())
+}
+
+fn evaluate_commitments(commitments: &[AffinePoint], i: SignerIndex) -> AffinePoint {
+	let x = Scalar::from(i as u64);
+
+	commitments.iter().rev().fold(ProjectivePoint::IDENTITY, |acc, c| acc * x + c).to_affine()
+}
+
+fn x_only(p: &AffinePoint) -> Result<XOnlyPublicKey> {
+	Ok(XOnlyPublicKey::from_slice(&p.x())?)
+}
+
+fn sign(negated: bool) -> Scalar {
+	if negated {
+		-Scalar::ONE
+	} else {
+		Scalar::ONE
+	}
+}
+
+fn random_scalar() -> Scalar {
+	Scalar::random(&mut rand::thread_rng())
+}
+
+fn tagged_hash(tag: &str, data: &[&[u8]]) -> [u8; 32] {
+	let tag = sha256::Hash::hash(tag.as_bytes());
+	let mut engine = sha256::Hash::engine();
+
+	engine.input(tag.as_ref());
+	engine.input(tag.as_ref());
+	data.iter().for_each(|d| engine.input(d));
+
+	sha256::Hash::from_engine(engine).to_byte_array()
+}
+#[cfg(test)]
+fn coordinator_for_test(shares: Vec<KeyShare>) -> Coordinator {
+	let public = shares[0].public.clone();
+	let signers = shares
+		.into_iter()
+		.map(|s| Transport::Local(std::sync::Arc::new(Participant::new(s, Policy::default()))));
+
+	Coordinator::new(public, None, signers.collect(), Network::Testnet).unwrap()
+}
+#[test]
+fn frost_should_work() {
+	let runtime = tokio::runtime::Runtime::new().unwrap();
+	let coordinator = coordinator_for_test(keygen(2, 3).unwrap());
+	let utxos = [1, 2, 3].map(|i| super::fixture::utxo(i, 10_000));
+	let tx = runtime
+		.block_on(
+			super::payout::PayoutTxBuilder {
+				network: Network::Testnet,
+				fee_rate: 1,
+				sender: &coordinator,
+				utxos: &utxos,
+				coin_selection: Default::default(),
+				payouts: &[super::payout::Payout {
+					recipient: super::fixture::RECIPIENT,
+					amount: 25_000,
+				}],
+			}
+			.build(),
+		)
+		.unwrap();
+	let prevouts = utxos.map(|u| bitcoin::TxOut {
+		script_pubkey: coordinator.script_public_key.clone(),
+		value: bitcoin::Amount::from_sat(u.value),
+	});
+	let output_key = coordinator.public.output_key(None).unwrap().key;
+	let mut hasher = bitcoin::sighash::SighashCache::new(&tx);
+
+	assert_eq!(tx.input.len(), 3);
+
+	// Every input is signed by the group for the vault's output key.
+	for (i, input) in tx.input.iter().enumerate() {
+		let sig = bitcoin::taproot::Signature::from_slice(&input.witness[0]).unwrap();
+		let sighash = hasher
+			.taproot_key_spend_signature_hash(
+				i,
+				&bitcoin::sighash::Prevouts::All(&prevouts),
+				sig.sighash_type,
+			)
+			.unwrap();
+
+		SECP256K1
+			.verify_schnorr(
+				&sig.signature,
+				&Message::from_digest(sighash.to_byte_array()),
+				&output_key,
+			)
+			.unwrap();
+	}
+
+	// Signers in any order and any subset of the threshold.
+	let shares = keygen(3, 5).unwrap();
+	let request = SignRequest::for_key_test(
+		shares[0].public.internal_key().unwrap(),
+		2,
+		SpendPath::Key { merkle_root: None },
+	);
+
+	for subset in [[4, 1, 3], [5, 4, 3]] {
+		let coordinator =
+			coordinator_for_test(subset.iter().map(|&i| shares[i - 1].clone()).collect());
+
+		assert_eq!(runtime.block_on(coordinator.sign(&request)).unwrap().len(), 2);
+	}
+
+	// Not enough signers.
+	assert!(matches!(
+		runtime.block_on(coordinator_for_test(shares[..2].to_vec()).sign(&request)),
+		Err(Error::Chain(ChainError::Frost { reason: "insufficient signers" }))
+	));
+
+	// A signer refusing the spend in the second round is replaced by another one.
+	let signers = shares[..4]
+		.iter()
+		.enumerate()
+		.map(|(k, s)| {
+			let policy = if k == 1 {
+				Policy { max_fee: Some(0), ..Default::default() }
+			} else {
+				Policy::default()
+			};
+
+			Transport::Local(std::sync::Arc::new(Participant::new(s.clone(), policy)))
+		})
+		.collect();
+	let coordinator =
+		Coordinator::new(shares[0].public.clone(), None, signers, Network::Testnet).unwrap();
+
+	assert_eq!(runtime.block_on(coordinator.sign(&request)).unwrap().len(), 2);
+}
+#[test]
+fn frost_should_reject_invalid_shares() {
+	let shares = keygen(2, 3).unwrap();
+	let coordinator =
+		Coordinator::new(shares[0].public.clone(), None, Vec::new(), Network::Testnet).unwrap();
+	let participants =
+		shares.into_iter().map(|s| Participant::new(s, Policy::default())).collect::<Vec<_>>();
+	let request = SignRequest::for_key_test(
+		coordinator.internal_key,
+		1,
+		SpendPath::Key { merkle_root: None },
+	);
+	let package = SigningPackage {
+		messages: request.sighashes().unwrap().iter().map(|h| h.to_byte_array()).collect(),
+		merkle_root: None,
+		commitments: participants[..2].iter().map(|p| (p.share.index, p.commit(1))).collect(),
+	};
+	let mut signature_shares = participants[..2]
+		.iter()
+		.map(|p| (p.share.index, p.sign(&request, &package).unwrap()))
+		.collect::<BTreeMap<_, _>>();
+
+	assert!(coordinator.aggregate(&package, &signature_shares).is_ok());
+
+	// The nonces are consumed.
+	assert!(participants[0].sign(&request, &package).is_err());
+
+	signature_shares.get_mut(&2).unwrap()[0] += Scalar::ONE;
+
+	assert!(matches!(
+		coordinator.aggregate(&package, &signature_shares),
+		Err(Error::Chain(ChainError::InvalidSignatureShare { index: 2 }))
+	));
+
+	// A package with other commitments than the pending round's.
+	let mut package = package;
+
+	participants[2].commit(1);
+	package.commitments.insert(3, participants[1].commit(1));
+
+	assert!(participants[2].sign(&request, &package).is_err());
+
+	// Messages other than the sighashes of the spend.
+	let mut package = SigningPackage {
+		messages: vec![[1; 32]],
+		merkle_root: None,
+		commitments: participants[..2].iter().map(|p| (p.share.index, p.commit(1))).collect(),
+	};
+
+	assert!(matches!(
+		participants[0].sign(&request, &package),
+		Err(Error::Chain(ChainError::Frost { reason: "signing package mismatches the spend" }))
+	));
+
+	// A spend violating the policy.
+	let participant = Participant::new(
+		participants[1].share.clone(),
+		Policy { max_fee: Some(0), ..Default::default() },
+	);
+
+	package.messages = request.sighashes().unwrap().iter().map(|h| h.to_byte_array()).collect();
+	package.commitments.insert(2, participant.commit(1));
+
+	assert!(matches!(
+		participant.sign(&request, &package),
+		Err(Error::Chain(ChainError::PolicyViolation { .. }))
+	));
+}
+#[test]
+fn frost_should_reject_foreign_inputs() {
+	// crates.io
+	use bitcoin::{Amount, OutPoint, TxIn, TxOut, Txid};
+
+	let runtime = tokio::runtime::Runtime::new().unwrap();
+	let shares = keygen(2, 3).unwrap();
+	let policy = || Policy {
+		max_amount: Some(1_000),
+		recipients: Some(vec![super::fixture::RECIPIENT.parse().unwrap()]),
+		..Default::default()
+	};
+	let signers = shares
+		.iter()
+		.map(|s| Transport::Local(std::sync::Arc::new(Participant::new(s.clone(), policy()))))
+		.collect();
+	let coordinator =
+		Coordinator::new(shares[0].public.clone(), None, signers, Network::Testnet).unwrap();
+	let mut request = SignRequest::for_key_test(
+		coordinator.internal_key,
+		1,
+		SpendPath::Key { merkle_root: None },
+	);
+	let attacker = ScriptBuf::new_p2wsh(&Hash::all_zeros());
+
+	// The whole vault UTXO paid to the script of an input of the coordinator's own.
+	request.tx.input.push(TxIn {
+		previous_output: OutPoint { txid: Txid::from_byte_array([9; 32]), vout: 0 },
+		..Default::default()
+	});
+	request
+		.prevouts
+		.push(TxOut { script_pubkey: attacker.clone(), value: Amount::from_sat(1_000) });
+	request.tx.output = vec![TxOut { script_pubkey: attacker, value: Amount::from_sat(10_500) }];
+
+	let participant = Participant::new(shares[0].clone(), policy());
+	let package = SigningPackage {
+		messages: request.sighashes().unwrap().iter().map(|h| h.to_byte_array()).collect(),
+		merkle_root: None,
+		commitments: [(participant.share.index, participant.commit(2))].into(),
+	};
+
+	assert!(matches!(
+		participant.sign(&request, &package),
+		Err(Error::Chain(ChainError::PolicyViolation { reason }))
+			if reason.ends_with("isn't the signer's")
+	));
+	// No signer agrees, so no signature can be aggregated.
+	assert!(runtime.block_on(coordinator.sign(&request)).is_err());
+}
+#[test]
+fn reshare_should_work() {
+	let runtime = tokio::runtime::Runtime::new().unwrap();
+	let shares = keygen(2, 3).unwrap();
+	let group_key = shares[0].public.group_key;
+	let internal_key = shares[0].public.internal_key().unwrap();
+	let address = coordinator_for_test(shares.clone()).address;
+	let dealers = [1, 3];
+	let dealings = [&shares[0], &shares[2]]
+		.iter()
+		.map(|s| s.reshare(&dealers, 3, 4).unwrap())
+		.collect::<Vec<_>>();
+	// Each new signer is only given its own shares.
+	let reshared = (1..=4)
+		.map(|i| {
+			let delivered = dealings.iter().map(|d| d.to(i).unwrap()).collect::<Vec<_>>();
+
+			KeyShare::from_dealings(i, internal_key, &delivered).unwrap()
+		})
+		.collect::<Vec<_>>();
+
+	assert!(reshared.iter().all(|s| s.public == reshared[0].public));
+	assert_eq!(reshared[0].public.threshold, 3);
+	assert_eq!(reshared[0].public.group_key, group_key);
+
+	let coordinator = coordinator_for_test(reshared[1..].to_vec());
+
+	assert_eq!(coordinator.address, address);
+	assert!(runtime
+		.block_on(coordinator.sign(&SignRequest::for_key_test(
+			shares[0].public.internal_key().unwrap(),
+			1,
+			SpendPath::Key { merkle_root: None }
+		)))
+		.is_ok());
+
+	// A single old signer can't reshare the key.
+	assert!(shares[0].reshare(&[1], 3, 4).is_err());
+	assert!(KeyShare::from_dealings(
+		1,
+		internal_key,
+		&[Dealing::new(shares[0].secret, 3, 4).unwrap()],
+	)
+	.is_err());
+
+	// Shares tampered in transit are detected.
+	let mut dealings = dealings;
+
+	*dealings[0].shares.get_mut(&1).unwrap() += Scalar::ONE;
+
+	assert!(KeyShare::from_dealings(1, internal_key, &dealings).is_err());
+	assert!(KeyShare::from_dealings(2, internal_key, &dealings).is_ok());
+	// Another signer's share isn't delivered.
+	assert!(KeyShare::from_dealings(2, internal_key, &[dealings[0].to(1).unwrap()]).is_err());
+}
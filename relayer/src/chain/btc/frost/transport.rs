@@ -0,0 +1,113 @@
+//! Transport of the signing rounds between the coordinator and the signers.
+//!
+//! Relayer instances on the same host talk over Unix sockets, one JSON request and response per
+//! connection. The socket must only be accessible by the relayers. Signers aren't told the bare
+//! messages, but the spend along with the signing package, and refuse the spends their policy
+//! rejects.
+
+// std
+use std::{path::PathBuf, sync::Arc};
+// crates.io
+use k256::Scalar;
+use serde::{Deserialize, Serialize};
+use tokio::{
+	io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+	net::{UnixListener, UnixStream},
+};
+// self
+use super::*;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Request {
+	Commit { count: usize },
+	Sign { request: Box<SignRequest>, package: SigningPackage },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Response {
+	Commit { index: SignerIndex, commitments: Vec<Commitment> },
+	Sign { index: SignerIndex, shares: Vec<Scalar> },
+	Error { message: String },
+}
+
+impl Participant {
+	pub fn handle(&self, request: &Request) -> Response {
+		let index = self.share.index;
+
+		match request {
+			Request::Commit { count } =>
+				Response::Commit { index, commitments: self.commit(*count) },
+			Request::Sign { request, package } => match self.sign(request, package) {
+				Ok(shares) => Response::Sign { index, shares },
+				Err(e) => Response::Error { message: e.to_string() },
+			},
+		}
+	}
+}
+
+/// Connection to a signer.
+#[derive(Debug)]
+pub enum Transport {
+	/// Signer in the same process.
+	Local(Arc<Participant>),
+	/// Signer listening on a Unix socket.
+	Unix(PathBuf),
+}
+impl Transport {
+	pub async fn request(&self, request: &Request) -> Result<Response> {
+		let response = match self {
+			Self::Local(p) => p.handle(request),
+			Self::Unix(path) => {
+				let mut stream = BufReader::new(UnixStream::connect(path).await?);
+				let mut line = serde_json::to_string(request)?;
+
+				line.push('\n');
+				stream.get_mut().write_all(line.as_bytes()).await?;
+				line.clear();
+				stream.read_line(&mut line).await?;
+
+				serde_json::from_str(&line)?
+			},
+		};
+
+		match response {
+			Response::Error { message } => Err(ChainError::FrostSigner { message })?,
+			r => Ok(r),
+		}
+	}
+}
+
+/// Answer the requests on the listener until it fails.
+pub async fn serve(listener: UnixListener, participant: Arc<Participant>) -> Result<()> {
+	loop {
+		let (stream, _) = listener.accept().await?;
+		let participant = participant.clone();
+
+		tokio::spawn(async move {
+			// A broken connection shouldn't stop the signer.
+			if let Err(e) = respond(stream, &participant).await {
+				tracing::warn!("fail to respond to the coordinator: {e}");
+			}
+		});
+	}
+}
+
+async fn respond(stream: UnixStream, participant: &Participant) -> Result<()> {
+	let mut stream = BufReader::new(stream);
+	let mut line = String::new();
+
+	stream.read_line(&mut line).await?;
+
+	let response = match serde_json::from_str::<Request>(&line) {
+		Ok(r) => participant.handle(&r),
+		Err(e) => Response::Error { message: e.to_string() },
+	};
+	let mut line = serde_json::to_string(&response)?;
+
+	line.push('\n');
+	stream.get_mut().write_all(line.as_bytes()).await?;
+
+	Ok(())
+}
@@ -0,0 +1,181 @@
+//! Header chain validation, following Bitcoin Core's `GetNextWorkRequired`.
+//!
+//! Timestamps are not checked against the median time past, the light client on X is the final
+//! arbiter.
+
+// crates.io
+use bitcoin::{block::Header, params::Params, BlockHash, CompactTarget, Target};
+// self
+use super::types::*;
+use crate::prelude::*;
+
+/// Headers of the chain which the validation depends on.
+#[derive(Debug)]
+pub struct Ancestors {
+	pub parent: Header,
+	// First header of the parent's difficulty period.
+	pub period_start: Header,
+	// Bits of the last header in the parent's difficulty period which is not mined with the
+	// minimum difficulty, only used by the networks allowing such blocks.
+	pub last_bits: CompactTarget,
+}
+
+/// Validate the header to be appended at `height`, returns its hash.
+pub fn validate(
+	params: &Params,
+	height: BlockNumber,
+	header: &Header,
+	ancestors: &Ancestors,
+) -> Result<BlockHash> {
+	let invalid = |reason| ChainError::InvalidHeader { height, reason };
+
+	if header.prev_blockhash != ancestors.parent.block_hash() {
+		Err(invalid("parent mismatch"))?;
+	}
+	if header.bits != required_bits(params, height, header, ancestors) {
+		Err(invalid("unexpected difficulty"))?;
+	}
+
+	Ok(header.validate_pow(header.target()).map_err(|_| invalid("insufficient proof of work"))?)
+}
+
+/// Whether the header at `height` starts a new difficulty period.
+pub fn is_period_start(params: &Params, height: BlockNumber) -> bool {
+	(height as u64).is_multiple_of(params.difficulty_adjustment_interval())
+}
+
+fn required_bits(
+	params: &Params,
+	height: BlockNumber,
+	header: &Header,
+	ancestors: &Ancestors,
+) -> CompactTarget {
+	let Ancestors { parent, period_start, last_bits } = ancestors;
+
+	if !is_period_start(params, height) {
+		if params.allow_min_difficulty_blocks {
+			// A block taking more than twice the target spacing can be mined with the minimum
+			// difficulty.
+			if header.time as u64 > parent.time as u64 + params.pow_target_spacing * 2 {
+				return params.max_attainable_target.to_compact_lossy();
+			}
+
+			return *last_bits;
+		}
+
+		return parent.bits;
+	}
+	if params.no_pow_retargeting {
+		return parent.bits;
+	}
+
+	let timespan = (parent.time as u64)
+		.saturating_sub(period_start.time as u64)
+		.clamp(params.pow_target_timespan / 4, params.pow_target_timespan * 4);
+	let target = mul_div(Target::from_compact(parent.bits), timespan, params.pow_target_timespan);
+
+	target.min(params.max_attainable_target).to_compact_lossy()
+}
+
+// `target * n / d`, with `n` and `d` fitting in 32 bits.
+fn mul_div(target: Target, n: u64, d: u64) -> Target {
+	let le = target.to_le_bytes();
+	// Base 2^32, with an extra limb for the overflow.
+	let mut limbs = [0_u64; 9];
+	let mut carry = 0;
+	let mut rem = 0;
+
+	for (i, l) in limbs.iter_mut().take(8).enumerate() {
+		*l = u32::from_le_bytes(le[i * 4..i * 4 + 4].try_into().unwrap()) as _;
+	}
+	for l in limbs.iter_mut() {
+		let v = *l * n + carry;
+
+		*l = v & 0xffff_ffff;
+		carry = v >> 32;
+	}
+	for l in limbs.iter_mut().rev() {
+		let v = (rem << 32) | *l;
+
+		*l = v / d;
+		rem = v % d;
+	}
+
+	if limbs[8] != 0 {
+		return Target::from_le_bytes([0xff; 32]);
+	}
+
+	let mut le = [0; 32];
+
+	for (i, l) in limbs.iter().take(8).enumerate() {
+		le[i * 4..i * 4 + 4].copy_from_slice(&(*l as u32).to_le_bytes());
+	}
+
+	Target::from_le_bytes(le)
+}
+#[test]
+fn retarget_should_work() {
+	let params = Params::new(bitcoin::Network::Bitcoin);
+	let header = |time, bits| Header {
+		version: bitcoin::block::Version::ONE,
+		prev_blockhash: bitcoin::hashes::Hash::all_zeros(),
+		merkle_root: bitcoin::hashes::Hash::all_zeros(),
+		time,
+		bits: CompactTarget::from_consensus(bits),
+		nonce: 0,
+	};
+	let required = |timespan, bits| {
+		required_bits(
+			&params,
+			2016,
+			&header(timespan, bits),
+			&Ancestors {
+				parent: header(timespan, bits),
+				period_start: header(0, bits),
+				last_bits: CompactTarget::from_consensus(bits),
+			},
+		)
+		.to_consensus()
+	};
+
+	// On schedule.
+	assert_eq!(required(1_209_600, 0x1b04864c), 0x1b04864c);
+	// Twice as fast, the target halves.
+	assert_eq!(required(604_800, 0x1d00ffff), 0x1c7fff80);
+	// The adjustment is limited to a factor of 4.
+	assert_eq!(required(1, 0x1c7fff80), 0x1c1fffe0);
+	// The target can't exceed the limit.
+	assert_eq!(required(1_209_600 * 10, 0x1c7fff80), 0x1d00ffff);
+	// No retargeting within the period.
+	assert_eq!(
+		required_bits(
+			&params,
+			2017,
+			&header(1, 0x1d00ffff),
+			&Ancestors {
+				parent: header(0, 0x1c7fff80),
+				period_start: header(0, 0x1d00ffff),
+				last_bits: CompactTarget::from_consensus(0x1d00ffff),
+			}
+		)
+		.to_consensus(),
+		0x1c7fff80
+	);
+
+	let params = Params::new(bitcoin::Network::Testnet);
+	let ancestors = Ancestors {
+		parent: header(0, 0x1d00ffff),
+		period_start: header(0, 0x1c7fff80),
+		last_bits: CompactTarget::from_consensus(0x1c7fff80),
+	};
+
+	// Minimum difficulty after 20 minutes on testnet.
+	assert_eq!(
+		required_bits(&params, 2017, &header(1_201, 0), &ancestors).to_consensus(),
+		0x1d00ffff
+	);
+	assert_eq!(
+		required_bits(&params, 2017, &header(1_200, 0), &ancestors).to_consensus(),
+		0x1c7fff80
+	);
+}
@@ -0,0 +1,485 @@
+//! Batched payouts of the withdrawals from the vault.
+//!
+//! A payout pays every withdrawal of the batch at the output of its index, followed by the change
+//! to the vault if any. The X chain bridge contract checks the paid output of each withdrawal with
+//! the SPV proof of the payout.
+//!
+//! Payouts signal replace-by-fee, a stuck one is replaced by the same payouts spending the same
+//! UTXOs at a higher fee rate, so that only one of its versions can ever confirm. Or it's
+//! accelerated by a child spending its change, which pays for both.
+
+// std
+use std::iter;
+// crates.io
+use bitcoin::{
+	absolute::LockTime, taproot::Signature, transaction::Version, Amount, Network, OutPoint,
+	Sequence, TapSighashType, Transaction, TxIn, TxOut, Witness,
+};
+// self
+use super::{
+	coin_selection::CoinSelection,
+	policy,
+	types::*,
+	util,
+	weight::{self, Spend},
+	KeySpendSigner, SignRequest, SpendPath,
+};
+use crate::prelude::*;
+
+/// Recipient and amount of a withdrawal.
+#[derive(Debug)]
+pub struct Payout<'a> {
+	pub recipient: &'a str,
+	pub amount: Satoshi,
+}
+
+#[derive(Debug)]
+pub struct PayoutTxBuilder<'a, K> {
+	pub network: Network,
+	pub fee_rate: Satoshi,
+	pub sender: &'a K,
+	pub utxos: &'a [Utxo],
+	pub coin_selection: CoinSelection,
+	pub payouts: &'a [Payout<'a>],
+}
+impl<K> PayoutTxBuilder<'_, K>
+where
+	K: KeySpendSigner,
+{
+	const LOCK_TIME: LockTime = LockTime::ZERO;
+	const SIGHASH_TYPE: TapSighashType = TapSighashType::Default;
+	const SPEND: Spend<'static> = Spend::Key(Self::SIGHASH_TYPE);
+	const VERSION: Version = Version::TWO;
+
+	/// Build the signed transaction, the payouts are paid at the outputs of their indexes.
+	pub async fn build(self) -> Result<Transaction> {
+		self.build_signed(false).await
+	}
+
+	/// Build the signed replacement of the payout spending the UTXOs, all of which are spent.
+	///
+	/// The payouts must be the replaced one's, in the same order, so that they stay at the same
+	/// outputs.
+	pub async fn build_replacement(self) -> Result<Transaction> {
+		self.build_signed(true).await
+	}
+
+	async fn build_signed(self, spend_all: bool) -> Result<Transaction> {
+		let sender = self.sender;
+		let (tx, prevouts) = self.build_unsigned(spend_all)?;
+		let tx = sign_key_spends(sender, tx, prevouts, Self::SIGHASH_TYPE).await?;
+
+		tracing::debug!("payout: {tx:?}");
+
+		Ok(tx)
+	}
+
+	// Select the UTXOs, or take all of them, and build the unsigned transaction, along with the
+	// spent outputs.
+	fn build_unsigned(self, spend_all: bool) -> Result<(Transaction, Vec<TxOut>)> {
+		let Self { network, fee_rate, sender, utxos, coin_selection, payouts } = self;
+
+		if payouts.is_empty() {
+			Err(ChainError::InvalidPayout { reason: "no payouts" })?;
+		}
+
+		let mut output = payouts
+			.iter()
+			.map(|p| {
+				Ok(TxOut {
+					script_pubkey: util::addr_from_str(p.recipient, network)?.script_pubkey(),
+					value: Amount::from_sat(p.amount),
+				})
+			})
+			.collect::<Result<Vec<_>>>()?;
+		let amount = payouts.iter().map(|p| p.amount).sum::<Satoshi>();
+		let change =
+			TxOut { script_pubkey: sender.script_public_key().clone(), value: Amount::ZERO };
+		let available = utxos.iter().map(|u| u.value).sum::<Satoshi>();
+		let (utxos, utxos_amount, with_change) = if spend_all {
+			(utxos.iter().collect(), available, true)
+		} else {
+			let selection = util::select_utxos(
+				utxos,
+				amount,
+				weight::base_vsize(&output),
+				Some(&change),
+				fee_rate,
+				&Self::SPEND,
+				coin_selection,
+			)?;
+
+			(selection.utxos, selection.amount, selection.change)
+		};
+		let v_size = weight::vsize(
+			utxos.len(),
+			output.iter().chain(with_change.then_some(&change)),
+			&Self::SPEND,
+		);
+		let spent = amount + v_size * fee_rate;
+
+		if utxos_amount < spent {
+			Err(ChainError::InsufficientFunds { required: spent as _, available: available as _ })?;
+		}
+		let change = TxOut { value: Amount::from_sat(utxos_amount - spent), ..change };
+		// Otherwise the excess is lower than the cost of the change or the dust limit, and goes to
+		// the fee.
+		let fee = if with_change && !policy::is_dust(&change) {
+			output.push(change);
+
+			spent - amount
+		} else {
+			utxos_amount - amount
+		};
+
+		tracing::info!("paying {} withdrawals, fee: {fee}", payouts.len());
+
+		let input = utxos
+			.iter()
+			.map(|u| TxIn {
+				previous_output: u.outpoint,
+				sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+				..Default::default()
+			})
+			.collect();
+		let prevouts = utxos
+			.iter()
+			.map(|u| TxOut {
+				script_pubkey: sender.script_public_key().clone(),
+				value: Amount::from_sat(u.value),
+			})
+			.collect();
+
+		let tx = Transaction { version: Self::VERSION, lock_time: Self::LOCK_TIME, input, output };
+
+		policy::check(&weight::dummy_signed(&tx, &Self::SPEND), fee)?;
+
+		Ok((tx, prevouts))
+	}
+}
+
+/// Child of a stuck payout spending its change back to the vault, whose fee lifts the package of
+/// both to the fee rate.
+///
+/// More vault UTXOs are spent if the change can't pay the fee.
+#[derive(Debug)]
+pub struct CpfpTxBuilder<'a, K> {
+	pub fee_rate: Satoshi,
+	pub sender: &'a K,
+	pub parent: &'a Transaction,
+	// Fee paid by the parent.
+	pub parent_fee: Satoshi,
+	// Vault UTXOs other than the change.
+	pub utxos: &'a [Utxo],
+	pub coin_selection: CoinSelection,
+}
+impl<K> CpfpTxBuilder<'_, K>
+where
+	K: KeySpendSigner,
+{
+	const LOCK_TIME: LockTime = LockTime::ZERO;
+	const SIGHASH_TYPE: TapSighashType = TapSighashType::Default;
+	const SPEND: Spend<'static> = Spend::Key(Self::SIGHASH_TYPE);
+	const VERSION: Version = Version::TWO;
+
+	/// Build the signed child, which spends the change at its first input.
+	///
+	/// The change is the last output paying the vault, a withdrawal to the vault address comes
+	/// before it.
+	pub async fn build(self) -> Result<Transaction> {
+		let Self { fee_rate, sender, parent, parent_fee, utxos, coin_selection } = self;
+		let txid = parent.compute_txid();
+		let change = parent
+			.output
+			.iter()
+			.enumerate()
+			.rfind(|(_, o)| o.script_pubkey == *sender.script_public_key())
+			.map(|(vout, o)| Utxo {
+				outpoint: OutPoint { txid, vout: vout as _ },
+				value: o.value.to_sat(),
+				confirmed: false,
+			})
+			.ok_or(ChainError::InvalidPayout { reason: "no change to spend" })?;
+		let parent_vsize = parent.vsize() as Satoshi;
+		// The output must stay spendable after the fee.
+		let dust = sender.script_public_key().minimal_non_dust().to_sat();
+		let output =
+			TxOut { script_pubkey: sender.script_public_key().clone(), value: Amount::ZERO };
+		// What the parent lacks, the child pays for the rest at the fee rate.
+		let deficit = (fee_rate * parent_vsize).saturating_sub(parent_fee);
+		let required = deficit + weight::vsize(1, [&output], &Self::SPEND) * fee_rate + dust;
+		let (utxos, amount) = if change.value >= required {
+			(vec![&change], change.value)
+		} else {
+			// The output takes the excess.
+			let selection = util::select_utxos(
+				utxos,
+				required - change.value,
+				0,
+				None,
+				fee_rate,
+				&Self::SPEND,
+				coin_selection,
+			)?;
+
+			(iter::once(&change).chain(selection.utxos).collect(), change.value + selection.amount)
+		};
+		let child_vsize = weight::vsize(utxos.len(), [&output], &Self::SPEND);
+		let fee = util::cpfp_fee(fee_rate, parent_vsize, parent_fee, child_vsize);
+
+		tracing::info!("accelerating payout {txid}, child fee: {fee}");
+
+		let input = utxos
+			.iter()
+			.map(|u| TxIn {
+				previous_output: u.outpoint,
+				sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+				..Default::default()
+			})
+			.collect();
+		let prevouts = utxos
+			.iter()
+			.map(|u| TxOut { value: Amount::from_sat(u.value), ..output.clone() })
+			.collect();
+		let tx = Transaction {
+			version: Self::VERSION,
+			lock_time: Self::LOCK_TIME,
+			input,
+			output: vec![TxOut { value: Amount::from_sat(amount - fee), ..output }],
+		};
+
+		policy::check(&weight::dummy_signed(&tx, &Self::SPEND), fee)?;
+
+		let tx = sign_key_spends(sender, tx, prevouts, Self::SIGHASH_TYPE).await?;
+
+		tracing::debug!("cpfp: {tx:?}");
+
+		Ok(tx)
+	}
+}
+
+// Sign the key path spends of the vault UTXOs.
+pub(super) async fn sign_key_spends<K>(
+	sender: &K,
+	tx: Transaction,
+	prevouts: Vec<TxOut>,
+	sighash_type: TapSighashType,
+) -> Result<Transaction>
+where
+	K: KeySpendSigner,
+{
+	let request = SignRequest {
+		tx,
+		prevouts,
+		sighash_type,
+		path: SpendPath::Key { merkle_root: sender.merkle_root() },
+	};
+	let sigs = sender.sign(&request).await?;
+	let mut tx = request.tx;
+
+	for (input, sig) in tx.input.iter_mut().zip(sigs) {
+		input.witness = Witness::p2tr_key_spend(&Signature { signature: sig, sighash_type });
+	}
+
+	Ok(tx)
+}
+#[test]
+fn payout_should_work() {
+	// crates.io
+	use bitcoin::{key::CompressedPublicKey, Address, PublicKey, ScriptBuf};
+	// self
+	use super::{fixture::*, Signer};
+
+	let rt = tokio::runtime::Runtime::new().unwrap();
+	let network = Network::Testnet;
+	let vault = vault();
+	let pk = CompressedPublicKey(vault.public_key().public_key(bitcoin::key::Parity::Even));
+	let script = ScriptBuf::from_bytes(vec![0x51]);
+	// Every output script type.
+	let recipients = [
+		Address::p2pkh(PublicKey::from(pk), network),
+		Address::p2sh(&script, network).unwrap(),
+		Address::p2wpkh(&pk, network),
+		Address::p2wsh(&script, network),
+		Address::from_script(&vault.script_public_key, network).unwrap(),
+	]
+	.map(|a| a.to_string());
+	let payouts = recipients
+		.iter()
+		.enumerate()
+		.map(|(i, r)| Payout { recipient: r, amount: 1_500 * (i as Satoshi + 1) })
+		.collect::<Vec<_>>();
+	let utxos = [utxo(1, 10_000), utxo(2, 20_000)];
+	let build = |fee_rate, payouts: &[Payout]| {
+		rt.block_on(
+			PayoutTxBuilder {
+				network,
+				fee_rate,
+				sender: &vault,
+				utxos: &utxos,
+				coin_selection: Default::default(),
+				payouts,
+			}
+			.build(),
+		)
+	};
+	let tx = build(3, &payouts).unwrap();
+	let fee = 30_000 - tx.output.iter().map(|o| o.value.to_sat()).sum::<Satoshi>();
+
+	assert_eq!(tx.input.len(), 2);
+	assert_eq!(tx.output.len(), 6);
+
+	for (o, p) in tx.output.iter().zip(&payouts) {
+		assert_eq!(
+			o.script_pubkey,
+			util::addr_from_str(p.recipient, network).unwrap().script_pubkey()
+		);
+		assert_eq!(o.value.to_sat(), p.amount);
+	}
+
+	assert_eq!(tx.output[5].script_pubkey, vault.script_public_key);
+	// The estimation is exact for the signed transaction.
+	assert_eq!(fee, tx.vsize() as Satoshi * 3);
+
+	// No change, the excess is lower than the cost of the change and goes to the fee.
+	let tx = build(1, &[Payout { recipient: &recipients[0], amount: 30_000 - 203 }]).unwrap();
+
+	assert_eq!(tx.output.len(), 1);
+	assert!(tx.vsize() < 203);
+	assert!(matches!(
+		build(1_000, &payouts),
+		Err(Error::Chain(ChainError::InsufficientFunds { .. }))
+	));
+	assert!(build(1, &[]).is_err());
+
+	// The replacement spends all the UTXOs, even if one of them would be enough.
+	let payouts = [Payout { recipient: &recipients[4], amount: 1_000 }];
+	let replace = |payouts: &[Payout]| {
+		rt.block_on(
+			PayoutTxBuilder {
+				network,
+				fee_rate: 2,
+				sender: &vault,
+				utxos: &utxos,
+				coin_selection: Default::default(),
+				payouts,
+			}
+			.build_replacement(),
+		)
+	};
+	let tx = build(1, &payouts).unwrap();
+	let replacement = replace(&payouts).unwrap();
+
+	assert_eq!(tx.input.len(), 1);
+	assert_eq!(replacement.input.len(), 2);
+	assert!(replacement.input.iter().all(|i| i.sequence.is_rbf()));
+	assert_eq!(replacement.output[0], tx.output[0]);
+
+	// The change would be 100, below the dust limit, and goes to the fee.
+	let replacement =
+		replace(&[Payout { recipient: &recipients[4], amount: 30_000 - 212 * 2 - 100 }]).unwrap();
+
+	assert_eq!(replacement.output.len(), 1);
+	assert_eq!(replacement.vsize(), 212 - 43);
+
+	// Nodes wouldn't relay an output below the dust limit.
+	assert!(matches!(
+		build(1, &[Payout { recipient: &recipients[4], amount: 329 }]),
+		Err(Error::Chain(ChainError::NonStandardTx { .. }))
+	));
+}
+#[test]
+fn cpfp_should_work() {
+	// crates.io
+	use bitcoin::Address;
+	// self
+	use super::fixture::*;
+
+	let rt = tokio::runtime::Runtime::new().unwrap();
+	let network = Network::Testnet;
+	let vault = vault();
+	let parent_utxos = [utxo(1, 20_000)];
+	let payouts = [Payout { recipient: RECIPIENT, amount: 10_000 }];
+	let parent = rt
+		.block_on(
+			PayoutTxBuilder {
+				network,
+				fee_rate: 1,
+				sender: &vault,
+				utxos: &parent_utxos,
+				coin_selection: Default::default(),
+				payouts: &payouts,
+			}
+			.build(),
+		)
+		.unwrap();
+	let parent_fee = 20_000 - parent.output.iter().map(|o| o.value.to_sat()).sum::<Satoshi>();
+	let build = |fee_rate, utxos: &[Utxo]| {
+		rt.block_on(
+			CpfpTxBuilder {
+				fee_rate,
+				sender: &vault,
+				parent: &parent,
+				parent_fee,
+				utxos,
+				coin_selection: Default::default(),
+			}
+			.build(),
+		)
+	};
+	let package_fee =
+		|child: &Transaction, spent| parent_fee + spent - child.output[0].value.to_sat();
+	let child = build(10, &[]).unwrap();
+
+	assert_eq!(child.input.len(), 1);
+	assert_eq!(child.input[0].previous_output, OutPoint { txid: parent.compute_txid(), vout: 1 });
+	assert_eq!(child.output[0].script_pubkey, vault.script_public_key);
+	// The estimation is exact for the signed child.
+	assert_eq!(
+		package_fee(&child, parent.output[1].value.to_sat()),
+		(parent.vsize() + child.vsize()) as Satoshi * 10
+	);
+
+	// The change can't pay the fee alone.
+	assert!(matches!(build(100, &[]), Err(Error::Chain(ChainError::InsufficientFunds { .. }))));
+
+	let child = build(100, &[utxo(2, 30_000)]).unwrap();
+
+	assert_eq!(child.input.len(), 2);
+	assert_eq!(
+		package_fee(&child, parent.output[1].value.to_sat() + 30_000),
+		(parent.vsize() + child.vsize()) as Satoshi * 100
+	);
+
+	// A withdrawal to the vault address is not the change.
+	let recipient = Address::from_script(&vault.script_public_key, network).unwrap().to_string();
+	let parent = rt
+		.block_on(
+			PayoutTxBuilder {
+				network,
+				fee_rate: 1,
+				sender: &vault,
+				utxos: &parent_utxos,
+				coin_selection: Default::default(),
+				payouts: &[Payout { recipient: &recipient, amount: 10_000 }],
+			}
+			.build(),
+		)
+		.unwrap();
+	let child = rt
+		.block_on(
+			CpfpTxBuilder {
+				fee_rate: 10,
+				sender: &vault,
+				parent: &parent,
+				parent_fee,
+				utxos: &[],
+				coin_selection: Default::default(),
+			}
+			.build(),
+		)
+		.unwrap();
+
+	assert_eq!(parent.output.len(), 2);
+	assert_eq!(child.input[0].previous_output, OutPoint { txid: parent.compute_txid(), vout: 1 });
+}
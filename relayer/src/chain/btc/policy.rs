@@ -0,0 +1,178 @@
+//! Standardness of the vault spends, checked before they reach the network.
+//!
+//! Nodes only relay the transactions passing their policy on top of the consensus rules, so a
+//! non-standard spend would never confirm. The checks follow Bitcoin Core's default policy, with
+//! the single 83 bytes OP_RETURN output of the versions before v30, so that the spends propagate
+//! through the whole network.
+
+// crates.io
+use bitcoin::{Script, Transaction, TxOut, WitnessVersion};
+// self
+use super::types::*;
+use crate::prelude::*;
+
+/// Maximum weight of a standard transaction.
+pub const MAX_STANDARD_TX_WEIGHT: u64 = 400_000;
+/// Minimum size of a standard transaction without its witness, smaller ones could be mistaken for
+/// the 64 bytes inner nodes of the merkle trees.
+pub const MIN_STANDARD_TX_NONWITNESS_SIZE: usize = 65;
+/// Maximum size of a standard OP_RETURN output script.
+pub const MAX_OP_RETURN_RELAY: usize = 83;
+/// Minimum fee rate (in satoshis per virtual byte) of a relayed transaction.
+pub const MIN_RELAY_FEE_RATE: Satoshi = 1;
+
+const MAX_STANDARD_VERSION: i32 = 3;
+const MAX_STANDARD_SCRIPTSIG_SIZE: usize = 1_650;
+
+/// Whether the output is worth less than the fee of spending it at the dust relay fee rate.
+///
+/// OP_RETURN outputs are never spent, and never dust.
+pub fn is_dust(output: &TxOut) -> bool {
+	!output.script_pubkey.is_op_return() && output.value < output.script_pubkey.minimal_non_dust()
+}
+
+/// Check the transaction paying the fee against the default standardness rules.
+///
+/// The witnesses must be complete, or filled with dummy signatures of the same sizes.
+pub fn check(tx: &Transaction, fee: Satoshi) -> Result<()> {
+	let non_standard = |reason| ChainError::NonStandardTx { reason };
+
+	if !(1..=MAX_STANDARD_VERSION).contains(&tx.version.0) {
+		Err(non_standard(format!("version {} is not standard", tx.version.0)))?;
+	}
+
+	let weight = tx.weight().to_wu();
+
+	if weight > MAX_STANDARD_TX_WEIGHT {
+		Err(non_standard(format!("weight {weight} exceeds {MAX_STANDARD_TX_WEIGHT}")))?;
+	}
+	if tx.base_size() < MIN_STANDARD_TX_NONWITNESS_SIZE {
+		Err(non_standard(format!(
+			"size without witness {} is below {MIN_STANDARD_TX_NONWITNESS_SIZE}",
+			tx.base_size()
+		)))?;
+	}
+
+	for (i, input) in tx.input.iter().enumerate() {
+		if input.script_sig.len() > MAX_STANDARD_SCRIPTSIG_SIZE || !input.script_sig.is_push_only()
+		{
+			Err(non_standard(format!("input {i} has a non-standard script sig")))?;
+		}
+	}
+
+	let mut op_returns = 0;
+
+	for (i, output) in tx.output.iter().enumerate() {
+		let spk = &output.script_pubkey;
+
+		if spk.is_op_return() {
+			if spk.len() > MAX_OP_RETURN_RELAY {
+				Err(non_standard(format!(
+					"OP_RETURN output {i} of {} bytes exceeds {MAX_OP_RETURN_RELAY}",
+					spk.len()
+				)))?;
+			}
+			if !Script::from_bytes(&spk.as_bytes()[1..]).is_push_only() {
+				Err(non_standard(format!("OP_RETURN output {i} isn't push only")))?;
+			}
+
+			op_returns += 1;
+		} else if !is_standard_output_script(spk) {
+			Err(non_standard(format!("output {i} pays to a non-standard script")))?;
+		} else if is_dust(output) {
+			Err(non_standard(format!(
+				"output {i} of {} sat is below the dust limit of {} sat",
+				output.value.to_sat(),
+				spk.minimal_non_dust().to_sat()
+			)))?;
+		}
+	}
+
+	if op_returns > 1 {
+		Err(non_standard(format!("{op_returns} OP_RETURN outputs, at most one is standard")))?;
+	}
+
+	let min_fee = tx.vsize() as Satoshi * MIN_RELAY_FEE_RATE;
+
+	if fee < min_fee {
+		Err(non_standard(format!(
+			"fee {fee} sat is below the minimum relay fee of {min_fee} sat"
+		)))?;
+	}
+
+	Ok(())
+}
+
+// Scripts of the addresses, any future witness version included.
+fn is_standard_output_script(spk: &Script) -> bool {
+	spk.is_p2pkh()
+		|| spk.is_p2sh()
+		|| spk.is_p2wpkh()
+		|| spk.is_p2wsh()
+		|| spk.witness_version().is_some_and(|v| v != WitnessVersion::V0)
+}
+#[test]
+fn policy_should_work() {
+	// crates.io
+	use bitcoin::{
+		absolute::LockTime, opcodes::all::*, script::PushBytesBuf, transaction::Version, Amount,
+		ScriptBuf, TapSighashType, TxIn,
+	};
+	// self
+	use super::weight::Spend;
+
+	let p2tr = ScriptBuf::from_bytes([&[0x51, 0x20][..], &[1; 32]].concat());
+	let op_return = |len| {
+		let mut data = PushBytesBuf::new();
+
+		data.extend_from_slice(&vec![1; len]).unwrap();
+
+		Script::builder().push_opcode(OP_RETURN).push_slice(data).into_script()
+	};
+	let tx = |output: Vec<(ScriptBuf, Satoshi)>| Transaction {
+		version: Version::TWO,
+		lock_time: LockTime::ZERO,
+		input: vec![TxIn {
+			witness: Spend::Key(TapSighashType::Default).dummy_witness(),
+			..Default::default()
+		}],
+		output: output
+			.into_iter()
+			.map(|(script_pubkey, value)| TxOut { script_pubkey, value: Amount::from_sat(value) })
+			.collect(),
+	};
+	let check = |output, fee| match check(&tx(output), fee) {
+		Ok(()) => None,
+		Err(Error::Chain(ChainError::NonStandardTx { reason })) => Some(reason),
+		Err(e) => panic!("{e:?}"),
+	};
+
+	assert_eq!(check(vec![(p2tr.clone(), 330), (op_return(80), 0)], 1_000), None);
+	assert!(is_dust(&tx(vec![(p2tr.clone(), 329)]).output[0]));
+	assert_eq!(
+		check(vec![(p2tr.clone(), 329)], 1_000).unwrap(),
+		"output 0 of 329 sat is below the dust limit of 330 sat"
+	);
+	assert_eq!(
+		check(vec![(p2tr.clone(), 330), (op_return(81), 0)], 1_000).unwrap(),
+		"OP_RETURN output 1 of 84 bytes exceeds 83"
+	);
+	assert_eq!(
+		check(vec![(op_return(1), 0), (op_return(1), 0), (p2tr.clone(), 330)], 1_000).unwrap(),
+		"2 OP_RETURN outputs, at most one is standard"
+	);
+	// A segwit v0 program of neither 20 nor 32 bytes.
+	assert_eq!(
+		check(vec![(ScriptBuf::from_bytes([&[0x00, 0x19][..], &[1; 25]].concat()), 1_000)], 1_000)
+			.unwrap(),
+		"output 0 pays to a non-standard script"
+	);
+	assert_eq!(
+		check(vec![(p2tr.clone(), 330)], 50).unwrap(),
+		"fee 50 sat is below the minimum relay fee of 111 sat"
+	);
+	// 10,000 P2TR outputs of 43 bytes each.
+	assert!(check(vec![(p2tr.clone(), 330); 10_000], 1_000_000).unwrap().starts_with("weight"));
+	// Smaller than a 64 bytes merkle tree node.
+	assert!(check(vec![(op_return(0), 0)], 1_000).unwrap().starts_with("size without witness"));
+}
@@ -0,0 +1,262 @@
+//! Offline signing of the vault spends with BIP-174 PSBTs, carrying the BIP-371 taproot fields.
+//!
+//! The relayer exports the unsigned PSBT with [`XTxBuilder::build_psbt`](super::XTxBuilder), an
+//! operator signs it on an air-gapped device and feeds it back. The signed PSBT is only finalized
+//! if it still spends the same outputs, pays the same outputs with the same fee, every key path
+//! signature is valid, and it's standard, see [`policy`](super::policy).
+
+// crates.io
+use bitcoin::{
+	hashes::Hash,
+	key::XOnlyPublicKey,
+	psbt::{Psbt, PsbtSighashType},
+	secp256k1::Message,
+	sighash::{Prevouts, SighashCache},
+	Transaction, TxOut, Witness,
+};
+// self
+use super::{policy, SECP256K1};
+use crate::prelude::*;
+
+/// Finalize the signed PSBT and extract the transaction, if it matches the exported one.
+pub fn finalize(exported: &Psbt, signed: Psbt) -> Result<Transaction> {
+	let invalid = |reason| ChainError::InvalidPsbt { reason };
+
+	if signed.unsigned_tx != exported.unsigned_tx {
+		Err(invalid("inputs or outputs changed"))?;
+	}
+
+	// Only the exported spent outputs are trusted, the signer must have seen the same ones.
+	let prevouts = exported
+		.inputs
+		.iter()
+		.map(|i| Ok(i.witness_utxo.clone().ok_or(invalid("missing witness utxo"))?))
+		.collect::<Result<Vec<_>>>()?;
+
+	if signed.inputs.iter().zip(&prevouts).any(|(i, p)| i.witness_utxo.as_ref() != Some(p)) {
+		Err(invalid("spent outputs changed"))?;
+	}
+	let fee = exported.fee().map_err(BitcoinError::Psbt)?;
+
+	if signed.fee().map_err(BitcoinError::Psbt)? != fee {
+		Err(invalid("fee changed"))?;
+	}
+
+	let mut hasher = SighashCache::new(&exported.unsigned_tx);
+	let mut witnesses = Vec::with_capacity(prevouts.len());
+
+	for (i, (input, prevout)) in signed.inputs.iter().zip(&prevouts).enumerate() {
+		let sig = input.tap_key_sig.ok_or(invalid("missing key path signature"))?;
+
+		if exported.inputs[i].sighash_type != Some(PsbtSighashType::from(sig.sighash_type)) {
+			Err(invalid("sighash type changed"))?;
+		}
+
+		let sighash = hasher
+			.taproot_key_spend_signature_hash(i, &Prevouts::All(&prevouts), sig.sighash_type)
+			.map_err(BitcoinError::SigHashTapRoot)?;
+
+		SECP256K1
+			.verify_schnorr(
+				&sig.signature,
+				&Message::from_digest(sighash.to_byte_array()),
+				&output_key(prevout).ok_or(invalid("not a taproot input"))?,
+			)
+			.map_err(|_| invalid("invalid key path signature"))?;
+		witnesses.push(Witness::p2tr_key_spend(&sig));
+	}
+
+	let mut tx = signed.unsigned_tx;
+
+	tx.input.iter_mut().zip(witnesses).for_each(|(i, w)| i.witness = w);
+
+	policy::check(&tx, fee.to_sat())?;
+
+	Ok(tx)
+}
+
+fn output_key(prevout: &TxOut) -> Option<XOnlyPublicKey> {
+	let spk = &prevout.script_pubkey;
+
+	if spk.is_p2tr() {
+		XOnlyPublicKey::from_slice(&spk.as_bytes()[2..]).ok()
+	} else {
+		None
+	}
+}
+#[cfg(test)]
+fn psbt_for_test(sighash_type: bitcoin::TapSighashType) -> (Psbt, bitcoin::bip32::Xpriv) {
+	// std
+	use std::str::FromStr;
+	// crates.io
+	use bitcoin::{
+		bip32::{DerivationPath, Xpriv},
+		Network, TapNodeHash,
+	};
+
+	let master = Xpriv::new_master(Network::Testnet, &[1; 32]).unwrap();
+	let path = DerivationPath::from_str("m/86'/1'/0'/0/0").unwrap();
+	let keypair = master.derive_priv(&SECP256K1, &path).unwrap().to_keypair(&SECP256K1);
+	let merkle_root = Some(TapNodeHash::from_byte_array([2; 32]));
+	let vault = super::TaprootKey::new(
+		super::signer::WatchOnly(keypair.x_only_public_key().0),
+		merkle_root,
+		Network::Testnet,
+	);
+
+	// Same vault as the one holding the key.
+	assert_eq!(
+		vault.address,
+		super::TaprootKey::from_untweaked_keypair(keypair, merkle_root, Network::Testnet).address
+	);
+
+	let utxos = [1, 2, 3].map(|i| super::fixture::utxo(i, 10_000));
+	let psbt = super::XTxBuilder {
+		network: Network::Testnet,
+		fee_rate: 1,
+		sender: &vault,
+		utxos: &utxos,
+		coin_selection: Default::default(),
+		sighash_type,
+		recipient: super::fixture::RECIPIENT,
+		x_target: crate::x::XTarget { id: 0_u32.into(), entity: [b'x'; 32].into() },
+		amount: 25_000,
+	}
+	.build_psbt(Some((master.fingerprint(&SECP256K1), path)))
+	.unwrap();
+
+	(psbt, master)
+}
+#[test]
+fn psbt_should_work() {
+	let (exported, master) = psbt_for_test(bitcoin::TapSighashType::Default);
+
+	assert_eq!(exported.inputs.len(), 3);
+	assert!(exported.inputs.iter().all(|i| i.tap_internal_key.is_some()
+		&& i.tap_merkle_root.is_some()
+		&& i.tap_key_origins.len() == 1
+		&& i.tap_key_sig.is_none()));
+
+	// Exchanged with the offline signer in base64.
+	let mut signed = exported.to_string().parse::<Psbt>().unwrap();
+
+	signed.sign(&master, &SECP256K1).unwrap();
+
+	let tx = finalize(&exported, signed).unwrap();
+
+	assert_eq!(tx.output, exported.unsigned_tx.output);
+	assert!(tx.input.iter().all(|i| i.witness.len() == 1));
+	assert_eq!(
+		exported.fee().unwrap().to_sat(),
+		30_000 - tx.output.iter().map(|o| o.value.to_sat()).sum::<u64>()
+	);
+
+	// The vault still can't sign by itself.
+	assert!(matches!(
+		tokio::runtime::Runtime::new().unwrap().block_on(super::signer::Signer::sign(
+			&super::signer::WatchOnly(exported.inputs[0].tap_internal_key.unwrap()),
+			&super::signer::SignRequest::for_test(
+				1,
+				super::signer::SpendPath::Key { merkle_root: None }
+			)
+		)),
+		Err(Error::Chain(ChainError::WatchOnly))
+	));
+}
+#[test]
+fn psbt_should_reject_tampered() {
+	let (exported, master) = psbt_for_test(bitcoin::TapSighashType::Default);
+	let mut signed = exported.clone();
+
+	signed.sign(&master, &SECP256K1).unwrap();
+
+	let reject = |f: &dyn Fn(&mut Psbt)| {
+		let mut s = signed.clone();
+
+		f(&mut s);
+
+		match finalize(&exported, s) {
+			Err(Error::Chain(ChainError::InvalidPsbt { reason })) => reason,
+			r => panic!("{r:?}"),
+		}
+	};
+
+	assert_eq!(
+		reject(&|s| s.unsigned_tx.output[0].value = bitcoin::Amount::from_sat(1)),
+		"inputs or outputs changed"
+	);
+	assert_eq!(
+		reject(&|s| s.inputs[1].witness_utxo.as_mut().unwrap().value = bitcoin::Amount::from_sat(1)),
+		"spent outputs changed"
+	);
+	assert_eq!(reject(&|s| s.inputs[2].tap_key_sig = None), "missing key path signature");
+	assert_eq!(
+		reject(&|s| s.inputs[0].tap_key_sig = s.inputs[1].tap_key_sig),
+		"invalid key path signature"
+	);
+	assert_eq!(
+		reject(&|s| s.inputs[0].tap_key_sig.as_mut().unwrap().sighash_type =
+			bitcoin::TapSighashType::All),
+		"sighash type changed"
+	);
+}
+#[test]
+fn psbt_should_commit_to_every_input() {
+	// crates.io
+	use bitcoin::{taproot::Signature, Amount, OutPoint, TapSighashType, TxIn};
+
+	let verify = |tx: &Transaction, prevouts: &[TxOut], sighash_type| {
+		let mut hasher = SighashCache::new(tx);
+
+		(0..tx.input.len()).all(|i| {
+			let sig = Signature::from_slice(&tx.input[i].witness[0]).unwrap();
+			let sighash = hasher
+				.taproot_key_spend_signature_hash(i, &Prevouts::All(prevouts), sighash_type)
+				.unwrap();
+
+			sig.sighash_type == sighash_type
+				&& SECP256K1
+					.verify_schnorr(
+						&sig.signature,
+						&Message::from_digest(sighash.to_byte_array()),
+						&output_key(&prevouts[i]).unwrap(),
+					)
+					.is_ok()
+		})
+	};
+
+	for sighash_type in [TapSighashType::Default, TapSighashType::All] {
+		let (exported, master) = psbt_for_test(sighash_type);
+		let mut signed = exported.clone();
+
+		signed.sign(&master, &SECP256K1).unwrap();
+
+		let tx = finalize(&exported, signed).unwrap();
+		let prevouts =
+			exported.inputs.iter().map(|i| i.witness_utxo.clone().unwrap()).collect::<Vec<_>>();
+
+		assert!(exported.inputs.iter().all(|i| i.sighash_type == Some(sighash_type.into())));
+		// The sighash type byte is omitted if it's the default.
+		assert!(tx
+			.input
+			.iter()
+			.all(|i| i.witness[0].len() == 64 + (sighash_type != TapSighashType::Default) as usize));
+		assert!(verify(&tx, &prevouts, sighash_type));
+
+		// Another spent amount invalidates the signatures.
+		let mut tampered = prevouts.clone();
+
+		tampered[2].value = Amount::from_sat(1);
+
+		assert!(!verify(&tx, &tampered, sighash_type));
+
+		// So does another input.
+		let mut appended = tx.clone();
+		let mut tampered = prevouts.clone();
+
+		appended.input.push(TxIn { previous_output: OutPoint::null(), ..tx.input[0].clone() });
+		tampered.push(prevouts[0].clone());
+
+		assert!(!verify(&appended, &tampered, sighash_type));
+	}
+}
@@ -0,0 +1,379 @@
+//! Timelocked script path of the vault, to recover the funds with the cold keys if the vault key
+//! is lost.
+//!
+//! The leaf is `<delay> OP_CSV OP_DROP <k1> OP_CHECKSIG <k2> OP_CHECKSIGADD .. <t> OP_NUMEQUAL`,
+//! a vault UTXO can be spent by `t` of the cold keys `delay` blocks after its confirmation.
+
+// crates.io
+use bitcoin::{
+	blockdata::{locktime::absolute::LockTime, opcodes::all::*, script, transaction::Version},
+	key::XOnlyPublicKey,
+	taproot::{LeafVersion, TapLeafHash, TaprootBuilder},
+	Address, Amount, Network, ScriptBuf, Sequence, TapNodeHash, TapSighashType, Transaction, TxIn,
+	TxOut, Witness,
+};
+#[cfg(test)]
+use bitcoin::{
+	hashes::Hash,
+	key::Keypair,
+	secp256k1::Message,
+	sighash::{Prevouts, SighashCache},
+	taproot::Signature,
+};
+// self
+use super::{policy, signer::*, types::*, util, weight, SECP256K1};
+use crate::prelude::*;
+
+#[derive(Clone, Debug)]
+pub struct Recovery {
+	keys: Vec<XOnlyPublicKey>,
+	threshold: usize,
+	delay: u16,
+}
+impl Recovery {
+	pub fn new(keys: Vec<XOnlyPublicKey>, threshold: usize, delay: u16) -> Result<Self> {
+		let invalid = |reason| ChainError::InvalidRecovery { reason };
+
+		if threshold == 0 || threshold > keys.len() {
+			Err(invalid("invalid threshold"))?;
+		}
+		if delay == 0 {
+			Err(invalid("zero delay"))?;
+		}
+		if keys.iter().enumerate().any(|(i, k)| keys[..i].contains(k)) {
+			Err(invalid("duplicate keys"))?;
+		}
+
+		Ok(Self { keys, threshold, delay })
+	}
+
+	pub fn script(&self) -> ScriptBuf {
+		let b = script::Builder::new()
+			.push_int(self.delay as _)
+			.push_opcode(OP_CSV)
+			.push_opcode(OP_DROP);
+		let b = self.keys.iter().enumerate().fold(b, |b, (i, k)| {
+			b.push_x_only_key(k).push_opcode(if i == 0 { OP_CHECKSIG } else { OP_CHECKSIGADD })
+		});
+
+		b.push_int(self.threshold as _).push_opcode(OP_NUMEQUAL).into_script()
+	}
+
+	pub fn address(&self, internal_key: XOnlyPublicKey, network: Network) -> Address {
+		Address::p2tr(&SECP256K1, internal_key, Some(self.merkle_root()), network)
+	}
+
+	/// Root of the vault's tap tree, which has the recovery leaf only.
+	pub fn merkle_root(&self) -> TapNodeHash {
+		TapLeafHash::from_script(&self.script(), LeafVersion::TapScript).into()
+	}
+}
+
+/// Sweep of all the given vault UTXOs to the recipient through the recovery leaf.
+#[derive(Debug)]
+pub struct RecoveryTxBuilder<'a, S> {
+	pub network: Network,
+	pub fee_rate: Satoshi,
+	pub recovery: &'a Recovery,
+	// Internal key of the vault.
+	pub internal_key: XOnlyPublicKey,
+	pub utxos: &'a [Utxo],
+	pub recipient: &'a str,
+	// Signers of at least `threshold` of the cold keys, in any order.
+	pub signers: &'a [S],
+}
+impl<S> RecoveryTxBuilder<'_, S>
+where
+	S: Signer,
+{
+	pub async fn build(self) -> Result<Transaction> {
+		let Self { network, fee_rate, recovery, internal_key, utxos, recipient, signers } = self;
+		let invalid = |reason| ChainError::InvalidRecovery { reason };
+		let script = recovery.script();
+		let spend_info = TaprootBuilder::new()
+			.add_leaf(0, script.clone())
+			.ok()
+			.and_then(|b| b.finalize(&SECP256K1, internal_key).ok())
+			.ok_or(invalid("invalid tap tree"))?;
+		let control_block = spend_info
+			.control_block(&(script.clone(), LeafVersion::TapScript))
+			.ok_or(invalid("invalid tap tree"))?;
+		let vault_spk = ScriptBuf::new_p2tr_tweaked(spend_info.output_key());
+		// Signer of each cold key, the first `threshold` available ones.
+		let mut signers = recovery
+			.keys
+			.iter()
+			.map(|k| signers.iter().find(|s| s.public_key() == *k))
+			.collect::<Vec<_>>();
+
+		signers.iter_mut().filter(|s| s.is_some()).skip(recovery.threshold).for_each(|s| *s = None);
+
+		if signers.iter().flatten().count() < recovery.threshold {
+			Err(invalid("insufficient cold keys"))?;
+		}
+		if utxos.is_empty() {
+			Err(invalid("no vault UTXO"))?;
+		}
+
+		let witness = |sigs: &[Vec<u8>]| {
+			// The first key is checked first, so its signature is at the top of the stack.
+			let mut w = Witness::from_slice(&sigs.iter().rev().collect::<Vec<_>>());
+
+			w.push(script.as_bytes());
+			w.push(control_block.serialize());
+
+			w
+		};
+		let recipient_spk = util::addr_from_str(recipient, network)?.script_pubkey();
+		let total = utxos.iter().map(|u| u.value).sum::<Satoshi>();
+		let mut tx = Transaction {
+			version: Version::TWO,
+			lock_time: LockTime::ZERO,
+			input: utxos
+				.iter()
+				.map(|u| TxIn {
+					previous_output: u.outpoint,
+					sequence: Sequence::from_height(recovery.delay),
+					..Default::default()
+				})
+				.collect(),
+			output: vec![TxOut { script_pubkey: recipient_spk, value: Amount::ZERO }],
+		};
+
+		let spend = weight::Spend::Script {
+			leaf: &script,
+			control_block: &control_block,
+			signatures: &signers.iter().rev().map(Option::is_some).collect::<Vec<_>>(),
+			sighash_type: TapSighashType::Default,
+		};
+		let fee = weight::signed_vsize(&tx, &spend) * fee_rate;
+		let value = total.saturating_sub(fee);
+
+		if value < tx.output[0].script_pubkey.minimal_non_dust().to_sat() {
+			Err(ChainError::InsufficientFunds { required: fee as _, available: total as _ })?;
+		}
+
+		tx.output[0].value = Amount::from_sat(value);
+
+		policy::check(&weight::dummy_signed(&tx, &spend), fee)?;
+
+		let request = SignRequest {
+			tx,
+			prevouts: utxos
+				.iter()
+				.map(|u| TxOut {
+					script_pubkey: vault_spk.clone(),
+					value: Amount::from_sat(u.value),
+				})
+				.collect(),
+			sighash_type: TapSighashType::Default,
+			path: SpendPath::Script {
+				leaf_hash: TapLeafHash::from_script(&script, LeafVersion::TapScript),
+			},
+		};
+		// Signatures of each cold key, per input.
+		let mut sigs = Vec::with_capacity(signers.len());
+
+		for s in &signers {
+			match s {
+				Some(s) => {
+					let ss = s.sign(&request).await?;
+
+					request.verify(s.public_key(), &ss)?;
+					sigs.push(ss.iter().map(|s| s.as_ref().to_vec()).collect());
+				},
+				None => sigs.push(vec![Vec::new(); utxos.len()]),
+			}
+		}
+
+		let mut tx = request.tx;
+
+		for (i, input) in tx.input.iter_mut().enumerate() {
+			input.witness =
+				witness(&sigs.iter().map(|s: &Vec<_>| s[i].clone()).collect::<Vec<_>>());
+		}
+
+		Ok(tx)
+	}
+}
+#[cfg(test)]
+fn keypair_for_test(i: u8) -> Keypair {
+	Keypair::from_seckey_slice(&SECP256K1, &[i; 32]).unwrap()
+}
+#[test]
+fn recovery_should_work() {
+	let cold_keys = [1, 2, 3].map(keypair_for_test);
+	let recovery =
+		Recovery::new(cold_keys.iter().map(|k| k.x_only_public_key().0).collect(), 2, 144).unwrap();
+	let vault = super::TaprootKey::from_untweaked_keypair(
+		keypair_for_test(9),
+		Some(recovery.merkle_root()),
+		Network::Testnet,
+	);
+
+	// The vault address commits to the recovery leaf.
+	assert_eq!(vault.address, recovery.address(vault.public_key(), Network::Testnet).to_string());
+
+	let utxos = [1, 2].map(|i| Utxo {
+		outpoint: bitcoin::OutPoint { txid: bitcoin::Txid::from_byte_array([i; 32]), vout: 1 },
+		value: 10_000,
+		confirmed: true,
+	});
+	// Any order, the extra key isn't used.
+	let signers = [cold_keys[2], cold_keys[0], keypair_for_test(4)].map(LocalSigner);
+	let tx = RecoveryTxBuilder {
+		network: Network::Testnet,
+		fee_rate: 2,
+		recovery: &recovery,
+		internal_key: vault.public_key(),
+		utxos: &utxos,
+		recipient: super::fixture::RECIPIENT,
+		signers: &signers,
+	}
+	.build();
+	let tx = tokio::runtime::Runtime::new().unwrap().block_on(tx).unwrap();
+	let script = recovery.script();
+	let leaf_hash = TapLeafHash::from_script(&script, LeafVersion::TapScript);
+	let prevouts = utxos
+		.iter()
+		.map(|u| TxOut {
+			script_pubkey: vault.script_public_key.clone(),
+			value: Amount::from_sat(u.value),
+		})
+		.collect::<Vec<_>>();
+	let output_key = XOnlyPublicKey::from_slice(&vault.script_public_key.as_bytes()[2..]).unwrap();
+	let mut hasher = SighashCache::new(&tx);
+
+	assert_eq!(tx.input.len(), 2);
+	assert_eq!(tx.output.len(), 1);
+	assert_eq!(tx.output[0].value.to_sat(), 20_000 - tx.vsize() as Satoshi * 2);
+
+	let control_block =
+		bitcoin::taproot::ControlBlock::decode(&tx.input[0].witness.to_vec()[4]).unwrap();
+	let spend = weight::Spend::Script {
+		leaf: &script,
+		control_block: &control_block,
+		signatures: &[true, false, true],
+		sighash_type: TapSighashType::Default,
+	};
+
+	// The template weighs exactly as much as the signed script path spends.
+	assert_eq!(weight::template(2, &tx.output, &spend).weight(), tx.weight());
+
+	for (i, input) in tx.input.iter().enumerate() {
+		let witness = input.witness.to_vec();
+
+		assert_eq!(input.sequence, Sequence::from_height(144));
+		assert_eq!(witness.len(), 5);
+		assert_eq!(witness[3], script.to_bytes());
+		assert!(bitcoin::taproot::ControlBlock::decode(&witness[4])
+			.unwrap()
+			.verify_taproot_commitment(&SECP256K1, output_key, &script));
+
+		let msg = Message::from_digest(
+			hasher
+				.taproot_script_spend_signature_hash(
+					i,
+					&Prevouts::All(&prevouts),
+					leaf_hash,
+					TapSighashType::Default,
+				)
+				.unwrap()
+				.to_byte_array(),
+		);
+
+		// The stack is reversed, the second key didn't sign.
+		assert!(witness[1].is_empty());
+
+		for (sig, key) in [(&witness[2], &cold_keys[0]), (&witness[0], &cold_keys[2])] {
+			let sig = Signature::from_slice(sig).unwrap();
+
+			assert_eq!(sig.sighash_type, TapSighashType::Default);
+			SECP256K1.verify_schnorr(&sig.signature, &msg, &key.x_only_public_key().0).unwrap();
+		}
+	}
+}
+#[test]
+fn recovery_should_reject_invalid() {
+	let keys = [1, 2].map(|i| keypair_for_test(i).x_only_public_key().0);
+
+	for (keys, threshold, delay) in [
+		(keys.to_vec(), 0, 1),
+		(keys.to_vec(), 3, 1),
+		(keys.to_vec(), 2, 0),
+		(vec![keys[0]; 2], 1, 1),
+	] {
+		assert!(matches!(
+			Recovery::new(keys, threshold, delay),
+			Err(Error::Chain(ChainError::InvalidRecovery { .. }))
+		));
+	}
+
+	let recovery = Recovery::new(keys.to_vec(), 2, 1).unwrap();
+	let builder = |signers, utxos| RecoveryTxBuilder {
+		network: Network::Testnet,
+		fee_rate: 1,
+		recovery: &recovery,
+		internal_key: keypair_for_test(9).x_only_public_key().0,
+		utxos,
+		recipient: super::fixture::RECIPIENT,
+		signers,
+	};
+	let utxos = [Utxo::new(10_000)];
+	let one = [keypair_for_test(1)].map(LocalSigner);
+	let two = [keypair_for_test(1), keypair_for_test(2)].map(LocalSigner);
+	let runtime = tokio::runtime::Runtime::new().unwrap();
+
+	assert!(matches!(
+		runtime.block_on(builder(&one, &utxos).build()),
+		Err(Error::Chain(ChainError::InvalidRecovery { reason: "insufficient cold keys" }))
+	));
+	assert!(matches!(
+		runtime.block_on(builder(&two, &[]).build()),
+		Err(Error::Chain(ChainError::InvalidRecovery { reason: "no vault UTXO" }))
+	));
+	assert!(matches!(
+		runtime.block_on(builder(&two, &[Utxo::new(100)]).build()),
+		Err(Error::Chain(ChainError::InsufficientFunds { .. }))
+	));
+}
+#[test]
+fn frost_vault_should_commit_to_recovery() {
+	let shares = super::frost::keygen(2, 3).unwrap();
+	let public = shares[0].public.clone();
+	let recovery = Recovery::new(vec![keypair_for_test(1).x_only_public_key().0], 1, 6).unwrap();
+	let coordinator = super::frost::Coordinator::new(
+		public,
+		Some(recovery.merkle_root()),
+		shares
+			.into_iter()
+			.map(|s| {
+				super::frost::Transport::Local(std::sync::Arc::new(super::frost::Participant::new(
+					s,
+					Default::default(),
+				)))
+			})
+			.collect(),
+		Network::Testnet,
+	)
+	.unwrap();
+	let internal_key = coordinator.public_key();
+	let address = recovery.address(internal_key, Network::Testnet);
+
+	assert_eq!(coordinator.address, address.to_string());
+
+	// Key path signatures are still valid for the tweaked output key.
+	let request = SignRequest::for_key_test(
+		internal_key,
+		1,
+		SpendPath::Key { merkle_root: Some(recovery.merkle_root()) },
+	);
+	let sigs =
+		tokio::runtime::Runtime::new().unwrap().block_on(coordinator.sign(&request)).unwrap();
+	let output_key = XOnlyPublicKey::from_slice(&address.script_pubkey().as_bytes()[2..]).unwrap();
+	let sighash = request.sighashes().unwrap()[0];
+
+	SECP256K1
+		.verify_schnorr(&sigs[0], &Message::from_digest(sighash.to_byte_array()), &output_key)
+		.unwrap();
+}
@@ -0,0 +1,186 @@
+//! Signers of the taproot spends, holding the key in process or behind a remote signer daemon.
+//!
+//! Signers are given the whole spend instead of the bare sighashes, so that they can compute the
+//! sighashes themselves and check the spend against their policy before signing.
+
+pub mod keystore;
+pub use keystore::*;
+
+pub mod remote;
+pub use remote::*;
+
+// crates.io
+use bitcoin::{
+	hashes::Hash,
+	key::{Keypair, TapTweak, XOnlyPublicKey},
+	secp256k1::{schnorr, Message},
+	sighash::{Prevouts, SighashCache},
+	TapLeafHash, TapNodeHash, TapSighash, TapSighashType, Transaction, TxOut,
+};
+use serde::{Deserialize, Serialize};
+// self
+use super::{types::Satoshi, SECP256K1};
+use crate::prelude::*;
+
+/// Signer of the taproot spends.
+pub trait Signer {
+	/// Untweaked public key, which is the internal key of the key path.
+	fn public_key(&self) -> XOnlyPublicKey;
+
+	/// Sign every input of the spend, in order.
+	async fn sign(&self, request: &SignRequest) -> Result<Vec<schnorr::Signature>>;
+}
+
+/// Path every input of a spend goes through.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SpendPath {
+	/// Key path, signed with the key tweaked by the tap tree's root.
+	Key { merkle_root: Option<TapNodeHash> },
+	/// Script path of the leaf, signed with the untweaked key.
+	Script { leaf_hash: TapLeafHash },
+}
+
+/// Unsigned transaction to sign, along with the outputs it spends.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SignRequest {
+	pub tx: Transaction,
+	pub prevouts: Vec<TxOut>,
+	pub sighash_type: TapSighashType,
+	pub path: SpendPath,
+}
+impl SignRequest {
+	pub fn sighashes(&self) -> Result<Vec<TapSighash>> {
+		if self.tx.input.len() != self.prevouts.len() {
+			Err(ChainError::InvalidSignRequest { reason: "prevouts mismatch" })?;
+		}
+
+		let mut hasher = SighashCache::new(&self.tx);
+		let prevouts = Prevouts::All(&self.prevouts);
+
+		(0..self.prevouts.len())
+			.map(|i| {
+				let sighash = match &self.path {
+					SpendPath::Key { .. } =>
+						hasher.taproot_key_spend_signature_hash(i, &prevouts, self.sighash_type),
+					SpendPath::Script { leaf_hash } => hasher.taproot_script_spend_signature_hash(
+						i,
+						&prevouts,
+						*leaf_hash,
+						self.sighash_type,
+					),
+				};
+
+				Ok(sighash.map_err(BitcoinError::SigHashTapRoot)?)
+			})
+			.collect()
+	}
+
+	/// Verify the signatures of the signer with the public key.
+	pub fn verify(
+		&self,
+		public_key: XOnlyPublicKey,
+		signatures: &[schnorr::Signature],
+	) -> Result<()> {
+		let key = match &self.path {
+			SpendPath::Key { merkle_root } =>
+				public_key.tap_tweak(&SECP256K1, *merkle_root).0.to_inner(),
+			SpendPath::Script { .. } => public_key,
+		};
+		let sighashes = self.sighashes()?;
+
+		if sighashes.len() != signatures.len() {
+			Err(ChainError::InvalidSignature)?;
+		}
+
+		for (h, s) in sighashes.iter().zip(signatures) {
+			SECP256K1
+				.verify_schnorr(s, &Message::from_digest(h.to_byte_array()), &key)
+				.map_err(|_| ChainError::InvalidSignature)?;
+		}
+
+		Ok(())
+	}
+
+	#[cfg(test)]
+	pub fn for_test(inputs: u8, path: SpendPath) -> Self {
+		Self::for_key_test(super::fixture::keypair().x_only_public_key().0, inputs, path)
+	}
+
+	// Spend of the key's outputs, paying the change back to them.
+	#[cfg(test)]
+	pub fn for_key_test(public_key: XOnlyPublicKey, inputs: u8, path: SpendPath) -> Self {
+		// crates.io
+		use bitcoin::{transaction::Version, Amount, OutPoint, Txid};
+
+		let merkle_root = match &path {
+			SpendPath::Key { merkle_root } => *merkle_root,
+			SpendPath::Script { .. } => None,
+		};
+		let prevout = TxOut {
+			script_pubkey: bitcoin::ScriptBuf::new_p2tr(&SECP256K1, public_key, merkle_root),
+			value: Amount::from_sat(10_000),
+		};
+
+		Self {
+			tx: Transaction {
+				version: Version::TWO,
+				lock_time: bitcoin::absolute::LockTime::ZERO,
+				input: (0..inputs)
+					.map(|i| bitcoin::TxIn {
+						previous_output: OutPoint { txid: Txid::from_byte_array([i; 32]), vout: 0 },
+						..Default::default()
+					})
+					.collect(),
+				output: vec![TxOut { value: Amount::from_sat(5_000), ..prevout.clone() }],
+			},
+			prevouts: vec![prevout; inputs as _],
+			sighash_type: TapSighashType::Default,
+			path,
+		}
+	}
+
+	/// Fee of the spend, `None` if the outputs exceed the spent ones.
+	pub fn fee(&self) -> Option<Satoshi> {
+		let spent = self.prevouts.iter().map(|o| o.value.to_sat()).sum::<Satoshi>();
+		let paid = self.tx.output.iter().map(|o| o.value.to_sat()).sum::<Satoshi>();
+
+		spent.checked_sub(paid)
+	}
+}
+
+/// Key held in process.
+#[derive(Debug)]
+pub struct LocalSigner(pub Keypair);
+impl Signer for LocalSigner {
+	fn public_key(&self) -> XOnlyPublicKey {
+		self.0.x_only_public_key().0
+	}
+
+	async fn sign(&self, request: &SignRequest) -> Result<Vec<schnorr::Signature>> {
+		let keypair = match &request.path {
+			SpendPath::Key { merkle_root } => self.0.tap_tweak(&SECP256K1, *merkle_root).to_inner(),
+			SpendPath::Script { .. } => self.0,
+		};
+
+		Ok(request
+			.sighashes()?
+			.iter()
+			.map(|h| SECP256K1.sign_schnorr(&Message::from_digest(h.to_byte_array()), &keypair))
+			.collect())
+	}
+}
+
+/// Key held by an offline signer, the spends must be exported as PSBTs.
+#[derive(Debug)]
+pub struct WatchOnly(pub XOnlyPublicKey);
+impl Signer for WatchOnly {
+	fn public_key(&self) -> XOnlyPublicKey {
+		self.0
+	}
+
+	async fn sign(&self, _: &SignRequest) -> Result<Vec<schnorr::Signature>> {
+		Err(ChainError::WatchOnly)?
+	}
+}
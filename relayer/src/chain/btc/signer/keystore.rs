@@ -0,0 +1,250 @@
+//! Encrypted keystore of the vault key.
+//!
+//! The secret key is encrypted with AES-256-GCM under a key derived from the passphrase by scrypt.
+//! The public key is authenticated along with it, so a keystore can't be passed off as another.
+
+// std
+#[cfg(test)] use std::os::unix::fs::PermissionsExt;
+use std::{
+	fs::{self, OpenOptions},
+	io::Write,
+	os::unix::fs::OpenOptionsExt,
+	path::Path,
+};
+// crates.io
+use aes_gcm::{
+	aead::{Aead, Payload},
+	Aes256Gcm, KeyInit, Nonce,
+};
+use bitcoin::{
+	key::{Keypair, XOnlyPublicKey},
+	secp256k1::{rand, schnorr, SecretKey},
+};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+// self
+use super::*;
+
+const VERSION: u8 = 1;
+
+/// Cost parameters of scrypt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Kdf {
+	pub log_n: u8,
+	pub r: u32,
+	pub p: u32,
+}
+impl Kdf {
+	// The key is erased once the cipher is initialized.
+	fn derive(&self, passphrase: &str, salt: &[u8]) -> Result<Aes256Gcm> {
+		let params = scrypt::Params::new(self.log_n, self.r, self.p, 32)
+			.map_err(|_| ChainError::InvalidKeystore { reason: "invalid scrypt parameters" })?;
+		let mut key = Zeroizing::new([0; 32]);
+
+		scrypt::scrypt(passphrase.as_bytes(), salt, &params, key.as_mut_slice())
+			.map_err(|_| ChainError::InvalidKeystore { reason: "invalid scrypt parameters" })?;
+
+		Ok(Aes256Gcm::new_from_slice(key.as_slice())
+			.map_err(|_| ChainError::InvalidKeystore { reason: "invalid key length" })?)
+	}
+}
+impl Default for Kdf {
+	// 128 MiB of memory, about a second to unlock.
+	fn default() -> Self {
+		Self { log_n: 17, r: 8, p: 1 }
+	}
+}
+
+/// Vault key encrypted under a passphrase.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Keystore {
+	pub version: u8,
+	pub public_key: XOnlyPublicKey,
+	pub kdf: Kdf,
+	pub salt: String,
+	pub nonce: String,
+	pub ciphertext: String,
+}
+impl Keystore {
+	/// Encrypt a new random key.
+	pub fn generate(passphrase: &str, kdf: Kdf) -> Result<Self> {
+		Self::encrypt(&Keypair::new(&SECP256K1, &mut rand::thread_rng()), passphrase, kdf)
+	}
+
+	pub fn encrypt(keypair: &Keypair, passphrase: &str, kdf: Kdf) -> Result<Self> {
+		if passphrase.is_empty() {
+			Err(ChainError::InvalidKeystore { reason: "empty passphrase" })?;
+		}
+
+		let public_key = keypair.x_only_public_key().0;
+		let salt = rand::random::<[u8; 32]>();
+		let nonce = rand::random::<[u8; 12]>();
+		let ciphertext = kdf
+			.derive(passphrase, &salt)?
+			.encrypt(
+				&Nonce::from(nonce),
+				Payload {
+					msg: Zeroizing::new(keypair.secret_bytes()).as_slice(),
+					aad: &public_key.serialize(),
+				},
+			)
+			.map_err(|_| ChainError::InvalidKeystore { reason: "fail to encrypt" })?;
+
+		Ok(Self {
+			version: VERSION,
+			public_key,
+			kdf,
+			salt: array_bytes::bytes2hex("", salt),
+			nonce: array_bytes::bytes2hex("", nonce),
+			ciphertext: array_bytes::bytes2hex("", ciphertext),
+		})
+	}
+
+	pub fn decrypt(&self, passphrase: &str) -> Result<Keypair> {
+		if self.version != VERSION {
+			Err(ChainError::InvalidKeystore { reason: "unsupported version" })?;
+		}
+
+		let salt = array_bytes::hex2bytes(&self.salt).map_err(Error::ArrayBytes)?;
+		let nonce = array_bytes::hex2array::<_, 12>(&self.nonce).map_err(Error::ArrayBytes)?;
+		let ciphertext = array_bytes::hex2bytes(&self.ciphertext).map_err(Error::ArrayBytes)?;
+		let secret_key = Zeroizing::new(
+			self.kdf
+				.derive(passphrase, &salt)?
+				.decrypt(
+					&Nonce::from(nonce),
+					Payload { msg: &ciphertext, aad: &self.public_key.serialize() },
+				)
+				.map_err(|_| ChainError::InvalidKeystore {
+					reason: "wrong passphrase or corrupted keystore",
+				})?,
+		);
+		let keypair = Keypair::from_secret_key(&SECP256K1, &SecretKey::from_slice(&secret_key)?);
+
+		if keypair.x_only_public_key().0 != self.public_key {
+			Err(ChainError::InvalidKeystore { reason: "secret key mismatches the public key" })?;
+		}
+
+		Ok(keypair)
+	}
+
+	/// Re-encrypt the key under a new passphrase, with a new salt and nonce.
+	pub fn change_passphrase(
+		&self,
+		passphrase: &str,
+		new_passphrase: &str,
+		kdf: Kdf,
+	) -> Result<Self> {
+		Self::encrypt(&self.decrypt(passphrase)?, new_passphrase, kdf)
+	}
+
+	pub fn load(path: &Path) -> Result<Self> {
+		Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+	}
+
+	/// Save the keystore readable by the owner only, an existing file is never overwritten.
+	pub fn save(&self, path: &Path) -> Result<()> {
+		OpenOptions::new()
+			.write(true)
+			.create_new(true)
+			.mode(0o600)
+			.open(path)?
+			.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+
+		Ok(())
+	}
+}
+
+/// Key unlocked from a keystore, erased from the memory once dropped.
+#[derive(Debug)]
+pub struct KeystoreSigner(LocalSigner);
+impl KeystoreSigner {
+	pub fn unlock(keystore: &Keystore, passphrase: &str) -> Result<Self> {
+		Ok(Self(LocalSigner(keystore.decrypt(passphrase)?)))
+	}
+}
+impl Signer for KeystoreSigner {
+	fn public_key(&self) -> XOnlyPublicKey {
+		self.0.public_key()
+	}
+
+	async fn sign(&self, request: &SignRequest) -> Result<Vec<schnorr::Signature>> {
+		self.0.sign(request).await
+	}
+}
+impl Drop for KeystoreSigner {
+	fn drop(&mut self) {
+		self.0 .0.non_secure_erase();
+	}
+}
+
+#[cfg(test)]
+const KDF_FOR_TEST: Kdf = Kdf { log_n: 4, r: 8, p: 1 };
+
+#[test]
+fn keystore_should_work() {
+	let path = std::env::temp_dir().join(format!("keystore-{}.json", std::process::id()));
+	let _ = fs::remove_file(&path);
+	let keystore = Keystore::generate("passphrase", KDF_FOR_TEST).unwrap();
+
+	keystore.save(&path).unwrap();
+
+	// Never overwrite a keystore.
+	assert!(keystore.save(&path).is_err());
+	assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+	let loaded = Keystore::load(&path).unwrap();
+
+	fs::remove_file(&path).unwrap();
+
+	assert_eq!(loaded, keystore);
+
+	let signer = KeystoreSigner::unlock(&loaded, "passphrase").unwrap();
+	let request = SignRequest::for_test(2, SpendPath::Key { merkle_root: None });
+
+	assert_eq!(signer.public_key(), keystore.public_key);
+
+	let sigs = tokio::runtime::Runtime::new().unwrap().block_on(signer.sign(&request)).unwrap();
+
+	request.verify(keystore.public_key, &sigs).unwrap();
+
+	let changed = keystore.change_passphrase("passphrase", "new passphrase", KDF_FOR_TEST).unwrap();
+
+	assert_eq!(changed.public_key, keystore.public_key);
+	assert_ne!(changed.salt, keystore.salt);
+	assert!(changed.decrypt("passphrase").is_err());
+	assert_eq!(changed.decrypt("new passphrase").unwrap(), keystore.decrypt("passphrase").unwrap());
+}
+#[test]
+fn keystore_should_reject_invalid() {
+	let keypair = Keypair::from_seckey_slice(&SECP256K1, &[1; 32]).unwrap();
+	let keystore = Keystore::encrypt(&keypair, "passphrase", KDF_FOR_TEST).unwrap();
+	let reason = |r: Result<Keypair>| match r {
+		Err(Error::Chain(ChainError::InvalidKeystore { reason })) => reason,
+		r => panic!("unexpected {r:?}"),
+	};
+
+	assert_eq!(reason(keystore.decrypt("wrong")), "wrong passphrase or corrupted keystore");
+	assert!(Keystore::encrypt(&keypair, "", KDF_FOR_TEST).is_err());
+
+	// The public key is authenticated.
+	let other = Keypair::from_seckey_slice(&SECP256K1, &[2; 32]).unwrap();
+	let swapped = Keystore { public_key: other.x_only_public_key().0, ..keystore };
+
+	assert_eq!(reason(swapped.decrypt("passphrase")), "wrong passphrase or corrupted keystore");
+
+	let keystore = Keystore { public_key: keypair.x_only_public_key().0, ..swapped };
+	let mut ciphertext = array_bytes::hex2bytes_unchecked(&keystore.ciphertext);
+
+	ciphertext[0] ^= 1;
+
+	let tampered = Keystore { ciphertext: array_bytes::bytes2hex("", ciphertext), ..keystore };
+
+	assert_eq!(reason(tampered.decrypt("passphrase")), "wrong passphrase or corrupted keystore");
+	assert_eq!(
+		reason(Keystore { version: 2, ..tampered }.decrypt("passphrase")),
+		"unsupported version"
+	);
+}
@@ -0,0 +1,577 @@
+//! Remote signer daemon, holding the key out of the relayer's process.
+//!
+//! The relayer sends the [`SignRequest`] as JSON, either as a line over a Unix socket or as the
+//! body of an HTTP `POST`, and gets a [`Response`] back. The daemon checks the spend against its
+//! [`Policy`] before signing, and the relayer verifies the returned signatures.
+//!
+//! The Unix socket is guarded by its file permissions. Over HTTP, the relayer authenticates with
+//! the daemon's bearer token.
+
+// std
+use std::{path::PathBuf, sync::Arc, time::Duration};
+// crates.io
+use bitcoin::{
+	address::NetworkUnchecked, key::XOnlyPublicKey, secp256k1::schnorr, Address, ScriptBuf,
+};
+use reqwest::ClientBuilder;
+use serde::{Deserialize, Serialize};
+use tokio::{
+	io::{AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+	net::{TcpListener, UnixListener, UnixStream},
+	time,
+};
+// self
+use super::*;
+use crate::http::{Client, Response as _};
+
+/// Where the signer daemon listens.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Endpoint {
+	Unix(PathBuf),
+	/// URI to `POST` the requests to, e.g. `http://127.0.0.1:9736`.
+	Http(String),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Response {
+	Sign { signatures: Vec<schnorr::Signature> },
+	Error { message: String },
+}
+
+/// Connection to a signer daemon.
+#[derive(Debug)]
+pub struct RemoteSigner {
+	endpoint: Endpoint,
+	// Key the daemon is expected to sign with.
+	public_key: XOnlyPublicKey,
+	// Bearer token of the HTTP endpoint.
+	token: Option<String>,
+	http: Client,
+}
+impl RemoteSigner {
+	pub fn new(
+		endpoint: Endpoint,
+		public_key: XOnlyPublicKey,
+		token: Option<String>,
+	) -> Result<Self> {
+		Ok(Self {
+			endpoint,
+			public_key,
+			token,
+			http: Client(ClientBuilder::new().timeout(Duration::from_secs(30)).build()?),
+		})
+	}
+}
+impl Signer for RemoteSigner {
+	fn public_key(&self) -> XOnlyPublicKey {
+		self.public_key
+	}
+
+	async fn sign(&self, request: &SignRequest) -> Result<Vec<schnorr::Signature>> {
+		let body = serde_json::to_string(request)?;
+		let response = match &self.endpoint {
+			Endpoint::Unix(path) => {
+				let mut stream = BufReader::new(UnixStream::connect(path).await?);
+				let mut line = body;
+
+				line.push('\n');
+				stream.get_mut().write_all(line.as_bytes()).await?;
+				line.clear();
+				stream.read_line(&mut line).await?;
+
+				serde_json::from_str(&line)?
+			},
+			Endpoint::Http(uri) => {
+				let mut request = self.http.0.post(uri).body(body);
+
+				if let Some(token) = &self.token {
+					request = request.bearer_auth(token);
+				}
+
+				let response = request.send().await?;
+
+				if !response.status().is_success() {
+					Err(ChainError::RemoteSigner { message: response.status().to_string() })?;
+				}
+
+				response.bytes().await?.json()?
+			},
+		};
+
+		match response {
+			Response::Sign { signatures } => {
+				// Never trust the daemon, a wrong signature would get the transaction stuck.
+				request.verify(self.public_key, &signatures)?;
+
+				Ok(signatures)
+			},
+			Response::Error { message } => Err(ChainError::RemoteSigner { message })?,
+		}
+	}
+}
+
+/// Spends the signer daemon agrees to sign, everything is allowed if unset.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Policy {
+	/// Maximum fee of a spend in satoshis.
+	pub max_fee: Option<Satoshi>,
+	/// Maximum amount sent out of the signer's output script in satoshis, the change excluded.
+	pub max_amount: Option<Satoshi>,
+	/// Addresses allowed to receive the funds, besides the change.
+	pub recipients: Option<Vec<Address<NetworkUnchecked>>>,
+	/// Allow the script path spends, e.g. the recovery.
+	#[serde(default)]
+	pub allow_script_path: bool,
+}
+impl Policy {
+	/// Check the spend of the signer's outputs, `public_key` is the signer's untweaked key.
+	///
+	/// Only the outputs paying back to the key path's output script count as change, and every
+	/// spent output must be paid to it. Otherwise, a spend could add an input of its own and pass
+	/// the vault's funds off as the change to that input's script.
+	///
+	/// The output script of a script path spend depends on the whole tap tree, which the request
+	/// doesn't carry, so none of its outputs count as change.
+	pub fn check(&self, public_key: XOnlyPublicKey, request: &SignRequest) -> Result<()> {
+		let reject = |reason: String| ChainError::PolicyViolation { reason };
+
+		// Otherwise the signatures don't commit to every input and output, which could be changed
+		// after the checks.
+		if !matches!(request.sighash_type, TapSighashType::Default | TapSighashType::All) {
+			Err(reject(format!("sighash type {}", request.sighash_type)))?;
+		}
+
+		let change = match &request.path {
+			SpendPath::Key { merkle_root } =>
+				Some(ScriptBuf::new_p2tr(&SECP256K1, public_key, *merkle_root)),
+			SpendPath::Script { .. } if self.allow_script_path => None,
+			SpendPath::Script { .. } => Err(reject("script path spend".into()))?,
+		};
+
+		if let Some(change) = &change {
+			if let Some(p) = request.prevouts.iter().find(|p| &p.script_pubkey != change) {
+				Err(reject(format!("spent output of {} isn't the signer's", p.script_pubkey)))?;
+			}
+		}
+
+		let fee = request.fee().ok_or(reject("outputs exceed the spent outputs".into()))?;
+
+		if let Some(max) = self.max_fee.filter(|&m| fee > m) {
+			Err(reject(format!("fee {fee} exceeds {max}")))?;
+		}
+
+		let outgoing = request
+			.tx
+			.output
+			.iter()
+			.filter(|o| change.as_ref() != Some(&o.script_pubkey))
+			.collect::<Vec<_>>();
+		// The value of an `OP_RETURN` output is burnt, it's sent out as well.
+		let amount = outgoing.iter().map(|o| o.value.to_sat()).sum::<Satoshi>();
+
+		if let Some(max) = self.max_amount.filter(|&m| amount > m) {
+			Err(reject(format!("amount {amount} exceeds {max}")))?;
+		}
+		if let Some(recipients) = &self.recipients {
+			// The script pubkey doesn't depend on the network.
+			let allowed = recipients
+				.iter()
+				.map(|a| a.assume_checked_ref().script_pubkey())
+				.collect::<Vec<ScriptBuf>>();
+
+			if let Some(o) = outgoing
+				.iter()
+				.filter(|o| !o.script_pubkey.is_op_return())
+				.find(|o| !allowed.contains(&o.script_pubkey))
+			{
+				Err(reject(format!("recipient {} isn't allowed", o.script_pubkey)))?;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// Signer daemon, signing the spends allowed by the policy.
+#[derive(Debug)]
+pub struct Daemon<S> {
+	pub signer: S,
+	pub policy: Policy,
+	/// Bearer token the HTTP requests must carry.
+	pub token: Option<String>,
+}
+impl<S> Daemon<S>
+where
+	S: Signer,
+{
+	// Size of the HTTP request line and headers.
+	const MAX_HEADERS_SIZE: u64 = 16 << 10;
+	// Size of the largest sign request, far above the JSON of a standard transaction spending
+	// outputs of at most 400 kB, so that a client can't exhaust the memory.
+	const MAX_REQUEST_SIZE: u64 = 4 << 20;
+	// A request takes at most this long, the connections are served one by one.
+	const TIMEOUT: Duration = Duration::from_secs(10);
+
+	pub async fn handle(&self, request: &str) -> Response {
+		let r = async {
+			let request = serde_json::from_str::<SignRequest>(request)?;
+
+			self.policy.check(self.signer.public_key(), &request)?;
+
+			self.signer.sign(&request).await
+		};
+
+		match r.await {
+			Ok(signatures) => Response::Sign { signatures },
+			Err(e) => {
+				tracing::warn!("sign request rejected: {e}");
+
+				Response::Error { message: e.to_string() }
+			},
+		}
+	}
+
+	/// Answer the requests on the Unix socket until it fails.
+	pub async fn serve_unix(self: Arc<Self>, listener: UnixListener) -> Result<()> {
+		loop {
+			let (stream, _) = listener.accept().await?;
+			let r = time::timeout(Self::TIMEOUT, async {
+				let mut stream = BufReader::new(stream);
+				let mut line = String::new();
+
+				(&mut stream).take(Self::MAX_REQUEST_SIZE).read_line(&mut line).await?;
+
+				let response = self.handle(&line).await;
+
+				respond(stream.get_mut(), "", &response).await
+			});
+
+			// A broken connection shouldn't stop the signer.
+			if let Err(e) = r.await.unwrap_or_else(|e| Err(std::io::Error::from(e).into())) {
+				tracing::warn!("fail to respond to the relayer: {e}");
+			}
+		}
+	}
+
+	/// Answer the HTTP `POST` requests on the listener until it fails.
+	pub async fn serve_http(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+		loop {
+			let (stream, _) = listener.accept().await?;
+			let r = time::timeout(Self::TIMEOUT, async {
+				let mut stream = BufReader::new(stream);
+				let mut headers = (&mut stream).take(Self::MAX_HEADERS_SIZE);
+				let mut line = String::new();
+				let mut content_length = 0_u64;
+				let mut authorization = None;
+
+				// Only the body matters, whatever the method and the path.
+				loop {
+					line.clear();
+
+					if headers.read_line(&mut line).await? == 0 {
+						Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof))?;
+					}
+
+					let header = line.trim_end();
+
+					if header.is_empty() {
+						break;
+					}
+					if let Some((k, v)) = header.split_once(':') {
+						if k.eq_ignore_ascii_case("content-length") {
+							content_length = v.trim().parse()?;
+						} else if k.eq_ignore_ascii_case("authorization") {
+							authorization = v.trim().strip_prefix("Bearer ").map(str::to_owned);
+						}
+					}
+				}
+
+				if let Some(token) = &self.token {
+					if !authorization
+						.is_some_and(|a| constant_time_eq(a.as_bytes(), token.as_bytes()))
+					{
+						tracing::warn!("unauthorized sign request");
+
+						let response = Response::Error { message: "unauthorized".into() };
+
+						return respond(stream.get_mut(), "401 Unauthorized", &response).await;
+					}
+				}
+				// Rejected before anything is allocated.
+				if content_length > Self::MAX_REQUEST_SIZE {
+					let response = Response::Error {
+						message: format!("request exceeds {} bytes", Self::MAX_REQUEST_SIZE),
+					};
+
+					return respond(stream.get_mut(), "413 Payload Too Large", &response).await;
+				}
+
+				let mut body = vec![0; content_length as _];
+
+				stream.read_exact(&mut body).await?;
+
+				let response = self.handle(&String::from_utf8_lossy(&body)).await;
+
+				respond(stream.get_mut(), "200 OK", &response).await
+			});
+
+			if let Err(e) = r.await.unwrap_or_else(|e| Err(std::io::Error::from(e).into())) {
+				tracing::warn!("fail to respond to the relayer: {e}");
+			}
+		}
+	}
+}
+
+// Respond with the HTTP status, or as a line over the Unix socket if it's empty.
+async fn respond<W>(stream: &mut W, status: &str, response: &Response) -> Result<()>
+where
+	W: Unpin + AsyncWrite,
+{
+	let body = serde_json::to_string(response)?;
+	let message = if status.is_empty() {
+		format!("{body}\n")
+	} else {
+		format!(
+			"HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+			Connection: close\r\n\r\n{body}",
+			body.len()
+		)
+	};
+
+	stream.write_all(message.as_bytes()).await?;
+
+	Ok(())
+}
+
+// Compare the secrets without leaking the length of their common prefix through the timing.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+	a.len() == b.len() && a.iter().zip(b).fold(0, |d, (x, y)| d | (x ^ y)) == 0
+}
+#[test]
+fn remote_signer_should_work() {
+	// crates.io
+	use bitcoin::Network;
+	// self
+	use crate::chain::btc::{
+		fixture::{self, utxo, RECIPIENT},
+		payout::{Payout, PayoutTxBuilder},
+		TaprootKey,
+	};
+
+	let runtime = tokio::runtime::Runtime::new().unwrap();
+	let keypair = fixture::keypair();
+	let unix = std::env::temp_dir().join(format!("remote-signer-{}.sock", std::process::id()));
+	let _ = std::fs::remove_file(&unix);
+	let (unix_listener, http_listener) = runtime.block_on(async {
+		(UnixListener::bind(&unix).unwrap(), TcpListener::bind("127.0.0.1:0").await.unwrap())
+	});
+	let http = format!("http://{}", http_listener.local_addr().unwrap());
+	let daemon = Arc::new(Daemon {
+		signer: LocalSigner(keypair),
+		policy: Policy {
+			max_fee: Some(1_000),
+			max_amount: Some(30_000),
+			recipients: Some(vec![RECIPIENT.parse().unwrap()]),
+			allow_script_path: false,
+		},
+		token: Some("token".into()),
+	});
+	let http_addr = http_listener.local_addr().unwrap();
+
+	runtime.spawn(daemon.clone().serve_unix(unix_listener));
+	runtime.spawn(daemon.serve_http(http_listener));
+
+	let utxos = [1, 2].map(|i| utxo(i, 20_000));
+	let build = |vault: &TaprootKey<RemoteSigner>, amount| {
+		runtime.block_on(
+			PayoutTxBuilder {
+				network: Network::Testnet,
+				fee_rate: 1,
+				sender: vault,
+				utxos: &utxos,
+				coin_selection: Default::default(),
+				payouts: &[Payout { recipient: RECIPIENT, amount }],
+			}
+			.build(),
+		)
+	};
+
+	for endpoint in [Endpoint::Unix(unix.clone()), Endpoint::Http(http.clone())] {
+		let vault = TaprootKey::new(
+			RemoteSigner::new(
+				endpoint.clone(),
+				keypair.x_only_public_key().0,
+				Some("token".into()),
+			)
+			.unwrap(),
+			None,
+			Network::Testnet,
+		);
+
+		assert!(build(&vault, 25_000).is_ok());
+		assert!(matches!(
+			build(&vault, 35_000),
+			Err(Error::Chain(ChainError::RemoteSigner { message }))
+				if message.contains("amount 35000 exceeds 30000")
+		));
+
+		// The daemon holds another key, the vault's outputs aren't its.
+		let vault = TaprootKey::new(
+			RemoteSigner::new(
+				endpoint,
+				Keypair::from_seckey_slice(&SECP256K1, &[2; 32]).unwrap().x_only_public_key().0,
+				Some("token".into()),
+			)
+			.unwrap(),
+			None,
+			Network::Testnet,
+		);
+
+		assert!(matches!(
+			build(&vault, 25_000),
+			Err(Error::Chain(ChainError::RemoteSigner { message }))
+				if message.ends_with("isn't the signer's")
+		));
+	}
+
+	// The HTTP requests must carry the token.
+	for token in [None, Some("another token".into())] {
+		let vault = TaprootKey::new(
+			RemoteSigner::new(Endpoint::Http(http.clone()), keypair.x_only_public_key().0, token)
+				.unwrap(),
+			None,
+			Network::Testnet,
+		);
+
+		assert!(matches!(
+			build(&vault, 25_000),
+			Err(Error::Chain(ChainError::RemoteSigner { message })) if message.contains("401")
+		));
+	}
+
+	// An oversized request is refused before its body is read.
+	let response = runtime.block_on(async {
+		let mut stream = tokio::net::TcpStream::connect(http_addr).await.unwrap();
+		let mut response = String::new();
+
+		stream
+			.write_all(
+				b"POST / HTTP/1.1\r\nAuthorization: Bearer token\r\nContent-Length: 1099511627776\r\n\r\n",
+			)
+			.await
+			.unwrap();
+		stream.read_to_string(&mut response).await.unwrap();
+
+		response
+	});
+
+	assert!(response.starts_with("HTTP/1.1 413"));
+
+	let _ = std::fs::remove_file(&unix);
+}
+#[test]
+fn policy_should_work() {
+	let public_key = super::super::fixture::keypair().x_only_public_key().0;
+	let mut request = SignRequest::for_test(2, SpendPath::Key { merkle_root: None });
+	let policy = Policy::default();
+	let reason = |policy: &Policy, request: &SignRequest| match policy.check(public_key, request) {
+		Err(Error::Chain(ChainError::PolicyViolation { reason })) => reason,
+		r => panic!("{r:?}"),
+	};
+
+	// Only the change output.
+	assert!(policy.check(public_key, &request).is_ok());
+
+	request.sighash_type = TapSighashType::All;
+
+	assert!(policy.check(public_key, &request).is_ok());
+
+	for (sighash_type, reason_) in [
+		(TapSighashType::Single, "sighash type SIGHASH_SINGLE"),
+		(TapSighashType::None, "sighash type SIGHASH_NONE"),
+		(TapSighashType::AllPlusAnyoneCanPay, "sighash type SIGHASH_ALL|SIGHASH_ANYONECANPAY"),
+	] {
+		request.sighash_type = sighash_type;
+
+		assert_eq!(reason(&policy, &request), reason_);
+	}
+
+	request.sighash_type = TapSighashType::Default;
+	assert_eq!(
+		reason(&Policy { max_fee: Some(14_999), ..Default::default() }, &request),
+		"fee 15000 exceeds 14999"
+	);
+
+	// Another key's change isn't the signer's.
+	assert!(reason(&policy, &{
+		let mut r = request.clone();
+
+		r.prevouts[1].script_pubkey = ScriptBuf::new_p2wsh(&bitcoin::hashes::Hash::all_zeros());
+
+		r
+	})
+	.ends_with("isn't the signer's"));
+
+	// The value of an `OP_RETURN` output is sent out, but it needs no allowed recipient.
+	request.tx.output[0].script_pubkey = ScriptBuf::new_op_return([0; 4]);
+
+	assert_eq!(
+		reason(&Policy { max_amount: Some(4_999), ..Default::default() }, &request),
+		"amount 5000 exceeds 4999"
+	);
+	assert!(Policy { recipients: Some(Vec::new()), ..Default::default() }
+		.check(public_key, &request)
+		.is_ok());
+
+	request.tx.output[0].script_pubkey = ScriptBuf::new_p2wsh(&bitcoin::hashes::Hash::all_zeros());
+
+	assert_eq!(
+		reason(&Policy { max_amount: Some(4_999), ..Default::default() }, &request),
+		"amount 5000 exceeds 4999"
+	);
+	assert!(reason(&Policy { recipients: Some(Vec::new()), ..Default::default() }, &request)
+		.ends_with("isn't allowed"));
+
+	request.tx.output[0].value = bitcoin::Amount::from_sat(20_001);
+
+	assert_eq!(reason(&policy, &request), "outputs exceed the spent outputs");
+
+	request.path = SpendPath::Script { leaf_hash: bitcoin::hashes::Hash::all_zeros() };
+
+	assert_eq!(reason(&policy, &request), "script path spend");
+	assert!(Policy { allow_script_path: true, ..Default::default() }
+		.check(public_key, &request)
+		.is_err());
+}
+#[test]
+fn policy_should_reject_foreign_inputs() {
+	// crates.io
+	use bitcoin::{hashes::Hash, Amount, OutPoint, TxIn, Txid};
+
+	let public_key = super::super::fixture::keypair().x_only_public_key().0;
+	let policy = Policy {
+		max_amount: Some(1_000),
+		recipients: Some(vec![super::super::fixture::RECIPIENT.parse().unwrap()]),
+		..Default::default()
+	};
+	let mut request = SignRequest::for_test(1, SpendPath::Key { merkle_root: None });
+	let attacker = ScriptBuf::new_p2wsh(&bitcoin::hashes::Hash::all_zeros());
+
+	// An input of the attacker's own, and the whole vault UTXO paid to its script as "change".
+	request.tx.input.push(TxIn {
+		previous_output: OutPoint { txid: Txid::from_byte_array([9; 32]), vout: 0 },
+		..Default::default()
+	});
+	request
+		.prevouts
+		.push(TxOut { script_pubkey: attacker.clone(), value: Amount::from_sat(1_000) });
+	request.tx.output = vec![TxOut { script_pubkey: attacker, value: Amount::from_sat(10_500) }];
+
+	assert!(matches!(
+		policy.check(public_key, &request),
+		Err(Error::Chain(ChainError::PolicyViolation { reason })) if reason.ends_with("isn't the signer's")
+	));
+}
@@ -0,0 +1,167 @@
+//! Simplified payment verification of the deposits.
+
+// crates.io
+use bitcoin::{
+	block::Header,
+	consensus,
+	hashes::{sha256d, Hash},
+	BlockHash, TxMerkleNode, Txid,
+};
+// self
+use crate::prelude::*;
+
+/// Proof of a transaction's inclusion in a block.
+///
+/// Serialized as `header (80 bytes) || index (u32 LE) || merkle branch (32 bytes each)`, all the
+/// hashes are in the internal byte order, so the X chain contract can verify it as is.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpvProof {
+	pub header: Header,
+	// Position of the transaction in the block.
+	pub index: u32,
+	// Sibling hashes from the transaction up to the merkle root.
+	pub merkle: Vec<TxMerkleNode>,
+}
+impl SpvProof {
+	const HEADER_SIZE: usize = 80;
+
+	/// Build the proof from the transaction ids of the whole block.
+	pub fn from_txids(header: Header, txids: &[Txid], txid: &Txid) -> Result<Self> {
+		let mut index =
+			txids.iter().position(|t| t == txid).ok_or_else(|| ChainError::InvalidSpvProof {
+				txid: txid.to_string(),
+				reason: "transaction not in block",
+			})?;
+		let mut level =
+			txids.iter().map(|t| TxMerkleNode::from_raw_hash(t.to_raw_hash())).collect::<Vec<_>>();
+		let mut merkle = Vec::new();
+		let proof_index = index as _;
+
+		while level.len() > 1 {
+			// The last node is paired with itself on an odd level.
+			merkle.push(*level.get(index ^ 1).unwrap_or(&level[index]));
+			level = level.chunks(2).map(|p| hash_pair(&p[0], p.get(1).unwrap_or(&p[0]))).collect();
+			index >>= 1;
+		}
+
+		Ok(Self { header, index: proof_index, merkle })
+	}
+
+	/// Verify the proof against the block, which must be checked to be part of the best chain by
+	/// the caller.
+	pub fn verify(&self, txid: &Txid, block_hash: &BlockHash) -> Result<()> {
+		let invalid = |reason| ChainError::InvalidSpvProof { txid: txid.to_string(), reason };
+
+		if self.header.block_hash() != *block_hash {
+			Err(invalid("header mismatch"))?;
+		}
+		if self.header.validate_pow(self.header.target()).is_err() {
+			Err(invalid("insufficient proof of work"))?;
+		}
+
+		let mut index = self.index;
+		let root =
+			self.merkle.iter().fold(TxMerkleNode::from_raw_hash(txid.to_raw_hash()), |h, s| {
+				let h = if index & 1 == 0 { hash_pair(&h, s) } else { hash_pair(s, &h) };
+
+				index >>= 1;
+
+				h
+			});
+
+		if index != 0 || root != self.header.merkle_root {
+			Err(invalid("merkle root mismatch"))?;
+		}
+
+		Ok(())
+	}
+
+	pub fn encode(&self) -> Vec<u8> {
+		let mut v = consensus::serialize(&self.header);
+
+		debug_assert_eq!(v.len(), Self::HEADER_SIZE);
+
+		v.extend_from_slice(&self.index.to_le_bytes());
+		self.merkle.iter().for_each(|h| v.extend_from_slice(h.as_byte_array()));
+
+		v
+	}
+}
+
+fn hash_pair(l: &TxMerkleNode, r: &TxMerkleNode) -> TxMerkleNode {
+	let mut v = [0; 64];
+
+	v[..32].copy_from_slice(l.as_byte_array());
+	v[32..].copy_from_slice(r.as_byte_array());
+
+	TxMerkleNode::from_raw_hash(sha256d::Hash::hash(&v))
+}
+#[test]
+fn spv_proof_should_work() {
+	// Block 100,000 of the mainnet.
+	let header = Header {
+		version: bitcoin::block::Version::ONE,
+		prev_blockhash: "000000000002d01c1fccc21636b607dfd930d31d01c3a62104612a1719011250"
+			.parse()
+			.unwrap(),
+		merkle_root: "f3e94742aca4b5ef85488dc37c06c3282295ffec960994b2c0d5ac2a25a95766"
+			.parse()
+			.unwrap(),
+		time: 1_293_623_863,
+		bits: bitcoin::CompactTarget::from_consensus(0x1b04864c),
+		nonce: 274_148_111,
+	};
+	let block_hash = "000000000003ba27aa200b1cecaad478d2b00432346c3f1f3986da1afd33e506"
+		.parse::<BlockHash>()
+		.unwrap();
+	let txids = [
+		"8c14f0db3df150123e6f3dbbf30f8b955a8249b62ac1d1ff16284aefa3d06d87",
+		"fff2525b8931402dd09222c50775608f75787bd2b87e56995a7bdd30f79702c4",
+		"6359f0868171b1d194cbee1af2f16ea598ae8fad666d9b012c8ed2b79a236ec4",
+		"e9a66845e05d5abc0ad04ec80f774a7e585c6e8db975962d069a522137b80c1d",
+	]
+	.map(|t| t.parse::<Txid>().unwrap());
+
+	assert_eq!(header.block_hash(), block_hash);
+
+	for txid in &txids {
+		let proof = SpvProof::from_txids(header, &txids, txid).unwrap();
+
+		assert_eq!(proof.merkle.len(), 2);
+		assert!(proof.verify(txid, &block_hash).is_ok());
+		assert_eq!(proof.encode().len(), 80 + 4 + 2 * 32);
+	}
+
+	let mut proof = SpvProof::from_txids(header, &txids, &txids[2]).unwrap();
+
+	assert!(proof.verify(&txids[1], &block_hash).is_err());
+	assert!(proof.verify(&txids[2], &header.prev_blockhash).is_err());
+
+	proof.index = 3;
+
+	assert!(matches!(
+		proof.verify(&txids[2], &block_hash),
+		Err(Error::Chain(ChainError::InvalidSpvProof { reason: "merkle root mismatch", .. }))
+	));
+	assert!(SpvProof::from_txids(header, &txids[..3], &txids[3]).is_err());
+
+	// Odd number of transactions, mined with the regtest difficulty.
+	let txids = &txids[..3];
+	let mut header = header;
+
+	header.merkle_root = TxMerkleNode::from_raw_hash(
+		bitcoin::merkle_tree::calculate_root(txids.iter().map(|t| t.to_raw_hash())).unwrap(),
+	);
+	header.bits = bitcoin::CompactTarget::from_consensus(0x207fffff);
+
+	while header.validate_pow(header.target()).is_err() {
+		header.nonce += 1;
+	}
+
+	for txid in txids {
+		assert!(SpvProof::from_txids(header, txids, txid)
+			.unwrap()
+			.verify(txid, &header.block_hash())
+			.is_ok());
+	}
+}
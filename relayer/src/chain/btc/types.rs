@@ -3,6 +3,7 @@
 // crates.io
 use bitcoin::OutPoint;
 #[cfg(test)] use bitcoin::{hashes::Hash, Txid};
+use serde::{Deserialize, Serialize};
 // self
 use crate::prelude::*;
 
@@ -18,16 +19,79 @@ fn max_btc_in_u64_should_work() {
 pub type BlockNumber = u32;
 pub type Index = u32;
 
+/// Confirmed transaction, normalized from the chain data sources.
+#[derive(Clone, Debug)]
+pub struct Tx {
+	pub txid: String,
+	pub vin: Vec<Vin>,
+	pub vout: Vec<Vout>,
+	pub block_height: BlockNumber,
+	pub block_hash: String,
+}
+#[derive(Clone, Debug)]
+pub struct Vin {
+	// Address of the spent output, `None` for the coinbase and the non-address scripts.
+	pub prevout_address: Option<String>,
+}
+#[derive(Clone, Debug)]
+pub struct Vout {
+	pub scriptpubkey_asm: String,
+	pub scriptpubkey_type: ScriptType,
+	pub scriptpubkey_address: Option<String>,
+	pub value: Satoshi,
+}
+/// Output script type, named after the Mempool API ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptType {
+	P2pkh,
+	P2sh,
+	V0P2wpkh,
+	V0P2wsh,
+	V1P2tr,
+	OpReturn,
+	// E.g. `p2pk`, `multisig`, and `anchor`.
+	#[serde(other)]
+	Unknown,
+}
+impl From<super::api::mempool::Tx> for Tx {
+	fn from(value: super::api::mempool::Tx) -> Self {
+		Self {
+			txid: value.txid,
+			vin: value
+				.vin
+				.into_iter()
+				.map(|v| Vin { prevout_address: v.prevout.and_then(|p| p.scriptpubkey_address) })
+				.collect(),
+			vout: value
+				.vout
+				.into_iter()
+				.map(|v| Vout {
+					scriptpubkey_asm: v.scriptpubkey_asm,
+					scriptpubkey_type: v.scriptpubkey_type,
+					scriptpubkey_address: v.scriptpubkey_address,
+					value: v.value,
+				})
+				.collect(),
+			block_height: value.status.block_height,
+			block_hash: value.status.block_hash,
+		}
+	}
+}
+
 #[cfg_attr(test, derive(PartialEq))]
 #[cfg_attr(not(test), derive(Debug))]
+#[derive(Clone)]
 pub struct Utxo {
 	pub outpoint: OutPoint,
 	pub value: Satoshi,
+	// Whether its transaction has been included in a block.
+	pub confirmed: bool,
 }
 #[cfg(test)]
 impl Utxo {
 	pub(crate) fn new(value: Satoshi) -> Self {
-		Self { outpoint: OutPoint { txid: Txid::all_zeros(), vout: 0 }, value }
+		Self { outpoint: OutPoint { txid: Txid::all_zeros(), vout: 0 }, value, confirmed: true }
 	}
 }
 #[cfg(test)]
@@ -46,6 +110,39 @@ impl TryFrom<super::api::mempool::Utxo> for Utxo {
 				vout: value.vout,
 			},
 			value: value.value,
+			confirmed: value.status.confirmed,
 		})
 	}
 }
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Fees {
+	pub fastest_fee: Satoshi,
+	pub half_hour_fee: Satoshi,
+	pub hour_fee: Satoshi,
+	pub economy_fee: Satoshi,
+	pub minimum_fee: Satoshi,
+}
+impl Fees {
+	pub fn of(&self, strategy: FeeType) -> Satoshi {
+		match strategy {
+			FeeType::Fastest => self.fastest_fee,
+			FeeType::HalfHour => self.half_hour_fee,
+			FeeType::Hour => self.hour_fee,
+			FeeType::Economy => self.economy_fee,
+			FeeType::Minimum => self.minimum_fee,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FeeType {
+	#[default]
+	Fastest,
+	HalfHour,
+	Hour,
+	Economy,
+	Minimum,
+}
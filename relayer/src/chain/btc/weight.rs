@@ -0,0 +1,187 @@
+//! Weight of the vault spends, measured on the transactions themselves.
+//!
+//! A schnorr signature is 64 bytes, plus the sighash type byte unless it's the default, so a
+//! template whose witnesses hold dummy signatures of those sizes weighs exactly as much as the
+//! signed transaction. Outputs are counted with their real scripts, whatever their types and
+//! lengths.
+
+// crates.io
+use bitcoin::{
+	absolute::LockTime, taproot::ControlBlock, transaction::Version, Script, TapSighashType,
+	Transaction, TxIn, TxOut, Witness,
+};
+// self
+use super::types::*;
+
+/// How an input is spent, as far as the size of its witness is concerned.
+#[derive(Clone, Debug)]
+pub enum Spend<'a> {
+	/// Key path of a taproot output, signed with the sighash type.
+	Key(TapSighashType),
+	/// Script path of a taproot output through the leaf.
+	///
+	/// `signatures` tells which of the leaf's keys sign, in the order of the stack, the others
+	/// are satisfied with empty signatures.
+	Script {
+		leaf: &'a Script,
+		control_block: &'a ControlBlock,
+		signatures: &'a [bool],
+		sighash_type: TapSighashType,
+	},
+}
+impl Spend<'_> {
+	/// Witness of the spend with zeroed signatures of the right sizes.
+	pub fn dummy_witness(&self) -> Witness {
+		match self {
+			Self::Key(sighash_type) => Witness::from_slice(&[dummy_signature(*sighash_type)]),
+			Self::Script { leaf, control_block, signatures, sighash_type } => {
+				let mut w = Witness::from_slice(
+					&signatures
+						.iter()
+						.map(|s| if *s { dummy_signature(*sighash_type) } else { Vec::new() })
+						.collect::<Vec<_>>(),
+				);
+
+				w.push(leaf.as_bytes());
+				w.push(control_block.serialize());
+
+				w
+			},
+		}
+	}
+
+	/// Virtual size the input adds to a segwit transaction, rounded up.
+	pub fn input_vsize(&self) -> Satoshi {
+		TxIn { witness: self.dummy_witness(), ..Default::default() }
+			.segwit_weight()
+			.to_vbytes_ceil()
+	}
+}
+
+fn dummy_signature(sighash_type: TapSighashType) -> Vec<u8> {
+	vec![0; 64 + (sighash_type != TapSighashType::Default) as usize]
+}
+
+/// Template of a transaction spending `input_count` inputs to the outputs, whose witnesses are
+/// dummy-signed.
+pub fn template<'a, I>(input_count: usize, output: I, spend: &Spend) -> Transaction
+where
+	I: IntoIterator<Item = &'a TxOut>,
+{
+	let witness = spend.dummy_witness();
+
+	Transaction {
+		version: Version::TWO,
+		lock_time: LockTime::ZERO,
+		input: (0..input_count)
+			.map(|_| TxIn { witness: witness.clone(), ..Default::default() })
+			.collect(),
+		output: output.into_iter().cloned().collect(),
+	}
+}
+
+/// Virtual size of a transaction spending `input_count` inputs to the outputs once signed.
+pub fn vsize<'a, I>(input_count: usize, output: I, spend: &Spend) -> Satoshi
+where
+	I: IntoIterator<Item = &'a TxOut>,
+{
+	template(input_count, output, spend).vsize() as _
+}
+
+/// Copy of the transaction whose witnesses are dummy-signed.
+pub fn dummy_signed(tx: &Transaction, spend: &Spend) -> Transaction {
+	let witness = spend.dummy_witness();
+	let mut tx = tx.to_owned();
+
+	tx.input.iter_mut().for_each(|i| i.witness = witness.clone());
+
+	tx
+}
+
+/// Virtual size of the transaction once every input is signed.
+pub fn signed_vsize(tx: &Transaction, spend: &Spend) -> Satoshi {
+	dummy_signed(tx, spend).vsize() as _
+}
+
+/// Virtual size of the transaction without its inputs, including the segwit marker and flag and
+/// a one byte input count.
+pub fn base_vsize<'a, I>(output: I) -> Satoshi
+where
+	I: IntoIterator<Item = &'a TxOut>,
+{
+	// A transaction without inputs is serialized with the segwit marker and flag.
+	template(0, output, &Spend::Key(TapSighashType::Default)).vsize() as _
+}
+#[test]
+fn weight_should_match_signed_key_spends() {
+	// crates.io
+	use bitcoin::{
+		hashes::Hash, key::CompressedPublicKey, opcodes::all::OP_RETURN, script::PushBytesBuf,
+		Address, Amount, Network, OutPoint, PublicKey, ScriptBuf, Txid,
+	};
+	// self
+	use super::{fixture, payout, Signer};
+
+	let rt = tokio::runtime::Runtime::new().unwrap();
+	let network = Network::Testnet;
+	let vault = fixture::vault();
+	let pk = CompressedPublicKey(vault.public_key().public_key(bitcoin::key::Parity::Even));
+	let script = ScriptBuf::from_bytes(vec![0x51]);
+	let op_return = |len| {
+		let mut data = PushBytesBuf::new();
+
+		data.extend_from_slice(&vec![1; len]).unwrap();
+
+		Script::builder().push_opcode(OP_RETURN).push_slice(data).into_script()
+	};
+	let output = [
+		Address::p2pkh(PublicKey::from(pk), network).script_pubkey(),
+		Address::p2sh(&script, network).unwrap().script_pubkey(),
+		Address::p2wpkh(&pk, network).script_pubkey(),
+		Address::p2wsh(&script, network).script_pubkey(),
+		vault.script_public_key.clone(),
+		op_return(0),
+		op_return(80),
+		// Pushed with OP_PUSHDATA2.
+		op_return(300),
+	]
+	.map(|script_pubkey| TxOut { script_pubkey, value: Amount::from_sat(1_000) });
+
+	// The input count takes three bytes from 253 inputs.
+	for input_count in [1, 3, 253] {
+		for sighash_type in [TapSighashType::Default, TapSighashType::All] {
+			let spend = Spend::Key(sighash_type);
+			let tx = Transaction {
+				input: (0..input_count)
+					.map(|i| TxIn {
+						previous_output: OutPoint {
+							txid: Txid::from_byte_array([1; 32]),
+							vout: i as _,
+						},
+						..Default::default()
+					})
+					.collect(),
+				..template(0, &output, &spend)
+			};
+			let prevouts = vec![
+				TxOut {
+					script_pubkey: vault.script_public_key.clone(),
+					value: Amount::from_sat(10_000),
+				};
+				input_count
+			];
+			let unsigned_vsize = signed_vsize(&tx, &spend);
+			let signed =
+				rt.block_on(payout::sign_key_spends(&vault, tx, prevouts, sighash_type)).unwrap();
+
+			assert_eq!(template(input_count, &output, &spend).weight(), signed.weight());
+			assert_eq!(vsize(input_count, &output, &spend), signed.vsize() as Satoshi);
+			assert_eq!(unsigned_vsize, signed.vsize() as Satoshi);
+			// The parts never add up to less than the whole.
+			assert!(
+				base_vsize(&output) + spend.input_vsize() * input_count as Satoshi + 2
+					>= signed.vsize() as Satoshi
+			);
+		}
+	}
+}
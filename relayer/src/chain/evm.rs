@@ -0,0 +1,439 @@
+pub mod api;
+
+pub mod types;
+use types::*;
+
+// crates.io
+use bitcoin::secp256k1::{All, Message, PublicKey, Secp256k1, SecretKey};
+use once_cell::sync::Lazy;
+use secp256k1::ecdsa::RecoverableSignature;
+use tiny_keccak::{Hasher, Keccak};
+// self
+use crate::prelude::*;
+
+static SECP256K1: Lazy<Secp256k1<All>> = Lazy::new(Secp256k1::new);
+
+/// Call of the X chain bridge contract's relay function:
+///
+/// `relay(bytes32 txid, uint256 amount, uint32 target, bytes recipient, bytes proof)`
+///
+/// - `txid` is the Bitcoin transaction id in the internal byte order, which is also the order used
+///   by the merkle proofs.
+/// - `target` is the `XTarget` id.
+/// - `proof` is the serialized SPV proof, see `SpvProof`.
+#[derive(Debug)]
+pub struct XCall<'a> {
+	pub txid: Hash,
+	pub amount: u128,
+	pub target: u32,
+	pub recipient: &'a [u8],
+	pub proof: &'a [u8],
+}
+impl XCall<'_> {
+	const SIGNATURE: &'static str = "relay(bytes32,uint256,uint32,bytes,bytes)";
+
+	pub fn encode(&self) -> Vec<u8> {
+		let Self { txid, amount, target, recipient, proof } = self;
+		let mut data = keccak256(Self::SIGNATURE.as_bytes())[..4].to_vec();
+		let recipient = abi_bytes(recipient);
+
+		data.extend_from_slice(txid);
+		data.extend_from_slice(&abi_uint(*amount));
+		data.extend_from_slice(&abi_uint(*target as _));
+		// Offsets of the dynamic `bytes` from the start of the arguments.
+		data.extend_from_slice(&abi_uint(5 * 32));
+		data.extend_from_slice(&abi_uint((5 * 32 + recipient.len()) as _));
+		data.extend(recipient);
+		data.extend(abi_bytes(proof));
+
+		data
+	}
+}
+
+/// Call of the X chain light client contract's submit function:
+///
+/// `submitHeaders(bytes headers)`
+///
+/// - `headers` are the consecutive 80 bytes raw Bitcoin headers, the parent of the first one must
+///   be known by the light client, which follows the heaviest chain.
+#[derive(Debug)]
+pub struct HeadersCall<'a> {
+	pub headers: &'a [u8],
+}
+impl HeadersCall<'_> {
+	const SIGNATURE: &'static str = "submitHeaders(bytes)";
+
+	pub fn encode(&self) -> Vec<u8> {
+		let mut data = keccak256(Self::SIGNATURE.as_bytes())[..4].to_vec();
+
+		data.extend_from_slice(&abi_uint(32));
+		data.extend(abi_bytes(self.headers));
+
+		data
+	}
+}
+
+/// Call of the X chain bridge contract's withdrawal completion function:
+///
+/// `completeWithdrawal(bytes32 id, bytes tx, uint32 vout, bytes proof)`
+///
+/// - `id` is the withdrawal id, see `WithdrawEvent`.
+/// - `tx` is the payout transaction serialized without the witnesses, whose double SHA-256 is the
+///   `txid` proven by `proof`.
+/// - `vout` is the output paying the withdrawal, the contract accepts an output only once.
+/// - `proof` is the serialized SPV proof, see `SpvProof`.
+#[derive(Debug)]
+pub struct CompleteWithdrawalCall<'a> {
+	pub id: Hash,
+	pub tx: &'a [u8],
+	pub vout: u32,
+	pub proof: &'a [u8],
+}
+impl CompleteWithdrawalCall<'_> {
+	const SIGNATURE: &'static str = "completeWithdrawal(bytes32,bytes,uint32,bytes)";
+
+	pub fn encode(&self) -> Vec<u8> {
+		let Self { id, tx, vout, proof } = self;
+		let mut data = keccak256(Self::SIGNATURE.as_bytes())[..4].to_vec();
+		let tx = abi_bytes(tx);
+
+		data.extend_from_slice(id);
+		data.extend_from_slice(&abi_uint(4 * 32));
+		data.extend_from_slice(&abi_uint(*vout as _));
+		data.extend_from_slice(&abi_uint((4 * 32 + tx.len()) as _));
+		data.extend(tx);
+		data.extend(abi_bytes(proof));
+
+		data
+	}
+}
+
+/// Event of the X chain bridge contract, emitted once the assets are burnt for a withdrawal:
+///
+/// `Withdraw(bytes32 indexed id, uint256 amount, string recipient)`
+///
+/// - `id` is assigned by the contract, unique per withdrawal.
+/// - `amount` is in satoshis.
+/// - `recipient` is the Bitcoin address to pay, validated by the relayer.
+#[derive(Debug)]
+pub struct WithdrawEvent {
+	pub id: Hash,
+	pub amount: u128,
+	pub recipient: String,
+}
+impl WithdrawEvent {
+	const SIGNATURE: &'static str = "Withdraw(bytes32,uint256,string)";
+
+	pub fn topic() -> Hash {
+		keccak256(Self::SIGNATURE.as_bytes())
+	}
+
+	pub fn decode(topics: &[Hash], data: &[u8]) -> Result<Self> {
+		let [topic, id] = topics else {
+			Err(ChainError::InvalidEvent { reason: "unexpected topics" })?
+		};
+
+		if *topic != Self::topic() {
+			Err(ChainError::InvalidEvent { reason: "not a withdraw event" })?;
+		}
+
+		let amount = abi_decode_uint(data, 0)?;
+		let offset = abi_decode_uint(data, 32)? as usize;
+		let recipient = abi_decode_bytes(data, offset)?;
+
+		Ok(Self {
+			id: *id,
+			amount,
+			recipient: String::from_utf8(recipient.to_vec())
+				.map_err(|_| ChainError::InvalidEvent { reason: "recipient is not utf-8" })?,
+		})
+	}
+}
+
+/// [EIP-1559](https://eips.ethereum.org/EIPS/eip-1559) transaction.
+#[derive(Debug)]
+pub struct Eip1559Tx {
+	pub chain_id: u64,
+	pub nonce: u64,
+	pub max_priority_fee_per_gas: Wei,
+	pub max_fee_per_gas: Wei,
+	pub gas: u64,
+	pub to: Address,
+	pub value: Wei,
+	pub data: Vec<u8>,
+}
+impl Eip1559Tx {
+	const TYPE: u8 = 2;
+
+	fn rlp_fields(&self) -> Vec<Vec<u8>> {
+		vec![
+			rlp_uint(self.chain_id as _),
+			rlp_uint(self.nonce as _),
+			rlp_uint(self.max_priority_fee_per_gas),
+			rlp_uint(self.max_fee_per_gas),
+			rlp_uint(self.gas as _),
+			rlp_bytes(&self.to),
+			rlp_uint(self.value),
+			rlp_bytes(&self.data),
+			// Empty access list.
+			rlp_list(&[]),
+		]
+	}
+
+	pub fn signing_hash(&self) -> Hash {
+		let mut payload = vec![Self::TYPE];
+
+		payload.extend(rlp_list(&self.rlp_fields()));
+
+		keccak256(&payload)
+	}
+
+	/// Sign the transaction, returns the raw transaction and its hash.
+	pub fn sign(&self, account: &Account) -> (Vec<u8>, Hash) {
+		let (recovery_id, sig) = account.sign(self.signing_hash()).serialize_compact();
+		let mut fields = self.rlp_fields();
+
+		// `y_parity`, `r`, and `s`.
+		fields.push(rlp_uint(recovery_id.to_i32() as _));
+		fields.push(rlp_bytes(trim_zeros(&sig[..32])));
+		fields.push(rlp_bytes(trim_zeros(&sig[32..])));
+
+		let mut raw = vec![Self::TYPE];
+
+		raw.extend(rlp_list(&fields));
+
+		let hash = keccak256(&raw);
+
+		(raw, hash)
+	}
+}
+
+#[derive(Debug)]
+pub struct Account {
+	secret_key: SecretKey,
+	pub address: Address,
+}
+impl Account {
+	pub fn from_secret_key(secret_key: SecretKey) -> Self {
+		let address = address_of(&secret_key.public_key(&SECP256K1));
+
+		Self { secret_key, address }
+	}
+
+	pub fn sign(&self, hash: Hash) -> RecoverableSignature {
+		SECP256K1.sign_ecdsa_recoverable(&Message::from_digest(hash), &self.secret_key)
+	}
+}
+
+/// Recover the signer's address of a signature.
+#[cfg(test)]
+pub fn recover(hash: Hash, sig: &RecoverableSignature) -> Result<Address> {
+	Ok(address_of(&SECP256K1.recover_ecdsa(&Message::from_digest(hash), sig)?))
+}
+
+fn address_of(public_key: &PublicKey) -> Address {
+	keccak256(&public_key.serialize_uncompressed()[1..])[12..].try_into().unwrap()
+}
+
+pub fn keccak256(data: &[u8]) -> Hash {
+	let mut k = Keccak::v256();
+	let mut h = [0; 32];
+
+	k.update(data);
+	k.finalize(&mut h);
+
+	h
+}
+
+fn abi_uint(v: u128) -> [u8; 32] {
+	let mut w = [0; 32];
+
+	w[16..].copy_from_slice(&v.to_be_bytes());
+
+	w
+}
+
+// Length prefixed and right padded to 32 bytes.
+fn abi_bytes(s: &[u8]) -> Vec<u8> {
+	let mut v = abi_uint(s.len() as _).to_vec();
+
+	v.extend_from_slice(s);
+	v.resize(v.len() + (32 - s.len() % 32) % 32, 0);
+
+	v
+}
+
+// Decode the word at `offset`, which must fit in a `u128`.
+fn abi_decode_uint(data: &[u8], offset: usize) -> Result<u128> {
+	let w = data
+		.get(offset..offset.saturating_add(32))
+		.ok_or(ChainError::InvalidEvent { reason: "data too short" })?;
+
+	if w[..16].iter().any(|b| *b != 0) {
+		Err(ChainError::InvalidEvent { reason: "uint overflows" })?;
+	}
+
+	Ok(u128::from_be_bytes(w[16..].try_into().unwrap()))
+}
+
+// Decode the length prefixed bytes at `offset`.
+fn abi_decode_bytes(data: &[u8], offset: usize) -> Result<&[u8]> {
+	let len = abi_decode_uint(data, offset)? as usize;
+	let start = offset.saturating_add(32);
+
+	Ok(data
+		.get(start..start.saturating_add(len))
+		.ok_or(ChainError::InvalidEvent { reason: "data too short" })?)
+}
+
+fn trim_zeros(s: &[u8]) -> &[u8] {
+	&s[s.iter().position(|b| *b != 0).unwrap_or(s.len())..]
+}
+
+fn rlp_uint(v: u128) -> Vec<u8> {
+	rlp_bytes(trim_zeros(&v.to_be_bytes()))
+}
+
+fn rlp_bytes(s: &[u8]) -> Vec<u8> {
+	match s {
+		[b] if *b < 0x80 => vec![*b],
+		_ => {
+			let mut v = rlp_length(s.len(), 0x80);
+
+			v.extend_from_slice(s);
+
+			v
+		},
+	}
+}
+
+fn rlp_list(items: &[Vec<u8>]) -> Vec<u8> {
+	let payload = items.concat();
+	let mut v = rlp_length(payload.len(), 0xc0);
+
+	v.extend(payload);
+
+	v
+}
+
+fn rlp_length(len: usize, offset: u8) -> Vec<u8> {
+	if len < 56 {
+		vec![offset + len as u8]
+	} else {
+		let len = trim_zeros(&len.to_be_bytes()).to_vec();
+		let mut v = vec![offset + 55 + len.len() as u8];
+
+		v.extend(len);
+
+		v
+	}
+}
+#[test]
+fn rlp_should_work() {
+	assert_eq!(rlp_bytes(b"dog"), [0x83, b'd', b'o', b'g']);
+	assert_eq!(
+		rlp_list(&[rlp_bytes(b"cat"), rlp_bytes(b"dog")]),
+		[0xc8, 0x83, b'c', b'a', b't', 0x83, b'd', b'o', b'g']
+	);
+	assert_eq!(rlp_bytes(b""), [0x80]);
+	assert_eq!(rlp_list(&[]), [0xc0]);
+	assert_eq!(rlp_uint(0), [0x80]);
+	assert_eq!(rlp_uint(15), [0x0f]);
+	assert_eq!(rlp_uint(1024), [0x82, 0x04, 0x00]);
+	assert_eq!(rlp_bytes(&[b'a'; 56])[..2], [0xb8, 56]);
+}
+#[test]
+fn account_and_abi_should_work() {
+	assert_eq!(
+		array_bytes::bytes2hex("", keccak256(b"")),
+		"c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+	);
+	assert_eq!(keccak256(b"transfer(address,uint256)")[..4], [0xa9, 0x05, 0x9c, 0xbb]);
+
+	let account = Account::from_secret_key(
+		"4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318".parse().unwrap(),
+	);
+
+	assert_eq!(
+		array_bytes::bytes2hex("0x", account.address),
+		"0x2c7536e3605d9c16a7a3d7b1898e529396a65c23"
+	);
+
+	let data = XCall { txid: [1; 32], amount: 2, target: 3, recipient: &[4; 20], proof: &[5; 84] }
+		.encode();
+
+	assert_eq!(data.len(), 4 + 5 * 32 + 2 * 32 + 4 * 32);
+	assert_eq!(data[4..36], [1; 32]);
+	assert_eq!(data[67], 2);
+	assert_eq!(data[99], 3);
+	assert_eq!(data[131], 160);
+	assert_eq!(data[163], 224);
+	assert_eq!(data[195], 20);
+	assert_eq!(data[196..216], [4; 20]);
+	assert_eq!(data[216..228], [0; 12]);
+	assert_eq!(data[259], 84);
+	assert_eq!(data[260..344], [5; 84]);
+	assert_eq!(data[344..], [0; 12]);
+
+	let data = HeadersCall { headers: &[1; 160] }.encode();
+
+	assert_eq!(data.len(), 4 + 2 * 32 + 5 * 32);
+	assert_eq!(data[35], 32);
+	assert_eq!(data[67], 160);
+	assert_eq!(data[68..], [1; 160]);
+
+	let tx = Eip1559Tx {
+		chain_id: 1,
+		nonce: 2,
+		max_priority_fee_per_gas: 3,
+		max_fee_per_gas: 4,
+		gas: 5,
+		to: [6; 20],
+		value: 0,
+		data,
+	};
+	let (raw, hash) = tx.sign(&account);
+	let sig = account.sign(tx.signing_hash());
+	let (recovery_id, compact) = sig.serialize_compact();
+	let mut fields = tx.rlp_fields();
+
+	fields.extend([
+		rlp_uint(recovery_id.to_i32() as _),
+		rlp_bytes(trim_zeros(&compact[..32])),
+		rlp_bytes(trim_zeros(&compact[32..])),
+	]);
+
+	assert_eq!(raw, [[2].as_slice(), &rlp_list(&fields)].concat());
+	assert_eq!(hash, keccak256(&raw));
+	assert_eq!(recover(tx.signing_hash(), &sig).unwrap(), account.address);
+}
+#[test]
+fn withdraw_event_should_work() {
+	let recipient = crate::chain::btc::fixture::RECIPIENT;
+	let mut data = abi_uint(10_000).to_vec();
+
+	data.extend_from_slice(&abi_uint(64));
+	data.extend(abi_bytes(recipient.as_bytes()));
+
+	let e = WithdrawEvent::decode(&[WithdrawEvent::topic(), [1; 32]], &data).unwrap();
+
+	assert_eq!(e.id, [1; 32]);
+	assert_eq!(e.amount, 10_000);
+	assert_eq!(e.recipient, recipient);
+	assert!(WithdrawEvent::decode(&[[0; 32], [1; 32]], &data).is_err());
+	assert!(WithdrawEvent::decode(&[WithdrawEvent::topic()], &data).is_err());
+	assert!(WithdrawEvent::decode(&[WithdrawEvent::topic(), [1; 32]], &data[..100]).is_err());
+
+	let data =
+		CompleteWithdrawalCall { id: [1; 32], tx: &[2; 40], vout: 7, proof: &[3; 84] }.encode();
+
+	assert_eq!(data.len(), 4 + 4 * 32 + 3 * 32 + 4 * 32);
+	assert_eq!(data[4..36], [1; 32]);
+	assert_eq!(data[67], 128);
+	assert_eq!(data[99], 7);
+	assert_eq!(data[131], 224);
+	assert_eq!(data[163], 40);
+	assert_eq!(data[164..204], [2; 40]);
+	assert_eq!(data[259], 84);
+	assert_eq!(data[260..344], [3; 84]);
+}
@@ -0,0 +1,173 @@
+//! Rust bindings for the [Ethereum JSON-RPC API](https://ethereum.org/en/developers/docs/apis/json-rpc).
+
+// crates.io
+use serde::Deserialize;
+use serde_json::json;
+// self
+use super::types::*;
+use crate::{http::*, prelude::*};
+
+#[derive(Debug)]
+pub struct Api<H>
+where
+	H: Http,
+{
+	pub http: H,
+	pub uri: String,
+}
+impl<H> Api<H>
+where
+	H: Http,
+{
+	// Returns the chain ID used for signing replay-protected transactions.
+	pub async fn chain_id(&self) -> Result<u64> {
+		Ok(quantity(&self.http.call::<_, _, String>(&self.uri, "eth_chainId", json!([])).await?)?
+			as _)
+	}
+
+	// Returns the number of transactions sent from an address, including the pending ones.
+	pub async fn get_transaction_count(&self, address: &Address) -> Result<u64> {
+		Ok(quantity(
+			&self
+				.http
+				.call::<_, _, String>(
+					&self.uri,
+					"eth_getTransactionCount",
+					json!([array_bytes::bytes2hex("0x", address), "pending"]),
+				)
+				.await?,
+		)? as _)
+	}
+
+	// Generates and returns an estimate of how much gas is necessary to allow the transaction to
+	// complete.
+	pub async fn estimate_gas(&self, from: &Address, to: &Address, data: &[u8]) -> Result<u64> {
+		Ok(quantity(
+			&self
+				.http
+				.call::<_, _, String>(
+					&self.uri,
+					"eth_estimateGas",
+					json!([{
+						"from": array_bytes::bytes2hex("0x", from),
+						"to": array_bytes::bytes2hex("0x", to),
+						"data": array_bytes::bytes2hex("0x", data),
+					}]),
+				)
+				.await?,
+		)? as _)
+	}
+
+	// Returns the number of the most recent block.
+	pub async fn block_number(&self) -> Result<u64> {
+		Ok(quantity(
+			&self.http.call::<_, _, String>(&self.uri, "eth_blockNumber", json!([])).await?,
+		)? as _)
+	}
+
+	// Returns the logs of the contract within the block range, filtered by the first topic.
+	pub async fn get_logs(
+		&self,
+		address: &Address,
+		topic: &Hash,
+		from_block: u64,
+		to_block: u64,
+	) -> Result<Vec<Log>> {
+		self.http
+			.call(
+				&self.uri,
+				"eth_getLogs",
+				json!([{
+					"address": array_bytes::bytes2hex("0x", address),
+					"topics": [array_bytes::bytes2hex("0x", topic)],
+					"fromBlock": format!("{from_block:#x}"),
+					"toBlock": format!("{to_block:#x}"),
+				}]),
+			)
+			.await
+	}
+
+	// Returns the base fee per gas of the latest block.
+	pub async fn base_fee_per_gas(&self) -> Result<Wei> {
+		let block = self
+			.http
+			.call::<_, _, Block>(&self.uri, "eth_getBlockByNumber", json!(["latest", false]))
+			.await?;
+
+		quantity(&block.base_fee_per_gas)
+	}
+
+	// Returns the suggested priority fee per gas.
+	pub async fn max_priority_fee_per_gas(&self) -> Result<Wei> {
+		quantity(
+			&self
+				.http
+				.call::<_, _, String>(&self.uri, "eth_maxPriorityFeePerGas", json!([]))
+				.await?,
+		)
+	}
+
+	// Submits a signed transaction, returns the transaction hash.
+	//
+	// Never retried, the transaction might have been accepted even if the call fails.
+	pub async fn send_raw_transaction(&self, tx: &[u8]) -> Result<String> {
+		self.http
+			.call_once(
+				&self.uri,
+				"eth_sendRawTransaction",
+				json!([array_bytes::bytes2hex("0x", tx)]),
+			)
+			.await
+	}
+
+	// Returns the receipt of a transaction by transaction hash, `None` if it's still pending.
+	pub async fn get_transaction_receipt(&self, hash: &str) -> Result<Option<Receipt>> {
+		self.http.call(&self.uri, "eth_getTransactionReceipt", json!([hash])).await
+	}
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Block {
+	base_fee_per_gas: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Receipt {
+	pub block_number: String,
+	// `0x1` for success, `0x0` for failure.
+	pub status: String,
+}
+impl Receipt {
+	pub fn succeeded(&self) -> bool {
+		self.status == "0x1"
+	}
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Log {
+	pub block_number: String,
+	pub transaction_hash: String,
+	pub topics: Vec<String>,
+	pub data: String,
+}
+impl Log {
+	pub fn block_number(&self) -> Result<u64> {
+		Ok(quantity(&self.block_number)? as _)
+	}
+
+	pub fn topics(&self) -> Result<Vec<Hash>> {
+		self.topics.iter().map(|t| array_bytes::hex2array(t).map_err(Error::ArrayBytes)).collect()
+	}
+
+	pub fn data(&self) -> Result<Vec<u8>> {
+		array_bytes::hex2bytes(&self.data).map_err(Error::ArrayBytes)
+	}
+}
+
+// Decode a hex encoded quantity, e.g. `0x41`.
+fn quantity(s: &str) -> Result<u128> {
+	Ok(u128::from_str_radix(s.trim_start_matches("0x"), 16)?)
+}
@@ -0,0 +1,3 @@
+pub type Address = [u8; 20];
+pub type Hash = [u8; 32];
+pub type Wei = u128;
@@ -1,4 +1,7 @@
 pub mod btc;
+pub mod evm;
+pub mod frost;
+pub mod header;
 
 // std
 use std::{fs, path::Path, process};
@@ -12,8 +15,137 @@ const DEFAULT_CONF: &str = r#"[btc]
 # Possible values: "mainnet", "testnet", "signet", "regtest".
 network = "testnet"
 
-# Vault secret key in hex format (optional "0x" prefix).
-vault-secret-key = "0x.."
+# The vault key is read from the keystore, see `[btc.keystore]`, unless it's held by a signer
+# daemon, see `[btc.remote-signer]`, or shared among the relayers, see `[btc.frost]`.
+# Deprecated, vault secret key in hex format (optional "0x" prefix) stored in plain text.
+# Move it into a keystore with the `keystore import` command.
+# vault-secret-key = "0x.."
+# X-only public key of a vault held by an offline signer, instead of the vault secret key.
+# The vault spends are exported as PSBTs by the `export-psbt` command, and broadcasted by the
+# `import-psbt` command once signed.
+# vault-public-key = ".."
+
+# Number of confirmations required before a deposit is confirmed and relayed.
+# Unrelayed deposits in orphaned blocks are rejected and tracked again from the fork point.
+min-confirmations = 6
+
+# Accept the legacy `XTarget` format without the versioned envelope in the deposits.
+# accept-legacy-x-target = false
+
+[btc.api]
+# Chain data source.
+# Possible values: "mempool", "esplora", "node".
+type = "mempool"
+
+# Mempool API endpoint, defaults to the official instance of the configured network.
+# uri = "https://mempool.space/testnet/api"
+
+# Esplora API endpoint, defaults to the Blockstream instance of the configured network.
+# uri = "https://blockstream.info/testnet/api"
+
+# Bitcoin Core JSON-RPC endpoint, required if `type = "node"`.
+# The vault address must be imported into the wallet as a watch-only descriptor.
+# uri = "http://127.0.0.1:18332"
+# wallet = "helix"
+
+# Bitcoin Core JSON-RPC authentication, either the cookie file or the user/password.
+# cookie = "/home/user/.bitcoin/testnet3/.cookie"
+# user = "user"
+# password = "password"
+
+# Vault key encrypted with a passphrase, created by the `keystore create` or `keystore import`
+# command, and re-encrypted by the `keystore change-passphrase` command.
+[btc.keystore]
+path = "/path/to/vault.json"
+# Source of the passphrase, it's prompted on start if not set.
+# passphrase = { env = "HELIX_VAULT_PASSPHRASE" }
+# passphrase = { file = "/run/secrets/helix-vault-passphrase" }
+
+# Vault key held by a signer daemon, e.g. the one run by the `signer` command.
+# The daemon checks every spend against its policy before signing.
+# [btc.remote-signer]
+# Unix socket or HTTP endpoint of the daemon.
+# endpoint = { unix = "/run/helix/vault-signer.sock" }
+# endpoint = { http = "http://127.0.0.1:9736" }
+# X-only public key of the daemon's key in hex format.
+# public-key = ".."
+# Source of the bearer token the HTTP endpoint requires, see the `signer --token-env` option.
+# token = { env = "HELIX_SIGNER_TOKEN" }
+
+# FROST threshold vault key, shared among the relayers on the same host.
+# Any `threshold` of them sign the vault transactions together.
+# [btc.frost]
+# Key share of this relayer, generated by the `keygen` or `reshare` command.
+# share = "/path/to/share-1.json"
+# Unix socket to answer the signing requests of the coordinator on.
+# listen = "/run/helix/signer-1.sock"
+# Unix sockets of the other signers, set on the relayer coordinating the signing.
+# peers = ["/run/helix/signer-2.sock", "/run/helix/signer-3.sock"]
+# Policy file of the spends this relayer's share signs, see the `signer` command.
+# Every spend is signed if not set.
+# policy = "/etc/helix/policy.toml"
+
+# Timelocked recovery script path of the vault, spendable by the cold keys with the `recover`
+# command if the vault key is lost.
+# Setting, or changing, it changes the vault address.
+# [btc.recovery]
+# X-only public keys of the cold keys in hex format.
+# keys = ["..", "..", ".."]
+# Number of cold keys required.
+# threshold = 2
+# Number of blocks since a vault UTXO's confirmation before it can be recovered, at most 65535.
+# delay = 4320
+
+# Per-user deposit addresses, issued by the `deposit-address` command and bound to an `XTarget`.
+# The deposits to them are credited without an `OP_RETURN` output, and swept into the vault by
+# the `sweep` command.
+# [btc.deposit]
+# Extended public key of the BIP-86 account holding the deposit addresses, e.g. `m/86'/1'/0'`.
+# The addresses are derived from its receive chain, `xpub/0/index`.
+# xpub = "tpub.."
+# Master key fingerprint and derivation path of the xpub, required by hardware signers.
+# fingerprint = ".."
+# derivation-path = "m/86'/1'/0'"
+
+# Batching of the withdrawal payouts, see `[evm.withdrawal]`.
+# [btc.payout]
+# Maximum number of withdrawals paid in a transaction, a full batch is paid at once.
+# batch-size = 50
+# Seconds the oldest withdrawal waits for the batch to fill before it's paid anyway.
+# flush-interval = 600
+
+# Vault transfers exported as PSBTs by the `export-psbt` command.
+# [btc.psbt]
+# Sighash type the transfers are signed with, the relayer's own spends always use
+# "SIGHASH_DEFAULT".
+# Possible values: "SIGHASH_DEFAULT", "SIGHASH_ALL".
+# sighash-type = "SIGHASH_DEFAULT"
+
+# Fee bumping of the stuck payouts.
+# [btc.fee-bump]
+# Seconds a payout stays unconfirmed before it's bumped to the recommended fee rate.
+# bump-after = 1800
+# Maximum fee rate of a bumped payout (in satoshis per virtual byte).
+# max-fee-rate = 100
+# Possible values:
+# - "rbf": replace the payout, or fall back to "cpfp" if its change can't pay the higher fee.
+# - "cpfp": spend the change of the payout in a child paying for both, e.g. if the recipients
+#   rely on the unconfirmed payouts.
+# method = "rbf"
+
+# Consolidation of the small vault UTXOs while the fees are low, disabled if not set.
+# [btc.consolidation]
+# Consolidate while the economy fee rate is at most this (in satoshis per virtual byte).
+# A consolidation unconfirmed for `bump-after` seconds of `[btc.fee-bump]` is replaced at the
+# economy fee rate, at most this one.
+# max-fee-rate = 2
+# Minimum and maximum number of UTXOs merged in a consolidation.
+# min-inputs = 20
+# max-inputs = 200
+# Number of the largest UTXOs left out, so that the payouts go on while a consolidation confirms.
+# keep = 2
+# Number of outputs the UTXOs are merged into.
+# outputs = 2
 
 [btc.fee-conf]
 # Fee strategy to use for transactions.
@@ -25,11 +157,64 @@ extra = 0
 
 # Force set the fee rate (in satoshis per byte).
 # force = 1
+
+[btc.fee-conf.coin-selection]
+# Possible values:
+# - "least-waste": run every algorithm below and keep the selection of the least waste.
+# - "branch-and-bound": search for a selection without change, or fall back to "knapsack".
+# - "knapsack": approximate the smallest selection covering the amount and a change.
+# - "single-random-draw": pick the UTXOs randomly.
+# strategy = "least-waste"
+# Fee rate the vault expects to spend its UTXOs at in the long run (in satoshis per virtual byte).
+# Below it, spending more inputs now is preferred.
+# long-term-fee-rate = 10
+
+# X chains to relay the deposits to, identified by the `XTarget` id.
+# Deposits to an unconfigured X chain stay confirmed until it's configured.
+# [[evm]]
+# id = 0
+# uri = "http://127.0.0.1:8545"
+# Bridge contract address.
+# contract = "0x.."
+# Submitter secret key in hex format (optional "0x" prefix), the account pays the gas.
+# secret-key = "0x.."
+# Cap of the max fee per gas (in wei).
+# max-fee-per-gas = 100000000000
+# Seconds a submission stays pending before it's replaced at higher fees, under the cap above.
+# replace-after = 300
+# Pay the withdrawals burnt on the X chain from the vault, and complete them with the SPV proofs of
+# the payouts. The vault key must be able to sign online.
+# [evm.withdrawal]
+# X block to watch the `Withdraw` events of the bridge contract from.
+# start-block = 0
+# Number of X blocks on top of an event before it's paid.
+# confirmations = 12
+
+# Relay the Bitcoin headers to a light client contract on an EVM X chain.
+# The data source is shared with `[btc.api]`.
+# [header]
+# Height to start relaying from, must be the first block of a difficulty period.
+# The light client must be initialized with the header at this height.
+# start-height = 2903040
+# Number of headers submitted in a transaction.
+# batch-size = 10
+# [header.x]
+# id = 0
+# uri = "http://127.0.0.1:8545"
+# Light client contract address.
+# contract = "0x.."
+# Submitter secret key in hex format (optional "0x" prefix).
+# Use a different account from the `[[evm]]` ones to avoid the nonce conflicts.
+# secret-key = "0x.."
+# max-fee-per-gas = 100000000000
 "#;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Conf {
 	pub btc: btc::Conf,
+	#[serde(default)]
+	pub evm: Vec<evm::Conf>,
+	pub header: Option<header::Conf>,
 	// pub ckb: ckb::Conf,
 }
 impl Conf {
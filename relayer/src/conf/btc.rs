@@ -1,16 +1,352 @@
+// std
+use std::{env, fs, path::PathBuf, result::Result as StdResult};
 // crates.io
-use bitcoin::Network;
-use serde::{Deserialize, Serialize};
+use bitcoin::{
+	bip32::{DerivationPath, Fingerprint, Xpub},
+	key::XOnlyPublicKey,
+	Network, TapSighashType,
+};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
 // self
-use crate::chain::btc::{api::mempool::FeeType, types::*};
+use super::frost;
+use crate::{
+	chain::btc::{coin_selection::CoinSelection, signer::Endpoint, types::*},
+	prelude::*,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Conf {
 	pub network: Network,
-	pub vault_secret_key: String,
+	#[serde(flatten)]
+	pub vault: VaultConf,
+	#[serde(default)]
+	pub api: ApiConf,
+	pub recovery: Option<RecoveryConf>,
+	pub deposit: Option<DepositConf>,
+	#[serde(default)]
+	pub payout: PayoutConf,
+	#[serde(default)]
+	pub fee_bump: FeeBumpConf,
+	pub consolidation: Option<ConsolidationConf>,
+	#[serde(default = "default_min_confirmations")]
+	pub min_confirmations: BlockNumber,
+	#[serde(default)]
+	pub accept_legacy_x_target: bool,
+	#[serde(default)]
+	pub psbt: PsbtConf,
 	pub fee_conf: FeeConf,
 }
+fn default_min_confirmations() -> BlockNumber {
+	6
+}
+
+// Exactly one of the vault keys must be set.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct VaultConf {
+	pub keystore: Option<KeystoreConf>,
+	// Deprecated in favor of the keystore, the secret key is stored in plain text.
+	pub vault_secret_key: Option<String>,
+	// Internal key of a vault held by an offline signer, whose spends are exported as PSBTs.
+	pub vault_public_key: Option<XOnlyPublicKey>,
+	pub remote_signer: Option<RemoteSignerConf>,
+	pub frost: Option<frost::Conf>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct KeystoreConf {
+	pub path: PathBuf,
+	#[serde(default)]
+	pub passphrase: Passphrase,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Passphrase {
+	Env(String),
+	File(PathBuf),
+	#[default]
+	Prompt,
+}
+impl Passphrase {
+	pub fn read(&self, prompt: &str) -> Result<String> {
+		match self {
+			Self::Env(name) => Ok(env::var(name).map_err(|_| ServiceError::FailToExtractItem {
+				item: "passphrase",
+				src: format!("env var {name}"),
+			})?),
+			// Only the line break appended by the editors is stripped.
+			Self::File(path) => Ok(fs::read_to_string(path)?.trim_end_matches(['\n', '\r']).into()),
+			Self::Prompt => Ok(rpassword::prompt_password(prompt)?),
+		}
+	}
+
+	/// Read a new passphrase, which must be typed twice if prompted.
+	pub fn read_new(&self, prompt: &str) -> Result<String> {
+		let passphrase = self.read(prompt)?;
+
+		if let Self::Prompt = self {
+			if rpassword::prompt_password("repeat the passphrase: ")? != passphrase {
+				Err(ServiceError::FailToExtractItem {
+					item: "passphrase",
+					src: "mismatched prompts".into(),
+				})?;
+			}
+		}
+
+		Ok(passphrase)
+	}
+}
+#[test]
+fn passphrase_should_work() {
+	let path = std::env::temp_dir().join(format!("passphrase-{}", std::process::id()));
+
+	fs::write(&path, "pass phrase \n").unwrap();
+
+	assert_eq!(Passphrase::File(path.clone()).read("").unwrap(), "pass phrase ");
+
+	fs::remove_file(&path).unwrap();
+
+	assert!(Passphrase::Env("HELIX_PASSPHRASE_FOR_TEST".into()).read("").is_err());
+	assert!(matches!(
+		toml::from_str::<KeystoreConf>("path = \"k.json\"\npassphrase = { env = \"P\" }")
+			.unwrap()
+			.passphrase,
+		Passphrase::Env(n) if n == "P"
+	));
+	assert!(matches!(
+		toml::from_str::<KeystoreConf>("path = \"k.json\"").unwrap().passphrase,
+		Passphrase::Prompt
+	));
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RemoteSignerConf {
+	pub endpoint: Endpoint,
+	// Untweaked public key of the signer daemon, which is the vault's internal key.
+	pub public_key: XOnlyPublicKey,
+	// Bearer token of the HTTP endpoint.
+	pub token: Option<Passphrase>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum ApiConf {
+	Mempool {
+		// Use the official instance of the configured network if not set.
+		uri: Option<String>,
+	},
+	Esplora {
+		// Use the Blockstream instance of the configured network if not set.
+		uri: Option<String>,
+	},
+	Node(NodeConf),
+}
+impl Default for ApiConf {
+	fn default() -> Self {
+		Self::Mempool { uri: None }
+	}
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct NodeConf {
+	pub uri: String,
+	pub wallet: Option<String>,
+	#[serde(flatten)]
+	pub auth: NodeAuth,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum NodeAuth {
+	Cookie { cookie: PathBuf },
+	UserPassword { user: String, password: String },
+}
+impl NodeAuth {
+	/// Credentials in `user:password` format, which is also the format of the cookie file.
+	pub fn credentials(&self) -> Result<String> {
+		match self {
+			Self::Cookie { cookie } => Ok(fs::read_to_string(cookie)?.trim().into()),
+			Self::UserPassword { user, password } => Ok(format!("{user}:{password}")),
+		}
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RecoveryConf {
+	// X-only public keys of the cold keys, the order matters.
+	pub keys: Vec<XOnlyPublicKey>,
+	pub threshold: usize,
+	// Number of blocks since a vault UTXO's confirmation before it can be recovered.
+	pub delay: u16,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DepositConf {
+	// Extended public key of the deposit addresses, e.g. of the BIP-86 account `m/86'/0'/0'`.
+	pub xpub: Xpub,
+	// BIP-32 origin of the xpub, required by the hardware signers to sign the sweeps.
+	pub fingerprint: Option<Fingerprint>,
+	pub derivation_path: Option<DerivationPath>,
+}
+
+// Only the vault transfers exported as PSBTs are signed with the configured sighash type, the
+// relayer's own spends always use SIGHASH_DEFAULT.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PsbtConf {
+	#[serde(default = "default_sighash_type", deserialize_with = "deserialize_sighash_type")]
+	pub sighash_type: TapSighashType,
+}
+impl Default for PsbtConf {
+	fn default() -> Self {
+		Self { sighash_type: default_sighash_type() }
+	}
+}
+fn default_sighash_type() -> TapSighashType {
+	TapSighashType::Default
+}
+// The other types don't commit to every input and output, so the signature could be reused.
+fn deserialize_sighash_type<'de, D>(deserializer: D) -> StdResult<TapSighashType, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	let sighash_type = TapSighashType::deserialize(deserializer)?;
+
+	if matches!(sighash_type, TapSighashType::Default | TapSighashType::All) {
+		Ok(sighash_type)
+	} else {
+		Err(D::Error::custom(format!(
+			"sighash type {sighash_type} is not supported, expected SIGHASH_DEFAULT or SIGHASH_ALL"
+		)))
+	}
+}
+#[test]
+fn psbt_conf_should_work() {
+	assert_eq!(toml::from_str::<PsbtConf>("").unwrap().sighash_type, TapSighashType::Default);
+	assert_eq!(
+		toml::from_str::<PsbtConf>("sighash-type = \"SIGHASH_ALL\"").unwrap().sighash_type,
+		TapSighashType::All
+	);
+	assert!(toml::from_str::<PsbtConf>("sighash-type = \"SIGHASH_NONE\"").is_err());
+	assert!(
+		toml::from_str::<PsbtConf>("sighash-type = \"SIGHASH_ALL|SIGHASH_ANYONECANPAY\"").is_err()
+	);
+}
+
+// The withdrawals are paid once a batch is full, or the oldest one has waited for the flush
+// interval.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PayoutConf {
+	#[serde(default = "default_batch_size")]
+	pub batch_size: usize,
+	// In seconds.
+	#[serde(default = "default_flush_interval")]
+	pub flush_interval: u64,
+}
+impl Default for PayoutConf {
+	fn default() -> Self {
+		Self { batch_size: default_batch_size(), flush_interval: default_flush_interval() }
+	}
+}
+fn default_batch_size() -> usize {
+	50
+}
+fn default_flush_interval() -> u64 {
+	600
+}
+
+// A payout unconfirmed for longer than `bump_after` is bumped to the recommended fee rate, at most
+// `max_fee_rate`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FeeBumpConf {
+	// In seconds.
+	#[serde(default = "default_bump_after")]
+	pub bump_after: u64,
+	// In satoshis per virtual byte.
+	#[serde(default = "default_max_fee_rate")]
+	pub max_fee_rate: Satoshi,
+	#[serde(default)]
+	pub method: BumpMethod,
+}
+impl Default for FeeBumpConf {
+	fn default() -> Self {
+		Self {
+			bump_after: default_bump_after(),
+			max_fee_rate: default_max_fee_rate(),
+			method: Default::default(),
+		}
+	}
+}
+fn default_bump_after() -> u64 {
+	1_800
+}
+fn default_max_fee_rate() -> Satoshi {
+	100
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BumpMethod {
+	// Replace the payout, falling back to CPFP if its change can't pay the higher fee.
+	#[default]
+	Rbf,
+	// Spend the change of the payout in a child paying for the package, the payout is never
+	// replaced.
+	Cpfp,
+}
+
+// The smallest vault UTXOs are consolidated while the economy fee rate is at most `max_fee_rate`,
+// except for the largest `keep` ones left to the payouts in the meantime.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ConsolidationConf {
+	// In satoshis per virtual byte.
+	#[serde(default = "default_consolidation_max_fee_rate")]
+	pub max_fee_rate: Satoshi,
+	#[serde(default = "default_min_inputs")]
+	pub min_inputs: usize,
+	#[serde(default = "default_max_inputs")]
+	pub max_inputs: usize,
+	#[serde(default = "default_keep")]
+	pub keep: usize,
+	#[serde(default = "default_outputs")]
+	pub outputs: usize,
+}
+impl Default for ConsolidationConf {
+	fn default() -> Self {
+		Self {
+			max_fee_rate: default_consolidation_max_fee_rate(),
+			min_inputs: default_min_inputs(),
+			max_inputs: default_max_inputs(),
+			keep: default_keep(),
+			outputs: default_outputs(),
+		}
+	}
+}
+fn default_consolidation_max_fee_rate() -> Satoshi {
+	2
+}
+fn default_min_inputs() -> usize {
+	20
+}
+fn default_max_inputs() -> usize {
+	200
+}
+fn default_keep() -> usize {
+	2
+}
+fn default_outputs() -> usize {
+	2
+}
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -18,4 +354,6 @@ pub struct FeeConf {
 	pub strategy: FeeType,
 	pub extra: Satoshi,
 	pub force: Option<Satoshi>,
+	#[serde(default)]
+	pub coin_selection: CoinSelection,
 }
@@ -0,0 +1,36 @@
+// crates.io
+use serde::{Deserialize, Serialize};
+// self
+use crate::chain::evm::types::*;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Conf {
+	pub id: u32,
+	pub uri: String,
+	pub contract: String,
+	pub secret_key: String,
+	pub max_fee_per_gas: Option<Wei>,
+	// Seconds a submission stays pending before it's replaced at higher fees.
+	#[serde(default = "default_replace_after")]
+	pub replace_after: u64,
+	// The withdrawals of the X chain are only paid if set.
+	pub withdrawal: Option<WithdrawalConf>,
+}
+
+fn default_replace_after() -> u64 {
+	300
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WithdrawalConf {
+	// X block to watch the withdrawal events from.
+	pub start_block: u64,
+	// Number of X blocks on top of a withdrawal event before it's paid.
+	#[serde(default = "default_confirmations")]
+	pub confirmations: u64,
+}
+fn default_confirmations() -> u64 {
+	12
+}
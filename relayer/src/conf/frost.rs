@@ -0,0 +1,18 @@
+// std
+use std::path::PathBuf;
+// crates.io
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Conf {
+	// Key share of this relayer, generated by the `keygen` or `reshare` command.
+	pub share: PathBuf,
+	// Unix socket to answer the signing requests on.
+	pub listen: Option<PathBuf>,
+	// Unix sockets of the other signers, this relayer coordinates the signing if set.
+	#[serde(default)]
+	pub peers: Vec<PathBuf>,
+	// Policy file of the spends this relayer's share signs, in the format of the signer daemon's.
+	pub policy: Option<PathBuf>,
+}
@@ -0,0 +1,19 @@
+// crates.io
+use serde::{Deserialize, Serialize};
+// self
+use super::evm;
+use crate::chain::btc::types::*;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Conf {
+	// Must be the first block of a difficulty period, the light client is initialized with it.
+	pub start_height: BlockNumber,
+	#[serde(default = "default_batch_size")]
+	pub batch_size: usize,
+	// The light client contract on X.
+	pub x: evm::Conf,
+}
+fn default_batch_size() -> usize {
+	10
+}
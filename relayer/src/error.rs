@@ -7,6 +7,9 @@ pub use chain::*;
 pub mod service;
 pub use service::*;
 
+pub mod sql;
+pub use sql::*;
+
 pub mod x;
 pub use x::*;
 
@@ -47,16 +50,28 @@ pub enum Error {
 	#[error(transparent)]
 	Servcie(#[from] ServiceError),
 	#[error(transparent)]
+	Sql(#[from] SqlError),
+	#[error(transparent)]
 	X(#[from] XError),
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum BitcoinError {
+	#[error(transparent)]
+	Bip32(#[from] bitcoin::bip32::Error),
+	#[error(transparent)]
+	Consensus(#[from] bitcoin::consensus::encode::Error),
+	#[error(transparent)]
+	ConsensusFromHex(#[from] bitcoin::consensus::encode::FromHexError),
 	#[error(transparent)]
 	HexToArray(#[from] bitcoin::hex::HexToArrayError),
 	#[error(transparent)]
 	Parse(#[from] bitcoin::address::ParseError),
 	#[error(transparent)]
+	Psbt(#[from] bitcoin::psbt::Error),
+	#[error(transparent)]
+	PsbtParse(#[from] bitcoin::psbt::PsbtParseError),
+	#[error(transparent)]
 	SigHashTapRoot(#[from] bitcoin::sighash::TaprootError),
 }
 
@@ -2,4 +2,12 @@
 pub enum ApiError {
 	#[error("[api] max retries exceeded after {retries} attempts")]
 	ExceededMaxRetries { retries: u32 },
+	#[error("[api] json-rpc call `{method}` failed with code {code}: {message}")]
+	JsonRpc { method: String, code: i64, message: String },
+	#[error("[api] package rejected: {message}")]
+	PackageRejected { message: String },
+	#[error(
+		"[api] address {address} isn't watched by the wallet, import it with `importdescriptors`"
+	)]
+	Unwatched { address: String },
 }
@@ -1,5 +1,39 @@
 #[derive(Debug, thiserror::Error)]
 pub enum ChainError {
+	#[error("[chain] frost: {reason}")]
+	Frost { reason: &'static str },
+	#[error("[chain] frost signer: {message}")]
+	FrostSigner { message: String },
 	#[error("[chain] insufficient funds: required {required}, available {available}")]
 	InsufficientFunds { required: u128, available: u128 },
+	#[error("[chain] invalid deposit key: {reason}")]
+	InvalidDepositKey { reason: &'static str },
+	#[error("[chain] invalid event: {reason}")]
+	InvalidEvent { reason: &'static str },
+	#[error("[chain] invalid header at {height}: {reason}")]
+	InvalidHeader { height: u32, reason: &'static str },
+	#[error("[chain] invalid keystore: {reason}")]
+	InvalidKeystore { reason: &'static str },
+	#[error("[chain] invalid payout: {reason}")]
+	InvalidPayout { reason: &'static str },
+	#[error("[chain] invalid psbt: {reason}")]
+	InvalidPsbt { reason: &'static str },
+	#[error("[chain] invalid recovery: {reason}")]
+	InvalidRecovery { reason: &'static str },
+	#[error("[chain] invalid signature from the signer")]
+	InvalidSignature,
+	#[error("[chain] invalid signature share of signer {index}")]
+	InvalidSignatureShare { index: u16 },
+	#[error("[chain] invalid sign request: {reason}")]
+	InvalidSignRequest { reason: &'static str },
+	#[error("[chain] invalid spv proof of {txid}: {reason}")]
+	InvalidSpvProof { txid: String, reason: &'static str },
+	#[error("[chain] non-standard transaction: {reason}")]
+	NonStandardTx { reason: String },
+	#[error("[chain] spend rejected by the policy: {reason}")]
+	PolicyViolation { reason: String },
+	#[error("[chain] remote signer: {message}")]
+	RemoteSigner { message: String },
+	#[error("[chain] the vault key is watch-only, export a psbt to sign it offline")]
+	WatchOnly,
 }
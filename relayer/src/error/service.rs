@@ -2,4 +2,13 @@
 pub enum ServiceError {
 	#[error("[service] fail to extract {item} from {src}")]
 	FailToExtractItem { item: &'static str, src: String },
+	#[error("[service] deposit {txid} can't be refunded, {reason}")]
+	NotRefundable { txid: String, reason: String },
+	#[error(
+		"[service] exactly one of `btc.keystore`, `btc.vault-secret-key`, `btc.vault-public-key`, \
+		`btc.remote-signer`, and `btc.frost` must be set"
+	)]
+	InvalidVaultConf,
+	#[error("[service] start height {height} is not the first block of a difficulty period")]
+	InvalidStartHeight { height: u32 },
 }
@@ -0,0 +1,12 @@
+// self
+use crate::sql::{WithdrawalStatus, XStatus};
+
+#[derive(Debug, thiserror::Error)]
+pub enum SqlError {
+	#[error("[sql] record {id} not found")]
+	RecordNotFound { id: u64 },
+	#[error("[sql] record {id} can not transit from {from} to {to}")]
+	IllegalTransition { id: u64, from: XStatus, to: XStatus },
+	#[error("[sql] withdrawal {id} can not transit from {from} to {to}")]
+	IllegalWithdrawalTransition { id: u64, from: WithdrawalStatus, to: WithdrawalStatus },
+}
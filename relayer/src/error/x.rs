@@ -2,4 +2,14 @@
 pub enum XError {
 	#[error("[x::XEntity] invalid size {0}")]
 	EntitySizeInvalid(usize),
+	#[error("[x::XEntity] length marker {marker} mismatches the actual size {actual}")]
+	EntityLengthMismatch { marker: usize, actual: usize },
+	#[error("[x::XTarget] payload truncated at size {0}")]
+	PayloadTruncated(usize),
+	#[error("[x::XTarget] magic mismatch")]
+	MagicMismatch,
+	#[error("[x::XTarget] unsupported version {0}")]
+	VersionUnsupported(u8),
+	#[error("[x::XTarget] checksum mismatch")]
+	ChecksumMismatch,
 }
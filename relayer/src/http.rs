@@ -3,7 +3,7 @@ use std::time::Duration;
 // crates.io
 use bytes::Bytes;
 use reqwest::{Body, Client as RClient, IntoUrl};
-use serde::de::DeserializeOwned;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use tokio::time;
 // self
 use crate::prelude::*;
@@ -99,6 +99,83 @@ where
 }
 impl Response for Bytes {}
 
+pub trait JsonRpc
+where
+	Self: Http,
+{
+	async fn call<U, P, R>(&self, uri: U, method: &str, params: P) -> Result<R>
+	where
+		U: IntoUrl,
+		P: Serialize,
+		R: DeserializeOwned,
+	{
+		let req = json_rpc_request(method, params)?;
+
+		json_rpc_result(method, self.post_with_retries(uri, req, 3, 1_000).await?)
+	}
+
+	// Call the method only once, for the calls which must not be repeated blindly, e.g. a request
+	// timing out might still have been handled by the server.
+	async fn call_once<U, P, R>(&self, uri: U, method: &str, params: P) -> Result<R>
+	where
+		U: IntoUrl,
+		P: Serialize,
+		R: DeserializeOwned,
+	{
+		let req = json_rpc_request(method, params)?;
+
+		json_rpc_result(method, self.post(uri, req).await?)
+	}
+}
+impl<H> JsonRpc for H where H: Http {}
+
+fn json_rpc_request<P>(method: &str, params: P) -> Result<String>
+where
+	P: Serialize,
+{
+	Ok(serde_json::to_string(&JsonRpcRequest {
+		jsonrpc: "2.0",
+		id: 0,
+		method,
+		params: serde_json::to_value(params)?,
+	})?)
+}
+
+fn json_rpc_result<R>(method: &str, response: Bytes) -> Result<R>
+where
+	R: DeserializeOwned,
+{
+	let JsonRpcResponse { result, error } = response.json::<JsonRpcResponse<R>>()?;
+
+	if let Some(e) = error {
+		Err(ApiError::JsonRpc { method: method.into(), code: e.code, message: e.message })?;
+	}
+
+	// Some methods return `null` on success, e.g. `R = ()`.
+	match result {
+		Some(r) => Ok(r),
+		None => Ok(serde_json::from_value(serde_json::Value::Null)?),
+	}
+}
+
+#[derive(Debug, Serialize)]
+struct JsonRpcRequest<'a> {
+	jsonrpc: &'static str,
+	id: u32,
+	method: &'a str,
+	params: serde_json::Value,
+}
+#[derive(Debug, Deserialize)]
+struct JsonRpcResponse<R> {
+	result: Option<R>,
+	error: Option<JsonRpcError>,
+}
+#[derive(Debug, Deserialize)]
+struct JsonRpcError {
+	code: i64,
+	message: String,
+}
+
 #[derive(Debug)]
 pub struct Client(pub RClient);
 impl Http for Client {
@@ -125,3 +202,56 @@ impl Http for Client {
 		Ok(self.0.post(uri).body(body).send().await?.bytes().await?)
 	}
 }
+
+/// JSON-RPC server mock, responds with the result of `f(method, params)`.
+///
+/// An object with an `error` field is responded as the error instead.
+#[cfg(test)]
+pub struct MockRpc(Box<MockRpcHandler>);
+#[cfg(test)]
+type MockRpcHandler = dyn Fn(&str, &serde_json::Value) -> serde_json::Value + Send + Sync;
+#[cfg(test)]
+impl MockRpc {
+	pub fn new<F>(f: F) -> Self
+	where
+		F: 'static + Fn(&str, &serde_json::Value) -> serde_json::Value + Send + Sync,
+	{
+		Self(Box::new(f))
+	}
+}
+#[cfg(test)]
+impl std::fmt::Debug for MockRpc {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.write_str("MockRpc")
+	}
+}
+#[cfg(test)]
+impl Http for MockRpc {
+	// The node backend only talks JSON-RPC, a stray GET fails the test instead of panicking the
+	// runtime.
+	async fn get<U>(&self, uri: U) -> Result<Bytes>
+	where
+		U: IntoUrl,
+	{
+		Err(std::io::Error::new(
+			std::io::ErrorKind::Unsupported,
+			format!("unexpected GET {} to the JSON-RPC mock", uri.as_str()),
+		))?
+	}
+
+	async fn post<U, B>(&self, _: U, body: B) -> Result<Bytes>
+	where
+		U: IntoUrl,
+		B: Into<Body>,
+	{
+		let req = serde_json::from_slice::<serde_json::Value>(body.into().as_bytes().unwrap())?;
+		let result = (self.0)(req["method"].as_str().unwrap(), &req["params"]);
+		let resp = if result.get("error").is_some() {
+			result
+		} else {
+			serde_json::json!({ "result": result })
+		};
+
+		Ok(serde_json::to_vec(&resp)?.into())
+	}
+}
@@ -21,9 +21,323 @@ mod prelude {
 	pub type Result<T> = std::result::Result<T, Error>;
 }
 
+// std
+use std::{net::SocketAddr, path::PathBuf};
+// crates.io
+use bitcoin::{
+	bip32::{DerivationPath, Fingerprint},
+	key::XOnlyPublicKey,
+	Network,
+};
+use clap::{Parser, Subcommand};
+// Only used by the benchmarks.
+#[cfg(test)] use criterion as _;
+// self
+use conf::btc::Passphrase;
+
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+	#[command(subcommand)]
+	command: Option<Command>,
+}
+#[derive(Debug, Subcommand)]
+enum Command {
+	/// Run the relayer, this is the default command.
+	Run,
+	/// Show the records and their status history of a Bitcoin deposit, or the status of a
+	/// withdrawal.
+	Status {
+		/// Deposit or payout transaction id, or withdrawal id.
+		txid: String,
+	},
+	/// Mark a rejected deposit as sent back to the user, once the refund is confirmed.
+	Refund {
+		/// Deposit transaction id.
+		txid: String,
+		/// Transaction id of the refund.
+		#[arg(long)]
+		refund_txid: String,
+	},
+	/// Show the deposit address bound to an X target, issuing a new one if there is none.
+	DepositAddress {
+		/// Id of the X chain.
+		#[arg(long)]
+		target: u32,
+		/// Entity on the X chain in hex format, 20 or 32 bytes.
+		#[arg(long)]
+		entity: String,
+	},
+	/// Export the unsigned PSBT sweeping the deposit addresses into the vault, to be signed
+	/// offline.
+	Sweep {
+		/// File to save the PSBT to, in base64.
+		#[arg(long)]
+		out: PathBuf,
+	},
+	/// Sweep all the vault UTXOs to an address through the recovery leaf, with the cold keys.
+	Recover {
+		/// Address to sweep the vault UTXOs to.
+		#[arg(long)]
+		to: String,
+		/// Files of the cold secret keys in hex format (optional "0x" prefix).
+		#[arg(long = "key", required = true)]
+		keys: Vec<PathBuf>,
+		/// Internal key of the vault, derived from the configured vault key if not set.
+		#[arg(long)]
+		internal_key: Option<XOnlyPublicKey>,
+		/// Broadcast the transaction, otherwise it's printed only.
+		#[arg(long)]
+		broadcast: bool,
+	},
+	/// Export the unsigned PSBT of a vault transfer, to be signed offline.
+	ExportPsbt {
+		/// Recipient address of the transfer.
+		#[arg(long)]
+		to: String,
+		/// Amount to transfer in satoshis.
+		#[arg(long)]
+		amount: u64,
+		/// Id of the X chain in the `OP_RETURN` output.
+		#[arg(long)]
+		target: u32,
+		/// Entity on the X chain in hex format, 20 or 32 bytes.
+		#[arg(long)]
+		entity: String,
+		/// Master key fingerprint of the vault key, required by hardware signers.
+		#[arg(long, requires = "derivation_path")]
+		fingerprint: Option<Fingerprint>,
+		/// BIP-32 derivation path of the vault key, e.g. "m/86'/0'/0'/0/0".
+		#[arg(long, requires = "fingerprint")]
+		derivation_path: Option<DerivationPath>,
+		/// File to save the PSBT to, in base64.
+		#[arg(long)]
+		out: PathBuf,
+	},
+	/// Finalize a PSBT signed offline, after checking it against the exported one.
+	ImportPsbt {
+		/// PSBT exported by the `export-psbt` command.
+		#[arg(long)]
+		exported: PathBuf,
+		/// Signed PSBT, in base64 or binary.
+		#[arg(long)]
+		signed: PathBuf,
+		/// Broadcast the transaction, otherwise it's printed only.
+		#[arg(long)]
+		broadcast: bool,
+	},
+	/// Run the reference remote signer daemon, signing the vault spends allowed by the policy.
+	Signer {
+		/// File of the secret key in hex format (optional "0x" prefix).
+		#[arg(long)]
+		key: PathBuf,
+		/// Policy file in TOML format, every spend is signed if not set.
+		#[arg(long)]
+		policy: Option<PathBuf>,
+		/// Unix socket to listen on.
+		#[arg(long, required_unless_present = "http", conflicts_with = "http")]
+		unix: Option<PathBuf>,
+		/// Address to listen on for the HTTP requests, e.g. "127.0.0.1:9736".
+		#[arg(long)]
+		http: Option<SocketAddr>,
+		/// Env var to read the bearer token of the HTTP requests from, required unless listening
+		/// on the loopback interface.
+		#[arg(long, conflicts_with = "token_file")]
+		token_env: Option<String>,
+		/// File to read the bearer token of the HTTP requests from.
+		#[arg(long)]
+		token_file: Option<PathBuf>,
+	},
+	/// Manage the encrypted keystore of the vault key.
+	Keystore {
+		#[command(subcommand)]
+		command: KeystoreCommand,
+	},
+	/// Split a new vault key into FROST key shares with a trusted dealer.
+	///
+	/// The dealer holds the whole vault key: run this on an offline, air-gapped machine only,
+	/// deliver each share to its signer privately, and destroy every copy left on the machine
+	/// afterwards.
+	Keygen {
+		/// Number of signers required to sign.
+		#[arg(long)]
+		threshold: u16,
+		/// Number of signers.
+		#[arg(long)]
+		signers: u16,
+		/// Directory to save the `share-{index}.json` files to.
+		#[arg(long)]
+		out: PathBuf,
+		/// Network of the vault address.
+		#[arg(long, default_value_t = Network::Testnet)]
+		network: Network,
+	},
+	/// Reshare the FROST vault key among a new group, keeping the vault address.
+	///
+	/// Each current signer deals its share on its own host, and each new signer combines the
+	/// dealings delivered to it on its own host, so that no host ever holds more than one share.
+	Reshare {
+		#[command(subcommand)]
+		command: ReshareCommand,
+	},
+}
+
+#[derive(Debug, Subcommand)]
+enum KeystoreCommand {
+	/// Encrypt a new random vault key into a keystore.
+	Create {
+		/// File to save the keystore to, an existing file is never overwritten.
+		#[arg(long)]
+		out: PathBuf,
+		/// Env var to read the passphrase from, it's prompted if neither source is set.
+		#[arg(long, conflicts_with = "passphrase_file")]
+		passphrase_env: Option<String>,
+		/// File to read the passphrase from.
+		#[arg(long)]
+		passphrase_file: Option<PathBuf>,
+		/// Network of the vault address.
+		#[arg(long, default_value_t = Network::Testnet)]
+		network: Network,
+	},
+	/// Encrypt an existing vault key into a keystore.
+	Import {
+		/// File of the secret key in hex format (optional "0x" prefix).
+		#[arg(long)]
+		key: PathBuf,
+		/// File to save the keystore to, an existing file is never overwritten.
+		#[arg(long)]
+		out: PathBuf,
+		/// Env var to read the passphrase from, it's prompted if neither source is set.
+		#[arg(long, conflicts_with = "passphrase_file")]
+		passphrase_env: Option<String>,
+		/// File to read the passphrase from.
+		#[arg(long)]
+		passphrase_file: Option<PathBuf>,
+		/// Network of the vault address.
+		#[arg(long, default_value_t = Network::Testnet)]
+		network: Network,
+	},
+	/// Re-encrypt a keystore under a new passphrase, in place.
+	///
+	/// The key itself is kept, so is the vault address.
+	ChangePassphrase {
+		/// Keystore to re-encrypt.
+		keystore: PathBuf,
+		/// Env var to read the current passphrase from, it's prompted if neither source is set.
+		#[arg(long, conflicts_with = "passphrase_file")]
+		passphrase_env: Option<String>,
+		/// File to read the current passphrase from.
+		#[arg(long)]
+		passphrase_file: Option<PathBuf>,
+		/// Env var to read the new passphrase from, it's prompted if neither source is set.
+		#[arg(long, conflicts_with = "new_passphrase_file")]
+		new_passphrase_env: Option<String>,
+		/// File to read the new passphrase from.
+		#[arg(long)]
+		new_passphrase_file: Option<PathBuf>,
+	},
+}
+
+#[derive(Debug, Subcommand)]
+enum ReshareCommand {
+	/// Deal the key share of a current signer to the new group.
+	///
+	/// Saves the `dealing-{dealer}-to-{index}.json` file of each new signer, to be delivered to it
+	/// privately and destroyed afterwards.
+	Deal {
+		/// Key share of the current signer.
+		share: PathBuf,
+		/// Indexes of the current signers dealing, at least the threshold, e.g. "1,3". Every
+		/// dealer must deal with the same ones.
+		#[arg(long, required = true, value_delimiter = ',')]
+		dealers: Vec<u16>,
+		/// Number of signers required to sign in the new group.
+		#[arg(long)]
+		threshold: u16,
+		/// Number of signers in the new group.
+		#[arg(long)]
+		signers: u16,
+		/// Directory to save the dealing files to.
+		#[arg(long)]
+		out: PathBuf,
+	},
+	/// Combine the dealings delivered to a new signer into its key share.
+	Combine {
+		/// Dealing files of every dealer to this signer.
+		#[arg(required = true)]
+		dealings: Vec<PathBuf>,
+		/// Index of the new signer.
+		#[arg(long)]
+		index: u16,
+		/// Internal key of the vault, which the new group keeps.
+		#[arg(long)]
+		internal_key: XOnlyPublicKey,
+		/// Directory to save the new `share-{index}.json` file to.
+		#[arg(long)]
+		out: PathBuf,
+		/// Network of the vault address.
+		#[arg(long, default_value_t = Network::Testnet)]
+		network: Network,
+	},
+}
+
 fn main() -> prelude::Result<()> {
 	color_eyre::install().unwrap();
 	tracing_subscriber::fmt::init();
 
-	service::run()
+	match Cli::parse().command.unwrap_or(Command::Run) {
+		Command::Run => service::run(),
+		Command::Status { txid } => service::status(txid),
+		Command::Refund { txid, refund_txid } => service::refund(txid, refund_txid),
+		Command::DepositAddress { target, entity } => service::deposit_address(target, entity),
+		Command::Sweep { out } => service::sweep(out),
+		Command::Recover { to, keys, internal_key, broadcast } =>
+			service::recover(to, keys, internal_key, broadcast),
+		Command::ExportPsbt { to, amount, target, entity, fingerprint, derivation_path, out } =>
+			service::export_psbt(to, amount, target, entity, fingerprint.zip(derivation_path), out),
+		Command::ImportPsbt { exported, signed, broadcast } =>
+			service::import_psbt(exported, signed, broadcast),
+		Command::Signer { key, policy, unix, http, token_env, token_file } => service::signer(
+			key,
+			policy,
+			unix,
+			http,
+			token_env.map(Passphrase::Env).or(token_file.map(Passphrase::File)),
+		),
+		Command::Keystore { command } => match command {
+			KeystoreCommand::Create { out, passphrase_env, passphrase_file, network } =>
+				service::keystore_create(out, passphrase(passphrase_env, passphrase_file), network),
+			KeystoreCommand::Import { key, out, passphrase_env, passphrase_file, network } =>
+				service::keystore_import(
+					key,
+					out,
+					passphrase(passphrase_env, passphrase_file),
+					network,
+				),
+			KeystoreCommand::ChangePassphrase {
+				keystore,
+				passphrase_env,
+				passphrase_file,
+				new_passphrase_env,
+				new_passphrase_file,
+			} => service::keystore_change_passphrase(
+				keystore,
+				passphrase(passphrase_env, passphrase_file),
+				passphrase(new_passphrase_env, new_passphrase_file),
+			),
+		},
+		Command::Keygen { threshold, signers, out, network } =>
+			service::keygen(threshold, signers, out, network),
+		Command::Reshare { command } => match command {
+			ReshareCommand::Deal { share, dealers, threshold, signers, out } =>
+				service::reshare_deal(share, dealers, threshold, signers, out),
+			ReshareCommand::Combine { dealings, index, internal_key, out, network } =>
+				service::reshare_combine(index, internal_key, dealings, out, network),
+		},
+	}
+}
+
+// The passphrase is prompted if neither source is set.
+fn passphrase(env: Option<String>, file: Option<PathBuf>) -> Passphrase {
+	env.map(Passphrase::Env).or(file.map(Passphrase::File)).unwrap_or_default()
 }
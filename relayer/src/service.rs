@@ -1,16 +1,41 @@
 mod btc;
+mod frost;
+mod header;
+mod keystore;
+mod signer;
+mod source;
+mod submitter;
 
 // std
-use std::{fmt::Debug, path::PathBuf, sync::Arc, time::Duration};
+use std::{
+	fmt::Debug,
+	fs,
+	net::SocketAddr,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
 // crates.io
 use app_dirs2::{AppDataType, AppInfo};
+use bitcoin::{
+	bip32::KeySource,
+	key::{Keypair, XOnlyPublicKey},
+	psbt::Psbt,
+	Network,
+};
 use deadpool_sqlite::Pool;
 use tokio::{
 	runtime::{Builder, Runtime},
 	task, time,
 };
 // self
-use crate::{conf::*, prelude::*, sql};
+use crate::{
+	chain::btc::types::Satoshi,
+	conf::{btc::Passphrase, *},
+	prelude::*,
+	sql,
+	x::*,
+};
 
 const APP_INFO: AppInfo = AppInfo { name: "helix-btc-bridge-relayer", author: "Xavier Lau" };
 
@@ -51,15 +76,13 @@ impl Service {
 			.enable_all()
 			// TODO: Need more tests.
 			// Increare this if there is a new relayer.
-			.worker_threads(1)
+			.worker_threads(3)
 			.build()?;
 		let p = Self::sql_path()?;
-		let p = sql::init(&p).map_err(|e| {
+		let p = sql::init(&p).inspect_err(|_| {
 			tracing::error!(
 				"an error occurred while initializing the database, please check {p:?}",
 			);
-
-			e
 		})?;
 
 		Ok(Context { runtime: Arc::new(rt), sql: Arc::new(p) })
@@ -67,17 +90,25 @@ impl Service {
 
 	fn register_relayers(context: Context) -> Result<Vec<Box<dyn Relay>>> {
 		let p = Self::conf_path()?;
-		let c = Conf::load_from(&p)?;
-		let rs = vec![btc::Relayer::new(c.btc, context).map(|r| Box::new(r) as Box<dyn Relay>)]
-			.into_iter()
-			.collect::<Result<_>>()
-			.map_err(|e| {
-				tracing::error!(
-					"an error occurred while parsing the configuration, please check {p:?}",
-				);
-
-				e
-			})?;
+		let Conf { btc, evm, header } = Conf::load_from(&p)?;
+		let mut rs = Vec::new();
+
+		if let Some(f) = btc.vault.frost.as_ref() {
+			if let Some(listen) = &f.listen {
+				rs.push(frost::new(&f.share, f.policy.as_deref(), listen.clone(), context.clone()));
+			}
+		}
+		if let Some(h) = header {
+			rs.push(header::new(h, btc.network, btc.api.clone(), context.clone()));
+		}
+
+		rs.push(btc::new(btc, evm, context));
+
+		let rs = rs.into_iter().collect::<Result<_>>().inspect_err(|_| {
+			tracing::error!(
+				"an error occurred while parsing the configuration, please check {p:?}",
+			);
+		})?;
 
 		Ok(rs)
 	}
@@ -95,6 +126,211 @@ struct Context {
 	runtime: Arc<Runtime>,
 	sql: Arc<Pool>,
 }
+#[cfg(test)]
+fn context_for_test(name: &str) -> Context {
+	let sql = std::env::temp_dir().join(format!("{name}-{}.db3", std::process::id()));
+	let _ = std::fs::remove_file(&sql);
+
+	Context {
+		runtime: Arc::new(
+			tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap(),
+		),
+		sql: Arc::new(sql::init(sql).unwrap()),
+	}
+}
+// Tests don't go through `run`, the connections must be closed within the runtime context.
+#[cfg(test)]
+impl Drop for Context {
+	fn drop(&mut self) {
+		let _g = self.runtime.enter();
+
+		self.sql.close();
+	}
+}
+
+pub fn status(txid: String) -> Result<()> {
+	let context = Service::register_context()?;
+
+	context.runtime.block_on(async {
+		let r = btc::status(&context, txid).await;
+
+		context.sql.close();
+
+		r
+	})
+}
+
+pub fn refund(txid: String, refund_txid: String) -> Result<()> {
+	let Conf { btc, .. } = Conf::load_from(&Service::conf_path()?)?;
+	let context = Service::register_context()?;
+
+	context.runtime.block_on(async {
+		let r = btc::refund(&context, btc, txid, refund_txid).await;
+
+		context.sql.close();
+
+		r
+	})
+}
+
+pub fn deposit_address(target: u32, entity: String) -> Result<()> {
+	let Conf { btc, .. } = Conf::load_from(&Service::conf_path()?)?;
+	let x_target = XTarget {
+		id: target.into(),
+		entity: XEntity::from_bytes(array_bytes::hex2bytes(&entity).map_err(Error::ArrayBytes)?)?,
+	};
+	let context = Service::register_context()?;
+
+	context.runtime.block_on(async {
+		let r = btc::deposit_address(&context, btc, x_target).await;
+
+		context.sql.close();
+
+		r
+	})
+}
+
+pub fn sweep(out: PathBuf) -> Result<()> {
+	let Conf { btc, .. } = Conf::load_from(&Service::conf_path()?)?;
+	let context = Service::register_context()?;
+	let psbt = context.runtime.block_on(async {
+		let r = btc::sweep(&context, btc).await;
+
+		context.sql.close();
+
+		r
+	})?;
+
+	fs::write(&out, psbt.to_string())?;
+
+	println!("psbt has been saved to {out:?}, sign it and broadcast it with `import-psbt`");
+
+	Ok(())
+}
+
+pub fn recover(
+	recipient: String,
+	key_files: Vec<PathBuf>,
+	internal_key: Option<XOnlyPublicKey>,
+	broadcast: bool,
+) -> Result<()> {
+	let Conf { btc, .. } = Conf::load_from(&Service::conf_path()?)?;
+	let keys = key_files.iter().map(|p| read_keypair(p)).collect::<Result<Vec<_>>>()?;
+
+	Builder::new_current_thread().enable_all().build()?.block_on(btc::recover(
+		btc,
+		recipient,
+		keys,
+		internal_key,
+		broadcast,
+	))
+}
+
+pub fn export_psbt(
+	recipient: String,
+	amount: Satoshi,
+	target: u32,
+	entity: String,
+	key_origin: Option<KeySource>,
+	out: PathBuf,
+) -> Result<()> {
+	let Conf { btc, .. } = Conf::load_from(&Service::conf_path()?)?;
+	let x_target = XTarget {
+		id: target.into(),
+		entity: XEntity::from_bytes(array_bytes::hex2bytes(&entity).map_err(Error::ArrayBytes)?)?,
+	};
+	let psbt = Builder::new_current_thread()
+		.enable_all()
+		.build()?
+		.block_on(btc::export_psbt(btc, recipient, amount, x_target, key_origin))?;
+
+	fs::write(&out, psbt.to_string())?;
+
+	println!("psbt has been saved to {out:?}");
+
+	Ok(())
+}
+
+pub fn import_psbt(exported: PathBuf, signed: PathBuf, broadcast: bool) -> Result<()> {
+	let Conf { btc, .. } = Conf::load_from(&Service::conf_path()?)?;
+
+	Builder::new_current_thread().enable_all().build()?.block_on(btc::import_psbt(
+		btc,
+		read_psbt(&exported)?,
+		read_psbt(&signed)?,
+		broadcast,
+	))
+}
+
+// Signers save the PSBTs either in base64 or in binary.
+fn read_psbt(path: &Path) -> Result<Psbt> {
+	let psbt = fs::read(path)?;
+
+	match std::str::from_utf8(&psbt) {
+		Ok(s) => Ok(s.trim().parse().map_err(BitcoinError::PsbtParse)?),
+		Err(_) => Ok(Psbt::deserialize(&psbt).map_err(BitcoinError::Psbt)?),
+	}
+}
+
+pub fn signer(
+	key: PathBuf,
+	policy: Option<PathBuf>,
+	unix: Option<PathBuf>,
+	http: Option<SocketAddr>,
+	token: Option<Passphrase>,
+) -> Result<()> {
+	signer::serve(read_keypair(&key)?, policy, unix, http, token)
+}
+
+// Secret key files are in hex format, with an optional "0x" prefix.
+fn read_keypair(path: &Path) -> Result<Keypair> {
+	Ok(fs::read_to_string(path)?.trim().trim_start_matches("0x").parse()?)
+}
+
+pub fn keystore_create(out: PathBuf, passphrase: Passphrase, network: Network) -> Result<()> {
+	keystore::create(&out, &passphrase, network)
+}
+
+pub fn keystore_import(
+	key: PathBuf,
+	out: PathBuf,
+	passphrase: Passphrase,
+	network: Network,
+) -> Result<()> {
+	keystore::import(read_keypair(&key)?, &out, &passphrase, network)
+}
+
+pub fn keystore_change_passphrase(
+	path: PathBuf,
+	passphrase: Passphrase,
+	new_passphrase: Passphrase,
+) -> Result<()> {
+	keystore::change_passphrase(&path, &passphrase, &new_passphrase)
+}
+
+pub fn keygen(threshold: u16, signers: u16, out: PathBuf, network: Network) -> Result<()> {
+	frost::keygen(threshold, signers, &out, network)
+}
+
+pub fn reshare_deal(
+	share: PathBuf,
+	dealers: Vec<u16>,
+	threshold: u16,
+	signers: u16,
+	out: PathBuf,
+) -> Result<()> {
+	frost::reshare_deal(&share, &dealers, threshold, signers, &out)
+}
+
+pub fn reshare_combine(
+	index: u16,
+	internal_key: XOnlyPublicKey,
+	dealings: Vec<PathBuf>,
+	out: PathBuf,
+	network: Network,
+) -> Result<()> {
+	frost::reshare_combine(index, internal_key, &dealings, &out, network)
+}
 
 pub fn run() -> Result<()> {
 	let Service { context, relayers } = Service::new()?;
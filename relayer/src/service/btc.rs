@@ -1,143 +1,761 @@
 mod util;
 
 // std
-use std::{sync::Arc, time::Duration};
+use std::{
+	collections::{HashMap, HashSet},
+	fmt::Debug,
+	iter, mem, slice,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
 // crates.io
-use bitcoin::Network;
+use bitcoin::{
+	address::NetworkUnchecked,
+	bip32::KeySource,
+	consensus,
+	key::{Keypair, XOnlyPublicKey},
+	psbt::Psbt,
+	secp256k1::schnorr,
+	Address, Amount, Network, OutPoint, ScriptBuf, TapNodeHash, Transaction, TxOut,
+};
 use chrono::Utc;
 use deadpool_sqlite::Pool;
 use reqwest::ClientBuilder;
 use tokio::{task, time};
 // self
-use super::{Context, Relay};
+use super::{
+	source::Source,
+	submitter::{self, Submitter},
+	Context, Relay,
+};
+#[cfg(test)] use crate::{chain::btc::api::mock, http::MockRpc};
 use crate::{
-	chain::btc::{api::mempool::Api, *},
-	conf::btc::*,
-	http::Client,
+	chain::btc::{
+		api::BtcSource,
+		consolidation::{self, ConsolidationTxBuilder},
+		deposit::{DepositKeys, SweepTxBuilder},
+		payout::{CpfpTxBuilder, Payout, PayoutTxBuilder},
+		policy, psbt,
+		recovery::{Recovery, RecoveryTxBuilder},
+		signer::*,
+		types::{BlockNumber, Satoshi, ScriptType},
+		*,
+	},
+	conf::{btc::*, evm},
+	http::{Client, Http},
 	prelude::*,
 	sql::*,
 	x::*,
 };
 
+const NAME: &str = "btc-x";
+// Number of X blocks scanned for the withdrawals at once, within the limits of the RPC providers.
+const WITHDRAWAL_SCAN_RANGE: u64 = 1_000;
+// Number of rounds a record's submission may revert in simulation before it's rejected, e.g. while
+// the light client catches up with its block.
+const MAX_SIMULATION_REVERTS: u32 = 120;
+
+/// Create the relayer with the chain data source selected in the configuration.
+pub(super) fn new(mut conf: Conf, evm: Vec<evm::Conf>, context: Context) -> Result<Box<dyn Relay>> {
+	let api = Source::new(mem::take(&mut conf.api), conf.network, NAME)?;
+	let submitters = evm
+		.into_iter()
+		.map(|c| Submitter::new(c, Client(ClientBuilder::new().user_agent(NAME).build()?)))
+		.collect::<Result<Vec<_>>>()?;
+
+	Ok(Box::new(Relayer::new(conf, api, submitters, context)?))
+}
+
+// Records of the relayer, accessed by the commands without running it.
+struct Records<'a>(&'a Context);
+impl X for Records<'_> {
+	const NAME: &'static str = NAME;
+}
+impl Sql for Records<'_> {
+	async fn pool(&self) -> &Arc<Pool> {
+		&self.0.sql
+	}
+}
+
+/// Print the records of the deposit and their status history, the withdrawals paid by the
+/// transaction or of the withdrawal id, or the consolidation.
+pub(super) async fn status(context: &Context, txid: String) -> Result<()> {
+	let records = Records(context);
+
+	records.init().await?;
+
+	let xrs = records.get_by_txid(txid.clone()).await?;
+	let payout = records.get_payout(txid.clone()).await?;
+	let wrs = records
+		.get_withdrawals_by_txid(txid.clone())
+		.await?
+		.into_iter()
+		.chain(records.get_withdrawals_by_id(txid.clone()).await?)
+		.collect::<Vec<_>>();
+
+	let consolidation = records.get_consolidation(txid.clone()).await?;
+
+	if xrs.is_empty() && wrs.is_empty() && consolidation.is_none() {
+		println!("no x record found for {txid}");
+	}
+	if let Some(c) = consolidation {
+		println!(
+			"consolidation {} of {} UTXOs worth {}, fee {} at {} sat/vB, created at {}{}",
+			c.txid,
+			c.inputs,
+			c.amount,
+			c.fee,
+			c.fee_rate,
+			c.created_at,
+			c.block_height.map(|h| format!(", included in block {h}")).unwrap_or_default()
+		);
+	}
+	if let Some(p) = payout {
+		println!(
+			"payout {} of {} withdrawals, created at {}{}",
+			p.txid,
+			wrs.len(),
+			p.created_at,
+			p.block_height.map(|h| format!(", confirmed in block {h}")).unwrap_or_default()
+		);
+	}
+
+	for wr in wrs {
+		println!(
+			"withdrawal {} #{} of X chain {} is {}{}{}",
+			wr.withdrawal_id,
+			wr.id,
+			wr.target.0,
+			wr.status,
+			wr.reason.map(|r| format!(": {r}")).unwrap_or_default(),
+			wr.finished_at.map(|t| format!(", finished at {t}")).unwrap_or_default()
+		);
+		println!(
+			"{} sat to {}, withdrawn in {} of X block {}, detected at {}",
+			wr.amount, wr.recipient, wr.x_hash, wr.x_block, wr.created_at
+		);
+
+		if let Some(txid) = wr.txid {
+			println!("paid in {txid}:{}", wr.vout.unwrap_or_default());
+		}
+		if let Some(hash) = wr.hash {
+			println!("completed in {hash}");
+		}
+	}
+
+	for xr in xrs {
+		println!(
+			"deposit {} #{} to X chain {} is {}{}",
+			xr.txid,
+			xr.id,
+			xr.target.0,
+			xr.status,
+			xr.finished_at.map(|t| format!(", finished at {t}")).unwrap_or_default()
+		);
+		println!(
+			"{} sat for {}, in block {}({}), detected at {}",
+			xr.amount, xr.recipient, xr.block_height, xr.block_hash, xr.created_at
+		);
+
+		if let Some(hash) = xr.hash {
+			println!("relayed in {hash}");
+		}
+
+		for t in records.get_history(xr.id).await? {
+			println!(
+				"{} {} -> {}: {}",
+				t.created_at,
+				t.from.map_or("-", XStatus::as_str),
+				t.to,
+				t.reason
+			);
+		}
+	}
+
+	Ok(())
+}
+
+/// Record the refund of a rejected deposit, sent back to the user in the confirmed Bitcoin
+/// transaction `refund_txid`.
+pub(super) async fn refund(
+	context: &Context,
+	conf: Conf,
+	txid: String,
+	refund_txid: String,
+) -> Result<()> {
+	let api = Source::new(conf.api, conf.network, NAME)?;
+	let records = Records(context);
+
+	records.init().await?;
+
+	let xrs = records.get_by_txid(txid.clone()).await?;
+	let not_refundable = |reason| ServiceError::NotRefundable { txid: txid.clone(), reason };
+
+	// A deposit being relayed, or relayed already, must never be refunded.
+	if let Some(xr) = xrs.iter().find(|xr| xr.status != XStatus::Rejected) {
+		Err(not_refundable(format!("its record is {}", xr.status)))?;
+	}
+
+	let Some(xr) = xrs.last() else { Err(not_refundable("no record found".into()))? };
+
+	if api.get_tx_block(&refund_txid).await?.is_none() {
+		Err(not_refundable(format!("{refund_txid} isn't confirmed")))?;
+	}
+
+	records.refund(xr.id, refund_txid.clone()).await?;
+
+	println!("x record of {txid} refunded in {refund_txid}");
+
+	Ok(())
+}
+
+/// Print the deposit address bound to the target, issuing a new one if there is none.
+pub(super) async fn deposit_address(
+	context: &Context,
+	conf: Conf,
+	x_target: XTarget,
+) -> Result<()> {
+	let keys = load_deposit(conf.deposit, conf.network)?
+		.ok_or(ServiceError::FailToExtractItem { item: "deposit", src: "btc".into() })?;
+	let records = Records(context);
+
+	records.init().await?;
+
+	let a = records
+		.issue_deposit_address(
+			x_target.id,
+			array_bytes::bytes2hex("0x", x_target.entity.as_bytes()),
+			move |i| Ok(keys.address(i)?.to_string()),
+		)
+		.await?;
+
+	println!("{}", a.address);
+	println!(
+		"index {}, issued at {}, bound to {}:{}",
+		a.index, a.created_at, a.target.0, a.recipient
+	);
+
+	Ok(())
+}
+
+/// Export the unsigned PSBT sweeping all the deposit addresses into the vault.
+pub(super) async fn sweep(context: &Context, conf: Conf) -> Result<Psbt> {
+	let Conf { network, vault, api, recovery, deposit, fee_conf, .. } = conf;
+	let keys = load_deposit(deposit, network)?
+		.ok_or(ServiceError::FailToExtractItem { item: "deposit", src: "btc".into() })?;
+	let recovery = load_recovery(recovery)?;
+	let vault = Vault::new(vault, recovery.as_ref(), network)?;
+	let api = Source::new(api, network, NAME)?;
+	let records = Records(context);
+
+	records.init().await?;
+
+	let mut deposits = Vec::new();
+
+	for a in records.get_deposit_addresses().await? {
+		let utxos = api.get_utxos(&a.address).await?;
+
+		if !utxos.is_empty() {
+			deposits.push((a.index, utxos));
+		}
+	}
+
+	let psbt = SweepTxBuilder {
+		fee_rate: fee_rate(&api, &fee_conf).await?,
+		keys: &keys,
+		deposits: &deposits,
+		vault: &vault,
+	}
+	.build_psbt()?;
+
+	println!("sweeping {} deposit addresses into {}", deposits.len(), vault.address());
+	println!("fee: {}", psbt.fee().map_err(BitcoinError::Psbt)?);
+
+	Ok(psbt)
+}
+
+/// Sweep all the vault UTXOs to the recipient through the recovery leaf.
+pub(super) async fn recover(
+	conf: Conf,
+	recipient: String,
+	keys: Vec<Keypair>,
+	internal_key: Option<XOnlyPublicKey>,
+	broadcast: bool,
+) -> Result<()> {
+	let Conf { network, vault, api, recovery: r, fee_conf, .. } = conf;
+	let recovery = load_recovery(r)?
+		.ok_or(ServiceError::FailToExtractItem { item: "recovery", src: "btc".into() })?;
+	let internal_key = match internal_key {
+		Some(k) => k,
+		None => Vault::new(vault, Some(&recovery), network)?.public_key(),
+	};
+	let api = Source::new(api, network, NAME)?;
+	let vault = recovery.address(internal_key, network);
+	let utxos = api.get_utxos(vault.to_string()).await?;
+	let fee_rate = fee_rate(&api, &fee_conf).await?;
+	let tx = RecoveryTxBuilder {
+		network,
+		fee_rate,
+		recovery: &recovery,
+		internal_key,
+		utxos: &utxos,
+		recipient: &recipient,
+		signers: &keys.into_iter().map(LocalSigner).collect::<Vec<_>>(),
+	}
+	.build()
+	.await?;
+	let tx_hex = bitcoin::consensus::encode::serialize_hex(&tx);
+	let fee = tx
+		.input
+		.iter()
+		.filter_map(|i| utxos.iter().find(|u| u.outpoint == i.previous_output))
+		.map(|u| u.value)
+		.sum::<Satoshi>()
+		- tx.output.iter().map(|o| o.value.to_sat()).sum::<Satoshi>();
+
+	println!("sweeping {} UTXOs of {vault} to {recipient}", utxos.len());
+	println!("{tx_hex}");
+
+	if broadcast {
+		println!("broadcasted {}", api.broadcast_standard(&tx, fee).await?);
+	}
+
+	Ok(())
+}
+
+/// Export the unsigned PSBT of a vault transfer, to be signed offline.
+pub(super) async fn export_psbt(
+	conf: Conf,
+	recipient: String,
+	amount: Satoshi,
+	x_target: XTarget,
+	key_origin: Option<KeySource>,
+) -> Result<Psbt> {
+	let Conf { network, vault, api, recovery, psbt, fee_conf, .. } = conf;
+	let recovery = load_recovery(recovery)?;
+	let vault = Vault::new(vault, recovery.as_ref(), network)?;
+	let api = Source::new(api, network, NAME)?;
+	let utxos = api.get_utxos(vault.address()).await?;
+	let psbt = XTxBuilder {
+		network,
+		fee_rate: fee_rate(&api, &fee_conf).await?,
+		sender: &vault,
+		utxos: &utxos,
+		coin_selection: fee_conf.coin_selection,
+		sighash_type: psbt.sighash_type,
+		recipient: &recipient,
+		x_target,
+		amount,
+	}
+	.build_psbt(key_origin)?;
+
+	println!("spending {} UTXOs of {}", psbt.inputs.len(), vault.address());
+	println!("fee: {}", psbt.fee().map_err(BitcoinError::Psbt)?);
+
+	Ok(psbt)
+}
+
+/// Finalize the PSBT signed offline against the exported one, and broadcast it if required.
+pub(super) async fn import_psbt(
+	conf: Conf,
+	exported: Psbt,
+	signed: Psbt,
+	broadcast: bool,
+) -> Result<()> {
+	let tx = psbt::finalize(&exported, signed)?;
+	let tx_hex = bitcoin::consensus::encode::serialize_hex(&tx);
+
+	println!("{tx_hex}");
+
+	if broadcast {
+		let api = Source::new(conf.api, conf.network, NAME)?;
+		let fee = exported.fee().map_err(BitcoinError::Psbt)?.to_sat();
+
+		println!("broadcasted {}", api.broadcast_standard(&tx, fee).await?);
+	}
+
+	Ok(())
+}
+
+async fn fee_rate<S>(api: &S, fee_conf: &FeeConf) -> Result<Satoshi>
+where
+	S: BtcSource,
+{
+	match fee_conf.force {
+		Some(r) => Ok(r),
+		None => Ok(api.get_recommended_fee().await?.of(fee_conf.strategy) + fee_conf.extra),
+	}
+}
+
+fn load_recovery(conf: Option<RecoveryConf>) -> Result<Option<Recovery>> {
+	conf.map(|RecoveryConf { keys, threshold, delay }| Recovery::new(keys, threshold, delay))
+		.transpose()
+}
+
+// Decode the signed transaction in hex.
+fn decode_tx(tx_hex: &str) -> Result<Transaction> {
+	Ok(consensus::encode::deserialize_hex(tx_hex).map_err(BitcoinError::from)?)
+}
+
+fn load_deposit(conf: Option<DepositConf>, network: Network) -> Result<Option<DepositKeys>> {
+	conf.map(|DepositConf { xpub, fingerprint, derivation_path }| {
+		DepositKeys::new(xpub, fingerprint.zip(derivation_path), network)
+	})
+	.transpose()
+}
+
+// Key of the vault, held by this relayer, a signer daemon, the relayers together, or offline.
+#[derive(Debug)]
+enum Vault {
+	Key(TaprootKey),
+	Keystore(TaprootKey<KeystoreSigner>),
+	Remote(TaprootKey<RemoteSigner>),
+	Frost(frost::Coordinator),
+	WatchOnly(TaprootKey<WatchOnly>),
+}
+impl Vault {
+	fn new(conf: VaultConf, recovery: Option<&Recovery>, network: Network) -> Result<Self> {
+		let merkle_root = recovery.map(Recovery::merkle_root);
+		let VaultConf { keystore, vault_secret_key, vault_public_key, remote_signer, frost } = conf;
+		let vault = match (keystore, vault_secret_key, vault_public_key, remote_signer, frost) {
+			(Some(c), None, None, None, None) => {
+				let keystore = Keystore::load(&c.path)?;
+				let passphrase =
+					c.passphrase.read(&format!("passphrase of the keystore {:?}: ", c.path))?;
+
+				Self::Keystore(TaprootKey::new(
+					KeystoreSigner::unlock(&keystore, &passphrase)?,
+					merkle_root,
+					network,
+				))
+			},
+			(None, Some(k), None, None, None) => {
+				tracing::warn!(
+					"`btc.vault-secret-key` is deprecated, move the key into a keystore with the \
+					`keystore import` command"
+				);
+
+				Self::Key(TaprootKey::from_untweaked_keypair(
+					k.trim_start_matches("0x").parse()?,
+					merkle_root,
+					network,
+				))
+			},
+			(None, None, Some(k), None, None) =>
+				Self::WatchOnly(TaprootKey::new(WatchOnly(k), merkle_root, network)),
+			(None, None, None, Some(c), None) => Self::Remote(TaprootKey::new(
+				RemoteSigner::new(
+					c.endpoint,
+					c.public_key,
+					c.token.map(|t| t.read("token of the remote signer: ")).transpose()?,
+				)?,
+				merkle_root,
+				network,
+			)),
+			(None, None, None, None, Some(c)) => {
+				let share = frost::KeyShare::load(&c.share)?;
+				let public = share.public.clone();
+				let participant = frost::Participant::new(
+					share,
+					super::signer::load_policy(c.policy.as_deref())?,
+				);
+				let signers = iter::once(frost::Transport::Local(Arc::new(participant)))
+					.chain(c.peers.into_iter().map(frost::Transport::Unix))
+					.collect();
+
+				Self::Frost(frost::Coordinator::new(public, merkle_root, signers, network)?)
+			},
+			_ => Err(ServiceError::InvalidVaultConf)?,
+		};
+
+		Ok(vault)
+	}
+
+	fn address(&self) -> &str {
+		match self {
+			Self::Key(k) => &k.address,
+			Self::Keystore(k) => &k.address,
+			Self::Remote(k) => &k.address,
+			Self::Frost(c) => &c.address,
+			Self::WatchOnly(k) => &k.address,
+		}
+	}
+}
+impl Signer for Vault {
+	fn public_key(&self) -> XOnlyPublicKey {
+		match self {
+			Self::Key(k) => k.public_key(),
+			Self::Keystore(k) => k.public_key(),
+			Self::Remote(k) => k.public_key(),
+			Self::Frost(c) => c.public_key(),
+			Self::WatchOnly(k) => k.public_key(),
+		}
+	}
+
+	async fn sign(&self, request: &SignRequest) -> Result<Vec<schnorr::Signature>> {
+		match self {
+			Self::Key(k) => k.sign(request).await,
+			Self::Keystore(k) => k.sign(request).await,
+			Self::Remote(k) => k.sign(request).await,
+			Self::Frost(c) => c.sign(request).await,
+			Self::WatchOnly(k) => k.sign(request).await,
+		}
+	}
+}
+impl KeySpendSigner for Vault {
+	fn script_public_key(&self) -> &ScriptBuf {
+		match self {
+			Self::Key(k) => k.script_public_key(),
+			Self::Keystore(k) => k.script_public_key(),
+			Self::Remote(k) => k.script_public_key(),
+			Self::Frost(c) => c.script_public_key(),
+			Self::WatchOnly(k) => k.script_public_key(),
+		}
+	}
+
+	fn merkle_root(&self) -> Option<TapNodeHash> {
+		match self {
+			Self::Key(k) => k.merkle_root(),
+			Self::Keystore(k) => k.merkle_root(),
+			Self::Remote(k) => k.merkle_root(),
+			Self::Frost(c) => c.merkle_root(),
+			Self::WatchOnly(k) => k.merkle_root(),
+		}
+	}
+}
+
 #[derive(Debug)]
-pub(super) struct Relayer {
+pub(super) struct Relayer<S, H = Client>
+where
+	H: Http,
+{
 	context: Context,
-	api: Api<Client>,
+	api: S,
+	submitters: Vec<Submitter<H>>,
 	network: Network,
-	vault: TaprootKey,
+	vault: Vault,
+	deposit: Option<DepositKeys>,
+	payout: PayoutConf,
+	fee_bump: FeeBumpConf,
+	consolidation: Option<ConsolidationConf>,
+	min_confirmations: BlockNumber,
+	accept_legacy_x_target: bool,
 	fee_conf: FeeConf,
+	// Number of reverted simulations of each confirmed record, since the start.
+	reverts: Mutex<HashMap<u64, u32>>,
 }
-impl Relayer {
-	pub fn new(conf: Conf, context: Context) -> Result<Self> {
-		let Conf { network, vault_secret_key, fee_conf } = conf;
-		let api = Api {
-			http: Client(ClientBuilder::new().user_agent(Self::NAME).build()?),
-			base_uri: if matches!(network, Network::Testnet) {
-				"https://mempool.space/testnet/api"
-			} else {
-				"https://mempool.space/api"
-			},
-		};
-		let vault = TaprootKey::from_untweaked_keypair(
-			vault_secret_key.trim_start_matches("0x").parse()?,
+impl<S, H> Relayer<S, H>
+where
+	S: BtcSource,
+	H: Http,
+{
+	fn new(conf: Conf, api: S, submitters: Vec<Submitter<H>>, context: Context) -> Result<Self> {
+		let Conf {
 			network,
-		);
+			vault,
+			api: _,
+			recovery,
+			deposit,
+			payout,
+			fee_bump,
+			consolidation,
+			min_confirmations,
+			accept_legacy_x_target,
+			psbt: _,
+			fee_conf,
+		} = conf;
+		let recovery = load_recovery(recovery)?;
+		let vault = Vault::new(vault, recovery.as_ref(), network)?;
+		let deposit = load_deposit(deposit, network)?;
 
-		Ok(Self { context, api, network, vault, fee_conf })
+		Ok(Self {
+			context,
+			api,
+			submitters,
+			network,
+			vault,
+			deposit,
+			payout,
+			fee_bump,
+			consolidation,
+			min_confirmations,
+			accept_legacy_x_target,
+			fee_conf,
+			reverts: Default::default(),
+		})
 	}
 
-	// TODO
-	#[allow(unused)]
-	async fn transfer(&self) -> Result<()> {
-		let fee_rate =
-			self.api.get_recommended_fee().await?.of(self.fee_conf.strategy) + self.fee_conf.extra;
+	// Reject the records whose block is no longer part of the best chain, so that the tracking
+	// resyncs from the fork point.
+	async fn handle_reorg(&self, tip: BlockNumber) -> Result<()> {
+		let mut orphaned = None;
 
-		tracing::info!("fee rate: {fee_rate}");
+		while let Some((bn, hash)) = self.get_block_before(orphaned).await? {
+			if bn <= tip as _ && self.api.get_block_hash(bn as _).await? == hash {
+				break;
+			}
 
-		let utxos = self
-			.api
-			.get_utxos(&self.vault.address)
-			.await?
-			.into_iter()
-			.map(TryInto::try_into)
-			.collect::<Result<Vec<_>>>()?;
-		let tx_hex = XTxBuilder {
-			network: self.network,
-			fee_rate,
-			sender: &self.vault,
-			utxos: utxos.as_slice(),
-			recipient: "tb1pedlrf67ss52md29qqkzr2avma6ghyrt4jx9ecp9457qsl75x247shsh6th",
-			x_target: XTarget { id: 0_u32.into(), entity: [b'x'; 32].into() },
-			amount: 1,
+			tracing::warn!("block {bn}({hash}) has been orphaned");
+
+			orphaned = Some(bn);
+		}
+
+		let Some(bn) = orphaned else {
+			return Ok(());
+		};
+		let xrs = self.get_since(bn).await?;
+		let n = xrs.len();
+
+		for xr in xrs {
+			let reason = format!("block {} has been orphaned", xr.block_hash);
+
+			if matches!(xr.status, XStatus::Detected | XStatus::Confirmed) {
+				self.reject(xr.id, reason).await?;
+
+				continue;
+			}
+
+			// Records are only submitted after reaching the required confirmations, a reorg deeper
+			// than that can't be undone by the relayer.
+			self.orphan(xr.id, reason).await?;
+
+			tracing::error!(
+				"x record {} in block {} has been relayed in {} but orphaned, \
+				manual intervention is required",
+				xr.txid,
+				xr.block_height,
+				xr.hash.unwrap_or_default()
+			);
 		}
-		.build()?;
 
-		self.api.broadcast(tx_hex).await?;
+		tracing::warn!("reorg detected, rejected {n} x records, resyncing from block {bn}");
+
+		Ok(())
+	}
+
+	// Confirm the detected records which have reached the required confirmations, along with
+	// their SPV proofs.
+	//
+	// The blocks of the records have been checked against the best chain in `handle_reorg`, so
+	// matching the header hash is enough to anchor the proof.
+	async fn check_confirmations(&self, tip: BlockNumber) -> Result<()> {
+		for xr in self.get_by_status(XStatus::Detected).await? {
+			let confirmations = (tip as u64 + 1).saturating_sub(xr.block_height) as BlockNumber;
+
+			if confirmations < self.min_confirmations {
+				continue;
+			}
+
+			let proof = self.api.get_spv_proof(&xr.txid, &xr.block_hash).await?;
+
+			// Retry in the next round, the data source might be lagging behind.
+			if let Err(e) = proof.verify(
+				&xr.txid.parse().map_err(BitcoinError::HexToArray)?,
+				&xr.block_hash.parse().map_err(BitcoinError::HexToArray)?,
+			) {
+				tracing::warn!("{e}");
+
+				continue;
+			}
+
+			self.confirm(xr.id, confirmations, proof.encode()).await?;
+
+			tracing::info!("x record confirmed: {}", xr.txid);
+		}
 
 		Ok(())
 	}
 
 	async fn track(&self) -> Result<()> {
+		let tip = self.api.get_tip_height().await?;
+
+		// The records migrated from the first schema version are anchored to the best chain, the
+		// ones above the tip are orphaned.
+		for bn in self.get_heights_without_hash().await?.into_iter().filter(|bn| *bn <= tip as _) {
+			self.fill_block_hash(bn, self.api.get_block_hash(bn as _).await?).await?;
+		}
+
+		self.handle_reorg(tip).await?;
+
 		let (bn, txid) =
 			self.get_latest().await?.map(|xr| (xr.block_height, xr.txid)).unwrap_or_default();
-		let mut after = None;
+		let mut after = None::<String>;
 		let mut xrs = Vec::new();
 
 		'outter: loop {
-			let txs = self.api.get_addr_txs_chain(&self.vault.address, after.as_ref()).await?;
+			let txs = self
+				.api
+				.get_addr_txs_chain_since(self.vault.address(), bn as _, after.as_ref())
+				.await?;
 			let len = txs.len();
 
 			for tx in txs {
 				// Reached the latest transaction, no new incoming transactions.
 				if tx.txid == txid
-				// Already iterated through all unrecorded txs.
-					&& tx.status.block_height <= bn as _
+				// Already iterated through all unrecorded blocks.
+					|| (tx.block_height as u64) < bn
 				{
 					break 'outter;
 				}
 
+				after = Some(tx.txid.clone());
+
+				// The vault spends pay the change to the vault, e.g. the payouts, the vault
+				// transfers, and the transactions finalized from the imported PSBTs.
+				if tx.vin.iter().any(|v| v.prevout_address.as_deref() == Some(self.vault.address()))
+					|| self.get_payout(tx.txid.clone()).await?.is_some()
+				{
+					continue;
+				}
+
 				let mut value = 0;
 				let mut xt = None;
 
 				for v in tx.vout {
-					// TODO: more types.
-					if v.scriptpubkey_type == "scriptpubkey_type"
-						&& v.scriptpubkey_address
-							.map(|a| a == self.vault.address)
-							.unwrap_or_default()
-					{
+					// Sum all the outputs paying to the vault, wherever they are.
+					if v.scriptpubkey_address.as_deref() == Some(self.vault.address()) {
 						value += v.value;
 					}
-					if v.scriptpubkey_type == "op_return" {
+					// Only the first `OP_RETURN` output is taken as the `XTarget`.
+					if v.scriptpubkey_type == ScriptType::OpReturn && xt.is_none() {
 						xt = Some(v.scriptpubkey_asm);
-
-						break;
 					}
 				}
 
-				let Some(xt) = xt.and_then(|xt| util::extract_xtarget(xt).ok()) else {
+				let Some(xt) =
+					xt.and_then(|xt| util::extract_xtarget(xt, self.accept_legacy_x_target).ok())
+				else {
 					// Not a valid cross-chain tx.
 					continue;
 				};
+				let recipient = array_bytes::bytes2hex("0x", xt.entity.as_bytes());
+
+				// Recorded already, the latest record might be of a deposit address in the same
+				// block.
+				if self.is_recorded(&tx.txid, xt.id, &recipient).await? {
+					continue;
+				}
 
 				xrs.push(XRecord {
-					block_height: tx.status.block_height as _,
+					id: 0,
+					block_height: tx.block_height as _,
+					block_hash: tx.block_hash,
 					txid: tx.txid.clone(),
 					target: xt.id,
-					recipient: array_bytes::bytes2hex("0x", xt.entity.as_bytes()),
+					recipient,
 					amount: value as _,
+					status: XStatus::Detected,
 					hash: None,
+					proof: None,
 					created_at: Utc::now(),
 					finished_at: None,
 				});
 
 				// TODO: improve log.
-				tracing::info!("x record found: {}", tx.txid);
-
-				after = Some(tx.txid);
+				tracing::info!("x record detected: {}", tx.txid);
 			}
 
-			if len < 25 {
+			if len < S::PAGE_SIZE {
 				// No more txs to track.
 				break;
 			}
@@ -145,50 +763,2353 @@ impl Relayer {
 			time::sleep(Duration::from_millis(1_000)).await;
 		}
 
-		// Iterate through txs in reverse order to ensure the later tx has a larger id when
-		// inserting into the DB.
-		self.insert(xrs.into_iter().rev()).await?;
+		// Iterate through txs in reverse order, and sort them along with the deposits to the
+		// deposit addresses, to ensure the later tx has a larger id when inserting into the DB.
+		let (dxrs, cursors) = self.track_deposits(tip).await?;
+		let mut xrs = xrs.into_iter().rev().chain(dxrs).collect::<Vec<_>>();
+
+		xrs.sort_by_key(|xr| xr.block_height);
+		self.insert(xrs.into_iter()).await?;
+		// A failure in between only rescans the deposits recorded already.
+		self.set_deposit_cursors(cursors).await?;
+		self.check_confirmations(tip).await?;
 
 		Ok(())
 	}
-}
-impl X for Relayer {
-	const NAME: &'static str = "btc-x";
-}
-impl Sql for Relayer {
-	async fn pool(&self) -> &Arc<Pool> {
-		&self.context.sql
-	}
-}
-impl Relay for Relayer {
-	fn name(&self) -> &'static str {
-		Self::NAME
+
+	// Credit the deposits to the issued deposit addresses to their bound targets, sorted with
+	// oldest first for each address, along with the new cursors of the scanned addresses.
+	//
+	// An address is only scanned once per tip, from `min_confirmations` below its cursor, so that
+	// the blocks replaced by a reorg are scanned again.
+	async fn track_deposits(&self, tip: BlockNumber) -> Result<(Vec<XRecord>, Vec<(u32, u64)>)> {
+		let mut xrs = Vec::new();
+		let mut cursors = Vec::new();
+
+		if self.deposit.is_none() {
+			return Ok((xrs, cursors));
+		}
+
+		for a in self.get_deposit_addresses().await? {
+			let cursor = self.get_deposit_cursor(a.index).await?;
+
+			if cursor == Some(tip as _) {
+				continue;
+			}
+
+			let since = cursor.map_or(0, |c| c.saturating_sub(self.min_confirmations as _));
+			let mut after = None::<String>;
+			let mut axrs = Vec::new();
+
+			'outter: loop {
+				let txs = self
+					.api
+					.get_addr_txs_chain_since(&a.address, since as _, after.as_ref())
+					.await?;
+				let len = txs.len();
+
+				for tx in txs {
+					// Reached the recorded transactions of the address, or the scanned blocks.
+					if (tx.block_height as u64) < since
+						|| self.is_recorded(&tx.txid, a.target, &a.recipient).await?
+					{
+						break 'outter;
+					}
+
+					after = Some(tx.txid.clone());
+
+					let value = tx
+						.vout
+						.iter()
+						.filter(|v| v.scriptpubkey_address.as_deref() == Some(&a.address))
+						.map(|v| v.value)
+						.sum::<types::Satoshi>();
+
+					// E.g. the sweep spending from the address.
+					if value == 0 {
+						continue;
+					}
+
+					axrs.push(XRecord {
+						id: 0,
+						block_height: tx.block_height as _,
+						block_hash: tx.block_hash,
+						txid: tx.txid.clone(),
+						target: a.target,
+						recipient: a.recipient.clone(),
+						amount: value as _,
+						status: XStatus::Detected,
+						hash: None,
+						proof: None,
+						created_at: Utc::now(),
+						finished_at: None,
+					});
+
+					tracing::info!("x record detected: {}, to {}", tx.txid, a.address);
+				}
+
+				if len < S::PAGE_SIZE {
+					break;
+				}
+
+				time::sleep(Duration::from_millis(1_000)).await;
+			}
+
+			xrs.extend(axrs.into_iter().rev());
+			cursors.push((a.index, tip as _));
+		}
+
+		Ok((xrs, cursors))
 	}
 
-	fn init(&self) -> Result<()> {
-		task::block_in_place(|| {
-			self.context.runtime.block_on(async { <Self as Sql>::init(self).await })
-		})
+	// Whether the transaction has been recorded for the target, the rejected records are ignored.
+	async fn is_recorded(&self, txid: &str, target: Id, recipient: &str) -> Result<bool> {
+		Ok(self.get_by_txid(txid.into()).await?.into_iter().any(|xr| {
+			xr.status != XStatus::Rejected && xr.target.0 == target.0 && xr.recipient == recipient
+		}))
 	}
 
-	fn run(&self) -> Result<()> {
-		tracing::info!("running {}", self.name());
+	// Submit the confirmed records to their X chains, and finalize the submitted ones once
+	// executed.
+	async fn relay(&self) -> Result<()> {
+		for s in &self.submitters {
+			for xr in self.get_by_status(XStatus::Submitted).await? {
+				if xr.target.0 != s.id.0 {
+					continue;
+				}
 
-		let ss = unsafe { &*(self as *const Self) };
+				let Some((hash, receipt)) =
+					s.check_submission(self, xr.hash.unwrap_or_default()).await?
+				else {
+					// Still pending.
+					continue;
+				};
 
-		task::block_in_place(|| {
-			self.context.runtime.block_on(async move {
-				let mut interval = time::interval(Duration::from_millis(5_000));
-				// TODO: https://github.com/rust-lang/rust/issues/35121.
+				if receipt.succeeded() {
+					self.finalize(xr.id).await?;
 
-				loop {
-					tokio::select! {
-						// TODO: test only.
-						// _ = ss.transfer() => { return Ok(()); }
-						_ = interval.tick() => { ss.track().await?; }
-					}
+					tracing::info!(
+						"x record finalized: {}, in X block {}",
+						xr.txid,
+						receipt.block_number
+					);
+				} else {
+					self.reject(xr.id, format!("execution reverted in {hash}")).await?;
+
+					tracing::warn!("x record rejected: {}, execution reverted", xr.txid);
 				}
-			})
-		})
+			}
+
+			let xrs = self
+				.get_by_status(XStatus::Confirmed)
+				.await?
+				.into_iter()
+				.filter(|xr| xr.target.0 == s.id.0)
+				.collect::<Vec<_>>();
+
+			if xrs.is_empty() {
+				continue;
+			}
+
+			let mut nonce = s.next_nonce(self).await?;
+
+			for xr in xrs {
+				match s.submit(&xr, nonce).await {
+					Ok(submission) => {
+						self.submit(xr.id, submission.clone()).await?;
+						self.reverts.lock().unwrap().remove(&xr.id);
+
+						nonce += 1;
+
+						tracing::info!("x record submitted: {}, in {}", xr.txid, submission.hash);
+
+						s.send_submission(&submission).await;
+					},
+					// E.g. the light client hasn't reached the record's block yet, retried in the
+					// next round unless it has been relayed already, or keeps reverting.
+					Err(e) if submitter::is_reverted(&e) => {
+						let reverts = {
+							let mut reverts = self.reverts.lock().unwrap();
+							let n = reverts.entry(xr.id).or_default();
+
+							*n += 1;
+
+							*n
+						};
+
+						if !submitter::is_relayed(&e) && reverts < MAX_SIMULATION_REVERTS {
+							tracing::warn!(
+								"submission of x record {} reverted in simulation: {e}",
+								xr.txid
+							);
+
+							continue;
+						}
+
+						self.reject(xr.id, format!("simulation reverted: {e}")).await?;
+						self.reverts.lock().unwrap().remove(&xr.id);
+
+						tracing::warn!("x record rejected: {}, simulation reverted", xr.txid);
+					},
+					Err(e) => Err(e)?,
+				}
+			}
+		}
+
+		Ok(())
 	}
+
+	// Pay the withdrawals from the X chains, and complete them with the proofs of their payouts.
+	async fn withdraw(&self) -> Result<()> {
+		let tip = self.api.get_tip_height().await?;
+
+		self.watch_withdrawals().await?;
+		self.pay_batches().await?;
+		self.check_payouts(tip).await?;
+		self.complete_withdrawals(tip).await
+	}
+
+	// Record the withdraw events which have reached the required X confirmations.
+	async fn watch_withdrawals(&self) -> Result<()> {
+		for s in &self.submitters {
+			let Some(c) = &s.withdrawal else {
+				continue;
+			};
+			let safe = s.block_number().await?.saturating_sub(c.confirmations);
+			let mut from = self.get_withdrawal_cursor(s.id).await?.map_or(c.start_block, |b| b + 1);
+
+			while from <= safe {
+				let to = safe.min(from + WITHDRAWAL_SCAN_RANGE - 1);
+				let wrs = s
+					.get_withdrawals(from, to)
+					.await?
+					.into_iter()
+					.map(|(x_block, x_hash, e)| {
+						tracing::info!(
+							"withdrawal detected: {x_hash}, {} to {}",
+							e.amount,
+							e.recipient
+						);
+
+						WithdrawalRecord {
+							id: 0,
+							target: s.id,
+							withdrawal_id: array_bytes::bytes2hex("0x", e.id),
+							x_block,
+							x_hash,
+							recipient: e.recipient,
+							amount: e.amount,
+							status: WithdrawalStatus::Detected,
+							txid: None,
+							vout: None,
+							hash: None,
+							reason: None,
+							created_at: Utc::now(),
+							finished_at: None,
+						}
+					})
+					.collect();
+
+				self.insert_withdrawals(s.id, to, wrs).await?;
+
+				from = to + 1;
+			}
+		}
+
+		Ok(())
+	}
+
+	// Pay the detected withdrawals in batches, each withdrawal at the output of its index in the
+	// batch.
+	//
+	// A batch is paid once full, or once its oldest withdrawal has waited for the flush interval.
+	// The payout is recorded before the broadcast and only ever broadcasted again or replaced by
+	// fee afterwards, so a withdrawal is never paid twice, even across restarts.
+	async fn pay_batches(&self) -> Result<()> {
+		let wrs = self.get_withdrawals_by_status(WithdrawalStatus::Detected).await?;
+
+		if wrs.is_empty() {
+			return Ok(());
+		}
+		if let Vault::WatchOnly(_) = self.vault {
+			tracing::warn!("{} withdrawals can't be paid by the watch-only vault", wrs.len());
+
+			return Ok(());
+		}
+
+		let mut payable = Vec::new();
+
+		for wr in wrs {
+			let amount = match Satoshi::try_from(wr.amount) {
+				Ok(a) if a > 0 => a,
+				_ => {
+					self.reject_withdrawal(wr.id, format!("invalid amount {}", wr.amount)).await?;
+
+					tracing::warn!("withdrawal rejected: {}, invalid amount", wr.withdrawal_id);
+
+					continue;
+				},
+			};
+
+			let recipient = match wr
+				.recipient
+				.parse::<Address<NetworkUnchecked>>()
+				.and_then(|a| a.require_network(self.network))
+			{
+				Ok(a) => a,
+				Err(e) => {
+					self.reject_withdrawal(wr.id, format!("invalid recipient: {e}")).await?;
+
+					tracing::warn!("withdrawal rejected: {}, invalid recipient", wr.withdrawal_id);
+
+					continue;
+				},
+			};
+			let output =
+				TxOut { script_pubkey: recipient.script_pubkey(), value: Amount::from_sat(amount) };
+
+			// Nodes wouldn't relay the payout.
+			if policy::is_dust(&output) {
+				self.reject_withdrawal(
+					wr.id,
+					format!(
+						"amount {amount} below the dust limit {}",
+						output.script_pubkey.minimal_non_dust().to_sat()
+					),
+				)
+				.await?;
+
+				tracing::warn!("withdrawal rejected: {}, dust amount", wr.withdrawal_id);
+
+				continue;
+			}
+
+			payable.push((wr, amount));
+		}
+
+		let PayoutConf { batch_size, flush_interval } = self.payout;
+		let mut spent = self.spent_outpoints().await?;
+
+		'batch: for batch in payable.chunks(batch_size.max(1)) {
+			let waited = (Utc::now() - batch[0].0.created_at).num_seconds();
+
+			// Wait for the batch to fill.
+			if batch.len() < batch_size && waited < flush_interval as i64 {
+				break;
+			}
+
+			let reason = match self.pay_batch(batch, &mut spent).await {
+				Ok(()) => continue,
+				// Paid once the vault is funded, in order.
+				Err(Error::Chain(ChainError::InsufficientFunds { required, available })) => {
+					tracing::warn!(
+						"{} withdrawals are waiting for funds, required {required}, available \
+						{available}",
+						batch.len()
+					);
+
+					break;
+				},
+				Err(Error::Chain(ChainError::NonStandardTx { reason })) => reason,
+				Err(e) => Err(e)?,
+			};
+
+			// The batch as a whole might be the issue, e.g. too heavy, so each withdrawal is paid
+			// on its own, and the ones which are still not standard are rejected.
+			tracing::warn!("batch of {} withdrawals isn't standard: {reason}", batch.len());
+
+			for w in batch {
+				match self.pay_batch(slice::from_ref(w), &mut spent).await {
+					Ok(()) => (),
+					Err(Error::Chain(ChainError::InsufficientFunds { required, available })) => {
+						tracing::warn!(
+							"withdrawal {} is waiting for funds, required {required}, available \
+							{available}",
+							w.0.withdrawal_id
+						);
+
+						break 'batch;
+					},
+					Err(Error::Chain(ChainError::NonStandardTx { reason })) => {
+						self.reject_withdrawal(w.0.id, format!("non-standard payout: {reason}"))
+							.await?;
+
+						tracing::warn!(
+							"withdrawal rejected: {}, non-standard payout",
+							w.0.withdrawal_id
+						);
+					},
+					Err(e) => Err(e)?,
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	// Pay the withdrawals in a transaction spending the confirmed vault UTXOs which aren't `spent`
+	// yet.
+	//
+	// An unconfirmed UTXO might never be, e.g. the change of a payout replaced by fee, or a
+	// deposit replaced by its sender, which would leave the payout stuck.
+	async fn pay_batch(
+		&self,
+		batch: &[(WithdrawalRecord, Satoshi)],
+		spent: &mut HashSet<OutPoint>,
+	) -> Result<()> {
+		let utxos = self
+			.api
+			.get_utxos(self.vault.address())
+			.await?
+			.into_iter()
+			.filter(|u| u.confirmed && !spent.contains(&u.outpoint))
+			.collect::<Vec<_>>();
+		let payouts = batch
+			.iter()
+			.map(|(wr, amount)| Payout { recipient: &wr.recipient, amount: *amount })
+			.collect::<Vec<_>>();
+		let fee_rate = fee_rate(&self.api, &self.fee_conf).await?;
+		let tx = PayoutTxBuilder {
+			network: self.network,
+			fee_rate,
+			sender: &self.vault,
+			utxos: &utxos,
+			coin_selection: self.fee_conf.coin_selection,
+			payouts: &payouts,
+		}
+		.build()
+		.await?;
+		let txid = tx.compute_txid().to_string();
+		let tx_hex = consensus::encode::serialize_hex(&tx);
+		let prevouts = tx
+			.input
+			.iter()
+			.filter_map(|i| utxos.iter().find(|u| u.outpoint == i.previous_output))
+			.map(|u| TxOut {
+				script_pubkey: self.vault.script_public_key().clone(),
+				value: Amount::from_sat(u.value),
+			})
+			.collect::<Vec<_>>();
+
+		self.pay_withdrawals(
+			PayoutRecord {
+				txid: txid.clone(),
+				origin: txid.clone(),
+				tx: tx_hex.clone(),
+				fee_rate,
+				prevouts: consensus::serialize(&prevouts),
+				block_height: None,
+				block_hash: None,
+				proof: None,
+				created_at: Utc::now(),
+			},
+			batch.iter().zip(0..).map(|((wr, _), vout)| (wr.id, vout)).collect(),
+		)
+		.await?;
+		spent.extend(tx.input.iter().map(|i| i.previous_output));
+
+		match self.api.broadcast(tx_hex).await {
+			Ok(_) => tracing::info!("{} withdrawals paid in {txid}", batch.len()),
+			// Broadcasted again until confirmed.
+			Err(e) => tracing::warn!("fail to broadcast the payout {txid}: {e}"),
+		}
+
+		Ok(())
+	}
+
+	// Confirm the payouts which have reached the required confirmations, along with their SPV
+	// proofs. Any version of a replaced payout might be the confirmed one.
+	//
+	// A payout whose inputs have been double spent by another transaction with the required
+	// confirmations is cancelled, and its withdrawals are paid again.
+	async fn check_payouts(&self, tip: BlockNumber) -> Result<()> {
+		for p in self.get_unconfirmed_payouts().await? {
+			let versions = self.get_payout_versions(p.origin.clone()).await?;
+			let mut included = None;
+
+			for v in &versions {
+				if let Some(block) = self.api.get_tx_block(&v.txid).await? {
+					included = Some((v.txid.clone(), block));
+
+					break;
+				}
+			}
+
+			let Some((txid, (bn, block_hash))) = included else {
+				match self.api.get_double_spend(&decode_tx(&p.tx)?).await? {
+					// The versions spend the same inputs, the confirmed one is found next round.
+					Some((txid, _)) if versions.iter().any(|v| v.txid == txid) => (),
+					Some((txid, bn)) =>
+						if (tip + 1).saturating_sub(bn) >= self.min_confirmations {
+							self.cancel_payout(p.txid.clone()).await?;
+
+							tracing::warn!(
+								"payout {} double spent by {txid}, paying its withdrawals again",
+								p.txid
+							);
+						},
+					None => self.bump_payout(p).await?,
+				}
+
+				continue;
+			};
+
+			if (tip + 1).saturating_sub(bn) < self.min_confirmations {
+				continue;
+			}
+
+			let proof = self.api.get_spv_proof(&txid, &block_hash).await?;
+
+			// Retry in the next round, the data source might be lagging behind.
+			if let Err(e) = proof.verify(
+				&txid.parse().map_err(BitcoinError::HexToArray)?,
+				&block_hash.parse().map_err(BitcoinError::HexToArray)?,
+			) {
+				tracing::warn!("{e}");
+
+				continue;
+			}
+			if txid != p.txid {
+				self.revert_payout(p.txid.clone(), txid.clone()).await?;
+
+				tracing::warn!("payout {} has been replaced by {txid} confirmed earlier", p.txid);
+			}
+
+			self.confirm_payout(txid.clone(), bn as _, block_hash, proof.encode()).await?;
+
+			tracing::info!("payout confirmed: {txid}");
+		}
+
+		Ok(())
+	}
+
+	// Broadcast the unconfirmed payout again in case it has been dropped, or bump its fee rate
+	// once it has been stuck for too long.
+	//
+	// A payout is replaced by fee if configured so, and accelerated by a child spending its change
+	// if the change can't pay the higher fee. Once accelerated, only its child is replaced.
+	//
+	// A payout whose change is spent by another recorded transaction, e.g. a payout made before the
+	// block including it was orphaned, is left as is, since the replacement would orphan that
+	// transaction. Bumping the descendant accelerates it as well.
+	async fn bump_payout(&self, p: PayoutRecord) -> Result<()> {
+		let FeeBumpConf { bump_after, max_fee_rate, method } = self.fee_bump;
+		let child = self.get_latest_cpfp(p.txid.clone()).await?;
+		let (bumped_at, package_fee_rate) =
+			child.as_ref().map_or((p.created_at, p.fee_rate), |c| (c.created_at, c.fee_rate));
+
+		if (Utc::now() - bumped_at).num_seconds() < bump_after as i64 {
+			return self.rebroadcast_payout(p, child).await;
+		}
+
+		let mut spent = self.spent_outpoints().await?;
+
+		// Replaced along with the payout.
+		if let Some(c) = &child {
+			for i in decode_tx(&c.tx)?.input {
+				spent.remove(&i.previous_output);
+			}
+		}
+		if spent.iter().any(|o| o.txid.to_string() == p.txid) {
+			tracing::warn!("payout {} has descendants, left to them to be accelerated", p.txid);
+
+			return self.rebroadcast_payout(p, child).await;
+		}
+
+		// The bump must pay at least the minimum relay fee rate on top.
+		let fee_rate =
+			fee_rate(&self.api, &self.fee_conf).await?.max(package_fee_rate + 1).min(max_fee_rate);
+
+		if fee_rate <= package_fee_rate {
+			tracing::warn!("payout {} is stuck at the maximum fee rate {max_fee_rate}", p.txid);
+
+			return self.rebroadcast_payout(p, child).await;
+		}
+
+		let bumped = match (method, &child) {
+			(BumpMethod::Rbf, None) =>
+				self.replace_by_fee(&p, fee_rate).await?
+					|| self.pay_for_parent(&p, fee_rate, None).await?,
+			_ => self.pay_for_parent(&p, fee_rate, child.as_ref()).await?,
+		};
+
+		if !bumped {
+			self.rebroadcast_payout(p, child).await?;
+		}
+
+		Ok(())
+	}
+
+	// Broadcast the payout again along with its child if any.
+	async fn rebroadcast_payout(&self, p: PayoutRecord, child: Option<CpfpRecord>) -> Result<()> {
+		let result = match child {
+			Some(c) => self.api.submit_package(vec![p.tx, c.tx]).await,
+			None => self.api.broadcast(p.tx).await.map(|_| ()),
+		};
+
+		if let Err(e) = result {
+			tracing::debug!("fail to broadcast the payout {} again: {e}", p.txid);
+		}
+
+		Ok(())
+	}
+
+	// Replace the payout by the same payouts spending all its UTXOs at the fee rate, so that only
+	// one of them can ever be confirmed.
+	//
+	// Returns `false` if the change is too small to pay the higher fee.
+	async fn replace_by_fee(&self, p: &PayoutRecord, fee_rate: Satoshi) -> Result<bool> {
+		let tx = decode_tx(&p.tx)?;
+		let prevouts =
+			consensus::deserialize::<Vec<TxOut>>(&p.prevouts).map_err(BitcoinError::Consensus)?;
+		let utxos = tx
+			.input
+			.iter()
+			.zip(&prevouts)
+			// Only the confirmed ones are ever spent.
+			.map(|(i, o)| types::Utxo {
+				outpoint: i.previous_output,
+				value: o.value.to_sat(),
+				confirmed: true,
+			})
+			.collect::<Vec<_>>();
+		let wrs = self.get_withdrawals_by_txid(p.txid.clone()).await?;
+		let payouts = wrs
+			.iter()
+			.map(|wr| Payout { recipient: &wr.recipient, amount: wr.amount as _ })
+			.collect::<Vec<_>>();
+		let built = PayoutTxBuilder {
+			network: self.network,
+			fee_rate,
+			sender: &self.vault,
+			utxos: &utxos,
+			coin_selection: self.fee_conf.coin_selection,
+			payouts: &payouts,
+		}
+		.build_replacement()
+		.await;
+		let replacement = match built {
+			Ok(t) => t,
+			Err(Error::Chain(ChainError::InsufficientFunds { required, available })) => {
+				tracing::warn!(
+					"payout {} can't be replaced, required {required}, available {available}",
+					p.txid
+				);
+
+				return Ok(false);
+			},
+			// Left as is, rather than blocking the other bumps.
+			Err(Error::Chain(ChainError::NonStandardTx { reason })) => {
+				tracing::warn!("payout {} can't be replaced, {reason}", p.txid);
+
+				return Ok(false);
+			},
+			Err(e) => Err(e)?,
+		};
+		let txid = replacement.compute_txid().to_string();
+		let tx_hex = consensus::encode::serialize_hex(&replacement);
+
+		self.replace_payout(
+			p.txid.clone(),
+			PayoutRecord {
+				txid: txid.clone(),
+				origin: p.origin.clone(),
+				tx: tx_hex.clone(),
+				fee_rate,
+				prevouts: p.prevouts.clone(),
+				block_height: None,
+				block_hash: None,
+				proof: None,
+				created_at: Utc::now(),
+			},
+		)
+		.await?;
+
+		match self.api.broadcast(tx_hex).await {
+			Ok(_) => tracing::info!("payout {} replaced by {txid}, fee rate {fee_rate}", p.txid),
+			// Broadcasted again until confirmed.
+			Err(e) => tracing::warn!("fail to broadcast the replacement {txid}: {e}"),
+		}
+
+		Ok(true)
+	}
+
+	// Accelerate the payout by a child spending its change, replacing the earlier `child` if any,
+	// so that the package of both reaches the fee rate.
+	//
+	// Returns `false` if the vault can't pay the fee of the child.
+	async fn pay_for_parent(
+		&self,
+		p: &PayoutRecord,
+		fee_rate: Satoshi,
+		child: Option<&CpfpRecord>,
+	) -> Result<bool> {
+		let parent = decode_tx(&p.tx)?;
+		let prevouts =
+			consensus::deserialize::<Vec<TxOut>>(&p.prevouts).map_err(BitcoinError::Consensus)?;
+		let parent_fee = prevouts.iter().map(|o| o.value.to_sat()).sum::<Satoshi>()
+			- parent.output.iter().map(|o| o.value.to_sat()).sum::<Satoshi>();
+		let parent_txid = parent.compute_txid();
+		let mut spent = self.spent_outpoints().await?;
+
+		// Free to spend again by the replacement.
+		if let Some(c) = child {
+			for i in decode_tx(&c.tx)?.input {
+				spent.remove(&i.previous_output);
+			}
+		}
+
+		let utxos = self
+			.api
+			.get_utxos(self.vault.address())
+			.await?
+			.into_iter()
+			.filter(|u| {
+				u.confirmed && u.outpoint.txid != parent_txid && !spent.contains(&u.outpoint)
+			})
+			.collect::<Vec<_>>();
+		let built = CpfpTxBuilder {
+			fee_rate,
+			sender: &self.vault,
+			parent: &parent,
+			parent_fee,
+			utxos: &utxos,
+			coin_selection: self.fee_conf.coin_selection,
+		}
+		.build()
+		.await;
+		let tx = match built {
+			Ok(t) => t,
+			Err(Error::Chain(ChainError::InsufficientFunds { required, available })) => {
+				tracing::warn!(
+					"payout {} can't be accelerated, required {required}, available {available}",
+					p.txid
+				);
+
+				return Ok(false);
+			},
+			Err(Error::Chain(ChainError::NonStandardTx { reason })) => {
+				tracing::warn!("payout {} can't be accelerated, {reason}", p.txid);
+
+				return Ok(false);
+			},
+			Err(e) => Err(e)?,
+		};
+		let txid = tx.compute_txid().to_string();
+		let tx_hex = consensus::encode::serialize_hex(&tx);
+
+		self.insert_cpfp(CpfpRecord {
+			parent: p.txid.clone(),
+			txid: txid.clone(),
+			tx: tx_hex.clone(),
+			fee_rate,
+			created_at: Utc::now(),
+		})
+		.await?;
+
+		match self.api.submit_package(vec![p.tx.clone(), tx_hex]).await {
+			Ok(_) => tracing::info!("payout {} accelerated by {txid}, fee rate {fee_rate}", p.txid),
+			// Broadcasted again until confirmed.
+			Err(e) => tracing::warn!("fail to broadcast the child {txid}: {e}"),
+		}
+
+		Ok(true)
+	}
+
+	// Merge the smallest vault UTXOs into a few larger ones while the economy fee rate is low.
+	//
+	// There is at most one consolidation at a time, whose UTXOs are locked until it's included.
+	// The largest UTXOs are left out, so that the payouts go on in the meantime, and a stuck
+	// consolidation is replaced by fee so that they aren't locked forever. It's abandoned once its
+	// inputs are gone.
+	async fn consolidate(&self) -> Result<()> {
+		let Some(ConsolidationConf { max_fee_rate, min_inputs, max_inputs, keep, outputs }) =
+			self.consolidation
+		else {
+			return Ok(());
+		};
+
+		if let Vault::WatchOnly(_) = self.vault {
+			return Ok(());
+		}
+
+		let versions = self.get_unconfirmed_consolidation_versions().await?;
+
+		// Any version of a replaced consolidation might be the included one.
+		for c in &versions {
+			if let Some((bn, _)) = self.api.get_tx_block(&c.txid).await? {
+				self.confirm_consolidation(c.txid.clone(), bn as _).await?;
+
+				tracing::info!("consolidation included: {}, in block {bn}", c.txid);
+
+				return Ok(());
+			}
+		}
+
+		if let Some(reason) = self.consolidation_spent_elsewhere(&versions).await? {
+			self.abandon_consolidation().await?;
+
+			tracing::warn!("consolidation {} abandoned, {reason}", versions[0].txid);
+
+			return Ok(());
+		}
+		if let Some(c) = versions.into_iter().next() {
+			return self.bump_consolidation(c, max_fee_rate, outputs).await;
+		}
+
+		let fee_rate = self.api.get_recommended_fee().await?.economy_fee.max(1);
+
+		if fee_rate > max_fee_rate {
+			return Ok(());
+		}
+
+		let spent = self.spent_outpoints().await?;
+		// Like the payouts, only the confirmed UTXOs are spent.
+		let mut utxos = self
+			.api
+			.get_utxos(self.vault.address())
+			.await?
+			.into_iter()
+			.filter(|u| u.confirmed && !spent.contains(&u.outpoint))
+			.collect::<Vec<_>>();
+
+		utxos.sort_by_key(|u| u.value);
+		utxos.truncate(utxos.len().saturating_sub(keep));
+
+		let utxos = utxos
+			.into_iter()
+			.filter(|u| consolidation::is_worth_spending(u, fee_rate))
+			.take(max_inputs)
+			.collect::<Vec<_>>();
+
+		if utxos.len() < min_inputs.max(2) {
+			return Ok(());
+		}
+
+		let tx = ConsolidationTxBuilder { fee_rate, sender: &self.vault, utxos: &utxos, outputs }
+			.build()
+			.await?;
+
+		self.send_consolidation(tx, &utxos, fee_rate).await
+	}
+
+	// Get the reason why none of the unconfirmed consolidation `versions` can ever be included,
+	// `None` if they still can.
+	//
+	// Its inputs are gone once double spent by another transaction with the required
+	// confirmations, or once one of them is the change of a replaced payout, as in the
+	// consolidations recorded while the unconfirmed UTXOs were still spent.
+	async fn consolidation_spent_elsewhere(
+		&self,
+		versions: &[ConsolidationRecord],
+	) -> Result<Option<String>> {
+		let Some(c) = versions.first() else {
+			return Ok(None);
+		};
+		let tx = decode_tx(&c.tx)?;
+
+		for i in &tx.input {
+			let txid = i.previous_output.txid.to_string();
+
+			if self.get_payout(txid.clone()).await?.is_some()
+				&& self.get_withdrawals_by_txid(txid.clone()).await?.is_empty()
+			{
+				return Ok(Some(format!("spent payout {txid} has been replaced")));
+			}
+		}
+
+		let Some((txid, bn)) = self.api.get_double_spend(&tx).await? else {
+			return Ok(None);
+		};
+		let tip = self.api.get_tip_height().await?;
+
+		// The confirmed version is found next round.
+		if versions.iter().any(|v| v.txid == txid)
+			|| (tip + 1).saturating_sub(bn) < self.min_confirmations
+		{
+			return Ok(None);
+		}
+
+		Ok(Some(format!("double spent by {txid}")))
+	}
+
+	// Broadcast the unconfirmed consolidation again in case it has been dropped, or replace it by
+	// the same consolidation at the economy fee rate once it has been stuck for too long, at most
+	// the maximum fee rate of the consolidations.
+	async fn bump_consolidation(
+		&self,
+		c: ConsolidationRecord,
+		max_fee_rate: Satoshi,
+		outputs: usize,
+	) -> Result<()> {
+		let stuck = (Utc::now() - c.created_at).num_seconds() >= self.fee_bump.bump_after as i64;
+		// The replacement must pay at least the minimum relay fee rate on top.
+		let fee_rate =
+			self.api.get_recommended_fee().await?.economy_fee.max(c.fee_rate + 1).min(max_fee_rate);
+
+		// Those of schema version 1 can't be signed again without their spent outputs.
+		if !stuck || fee_rate <= c.fee_rate || c.prevouts.is_empty() {
+			if stuck {
+				tracing::warn!("consolidation {} is stuck at fee rate {}", c.txid, c.fee_rate);
+			}
+			if let Err(e) = self.api.broadcast(c.tx).await {
+				tracing::debug!("fail to broadcast the consolidation {} again: {e}", c.txid);
+			}
+
+			return Ok(());
+		}
+
+		let prevouts =
+			consensus::deserialize::<Vec<TxOut>>(&c.prevouts).map_err(BitcoinError::Consensus)?;
+		let utxos = decode_tx(&c.tx)?
+			.input
+			.iter()
+			.zip(&prevouts)
+			// Only the confirmed ones are ever spent.
+			.map(|(i, o)| types::Utxo {
+				outpoint: i.previous_output,
+				value: o.value.to_sat(),
+				confirmed: true,
+			})
+			.collect::<Vec<_>>();
+		let built =
+			ConsolidationTxBuilder { fee_rate, sender: &self.vault, utxos: &utxos, outputs }
+				.build()
+				.await;
+		let replacement = match built {
+			Ok(t) => t,
+			// Left as is, rather than blocking the other phases.
+			Err(e @ Error::Chain(ChainError::InsufficientFunds { .. }))
+			| Err(e @ Error::Chain(ChainError::NonStandardTx { .. })) => {
+				tracing::warn!("consolidation {} can't be replaced, {e}", c.txid);
+
+				return Ok(());
+			},
+			Err(e) => Err(e)?,
+		};
+
+		tracing::info!(
+			"consolidation {} replaced by {}, fee rate {fee_rate}",
+			c.txid,
+			replacement.compute_txid()
+		);
+
+		self.send_consolidation(replacement, &utxos, fee_rate).await
+	}
+
+	// Record the signed consolidation of the UTXOs, and broadcast it.
+	async fn send_consolidation(
+		&self,
+		tx: Transaction,
+		utxos: &[types::Utxo],
+		fee_rate: Satoshi,
+	) -> Result<()> {
+		let txid = tx.compute_txid().to_string();
+		let tx_hex = consensus::encode::serialize_hex(&tx);
+		let amount = utxos.iter().map(|u| u.value).sum::<Satoshi>();
+		let prevouts = utxos
+			.iter()
+			.map(|u| TxOut {
+				script_pubkey: self.vault.script_public_key().clone(),
+				value: Amount::from_sat(u.value),
+			})
+			.collect::<Vec<_>>();
+
+		self.insert_consolidation(ConsolidationRecord {
+			txid: txid.clone(),
+			tx: tx_hex.clone(),
+			inputs: utxos.len() as _,
+			amount,
+			fee: amount - tx.output.iter().map(|o| o.value.to_sat()).sum::<Satoshi>(),
+			fee_rate,
+			prevouts: consensus::serialize(&prevouts),
+			block_height: None,
+			created_at: Utc::now(),
+		})
+		.await?;
+
+		match self.api.broadcast(tx_hex).await {
+			Ok(_) =>
+				tracing::info!("consolidating {} UTXOs in {txid}, fee rate {fee_rate}", utxos.len()),
+			// Broadcasted again until included.
+			Err(e) => tracing::warn!("fail to broadcast the consolidation {txid}: {e}"),
+		}
+
+		Ok(())
+	}
+
+	// Outputs spent by the unconfirmed payouts, their children, and the unconfirmed consolidation,
+	// which the data source might still report as UTXOs.
+	async fn spent_outpoints(&self) -> Result<HashSet<OutPoint>> {
+		let mut spent = HashSet::new();
+
+		if let Some(c) = self.get_unconfirmed_consolidation().await? {
+			spent.extend(decode_tx(&c.tx)?.input.into_iter().map(|i| i.previous_output));
+		}
+
+		for p in self.get_unconfirmed_payouts().await? {
+			spent.extend(decode_tx(&p.tx)?.input.into_iter().map(|i| i.previous_output));
+
+			if let Some(c) = self.get_latest_cpfp(p.txid).await? {
+				spent.extend(decode_tx(&c.tx)?.input.into_iter().map(|i| i.previous_output));
+			}
+		}
+
+		Ok(spent)
+	}
+
+	// Submit the completions of the confirmed payouts to their X chains, and finalize the
+	// submitted ones once executed.
+	async fn complete_withdrawals(&self, tip: BlockNumber) -> Result<()> {
+		for s in &self.submitters {
+			for wr in self.get_withdrawals_by_status(WithdrawalStatus::Submitted).await? {
+				if wr.target.0 != s.id.0 {
+					continue;
+				}
+
+				let Some((hash, receipt)) =
+					s.check_submission(self, wr.hash.unwrap_or_default()).await?
+				else {
+					// Still pending.
+					continue;
+				};
+
+				self.finalize_withdrawal(wr.id, receipt.succeeded()).await?;
+
+				if receipt.succeeded() {
+					tracing::info!("withdrawal finalized: {}", wr.withdrawal_id);
+				} else {
+					tracing::warn!(
+						"completion of withdrawal {} reverted in {hash}, submitting again",
+						wr.withdrawal_id
+					);
+				}
+			}
+
+			let wrs = self
+				.get_withdrawals_by_status(WithdrawalStatus::Confirmed)
+				.await?
+				.into_iter()
+				.filter(|wr| wr.target.0 == s.id.0)
+				.collect::<Vec<_>>();
+
+			if wrs.is_empty() {
+				continue;
+			}
+
+			let mut nonce = s.next_nonce(self).await?;
+
+			for wr in wrs {
+				let txid = wr.txid.clone().unwrap_or_default();
+				let Some(payout) = self.get_payout(txid.clone()).await? else {
+					continue;
+				};
+				let Some(block_hash) = &payout.block_hash else {
+					// Unconfirmed along with an earlier withdrawal of the same payout.
+					continue;
+				};
+				let bn = payout.block_height.unwrap_or_default();
+
+				// The proof is anchored to a block which is no longer part of the best chain.
+				if bn > tip as _ || self.api.get_block_hash(bn as _).await? != *block_hash {
+					self.unconfirm_payout(txid.clone()).await?;
+
+					tracing::warn!("block of payout {txid} orphaned");
+
+					continue;
+				}
+
+				match s.complete_withdrawal(&wr, &payout, nonce).await {
+					Ok(submission) => {
+						self.submit_withdrawal(wr.id, submission.clone()).await?;
+
+						nonce += 1;
+
+						tracing::info!(
+							"withdrawal completion submitted: {}, in {}",
+							wr.withdrawal_id,
+							submission.hash
+						);
+
+						s.send_submission(&submission).await;
+					},
+					// E.g. the light client hasn't reached the payout block yet, retry in the next
+					// round.
+					Err(e) if submitter::is_reverted(&e) => tracing::warn!(
+						"completion of withdrawal {} reverted in simulation: {e}",
+						wr.withdrawal_id
+					),
+					Err(e) => Err(e)?,
+				}
+			}
+		}
+
+		Ok(())
+	}
+}
+impl<S, H> X for Relayer<S, H>
+where
+	H: Http,
+{
+	const NAME: &'static str = NAME;
+}
+impl<S, H> Sql for Relayer<S, H>
+where
+	S: BtcSource,
+	H: Http,
+{
+	async fn pool(&self) -> &Arc<Pool> {
+		&self.context.sql
+	}
+}
+impl<S, H> Relay for Relayer<S, H>
+where
+	S: BtcSource,
+	H: Debug + Http + Send + Sync,
+{
+	fn name(&self) -> &'static str {
+		Self::NAME
+	}
+
+	fn init(&self) -> Result<()> {
+		task::block_in_place(|| {
+			self.context.runtime.block_on(async { <Self as Sql>::init(self).await })
+		})
+	}
+
+	fn run(&self) -> Result<()> {
+		tracing::info!("running {}", self.name());
+
+		let ss = unsafe { &*(self as *const Self) };
+
+		task::block_in_place(|| {
+			self.context.runtime.block_on(async move {
+				let mut interval = time::interval(Duration::from_millis(5_000));
+				// TODO: https://github.com/rust-lang/rust/issues/35121.
+
+				loop {
+					tokio::select! {
+						_ = interval.tick() => {
+							// A failed phase is retried in the next round, without holding the
+							// others back.
+							let results = [
+								("track", ss.track().await),
+								("relay", ss.relay().await),
+								("withdraw", ss.withdraw().await),
+								("consolidate", ss.consolidate().await),
+							];
+
+							for (phase, r) in results {
+								if let Err(e) = r {
+									tracing::error!("fail to {phase}: {e:?}");
+								}
+							}
+						}
+					}
+				}
+			})
+		})
+	}
+}
+
+#[cfg(test)]
+fn relayer_for_test<S>(name: &str, api: S) -> Relayer<S, MockRpc>
+where
+	S: BtcSource,
+{
+	let context = super::context_for_test(&format!("{NAME}-{name}"));
+	let conf = Conf {
+		network: Network::Testnet,
+		vault: VaultConf {
+			vault_secret_key: Some(format!("0x{}", "01".repeat(32))),
+			..Default::default()
+		},
+		api: Default::default(),
+		recovery: None,
+		deposit: None,
+		payout: Default::default(),
+		fee_bump: Default::default(),
+		consolidation: None,
+		min_confirmations: 1,
+		accept_legacy_x_target: false,
+		psbt: Default::default(),
+		fee_conf: Default::default(),
+	};
+	let r = Relayer::new(conf, api, Vec::new(), context).unwrap();
+
+	r.context.runtime.block_on(<Relayer<S, MockRpc> as Sql>::init(&r)).unwrap();
+
+	r
+}
+// Relayer paying a withdrawal of 10,000 satoshis from a vault UTXO of 100,000 satoshis at 2
+// satoshis per virtual byte, to be bumped with `fee_bump`.
+#[cfg(test)]
+fn payout_for_test(name: &str, fee_bump: FeeBumpConf) -> Relayer<mock::Api, MockRpc> {
+	let mut r = relayer_for_test(name, mock::Api { fee_rate: 2, ..Default::default() });
+
+	r.payout.flush_interval = 0;
+	r.fee_bump = fee_bump;
+	r.api.utxos.lock().unwrap().push(fixture::utxo(9, 100_000));
+	r.api.mine(vec![]);
+	r.context
+		.runtime
+		.block_on(r.insert_withdrawals(Id(1), 10, vec![WithdrawalRecord::for_test("0x01", 10_000)]))
+		.unwrap();
+	r.context.runtime.block_on(r.pay_batches()).unwrap();
+
+	r
+}
+#[cfg(test)]
+fn last_broadcasted(r: &Relayer<mock::Api, MockRpc>) -> Transaction {
+	consensus::encode::deserialize_hex(r.api.broadcasted.lock().unwrap().last().unwrap()).unwrap()
+}
+#[cfg(test)]
+fn deposit_for_test(
+	txid: u8,
+	vault: &str,
+	value: types::Satoshi,
+	x_target: Option<&XTarget>,
+) -> types::Tx {
+	let mut vout = vec![types::Vout {
+		scriptpubkey_asm: "OP_PUSHNUM_1 OP_PUSHBYTES_32 00".into(),
+		scriptpubkey_type: ScriptType::V1P2tr,
+		scriptpubkey_address: Some(vault.into()),
+		value,
+	}];
+
+	if let Some(xt) = x_target {
+		let xt = xt.encode().unwrap();
+
+		vout.push(types::Vout {
+			scriptpubkey_asm: format!(
+				"OP_RETURN OP_PUSHBYTES_{} {}",
+				xt.len(),
+				array_bytes::bytes2hex("", xt.as_bytes())
+			),
+			scriptpubkey_type: ScriptType::OpReturn,
+			scriptpubkey_address: None,
+			value: 0,
+		});
+	}
+
+	// The block fields are set once mined.
+	types::Tx {
+		txid: array_bytes::bytes2hex("", [txid; 32]),
+		vin: Vec::new(),
+		vout,
+		block_height: 0,
+		block_hash: Default::default(),
+	}
+}
+#[cfg(test)]
+fn records_for_test<S>(r: &Relayer<S, MockRpc>) -> Vec<(String, XStatus)>
+where
+	S: BtcSource,
+{
+	r.context
+		.runtime
+		.block_on(r.interact(|c| {
+			Ok(c.prepare(&format!("SELECT txid, status FROM [{NAME}] ORDER BY id"))?
+				.query_map((), |r| Ok((r.get(0)?, r.get(1)?)))?
+				.collect::<rusqlite::Result<_>>()?)
+		}))
+		.unwrap()
+}
+#[test]
+fn track_should_work() {
+	let r = relayer_for_test("track", mock::Api::default());
+	let vault = r.vault.address().to_owned();
+	let xt = XTarget { id: 1_u32.into(), entity: [b'x'; 20].into() };
+	let txid = |i| array_bytes::bytes2hex("", [i; 32]);
+
+	r.api.mine(vec![
+		deposit_for_test(1, &vault, 1_000, Some(&xt)),
+		// Not a cross-chain tx.
+		deposit_for_test(2, &vault, 1_000, None),
+	]);
+	r.api.mine(vec![deposit_for_test(3, &vault, 2_000, Some(&xt))]);
+	r.api.mine(vec![deposit_for_test(4, &vault, 3_000, Some(&xt))]);
+	r.context.runtime.block_on(r.track()).unwrap();
+
+	assert_eq!(
+		records_for_test(&r),
+		[
+			(txid(1), XStatus::Confirmed),
+			(txid(3), XStatus::Confirmed),
+			(txid(4), XStatus::Confirmed)
+		]
+	);
+
+	let latest = r.context.runtime.block_on(r.get_latest()).unwrap().unwrap();
+
+	assert_eq!(latest.block_height, 2);
+	assert_eq!(latest.target, xt.id);
+	assert_eq!(latest.recipient, array_bytes::bytes2hex("0x", [b'x'; 20]));
+	// Header, index, and the sibling of the only transaction.
+	assert_eq!(latest.proof.unwrap().len(), 80 + 4);
+
+	r.api.mine(vec![
+		deposit_for_test(5, &vault, 4_000, Some(&xt)),
+		deposit_for_test(6, &vault, 5_000, Some(&xt)),
+	]);
+	r.context.runtime.block_on(r.track()).unwrap();
+
+	assert_eq!(records_for_test(&r).len(), 5);
+	assert_eq!(records_for_test(&r)[4], (txid(6), XStatus::Confirmed));
+}
+#[test]
+fn track_should_skip_vault_spends() {
+	let r = relayer_for_test("vault-spend", mock::Api::default());
+	let vault = r.vault.address().to_owned();
+	let xt = XTarget { id: 1_u32.into(), entity: [b'x'; 20].into() };
+	let txid = |i| array_bytes::bytes2hex("", [i; 32]);
+	let spend = |i, prevout_address: &str| types::Tx {
+		vin: vec![types::Vin { prevout_address: Some(prevout_address.into()) }],
+		..deposit_for_test(i, &vault, 1_000, Some(&xt))
+	};
+
+	r.api.mine(vec![
+		deposit_for_test(1, &vault, 1_000, Some(&xt)),
+		// E.g. a vault transfer paying its change back to the vault.
+		spend(2, &vault),
+		spend(3, "tb1q.."),
+	]);
+	r.context.runtime.block_on(r.track()).unwrap();
+
+	assert_eq!(
+		records_for_test(&r),
+		[(txid(1), XStatus::Confirmed), (txid(3), XStatus::Confirmed)]
+	);
+}
+#[test]
+fn track_should_handle_reorg() {
+	let mut r = relayer_for_test("reorg", mock::Api::default());
+	let vault = r.vault.address().to_owned();
+	let xt = XTarget { id: 1_u32.into(), entity: [b'x'; 20].into() };
+	let txid = |i| array_bytes::bytes2hex("", [i; 32]);
+
+	r.min_confirmations = 2;
+	r.api.mine(vec![deposit_for_test(1, &vault, 1_000, Some(&xt))]);
+	r.api.mine(vec![deposit_for_test(2, &vault, 1_000, Some(&xt))]);
+	r.api.mine(vec![deposit_for_test(3, &vault, 1_000, Some(&xt))]);
+	r.context.runtime.block_on(r.track()).unwrap();
+
+	// Block 2 has only one confirmation.
+	assert_eq!(
+		records_for_test(&r),
+		[
+			(txid(1), XStatus::Confirmed),
+			(txid(2), XStatus::Confirmed),
+			(txid(3), XStatus::Detected)
+		]
+	);
+
+	// Replace the blocks above 0, tx 2 is moved to a later block and tx 3 is dropped.
+	r.api.reorg(0);
+	r.api.mine(vec![deposit_for_test(4, &vault, 1_000, Some(&xt))]);
+	r.api.mine(vec![]);
+	r.api.mine(vec![deposit_for_test(2, &vault, 1_000, Some(&xt))]);
+	r.api.mine(vec![]);
+	r.context.runtime.block_on(r.track()).unwrap();
+
+	assert_eq!(
+		records_for_test(&r),
+		[
+			(txid(1), XStatus::Confirmed),
+			(txid(2), XStatus::Rejected),
+			(txid(3), XStatus::Rejected),
+			(txid(4), XStatus::Confirmed),
+			(txid(2), XStatus::Confirmed)
+		]
+	);
+
+	let latest = r.context.runtime.block_on(r.get_latest()).unwrap().unwrap();
+
+	assert_eq!(latest.block_height, 3);
+	assert_eq!(latest.block_hash, r.api.blocks.lock().unwrap()[3].hash);
+
+	// The chain gets shorter.
+	r.api.reorg(1);
+	r.api.mine(vec![]);
+	r.context.runtime.block_on(r.track()).unwrap();
+
+	assert_eq!(records_for_test(&r)[4], (txid(2), XStatus::Rejected));
+
+	// A submitted record can't be rejected.
+	let tx4 = r.context.runtime.block_on(r.get_by_txid(txid(4))).unwrap().remove(0);
+
+	r.context.runtime.block_on(r.submit(tx4.id, SubmissionRecord::for_test("0x", 0))).unwrap();
+	r.api.reorg(0);
+	r.api.mine(vec![]);
+	r.api.mine(vec![]);
+
+	r.context.runtime.block_on(r.track()).unwrap();
+
+	// The submitted record is flagged instead, and the tracking goes on.
+	assert_eq!(
+		records_for_test(&r),
+		[
+			(txid(1), XStatus::Confirmed),
+			(txid(2), XStatus::Rejected),
+			(txid(3), XStatus::Rejected),
+			(txid(4), XStatus::Orphaned),
+			(txid(2), XStatus::Rejected)
+		]
+	);
+	assert_eq!(r.context.runtime.block_on(r.get_latest()).unwrap().unwrap().txid, txid(1));
+}
+#[test]
+fn relay_should_work() {
+	// std
+	use std::sync::Mutex;
+	// crates.io
+	use serde_json::json;
+
+	let mut r = relayer_for_test("relay", mock::Api::default());
+	let vault = r.vault.address().to_owned();
+	let xt = XTarget { id: 1_u32.into(), entity: [b'x'; 20].into() };
+	let txid = |i| array_bytes::bytes2hex("", [i; 32]);
+	let sent = Arc::new(Mutex::new(Vec::new()));
+	let executed = Arc::new(Mutex::new(None::<String>));
+	let sent_ = sent.clone();
+	let executed_ = executed.clone();
+	let http = MockRpc::new(move |method, params| match method {
+		"eth_chainId" => json!("0x2b"),
+		"eth_getTransactionCount" => json!(format!("{:#x}", sent_.lock().unwrap().len())),
+		// The contract has relayed tx 2 already.
+		"eth_estimateGas" if params[0]["data"].as_str().unwrap()[10..74] == "02".repeat(32) =>
+			json!({ "error": { "code": 3, "message": "execution reverted: relayed" } }),
+		// The light client never reaches the block of tx 4.
+		"eth_estimateGas" if params[0]["data"].as_str().unwrap()[10..74] == "04".repeat(32) =>
+			json!({ "error": { "code": 3, "message": "execution reverted: unknown block" } }),
+		"eth_estimateGas" => json!("0x5208"),
+		"eth_getBlockByNumber" => json!({ "baseFeePerGas": "0x3b9aca00" }),
+		"eth_maxPriorityFeePerGas" => json!("0x1"),
+		"eth_sendRawTransaction" => {
+			let raw = array_bytes::hex2bytes(params[0].as_str().unwrap()).unwrap();
+
+			sent_.lock().unwrap().push(raw.clone());
+
+			json!(array_bytes::bytes2hex("0x", crate::chain::evm::keccak256(&raw)))
+		},
+		"eth_getTransactionReceipt"
+			if executed_.lock().unwrap().as_deref() == params[0].as_str() =>
+			json!({ "blockNumber": "0x1", "status": "0x1" }),
+		"eth_getTransactionReceipt" => json!(null),
+		_ => unreachable!(),
+	});
+
+	r.submitters.push(
+		Submitter::new(
+			evm::Conf {
+				id: 1,
+				uri: "http://127.0.0.1:8545".into(),
+				contract: format!("0x{}", "06".repeat(20)),
+				secret_key: format!("0x{}", "02".repeat(32)),
+				max_fee_per_gas: None,
+				replace_after: 300,
+				withdrawal: None,
+			},
+			http,
+		)
+		.unwrap(),
+	);
+	// The entity of `xt` is moved into the target of tx 3.
+	let tx4 = deposit_for_test(4, &vault, 1_000, Some(&xt));
+	r.api.mine(vec![
+		deposit_for_test(1, &vault, 1_000, Some(&xt)),
+		deposit_for_test(2, &vault, 1_000, Some(&xt)),
+		// X chain 2 is not configured.
+		deposit_for_test(3, &vault, 1_000, Some(&XTarget { id: 2_u32.into(), ..xt })),
+		tx4,
+	]);
+	r.context.runtime.block_on(r.track()).unwrap();
+	r.context.runtime.block_on(r.relay()).unwrap();
+
+	// Tx 4 is retried in the next rounds.
+	assert_eq!(
+		records_for_test(&r),
+		[
+			(txid(1), XStatus::Submitted),
+			(txid(2), XStatus::Rejected),
+			(txid(3), XStatus::Confirmed),
+			(txid(4), XStatus::Confirmed)
+		]
+	);
+	assert_eq!(sent.lock().unwrap().len(), 1);
+
+	let xr = r.context.runtime.block_on(r.get_by_txid(txid(1))).unwrap().remove(0);
+
+	assert_eq!(
+		xr.hash.unwrap(),
+		array_bytes::bytes2hex("0x", crate::chain::evm::keccak256(&sent.lock().unwrap()[0]))
+	);
+
+	// Not executed yet, sent again.
+	r.context.runtime.block_on(r.relay()).unwrap();
+
+	assert_eq!(records_for_test(&r)[0], (txid(1), XStatus::Submitted));
+	{
+		let sent = sent.lock().unwrap();
+
+		assert_eq!(sent.len(), 2);
+		assert_eq!(sent[0], sent[1]);
+	}
+
+	// Stuck, replaced at higher fees.
+	r.submitters[0].replace_after = 0;
+	r.context.runtime.block_on(r.relay()).unwrap();
+
+	let xr = r.context.runtime.block_on(r.get_by_txid(txid(1))).unwrap().remove(0);
+	let versions =
+		r.context.runtime.block_on(r.get_submission_versions(xr.hash.clone().unwrap())).unwrap();
+
+	assert_eq!(sent.lock().unwrap().len(), 3);
+	assert_eq!(
+		xr.hash.unwrap(),
+		array_bytes::bytes2hex("0x", crate::chain::evm::keccak256(&sent.lock().unwrap()[2]))
+	);
+	assert_eq!(versions.len(), 2);
+	assert_eq!(versions[0].nonce, versions[1].nonce);
+	assert!(versions[0].max_fee_per_gas >= versions[1].max_fee_per_gas * 9 / 8);
+	assert!(versions[0].max_priority_fee_per_gas >= versions[1].max_priority_fee_per_gas * 9 / 8);
+
+	// The replaced version gets executed after all.
+	*executed.lock().unwrap() = Some(versions[1].hash.clone());
+	r.context.runtime.block_on(r.relay()).unwrap();
+
+	assert_eq!(records_for_test(&r)[0], (txid(1), XStatus::Finalized));
+	assert_eq!(sent.lock().unwrap().len(), 3);
+	assert_eq!(records_for_test(&r)[3], (txid(4), XStatus::Confirmed));
+
+	// Until it has reverted for too long.
+	for _ in 0..MAX_SIMULATION_REVERTS {
+		r.context.runtime.block_on(r.relay()).unwrap();
+	}
+
+	assert_eq!(records_for_test(&r)[3], (txid(4), XStatus::Rejected));
+	assert_eq!(sent.lock().unwrap().len(), 3);
+}
+#[test]
+fn track_should_sum_deposits() {
+	let mut r = relayer_for_test("sum", mock::Api::default());
+	let txs = serde_json::from_str::<Vec<crate::chain::btc::api::mempool::Tx>>(include_str!(
+		concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/mempool/address-txs-chain.json")
+	))
+	.unwrap();
+
+	// The fixture deposits use the legacy `XTarget` format.
+	r.accept_legacy_x_target = true;
+
+	// The fixture is sorted with newest first.
+	for tx in txs.into_iter().rev() {
+		r.api.mine(vec![tx.into()]);
+	}
+
+	r.context.runtime.block_on(r.track()).unwrap();
+
+	let amounts = r
+		.context
+		.runtime
+		.block_on(r.get_since(0))
+		.unwrap()
+		.into_iter()
+		.map(|xr| (xr.txid, xr.amount))
+		.collect::<Vec<_>>();
+
+	assert_eq!(
+		amounts,
+		[
+			("7c1d9e2f3a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5".into(), 100_000),
+			// Including the vault output after the `OP_RETURN` one.
+			("3f6e4b0c2a91d5e7b8c0f1a2d3e4f5061728394a5b6c7d8e9f0a1b2c3d4e5f60".into(), 60_000),
+		]
+	);
+}
+#[test]
+fn track_should_credit_deposit_addresses() {
+	// std
+	use std::str::FromStr;
+	// crates.io
+	use bitcoin::{
+		bip32::{DerivationPath, Xpriv, Xpub},
+		secp256k1::Secp256k1,
+	};
+
+	let mut r = relayer_for_test("deposit-address", mock::Api::default());
+	let secp = Secp256k1::new();
+	let xpriv = Xpriv::new_master(Network::Testnet, &[1; 32])
+		.unwrap()
+		.derive_priv(&secp, &DerivationPath::from_str("m/86'/1'/0'").unwrap())
+		.unwrap();
+	let keys = DepositKeys::new(Xpub::from_priv(&secp, &xpriv), None, Network::Testnet).unwrap();
+	let vault = r.vault.address().to_owned();
+	let xt = XTarget { id: 1_u32.into(), entity: [b'x'; 20].into() };
+	let txid = |i| array_bytes::bytes2hex("", [i; 32]);
+	let issue = |r: &Relayer<_, _>, entity: u8, keys: &DepositKeys| {
+		let keys = keys.clone();
+
+		r.context
+			.runtime
+			.block_on(r.issue_deposit_address(
+				Id(2),
+				array_bytes::bytes2hex("0x", [entity; 32]),
+				move |i| Ok(keys.address(i)?.to_string()),
+			))
+			.unwrap()
+	};
+	let a = issue(&r, 1, &keys);
+	let b = issue(&r, 2, &keys);
+
+	assert_eq!(a.address, keys.address(0).unwrap().to_string());
+	assert_eq!(b.address, keys.address(1).unwrap().to_string());
+
+	r.deposit = Some(keys);
+	r.api.mine(vec![
+		// No `OP_RETURN` output is required.
+		deposit_for_test(1, &a.address, 1_000, None),
+		deposit_for_test(2, &vault, 2_000, Some(&xt)),
+	]);
+
+	// One transaction paying both deposit addresses, e.g. an exchange's batch.
+	let mut batch = deposit_for_test(3, &a.address, 3_000, None);
+
+	batch.vout.push(types::Vout {
+		scriptpubkey_address: Some(b.address.clone()),
+		..batch.vout[0].clone()
+	});
+	batch.vout[1].value = 4_000;
+	r.api.mine(vec![batch]);
+	r.context.runtime.block_on(r.track()).unwrap();
+	// Nothing new.
+	r.context.runtime.block_on(r.track()).unwrap();
+
+	let credits = || {
+		r.context
+			.runtime
+			.block_on(r.get_since(0))
+			.unwrap()
+			.into_iter()
+			.map(|xr| (xr.txid, xr.target.0, xr.recipient, xr.amount))
+			.collect::<Vec<_>>()
+	};
+
+	assert_eq!(
+		credits(),
+		[
+			(txid(2), 1, array_bytes::bytes2hex("0x", [b'x'; 20]), 2_000),
+			(txid(1), 2, a.recipient.clone(), 1_000),
+			(txid(3), 2, a.recipient.clone(), 3_000),
+			(txid(3), 2, b.recipient.clone(), 4_000),
+		]
+	);
+
+	r.api.mine(vec![deposit_for_test(4, &b.address, 5_000, None)]);
+	r.context.runtime.block_on(r.track()).unwrap();
+
+	assert_eq!(credits().len(), 5);
+	assert_eq!(credits()[4], (txid(4), 2, b.recipient.clone(), 5_000));
+
+	let tip = r.context.runtime.block_on(r.api.get_tip_height()).unwrap() as u64;
+
+	// Both addresses have been scanned up to the tip.
+	for i in [a.index, b.index] {
+		assert_eq!(r.context.runtime.block_on(r.get_deposit_cursor(i)).unwrap(), Some(tip));
+	}
+}
+#[test]
+fn withdraw_should_work() {
+	// std
+	use std::sync::{
+		atomic::{AtomicBool, AtomicU64, Ordering},
+		Mutex,
+	};
+	// crates.io
+	use bitcoin::{hashes::Hash, OutPoint, Txid};
+	use serde_json::json;
+	// self
+	use crate::chain::evm::{keccak256, WithdrawEvent};
+
+	fn block_of(log: &serde_json::Value) -> u64 {
+		u64::from_str_radix(&log["blockNumber"].as_str().unwrap()[2..], 16).unwrap()
+	}
+
+	let mut r = relayer_for_test("withdraw", mock::Api { fee_rate: 2, ..Default::default() });
+	let recipient = fixture::RECIPIENT;
+	let head = Arc::new(AtomicU64::new(100));
+	let sent = Arc::new(Mutex::new(Vec::new()));
+	let executed = Arc::new(AtomicBool::new(false));
+	let (head_, sent_, executed_) = (head.clone(), sent.clone(), executed.clone());
+	let log = |block: u64, id: u8, amount: u64, recipient: &str| {
+		let word = |v: usize| format!("{v:064x}");
+		let padded = recipient.len().div_ceil(32) * 32;
+
+		json!({
+			"blockNumber": format!("{block:#x}"),
+			"transactionHash": format!("0x{}", format!("{id:02x}").repeat(32)),
+			"topics": [
+				array_bytes::bytes2hex("0x", WithdrawEvent::topic()),
+				array_bytes::bytes2hex("0x", [id; 32]),
+			],
+			"data": format!(
+				"0x{}{}{}{}{}",
+				word(amount as _),
+				word(64),
+				word(recipient.len()),
+				array_bytes::bytes2hex("", recipient),
+				"00".repeat(padded - recipient.len())
+			),
+		})
+	};
+	let logs = [
+		log(10, 1, 10_000, recipient),
+		log(20, 2, 5_000, "not an address"),
+		// Below the dust limit of the recipient.
+		log(20, 5, 329, recipient),
+		log(30, 4, 7_000, recipient),
+		// Not confirmed on X yet.
+		log(95, 3, 20_000, recipient),
+	];
+	let http = MockRpc::new(move |method, params| match method {
+		"eth_blockNumber" => json!(format!("{:#x}", head_.load(Ordering::SeqCst))),
+		"eth_getLogs" => {
+			let block = |k: &str| u64::from_str_radix(&params[0][k].as_str().unwrap()[2..], 16);
+			let (from, to) = (block("fromBlock").unwrap(), block("toBlock").unwrap());
+
+			json!(logs
+				.iter()
+				.filter(|l| (from..=to).contains(&block_of(l)))
+				.cloned()
+				.collect::<Vec<_>>())
+		},
+		"eth_chainId" => json!("0x2b"),
+		"eth_getTransactionCount" => json!(format!("{:#x}", sent_.lock().unwrap().len())),
+		"eth_estimateGas" => json!("0x5208"),
+		"eth_getBlockByNumber" => json!({ "baseFeePerGas": "0x3b9aca00" }),
+		"eth_maxPriorityFeePerGas" => json!("0x1"),
+		"eth_sendRawTransaction" => {
+			let raw = array_bytes::hex2bytes(params[0].as_str().unwrap()).unwrap();
+
+			sent_.lock().unwrap().push(raw.clone());
+
+			json!(array_bytes::bytes2hex("0x", keccak256(&raw)))
+		},
+		"eth_getTransactionReceipt" if executed_.load(Ordering::SeqCst) =>
+			json!({ "blockNumber": "0x70", "status": "0x1" }),
+		"eth_getTransactionReceipt" => json!(null),
+		_ => unreachable!(),
+	});
+	let withdrawals = |r: &Relayer<_, _>| {
+		r.context
+			.runtime
+			.block_on(r.interact(|c| {
+				Ok(c.prepare(&format!(
+					"SELECT withdrawal_id, status FROM [{NAME}-withdrawal] ORDER BY id"
+				))?
+				.query_map((), |r| Ok((r.get::<_, String>(0)?, r.get(1)?)))?
+				.collect::<rusqlite::Result<Vec<(String, WithdrawalStatus)>>>()?)
+			}))
+			.unwrap()
+	};
+	let id = |i| array_bytes::bytes2hex("0x", [i; 32]);
+
+	r.submitters.push(
+		Submitter::new(
+			evm::Conf {
+				id: 1,
+				uri: "http://127.0.0.1:8545".into(),
+				contract: format!("0x{}", "06".repeat(20)),
+				secret_key: format!("0x{}", "02".repeat(32)),
+				max_fee_per_gas: None,
+				replace_after: 300,
+				withdrawal: Some(evm::WithdrawalConf { start_block: 5, confirmations: 12 }),
+			},
+			http,
+		)
+		.unwrap(),
+	);
+	r.api.utxos.lock().unwrap().extend([
+		types::Utxo {
+			outpoint: OutPoint { txid: Txid::from_byte_array([9; 32]), vout: 0 },
+			value: 100_000,
+			confirmed: true,
+		},
+		types::Utxo {
+			outpoint: OutPoint { txid: Txid::from_byte_array([8; 32]), vout: 1 },
+			value: 50_000,
+			confirmed: true,
+		},
+	]);
+	r.api.mine(vec![]);
+	r.payout = PayoutConf { batch_size: 3, flush_interval: 600 };
+	r.context.runtime.block_on(r.withdraw()).unwrap();
+
+	// Waiting for the batch to fill.
+	assert_eq!(
+		withdrawals(&r),
+		[
+			(id(1), WithdrawalStatus::Detected),
+			(id(2), WithdrawalStatus::Rejected),
+			(id(5), WithdrawalStatus::Rejected),
+			(id(4), WithdrawalStatus::Detected)
+		]
+	);
+	assert!(r.api.broadcasted.lock().unwrap().is_empty());
+
+	r.payout.flush_interval = 0;
+	r.context.runtime.block_on(r.withdraw()).unwrap();
+
+	assert_eq!(
+		withdrawals(&r),
+		[
+			(id(1), WithdrawalStatus::Paid),
+			(id(2), WithdrawalStatus::Rejected),
+			(id(5), WithdrawalStatus::Rejected),
+			(id(4), WithdrawalStatus::Paid)
+		]
+	);
+
+	let payout = r.api.broadcasted.lock().unwrap()[0].clone();
+	let tx = consensus::encode::deserialize_hex::<Transaction>(&payout).unwrap();
+	let script =
+		recipient.parse::<Address<NetworkUnchecked>>().unwrap().assume_checked().script_pubkey();
+
+	// Both paid in one transaction, followed by the change.
+	assert_eq!(tx.output.len(), 3);
+	assert_eq!((tx.output[0].value.to_sat(), &tx.output[0].script_pubkey), (10_000, &script));
+	assert_eq!((tx.output[1].value.to_sat(), &tx.output[1].script_pubkey), (7_000, &script));
+	assert_eq!(tx.output[2].script_pubkey, *r.vault.script_public_key());
+
+	// The unconfirmed payout is broadcasted again, even if the scan restarts from the beginning.
+	r.context
+		.runtime
+		.block_on(
+			r.interact(|c| Ok(c.execute(&format!("DELETE FROM [{NAME}-withdrawal-cursor]"), [])?)),
+		)
+		.unwrap();
+	r.context.runtime.block_on(r.withdraw()).unwrap();
+
+	assert_eq!(withdrawals(&r).len(), 4);
+	assert!(r.api.broadcasted.lock().unwrap().iter().all(|t| *t == payout));
+	assert!(r.api.broadcasted.lock().unwrap().len() > 1);
+
+	r.api.mine_broadcasted(Network::Testnet);
+	r.context.runtime.block_on(r.track()).unwrap();
+
+	// The payout isn't a deposit.
+	assert!(records_for_test(&r).is_empty());
+
+	r.context.runtime.block_on(r.withdraw()).unwrap();
+
+	assert_eq!(withdrawals(&r)[0], (id(1), WithdrawalStatus::Submitted));
+	assert_eq!(withdrawals(&r)[3], (id(4), WithdrawalStatus::Submitted));
+	assert_eq!(sent.lock().unwrap().len(), 2);
+
+	let txid = tx.compute_txid().to_string();
+	let wrs = r.context.runtime.block_on(r.get_withdrawals_by_txid(txid.clone())).unwrap();
+
+	assert_eq!(
+		wrs.iter().map(|wr| (wr.withdrawal_id.clone(), wr.vout)).collect::<Vec<_>>(),
+		[(id(1), Some(0)), (id(4), Some(1))]
+	);
+	assert!(r.context.runtime.block_on(r.get_payout(txid)).unwrap().unwrap().proof.is_some());
+	assert_eq!(
+		wrs[0].hash.as_deref().unwrap(),
+		array_bytes::bytes2hex("0x", keccak256(&sent.lock().unwrap()[0]))
+	);
+
+	executed.store(true, Ordering::SeqCst);
+	head.store(120, Ordering::SeqCst);
+	r.context.runtime.block_on(r.withdraw()).unwrap();
+
+	// The last withdrawal is paid from the UTXO left.
+	assert_eq!(
+		withdrawals(&r),
+		[
+			(id(1), WithdrawalStatus::Finalized),
+			(id(2), WithdrawalStatus::Rejected),
+			(id(5), WithdrawalStatus::Rejected),
+			(id(4), WithdrawalStatus::Finalized),
+			(id(3), WithdrawalStatus::Paid)
+		]
+	);
+	assert_eq!(sent.lock().unwrap().len(), 2);
+
+	let tx3 =
+		consensus::encode::deserialize_hex::<Transaction>(&r.api.broadcasted.lock().unwrap()[0])
+			.unwrap();
+
+	assert!(tx3
+		.input
+		.iter()
+		.all(|i| tx.input.iter().all(|j| i.previous_output != j.previous_output)));
+}
+#[test]
+fn non_standard_batch_should_be_split() {
+	// crates.io
+	use bitcoin::{hashes::Hash, Txid};
+
+	let mut r = relayer_for_test("split", mock::Api { fee_rate: 1, ..Default::default() });
+	let id = |i| array_bytes::bytes2hex("0x", [i; 32]);
+
+	// Too many UTXOs for a standard transaction paying the whole batch.
+	r.api.utxos.lock().unwrap().extend((0..5_000).map(|vout| types::Utxo {
+		outpoint: OutPoint { txid: Txid::from_byte_array([9; 32]), vout },
+		value: 1_000,
+		confirmed: true,
+	}));
+	r.payout = PayoutConf { batch_size: 3, flush_interval: 0 };
+	// The quickest algorithm on so many UTXOs.
+	r.fee_conf.coin_selection.strategy = coin_selection::SelectionStrategy::SingleRandomDraw;
+	r.context
+		.runtime
+		.block_on(
+			r.insert_withdrawals(
+				Id(1),
+				10,
+				[(1, 900_000), (2, 900_000), (3, 1_900_000)]
+					.map(|(i, amount)| WithdrawalRecord::for_test(&id(i), amount))
+					.into(),
+			),
+		)
+		.unwrap();
+	r.context.runtime.block_on(r.pay_batches()).unwrap();
+
+	let wr = |i| r.context.runtime.block_on(r.get_withdrawals_by_id(id(i))).unwrap().remove(0);
+
+	// Paid on their own, while the last one isn't standard even alone.
+	assert_eq!(wr(1).status, WithdrawalStatus::Paid);
+	assert_eq!(wr(2).status, WithdrawalStatus::Paid);
+	assert_ne!(wr(1).txid, wr(2).txid);
+	assert_eq!(wr(3).status, WithdrawalStatus::Rejected);
+	assert!(wr(3).reason.unwrap().starts_with("non-standard payout: weight"));
+	assert_eq!(r.api.broadcasted.lock().unwrap().len(), 2);
+}
+#[test]
+fn payout_should_be_replaced_by_fee() {
+	let mut r = payout_for_test(
+		"rbf",
+		FeeBumpConf { bump_after: 3_600, max_fee_rate: 5, method: BumpMethod::Rbf },
+	);
+	let decode = |t: &str| consensus::encode::deserialize_hex::<Transaction>(t).unwrap();
+	let paid_by = |r: &Relayer<mock::Api, _>| {
+		r.context.runtime.block_on(r.get_withdrawals_by_id("0x01".into())).unwrap().remove(0)
+	};
+	let fee_rate = |tx: &Transaction| {
+		(100_000 - tx.output.iter().map(|o| o.value.to_sat()).sum::<Satoshi>())
+			/ tx.vsize() as Satoshi
+	};
+
+	let tx = last_broadcasted(&r);
+
+	assert!(tx.input.iter().all(|i| i.sequence.is_rbf()));
+	assert_eq!(fee_rate(&tx), 2);
+
+	// Not stuck for long enough, broadcasted again.
+	r.context.runtime.block_on(r.check_payouts(0)).unwrap();
+
+	assert_eq!(r.api.broadcasted.lock().unwrap().len(), 2);
+	assert_eq!(last_broadcasted(&r), tx);
+
+	r.fee_bump.bump_after = 0;
+	r.api.fee_rate = 4;
+	r.context.runtime.block_on(r.check_payouts(0)).unwrap();
+
+	let tx2 = last_broadcasted(&r);
+
+	// Same inputs and payout, less change.
+	assert_eq!(tx2.input[0].previous_output, tx.input[0].previous_output);
+	assert_eq!(tx2.output[0], tx.output[0]);
+	assert_eq!(fee_rate(&tx2), 4);
+	assert_eq!(paid_by(&r).txid.unwrap(), tx2.compute_txid().to_string());
+
+	// Capped by the maximum fee rate.
+	r.api.fee_rate = 10;
+	r.context.runtime.block_on(r.check_payouts(0)).unwrap();
+
+	let tx3 = last_broadcasted(&r);
+
+	assert_eq!(fee_rate(&tx3), 5);
+
+	r.context.runtime.block_on(r.check_payouts(0)).unwrap();
+
+	assert_eq!(last_broadcasted(&r), tx3);
+	assert_eq!(
+		r.context
+			.runtime
+			.block_on(r.get_payout_versions(tx.compute_txid().to_string()))
+			.unwrap()
+			.len(),
+		3
+	);
+
+	// The first replacement is confirmed after all.
+	r.api.broadcasted.lock().unwrap().retain(|t| decode(t) == tx2);
+	r.api.mine_broadcasted(Network::Testnet);
+	r.context.runtime.block_on(r.check_payouts(1)).unwrap();
+
+	let wr = paid_by(&r);
+
+	assert_eq!(wr.txid.unwrap(), tx2.compute_txid().to_string());
+	assert_eq!(wr.status, WithdrawalStatus::Confirmed);
+}
+#[test]
+fn payout_should_be_accelerated_by_child() {
+	let mut r = payout_for_test(
+		"cpfp",
+		FeeBumpConf { bump_after: 0, max_fee_rate: 20, method: BumpMethod::Cpfp },
+	);
+	let decode = |t: &str| consensus::encode::deserialize_hex::<Transaction>(t).unwrap();
+	let paid_by = |r: &Relayer<mock::Api, _>| {
+		r.context.runtime.block_on(r.get_withdrawals_by_id("0x01".into())).unwrap().remove(0)
+	};
+
+	let parent = last_broadcasted(&r);
+	let parent_txid = parent.compute_txid();
+	let parent_fee = 100_000 - parent.output.iter().map(|o| o.value.to_sat()).sum::<Satoshi>();
+	let change = parent.output[1].value.to_sat();
+
+	r.api.fee_rate = 6;
+	r.context.runtime.block_on(r.check_payouts(0)).unwrap();
+
+	let child = last_broadcasted(&r);
+
+	// Submitted along with the parent.
+	assert_eq!(r.api.broadcasted.lock().unwrap().len(), 3);
+	assert_eq!(child.input.len(), 1);
+	assert_eq!(child.input[0].previous_output, OutPoint { txid: parent_txid, vout: 1 });
+	assert_eq!(
+		parent_fee + change - child.output[0].value.to_sat(),
+		6 * (parent.vsize() + child.vsize()) as Satoshi
+	);
+	// The payout itself is never replaced.
+	assert_eq!(paid_by(&r).txid.unwrap(), parent_txid.to_string());
+
+	// The child is replaced at the minimum relay fee rate on top.
+	r.context.runtime.block_on(r.check_payouts(0)).unwrap();
+
+	let child2 = last_broadcasted(&r);
+	let cpfp =
+		r.context.runtime.block_on(r.get_latest_cpfp(parent_txid.to_string())).unwrap().unwrap();
+
+	assert_eq!(child2.input[0].previous_output, child.input[0].previous_output);
+	assert_eq!((cpfp.txid, cpfp.fee_rate), (child2.compute_txid().to_string(), 7));
+
+	r.api.broadcasted.lock().unwrap().retain(|t| [&parent, &child2].contains(&&decode(t)));
+	r.api.mine_broadcasted(Network::Testnet);
+	r.context.runtime.block_on(r.check_payouts(1)).unwrap();
+
+	let wr = paid_by(&r);
+
+	assert_eq!(wr.txid.unwrap(), parent_txid.to_string());
+	assert_eq!(wr.status, WithdrawalStatus::Confirmed);
+}
+#[test]
+fn chained_payout_should_not_be_replaced() {
+	let mut r = payout_for_test(
+		"chained",
+		FeeBumpConf { bump_after: 0, max_fee_rate: 20, method: BumpMethod::Rbf },
+	);
+	let paid_by = |r: &Relayer<mock::Api, _>, id: &str| {
+		r.context.runtime.block_on(r.get_withdrawals_by_id(id.into())).unwrap().remove(0)
+	};
+
+	let parent = last_broadcasted(&r);
+	let change = OutPoint { txid: parent.compute_txid(), vout: 1 };
+
+	// Reported as confirmed before the block including the payout was orphaned.
+	r.api.utxos.lock().unwrap().push(types::Utxo {
+		outpoint: change,
+		value: parent.output[1].value.to_sat(),
+		confirmed: true,
+	});
+	r.context
+		.runtime
+		.block_on(r.insert_withdrawals(Id(1), 11, vec![WithdrawalRecord::for_test("0x02", 5_000)]))
+		.unwrap();
+	r.context.runtime.block_on(r.pay_batches()).unwrap();
+
+	let descendant = last_broadcasted(&r);
+
+	assert_eq!(descendant.input[0].previous_output, change);
+
+	r.api.fee_rate = 6;
+	r.context.runtime.block_on(r.check_payouts(0)).unwrap();
+
+	// Only the descendant is replaced, spending the same change.
+	let replacement = last_broadcasted(&r);
+
+	assert_eq!(paid_by(&r, "0x01").txid.unwrap(), change.txid.to_string());
+	assert_ne!(replacement, descendant);
+	assert_eq!(replacement.input[0].previous_output, change);
+	assert_eq!(paid_by(&r, "0x02").txid.unwrap(), replacement.compute_txid().to_string());
+	assert!(r
+		.context
+		.runtime
+		.block_on(r.get_latest_cpfp(change.txid.to_string()))
+		.unwrap()
+		.is_none());
+}
+#[test]
+fn double_spent_payout_should_be_paid_again() {
+	// crates.io
+	use bitcoin::{absolute::LockTime, transaction::Version, TxIn};
+	// self
+	use fixture::utxo;
+
+	let mut r = relayer_for_test("double-spend", mock::Api { fee_rate: 2, ..Default::default() });
+	let paid_by = |r: &Relayer<mock::Api, _>| {
+		r.context.runtime.block_on(r.get_withdrawals_by_id("0x01".into())).unwrap().remove(0)
+	};
+
+	r.payout.flush_interval = 0;
+	r.min_confirmations = 2;
+	// The larger one might never be confirmed.
+	r.api
+		.utxos
+		.lock()
+		.unwrap()
+		.extend([utxo(9, 100_000), types::Utxo { confirmed: false, ..utxo(8, 1_000_000) }]);
+	r.api.mine(vec![]);
+	r.context
+		.runtime
+		.block_on(r.insert_withdrawals(Id(1), 10, vec![WithdrawalRecord::for_test("0x01", 10_000)]))
+		.unwrap();
+	r.context.runtime.block_on(r.pay_batches()).unwrap();
+
+	let payout = last_broadcasted(&r);
+
+	assert_eq!(
+		payout.input.iter().map(|i| i.previous_output).collect::<Vec<_>>(),
+		[utxo(9, 0).outpoint]
+	);
+
+	// The vault UTXO is spent by another transaction, e.g. a recovery.
+	let other = Transaction {
+		version: Version::TWO,
+		lock_time: LockTime::ZERO,
+		input: vec![TxIn { previous_output: utxo(9, 0).outpoint, ..Default::default() }],
+		output: vec![TxOut {
+			script_pubkey: r.vault.script_public_key().clone(),
+			value: Amount::from_sat(99_000),
+		}],
+	};
+
+	*r.api.broadcasted.lock().unwrap() = vec![consensus::encode::serialize_hex(&other)];
+	r.api.mine_broadcasted(Network::Testnet);
+	r.api.utxos.lock().unwrap().push(utxo(7, 100_000));
+	r.context.runtime.block_on(r.check_payouts(1)).unwrap();
+
+	// Waiting for the double spend to be confirmed enough.
+	assert_eq!(paid_by(&r).status, WithdrawalStatus::Paid);
+
+	r.context.runtime.block_on(r.check_payouts(2)).unwrap();
+
+	let wr = paid_by(&r);
+
+	assert_eq!((wr.status, wr.txid), (WithdrawalStatus::Detected, None));
+
+	r.context.runtime.block_on(r.pay_batches()).unwrap();
+
+	let repaid = last_broadcasted(&r);
+
+	assert_eq!(repaid.input[0].previous_output, utxo(7, 0).outpoint);
+	assert_eq!(paid_by(&r).txid.unwrap(), repaid.compute_txid().to_string());
+}
+#[test]
+fn vault_should_be_consolidated() {
+	// crates.io
+	use bitcoin::hashes::Hash;
+	// self
+	use fixture::utxo;
+
+	let mut r = relayer_for_test("consolidation", mock::Api { fee_rate: 5, ..Default::default() });
+	let consolidation = |r: &Relayer<mock::Api, _>| {
+		r.context.runtime.block_on(r.get_unconfirmed_consolidation()).unwrap()
+	};
+
+	r.consolidation = Some(ConsolidationConf {
+		max_fee_rate: 2,
+		min_inputs: 3,
+		keep: 1,
+		outputs: 2,
+		..Default::default()
+	});
+	// Not worth spending, and the largest one is kept.
+	r.api.utxos.lock().unwrap().extend([
+		utxo(1, 3_000),
+		utxo(2, 50),
+		utxo(3, 1_000),
+		utxo(4, 100_000),
+		utxo(5, 2_000),
+		utxo(6, 4_000),
+	]);
+	r.api.mine(vec![]);
+	r.context.runtime.block_on(r.consolidate()).unwrap();
+
+	// The fees are too high.
+	assert!(r.api.broadcasted.lock().unwrap().is_empty());
+
+	r.api.fee_rate = 1;
+	r.context.runtime.block_on(r.consolidate()).unwrap();
+
+	let tx = consensus::encode::deserialize_hex::<Transaction>(
+		r.api.broadcasted.lock().unwrap().last().unwrap(),
+	)
+	.unwrap();
+	let c = consolidation(&r).unwrap();
+
+	assert_eq!(
+		tx.input.iter().map(|i| i.previous_output.txid.to_byte_array()[0]).collect::<Vec<_>>(),
+		[3, 5, 1, 6]
+	);
+	assert_eq!(tx.output.len(), 2);
+	assert!(tx.output.iter().all(|o| &o.script_pubkey == r.vault.script_public_key()));
+	assert_eq!((c.txid, c.inputs, c.amount), (tx.compute_txid().to_string(), 4, 10_000));
+	assert_eq!(c.fee, tx.vsize() as Satoshi);
+
+	// Broadcasted again until included, and the UTXOs are locked in the meantime.
+	r.context.runtime.block_on(r.consolidate()).unwrap();
+
+	assert_eq!(r.api.broadcasted.lock().unwrap().len(), 2);
+	assert_eq!(
+		r.context.runtime.block_on(r.spent_outpoints()).unwrap(),
+		tx.input.iter().map(|i| i.previous_output).collect()
+	);
+
+	// Replaced by the same consolidation at the higher fee rate once stuck, capped by the maximum.
+	r.fee_bump.bump_after = 0;
+	r.api.fee_rate = 5;
+	r.context.runtime.block_on(r.consolidate()).unwrap();
+
+	let replacement = consensus::encode::deserialize_hex::<Transaction>(
+		r.api.broadcasted.lock().unwrap().last().unwrap(),
+	)
+	.unwrap();
+	let c = consolidation(&r).unwrap();
+
+	assert_ne!(replacement.compute_txid(), tx.compute_txid());
+	assert_eq!(
+		replacement.input.iter().map(|i| i.previous_output).collect::<Vec<_>>(),
+		tx.input.iter().map(|i| i.previous_output).collect::<Vec<_>>()
+	);
+	assert_eq!((c.txid, c.fee_rate), (replacement.compute_txid().to_string(), 2));
+	assert_eq!(c.fee, replacement.vsize() as Satoshi * 2);
+
+	// Stuck at the maximum fee rate, broadcasted again only.
+	r.context.runtime.block_on(r.consolidate()).unwrap();
+
+	assert_eq!(consolidation(&r).unwrap().txid, replacement.compute_txid().to_string());
+
+	// The replaced version is included nonetheless.
+	r.api.broadcasted.lock().unwrap().truncate(1);
+	r.api.mine_broadcasted(Network::Testnet);
+	r.context.runtime.block_on(r.consolidate()).unwrap();
+
+	let c = r
+		.context
+		.runtime
+		.block_on(r.get_consolidation(tx.compute_txid().to_string()))
+		.unwrap()
+		.unwrap();
+
+	assert_eq!(c.block_height, Some(1));
+	assert!(consolidation(&r).is_none());
+	assert!(r
+		.context
+		.runtime
+		.block_on(r.get_consolidation(replacement.compute_txid().to_string()))
+		.unwrap()
+		.is_none());
+	assert!(r.context.runtime.block_on(r.spent_outpoints()).unwrap().is_empty());
+}
+#[test]
+fn consolidation_should_be_abandoned() {
+	// crates.io
+	use bitcoin::{absolute::LockTime, hashes::Hash, transaction::Version, TxIn, Txid};
+	// self
+	use fixture::utxo;
+
+	let mut r = relayer_for_test("abandon", mock::Api { fee_rate: 1, ..Default::default() });
+	let consolidation = |r: &Relayer<mock::Api, _>| {
+		r.context.runtime.block_on(r.get_unconfirmed_consolidation()).unwrap()
+	};
+	let spending = |outpoint| Transaction {
+		version: Version::TWO,
+		lock_time: LockTime::ZERO,
+		input: vec![TxIn { previous_output: outpoint, ..Default::default() }],
+		output: vec![TxOut { script_pubkey: ScriptBuf::new(), value: Amount::from_sat(1_000) }],
+	};
+
+	r.consolidation = Some(ConsolidationConf {
+		max_fee_rate: 2,
+		min_inputs: 2,
+		keep: 0,
+		outputs: 1,
+		..Default::default()
+	});
+	// The unconfirmed one is left out.
+	r.api.utxos.lock().unwrap().extend([
+		utxo(1, 3_000),
+		utxo(2, 4_000),
+		types::Utxo { confirmed: false, ..utxo(3, 5_000) },
+	]);
+	r.api.mine(vec![]);
+	r.context.runtime.block_on(r.consolidate()).unwrap();
+
+	assert_eq!(consolidation(&r).unwrap().inputs, 2);
+
+	// Double spent by another transaction.
+	*r.api.broadcasted.lock().unwrap() =
+		vec![consensus::encode::serialize_hex(&spending(utxo(1, 0).outpoint))];
+	r.api.mine_broadcasted(Network::Testnet);
+	r.context.runtime.block_on(r.consolidate()).unwrap();
+
+	assert!(consolidation(&r).is_none());
+	assert!(r.context.runtime.block_on(r.spent_outpoints()).unwrap().is_empty());
+
+	// Recorded while the unconfirmed change of the payouts was still spent, and the payout has
+	// been replaced since.
+	let replaced = Txid::from_byte_array([1; 32]);
+	let payout = |txid: Txid| PayoutRecord {
+		txid: txid.to_string(),
+		origin: replaced.to_string(),
+		tx: "00".into(),
+		fee_rate: 1,
+		prevouts: Vec::new(),
+		block_height: None,
+		block_hash: None,
+		proof: None,
+		created_at: Utc::now(),
+	};
+	let tx = spending(OutPoint { txid: replaced, vout: 1 });
+
+	r.context.runtime.block_on(async {
+		r.insert_withdrawals(Id(1), 10, vec![WithdrawalRecord::for_test("0x01", 10_000)])
+			.await
+			.unwrap();
+
+		let id = r.get_withdrawals_by_id("0x01".into()).await.unwrap()[0].id;
+
+		r.pay_withdrawals(payout(replaced), vec![(id, 0)]).await.unwrap();
+		r.replace_payout(replaced.to_string(), payout(Txid::from_byte_array([2; 32])))
+			.await
+			.unwrap();
+		r.insert_consolidation(ConsolidationRecord {
+			txid: tx.compute_txid().to_string(),
+			tx: consensus::encode::serialize_hex(&tx),
+			inputs: 1,
+			amount: 2_000,
+			fee: 1_000,
+			fee_rate: 1,
+			prevouts: Vec::new(),
+			block_height: None,
+			created_at: Utc::now(),
+		})
+		.await
+		.unwrap();
+		r.consolidate().await.unwrap();
+	});
+
+	assert!(consolidation(&r).is_none());
 }
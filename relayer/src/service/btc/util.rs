@@ -1,7 +1,7 @@
 // self
 use crate::{prelude::*, x::XTarget};
 
-pub fn extract_xtarget<S>(s: S) -> Result<XTarget>
+pub fn extract_xtarget<S>(s: S, accept_legacy: bool) -> Result<XTarget>
 where
 	S: AsRef<str>,
 {
@@ -11,5 +11,5 @@ where
 		.ok_or(ServiceError::FailToExtractItem { item: "XTarget", src: s.into() })?;
 	let s = array_bytes::hex2bytes(s).map_err(Error::ArrayBytes)?;
 
-	XTarget::decode(s)
+	XTarget::decode(s, accept_legacy)
 }
@@ -0,0 +1,188 @@
+// std
+use std::{
+	fs::{self, Permissions},
+	os::unix::fs::PermissionsExt,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+// crates.io
+use bitcoin::{key::XOnlyPublicKey, Network};
+use tokio::{net::UnixListener, task};
+// self
+use super::{Context, Relay};
+#[cfg(test)] use crate::chain::btc::signer::{self, SignRequest, SpendPath};
+use crate::{
+	chain::btc::frost::{self, Coordinator, Dealing, KeyShare, Participant, SignerIndex},
+	prelude::*,
+};
+
+const NAME: &str = "frost-signer";
+
+/// Create the signer answering the coordinator's requests with the key share, for the spends
+/// allowed by the policy.
+pub(super) fn new(
+	share: &Path,
+	policy: Option<&Path>,
+	listen: PathBuf,
+	context: Context,
+) -> Result<Box<dyn Relay>> {
+	Ok(Box::new(Signer {
+		context,
+		listen,
+		participant: Arc::new(Participant::new(
+			KeyShare::load(share)?,
+			super::signer::load_policy(policy)?,
+		)),
+	}))
+}
+
+/// Split a new vault key into the shares of `1..=signers` with a trusted dealer.
+///
+/// The dealer holds the whole key at once, so this must only ever run on an offline, air-gapped
+/// machine. Each share must be delivered to its signer privately, and every copy left on the
+/// dealer's machine destroyed afterwards.
+pub(super) fn keygen(threshold: u16, signers: u16, out: &Path, network: Network) -> Result<()> {
+	save(&frost::keygen(threshold, signers)?, out, network)
+}
+
+/// Deal the share of a current signer to the new group of `1..=signers`, on the signer's host.
+///
+/// Only the dealing of each new signer is saved to its own file, which must be delivered to it
+/// privately and destroyed afterwards. No host ever holds more than one share of the vault key.
+pub(super) fn reshare_deal(
+	share: &Path,
+	dealers: &[SignerIndex],
+	threshold: u16,
+	signers: u16,
+	out: &Path,
+) -> Result<()> {
+	let share = KeyShare::load(share)?;
+	let dealing = share.reshare(dealers, threshold, signers)?;
+
+	fs::create_dir_all(out)?;
+
+	for i in 1..=signers {
+		let p = out.join(format!("dealing-{}-to-{i}.json", share.index));
+
+		dealing.to(i)?.save(&p)?;
+
+		tracing::info!("dealing to signer {i} has been saved to {p:?}");
+	}
+
+	Ok(())
+}
+
+/// Combine the dealings delivered to the new signer `index` into its key share, on its host.
+///
+/// The group keeps the vault's `internal_key`, so the vault address doesn't change.
+pub(super) fn reshare_combine(
+	index: SignerIndex,
+	internal_key: XOnlyPublicKey,
+	dealings: &[PathBuf],
+	out: &Path,
+	network: Network,
+) -> Result<()> {
+	let dealings = dealings.iter().map(|p| Dealing::load(p)).collect::<Result<Vec<_>>>()?;
+
+	save(&[KeyShare::from_dealings(index, internal_key, &dealings)?], out, network)?;
+
+	tracing::warn!("the dealings must be destroyed now that they have been combined");
+
+	Ok(())
+}
+
+fn save(shares: &[KeyShare], out: &Path, network: Network) -> Result<()> {
+	fs::create_dir_all(out)?;
+
+	for s in shares {
+		let p = out.join(format!("share-{}.json", s.index));
+
+		s.save(&p)?;
+
+		tracing::info!("key share {} has been saved to {p:?}", s.index);
+	}
+
+	if let Some(s) = shares.first() {
+		tracing::info!(
+			"vault address without the recovery leaf: {}",
+			Coordinator::new(s.public.clone(), None, Vec::new(), network)?.address
+		);
+	}
+
+	Ok(())
+}
+
+#[derive(Debug)]
+struct Signer {
+	context: Context,
+	listen: PathBuf,
+	participant: Arc<Participant>,
+}
+impl Relay for Signer {
+	fn name(&self) -> &'static str {
+		NAME
+	}
+
+	fn init(&self) -> Result<()> {
+		Ok(())
+	}
+
+	fn run(&self) -> Result<()> {
+		tracing::info!("running {}", self.name());
+
+		task::block_in_place(|| {
+			self.context.runtime.block_on(async {
+				// Remove the socket left by the last run.
+				if self.listen.exists() {
+					fs::remove_file(&self.listen)?;
+				}
+
+				let listener = UnixListener::bind(&self.listen)?;
+
+				// Only the relayers running as the same user can request the signatures.
+				fs::set_permissions(&self.listen, Permissions::from_mode(0o600))?;
+
+				frost::serve(listener, self.participant.clone()).await
+			})
+		})
+	}
+}
+
+#[test]
+fn unix_transport_should_work() {
+	let shares = frost::keygen(2, 3).unwrap();
+	let public = shares[0].public.clone();
+	let listen = std::env::temp_dir().join(format!("{NAME}-{}.sock", std::process::id()));
+	let _ = fs::remove_file(&listen);
+	let runtime = tokio::runtime::Runtime::new().unwrap();
+	let [s1, _, s3] = <[KeyShare; 3]>::try_from(shares).unwrap();
+	let listener = {
+		let _g = runtime.enter();
+
+		UnixListener::bind(&listen).unwrap()
+	};
+
+	runtime.spawn(frost::serve(listener, Arc::new(Participant::new(s3, Default::default()))));
+
+	let coordinator = Coordinator::new(
+		public,
+		None,
+		vec![
+			// Unreachable.
+			frost::Transport::Unix(listen.with_extension("none")),
+			frost::Transport::Local(Arc::new(Participant::new(s1, Default::default()))),
+			frost::Transport::Unix(listen.clone()),
+		],
+		Network::Testnet,
+	)
+	.unwrap();
+	let request = SignRequest::for_key_test(
+		signer::Signer::public_key(&coordinator),
+		1,
+		SpendPath::Key { merkle_root: None },
+	);
+
+	assert_eq!(runtime.block_on(signer::Signer::sign(&coordinator, &request)).unwrap().len(), 1);
+
+	let _ = fs::remove_file(&listen);
+}
@@ -0,0 +1,582 @@
+//! Maintain the Bitcoin header chain and relay it to the light client contract on X.
+
+// std
+use std::{fmt::Debug, sync::Arc, time::Duration};
+// crates.io
+use bitcoin::{block::Header, consensus, params::Params, CompactTarget, Network};
+use deadpool_sqlite::Pool;
+use reqwest::ClientBuilder;
+use rusqlite::OptionalExtension;
+use tokio::{task, time};
+// self
+use super::{source::Source, submitter::Submitter, Context, Relay};
+#[cfg(test)] use crate::{chain::btc::api::mock, http::MockRpc};
+use crate::{
+	chain::btc::{api::BtcSource, header::*, types::BlockNumber},
+	conf::{btc::ApiConf, header::Conf},
+	http::{Client, Http},
+	prelude::*,
+	sql::*,
+	x::*,
+};
+
+const NAME: &str = "btc-header";
+// Maximum number of headers to sync in a round.
+const SYNC_LIMIT: BlockNumber = 2_016;
+
+/// Create the relayer with the chain data source shared with the `btc` relayer.
+pub(super) fn new(
+	conf: Conf,
+	network: Network,
+	api: ApiConf,
+	context: Context,
+) -> Result<Box<dyn Relay>> {
+	let api = Source::new(api, network, NAME)?;
+	let submitter = Submitter::new(conf.x, Client(ClientBuilder::new().user_agent(NAME).build()?))?;
+
+	Ok(Box::new(Relayer::new(
+		conf.start_height,
+		conf.batch_size,
+		network,
+		api,
+		submitter,
+		context,
+	)?))
+}
+
+#[derive(Debug)]
+pub(super) struct Relayer<S, H = Client>
+where
+	H: Http,
+{
+	context: Context,
+	api: S,
+	submitter: Submitter<H>,
+	params: Params,
+	start_height: BlockNumber,
+	batch_size: usize,
+}
+impl<S, H> Relayer<S, H>
+where
+	S: BtcSource,
+	H: Http,
+{
+	fn new(
+		start_height: BlockNumber,
+		batch_size: usize,
+		network: Network,
+		api: S,
+		submitter: Submitter<H>,
+		context: Context,
+	) -> Result<Self> {
+		let params = Params::new(network);
+
+		// The retargeting needs the whole difficulty period.
+		if !is_period_start(&params, start_height) {
+			Err(ServiceError::InvalidStartHeight { height: start_height })?;
+		}
+
+		Ok(Self { context, api, submitter, params, start_height, batch_size })
+	}
+
+	// Get the stored tip, `(height, hash)`.
+	async fn get_tip(&self) -> Result<Option<(BlockNumber, String)>> {
+		self.interact(|sql| {
+			Ok(sql
+				.query_row(
+					&format!("SELECT height, hash FROM [{NAME}] ORDER BY height DESC LIMIT 1"),
+					(),
+					|r| Ok((r.get(0)?, r.get(1)?)),
+				)
+				.optional()?)
+		})
+		.await
+	}
+
+	async fn get_header(&self, height: BlockNumber) -> Result<Header> {
+		let header = self
+			.interact(move |sql| {
+				Ok(sql.query_row(
+					&format!("SELECT header FROM [{NAME}] WHERE height = ?1"),
+					[height],
+					|r| r.get::<_, Vec<u8>>(0),
+				)?)
+			})
+			.await?;
+
+		Ok(consensus::deserialize(&header).map_err(BitcoinError::from)?)
+	}
+
+	// Collect the stored headers which the header at `height` depends on.
+	async fn get_ancestors(&self, height: BlockNumber) -> Result<Ancestors> {
+		let parent = self.get_header(height - 1).await?;
+		let period_start_height = height
+			- 1
+			- ((height - 1) as u64 % self.params.difficulty_adjustment_interval()) as u32;
+		let period_start = self.get_header(period_start_height).await?;
+		let limit = self.params.max_attainable_target.to_compact_lossy().to_consensus();
+		let last_bits = self
+			.interact(move |sql| {
+				Ok(sql
+					.query_row(
+						&format!(
+							"SELECT bits FROM [{NAME}] \
+							WHERE height >= ?1 AND height < ?2 AND bits != ?3 \
+							ORDER BY height DESC LIMIT 1"
+						),
+						[period_start_height, height, limit],
+						|r| r.get::<_, u32>(0),
+					)
+					.optional()?)
+			})
+			.await?
+			.map_or(period_start.bits, CompactTarget::from_consensus);
+
+		Ok(Ancestors { parent, period_start, last_bits })
+	}
+
+	// Store the validated header, the trusted start header is stored as relayed.
+	async fn insert(&self, height: BlockNumber, header: Header) -> Result<()> {
+		let relayed = height == self.start_height;
+
+		self.interact(move |sql| {
+			sql.execute(
+				&format!(
+					"INSERT INTO [{NAME}] (height, hash, bits, header, relayed) \
+					VALUES (?1, ?2, ?3, ?4, ?5)"
+				),
+				rusqlite::params![
+					height,
+					header.block_hash().to_string(),
+					header.bits.to_consensus(),
+					consensus::serialize(&header),
+					relayed
+				],
+			)?;
+
+			Ok(())
+		})
+		.await
+	}
+
+	// Drop the stored headers which are no longer part of the best chain, returns the height of
+	// the stored tip.
+	//
+	// The light client is expected to follow the heaviest chain, the new branch is submitted from
+	// the fork point.
+	async fn rollback(&self, tip: BlockNumber) -> Result<Option<BlockNumber>> {
+		while let Some((height, hash)) = self.get_tip().await? {
+			if height <= tip && self.api.get_block_hash(height).await? == hash {
+				return Ok(Some(height));
+			}
+
+			tracing::warn!("header {height}({hash}) has been orphaned");
+
+			self.interact(move |sql| {
+				sql.execute(&format!("DELETE FROM [{NAME}] WHERE height = ?1"), [height])?;
+
+				Ok(())
+			})
+			.await?;
+		}
+
+		Ok(None)
+	}
+
+	// Sync the headers from the data source, validating the proof-of-work and the difficulty
+	// retargeting against the stored chain.
+	async fn sync(&self) -> Result<()> {
+		let tip = self.api.get_tip_height().await?;
+		let height = match self.rollback(tip).await? {
+			Some(h) => h,
+			None => {
+				if tip < self.start_height {
+					return Ok(());
+				}
+
+				let hash = self.api.get_block_hash(self.start_height).await?;
+
+				self.insert(self.start_height, self.api.get_block_header(&hash).await?).await?;
+
+				self.start_height
+			},
+		};
+
+		for h in height + 1..=tip.min(height + SYNC_LIMIT) {
+			let hash = self.api.get_block_hash(h).await?;
+			let header = self.api.get_block_header(&hash).await?;
+
+			validate(&self.params, h, &header, &self.get_ancestors(h).await?)?;
+			self.insert(h, header).await?;
+		}
+
+		Ok(())
+	}
+
+	// Submit the synced headers to the light client in batches, with at most one batch in flight.
+	//
+	// A batch is recorded before it's sent, the pending one is sent again until executed and
+	// replaced at higher fees once it has been stuck for too long.
+	async fn relay(&self) -> Result<()> {
+		let pending = self
+			.interact(|sql| {
+				Ok(sql
+					.query_row(
+						&format!(
+							"SELECT submitted_in FROM [{NAME}] \
+							WHERE submitted_in IS NOT NULL AND relayed = 0 LIMIT 1"
+						),
+						(),
+						|r| r.get::<_, String>(0),
+					)
+					.optional()?)
+			})
+			.await?;
+
+		if let Some(hash) = pending {
+			let Some((executed, receipt)) =
+				self.submitter.check_submission(self, hash.clone()).await?
+			else {
+				// Still pending.
+				return Ok(());
+			};
+			let succeeded = receipt.succeeded();
+
+			if succeeded {
+				tracing::info!("headers relayed in {executed}");
+			} else {
+				tracing::warn!("headers submission reverted in {executed}, resubmitting");
+			}
+
+			self.interact(move |sql| {
+				sql.execute(
+					&if succeeded {
+						format!("UPDATE [{NAME}] SET relayed = 1 WHERE submitted_in = ?1")
+					} else {
+						format!("UPDATE [{NAME}] SET submitted_in = NULL WHERE submitted_in = ?1")
+					},
+					[&hash],
+				)?;
+
+				Ok(())
+			})
+			.await?;
+		}
+
+		let batch_size = self.batch_size;
+		let batch = self
+			.interact(move |sql| {
+				Ok(sql
+					.prepare(&format!(
+						"SELECT height, header FROM [{NAME}] \
+						WHERE submitted_in IS NULL AND relayed = 0 ORDER BY height LIMIT ?1"
+					))?
+					.query_map([batch_size], |r| Ok((r.get::<_, BlockNumber>(0)?, r.get(1)?)))?
+					.collect::<rusqlite::Result<Vec<(_, Vec<u8>)>>>()?)
+			})
+			.await?;
+		let (Some((from, _)), Some((to, _))) = (batch.first(), batch.last()) else {
+			return Ok(());
+		};
+		let (from, to) = (*from, *to);
+		let headers = batch.into_iter().flat_map(|(_, h)| h).collect::<Vec<_>>();
+		let submission =
+			self.submitter.submit_headers(&headers, self.submitter.next_nonce(self).await?).await?;
+		let submission_ = submission.clone();
+
+		self.interact(move |c| {
+			let t = c.unchecked_transaction()?;
+
+			t.execute(
+				&format!(
+					"UPDATE [{NAME}] SET submitted_in = ?1 WHERE height >= ?2 AND height <= ?3"
+				),
+				rusqlite::params![submission_.hash, from, to],
+			)?;
+			insert_submission(&t, NAME, &submission_)?;
+			t.commit()?;
+
+			Ok(())
+		})
+		.await?;
+
+		tracing::info!("headers {from}..={to} submitted in {}", submission.hash);
+
+		self.submitter.send_submission(&submission).await;
+
+		Ok(())
+	}
+}
+impl<S, H> X for Relayer<S, H>
+where
+	H: Http,
+{
+	const NAME: &'static str = NAME;
+}
+impl<S, H> Sql for Relayer<S, H>
+where
+	S: BtcSource,
+	H: Http,
+{
+	async fn pool(&self) -> &Arc<Pool> {
+		&self.context.sql
+	}
+
+	// The headers are stored instead of the x records.
+	async fn init(&self) -> Result<()> {
+		self.interact(|sql| {
+			sql.execute_batch(&format!(
+				"CREATE TABLE IF NOT EXISTS [{NAME}] (\
+					height INTEGER PRIMARY KEY,\
+					hash TEXT NOT NULL,\
+					bits INTEGER NOT NULL,\
+					header BLOB NOT NULL,\
+					submitted_in TEXT,\
+					relayed INTEGER NOT NULL DEFAULT 0\
+				);\
+				CREATE TABLE IF NOT EXISTS [{NAME}-submission] (\
+					id INTEGER PRIMARY KEY AUTOINCREMENT,\
+					target INTEGER NOT NULL,\
+					nonce INTEGER NOT NULL,\
+					hash TEXT NOT NULL UNIQUE,\
+					tx TEXT NOT NULL,\
+					data BLOB NOT NULL,\
+					gas INTEGER NOT NULL,\
+					max_fee_per_gas TEXT NOT NULL,\
+					max_priority_fee_per_gas TEXT NOT NULL,\
+					created_at DATETIME NOT NULL\
+				);"
+			))?;
+
+			Ok(())
+		})
+		.await
+	}
+
+	// The headers submitted in the stuck submission `hash` follow the replacement.
+	async fn replace_submission(&self, hash: String, submission: SubmissionRecord) -> Result<()> {
+		self.interact(move |c| {
+			let t = c.unchecked_transaction()?;
+
+			insert_submission(&t, NAME, &submission)?;
+			t.execute(
+				&format!("UPDATE [{NAME}] SET submitted_in = ?1 WHERE submitted_in = ?2"),
+				rusqlite::params![submission.hash, hash],
+			)?;
+			t.commit()?;
+
+			Ok(())
+		})
+		.await
+	}
+}
+impl<S, H> Relay for Relayer<S, H>
+where
+	S: BtcSource,
+	H: Debug + Http + Send + Sync,
+{
+	fn name(&self) -> &'static str {
+		Self::NAME
+	}
+
+	fn init(&self) -> Result<()> {
+		task::block_in_place(|| {
+			self.context.runtime.block_on(async { <Self as Sql>::init(self).await })
+		})
+	}
+
+	fn run(&self) -> Result<()> {
+		tracing::info!("running {}", self.name());
+
+		task::block_in_place(|| {
+			self.context.runtime.block_on(async {
+				let mut interval = time::interval(Duration::from_millis(5_000));
+
+				loop {
+					interval.tick().await;
+
+					// A failed phase is retried in the next round, the synced headers are relayed
+					// even if the sync fails.
+					let results = [("sync", self.sync().await), ("relay", self.relay().await)];
+
+					for (phase, r) in results {
+						if let Err(e) = r {
+							tracing::error!("fail to {phase} the headers: {e:?}");
+						}
+					}
+				}
+			})
+		})
+	}
+}
+#[test]
+fn header_relay_should_work() {
+	// std
+	use std::sync::{
+		atomic::{AtomicBool, Ordering},
+		Mutex,
+	};
+	// crates.io
+	use serde_json::json;
+
+	let submitted = Arc::new(Mutex::new(Vec::new()));
+	let executed = Arc::new(AtomicBool::new(false));
+	let submitted_ = submitted.clone();
+	let executed_ = executed.clone();
+	let http = MockRpc::new(move |method, params| match method {
+		"eth_chainId" => json!("0x2b"),
+		"eth_getTransactionCount" => json!(format!("{:#x}", submitted_.lock().unwrap().len())),
+		"eth_estimateGas" => json!("0x5208"),
+		"eth_getBlockByNumber" => json!({ "baseFeePerGas": "0x1" }),
+		"eth_maxPriorityFeePerGas" => json!("0x1"),
+		"eth_sendRawTransaction" => {
+			let mut s = submitted_.lock().unwrap();
+
+			s.push(params[0].as_str().unwrap().to_owned());
+
+			json!(format!("0x{:064x}", s.len()))
+		},
+		"eth_getTransactionReceipt" if executed_.load(Ordering::SeqCst) =>
+			json!({ "blockNumber": "0x1", "status": "0x1" }),
+		"eth_getTransactionReceipt" => json!(null),
+		_ => unreachable!(),
+	});
+	let submitter = Submitter::new(
+		crate::conf::evm::Conf {
+			id: 0,
+			uri: "http://127.0.0.1:8545".into(),
+			contract: format!("0x{}", "07".repeat(20)),
+			secret_key: format!("0x{}", "03".repeat(32)),
+			max_fee_per_gas: None,
+			replace_after: 300,
+			withdrawal: None,
+		},
+		http,
+	)
+	.unwrap();
+	let r = Relayer::new(
+		0,
+		2,
+		Network::Regtest,
+		mock::Api::default(),
+		submitter,
+		super::context_for_test(NAME),
+	)
+	.unwrap();
+	let relayed = || {
+		r.context
+			.runtime
+			.block_on(r.interact(|sql| {
+				Ok(sql
+					.prepare(&format!("SELECT height, relayed FROM [{NAME}] ORDER BY height"))?
+					.query_map((), |r| Ok((r.get(0)?, r.get(1)?)))?
+					.collect::<rusqlite::Result<Vec<(BlockNumber, bool)>>>()?)
+			}))
+			.unwrap()
+	};
+	let pending = || {
+		r.context
+			.runtime
+			.block_on(r.interact(|sql| {
+				Ok(sql.query_row(
+					&format!("SELECT submitted_in FROM [{NAME}] WHERE height = 1"),
+					(),
+					|r| r.get::<_, String>(0),
+				)?)
+			}))
+			.unwrap()
+	};
+
+	assert!(matches!(
+		Relayer::new(
+			1,
+			2,
+			Network::Regtest,
+			mock::Api::default(),
+			Submitter::new(
+				crate::conf::evm::Conf {
+					id: 0,
+					uri: "".into(),
+					contract: format!("0x{}", "07".repeat(20)),
+					secret_key: format!("0x{}", "03".repeat(32)),
+					max_fee_per_gas: None,
+					replace_after: 300,
+					withdrawal: None,
+				},
+				MockRpc::new(|_, _| json!(null)),
+			)
+			.unwrap(),
+			super::context_for_test("header-invalid"),
+		),
+		Err(Error::Servcie(ServiceError::InvalidStartHeight { height: 1 }))
+	));
+
+	r.context.runtime.block_on(<Relayer<_, _> as Sql>::init(&r)).unwrap();
+
+	for _ in 0..4 {
+		r.api.mine(Vec::new());
+	}
+
+	r.context.runtime.block_on(r.sync()).unwrap();
+	r.context.runtime.block_on(r.relay()).unwrap();
+
+	assert_eq!(relayed(), [(0, true), (1, false), (2, false), (3, false)]);
+	assert_eq!(submitted.lock().unwrap().len(), 1);
+
+	// Wait for the pending batch, which is sent again in case it has been dropped.
+	r.context.runtime.block_on(r.relay()).unwrap();
+
+	let sent = submitted.lock().unwrap().clone();
+
+	assert_eq!(sent.len(), 2);
+	assert_eq!(sent[0], sent[1]);
+
+	// Replace the stuck batch.
+	r.context
+		.runtime
+		.block_on(r.interact(|sql| {
+			sql.execute(
+				&format!("UPDATE [{NAME}-submission] SET created_at = ?1"),
+				[chrono::Utc::now() - chrono::Duration::seconds(300)],
+			)?;
+
+			Ok(())
+		}))
+		.unwrap();
+	r.context.runtime.block_on(r.relay()).unwrap();
+
+	let versions = r.context.runtime.block_on(r.get_submission_versions(pending())).unwrap();
+
+	assert_eq!(submitted.lock().unwrap().len(), 3);
+	assert_eq!(versions.len(), 2);
+	assert_eq!(versions[0].hash, pending());
+	assert_eq!(versions[0].nonce, versions[1].nonce);
+
+	executed.store(true, Ordering::SeqCst);
+	r.context.runtime.block_on(r.relay()).unwrap();
+
+	assert_eq!(relayed(), [(0, true), (1, true), (2, true), (3, false)]);
+	assert_eq!(submitted.lock().unwrap().len(), 4);
+
+	// Replace the blocks above 1.
+	r.api.reorg(1);
+	r.api.mine(Vec::new());
+	r.api.mine(Vec::new());
+	r.api.mine(Vec::new());
+	r.context.runtime.block_on(r.sync()).unwrap();
+
+	let tip = r.context.runtime.block_on(r.get_tip()).unwrap().unwrap();
+
+	assert_eq!(tip, (4, r.api.blocks.lock().unwrap()[4].hash.clone()));
+	assert_eq!(relayed(), [(0, true), (1, true), (2, false), (3, false), (4, false)]);
+
+	// A header not extending the stored chain.
+	let mut header = r.context.runtime.block_on(r.get_header(4)).unwrap();
+
+	header.prev_blockhash = bitcoin::hashes::Hash::all_zeros();
+
+	assert!(matches!(
+		validate(&r.params, 5, &header, &r.context.runtime.block_on(r.get_ancestors(5)).unwrap()),
+		Err(Error::Chain(ChainError::InvalidHeader { height: 5, reason: "parent mismatch" }))
+	));
+}
@@ -0,0 +1,70 @@
+// std
+use std::{
+	ffi::OsString,
+	fs,
+	path::{Path, PathBuf},
+};
+// crates.io
+use bitcoin::{key::Keypair, Network};
+// self
+use crate::{
+	chain::btc::{
+		signer::{Kdf, Keystore, WatchOnly},
+		TaprootKey,
+	},
+	conf::btc::Passphrase,
+	prelude::*,
+};
+
+/// Encrypt a new random vault key into a keystore.
+pub(super) fn create(out: &Path, passphrase: &Passphrase, network: Network) -> Result<()> {
+	let passphrase = passphrase.read_new("passphrase of the new keystore: ")?;
+
+	save(&Keystore::generate(&passphrase, Kdf::default())?, out, network)
+}
+
+/// Encrypt an existing vault key into a keystore.
+pub(super) fn import(
+	keypair: Keypair,
+	out: &Path,
+	passphrase: &Passphrase,
+	network: Network,
+) -> Result<()> {
+	let passphrase = passphrase.read_new("passphrase of the new keystore: ")?;
+
+	save(&Keystore::encrypt(&keypair, &passphrase, Kdf::default())?, out, network)
+}
+
+/// Re-encrypt the keystore under a new passphrase, in place.
+pub(super) fn change_passphrase(
+	path: &Path,
+	passphrase: &Passphrase,
+	new_passphrase: &Passphrase,
+) -> Result<()> {
+	let keystore = Keystore::load(path)?;
+	let passphrase = passphrase.read(&format!("passphrase of the keystore {path:?}: "))?;
+	let new_passphrase = new_passphrase.read_new("new passphrase: ")?;
+	let changed = keystore.change_passphrase(&passphrase, &new_passphrase, Kdf::default())?;
+	let tmp = PathBuf::from([path.as_os_str(), ".new".as_ref()].into_iter().collect::<OsString>());
+
+	// The keystore is replaced at once, it's never left half written.
+	changed.save(&tmp)?;
+	fs::rename(&tmp, path)?;
+
+	tracing::info!("keystore {path:?} has been re-encrypted");
+
+	Ok(())
+}
+
+fn save(keystore: &Keystore, out: &Path, network: Network) -> Result<()> {
+	keystore.save(out)?;
+
+	tracing::info!("keystore has been saved to {out:?}");
+	tracing::info!("public key: {}", keystore.public_key);
+	tracing::info!(
+		"vault address without the recovery leaf: {}",
+		TaprootKey::new(WatchOnly(keystore.public_key), None, network).address
+	);
+
+	Ok(())
+}
@@ -0,0 +1,92 @@
+// std
+use std::{
+	fs::{self, Permissions},
+	net::SocketAddr,
+	os::unix::fs::PermissionsExt,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+// crates.io
+use bitcoin::key::Keypair;
+use tokio::{
+	net::{TcpListener, UnixListener},
+	runtime::Builder,
+};
+// self
+use crate::{
+	chain::btc::signer::{Daemon, LocalSigner, Policy, Signer},
+	conf::btc::Passphrase,
+	prelude::*,
+};
+
+/// Load the policy file, every spend is allowed if it's not set.
+pub(super) fn load_policy(path: Option<&Path>) -> Result<Policy> {
+	match path {
+		Some(p) => Ok(toml::from_str(&fs::read_to_string(p)?)?),
+		None => {
+			tracing::warn!("no policy is set, every spend will be signed");
+
+			Ok(Policy::default())
+		},
+	}
+}
+
+/// Run the reference signer daemon with the key, until the listener fails.
+///
+/// The HTTP endpoint requires the bearer token, unless it only listens on the loopback interface.
+pub(super) fn serve(
+	keypair: Keypair,
+	policy: Option<PathBuf>,
+	unix: Option<PathBuf>,
+	http: Option<SocketAddr>,
+	token: Option<Passphrase>,
+) -> Result<()> {
+	let policy = load_policy(policy.as_deref())?;
+	let token = token.map(|t| t.read("bearer token: ")).transpose()?;
+
+	if let Some(addr) = http {
+		if token.is_none() {
+			if !addr.ip().is_loopback() {
+				Err(ServiceError::FailToExtractItem {
+					item: "bearer token",
+					src: format!("arguments, required to listen on {addr}"),
+				})?;
+			}
+
+			tracing::warn!("no bearer token is set, every local process can request signatures");
+		}
+	}
+
+	let daemon = Arc::new(Daemon { signer: LocalSigner(keypair), policy, token });
+
+	tracing::info!("signing with public key {}", daemon.signer.public_key());
+
+	Builder::new_current_thread().enable_all().build()?.block_on(async {
+		match (unix, http) {
+			(Some(path), None) => {
+				// Remove the socket left by the last run.
+				if path.exists() {
+					fs::remove_file(&path)?;
+				}
+
+				let listener = UnixListener::bind(&path)?;
+
+				// Only the relayers running as the same user can request the signatures.
+				fs::set_permissions(&path, Permissions::from_mode(0o600))?;
+
+				tracing::info!("listening on {path:?}");
+
+				daemon.serve_unix(listener).await
+			},
+			(None, Some(addr)) => {
+				tracing::info!("listening on http://{addr}");
+
+				daemon.serve_http(TcpListener::bind(addr).await?).await
+			},
+			_ => Err(ServiceError::FailToExtractItem {
+				item: "listen address",
+				src: "arguments".into(),
+			})?,
+		}
+	})
+}
@@ -0,0 +1,289 @@
+// crates.io
+use bitcoin::{
+	base64::{engine::general_purpose::STANDARD, Engine},
+	block::Header,
+	Network, Transaction,
+};
+use bytes::Bytes;
+use reqwest::{
+	header::{HeaderValue, AUTHORIZATION},
+	Body, Client as RClient, ClientBuilder, IntoUrl,
+};
+// self
+use crate::{
+	chain::btc::{
+		api::{esplora, mempool, node, BtcSource},
+		spv::SpvProof,
+		types::*,
+	},
+	conf::btc::*,
+	http::{Client, Http},
+	prelude::*,
+};
+
+/// Chain data source selected in the configuration.
+#[derive(Debug)]
+pub enum Source {
+	Mempool(mempool::Api<Client>),
+	Esplora(esplora::Api<Client>),
+	Node(node::Api<NodeClient>),
+}
+impl Source {
+	pub fn new(conf: ApiConf, network: Network, user_agent: &str) -> Result<Self> {
+		let s = match conf {
+			ApiConf::Mempool { uri } => Self::Mempool(mempool::Api {
+				http: Client(ClientBuilder::new().user_agent(user_agent).build()?),
+				base_uri: uri.unwrap_or_else(|| {
+					if matches!(network, Network::Testnet) {
+						"https://mempool.space/testnet/api"
+					} else {
+						"https://mempool.space/api"
+					}
+					.into()
+				}),
+			}),
+			ApiConf::Esplora { uri } => Self::Esplora(esplora::Api(mempool::Api {
+				http: Client(ClientBuilder::new().user_agent(user_agent).build()?),
+				base_uri: uri.unwrap_or_else(|| {
+					if matches!(network, Network::Testnet) {
+						"https://blockstream.info/testnet/api"
+					} else {
+						"https://blockstream.info/api"
+					}
+					.into()
+				}),
+			})),
+			ApiConf::Node(NodeConf { uri, wallet, auth }) => {
+				let http = NodeClient {
+					client: ClientBuilder::new().user_agent(user_agent).build()?,
+					auth,
+				};
+
+				// Fail early on the unreadable credentials.
+				http.authorization()?;
+
+				Self::Node(node::Api {
+					http,
+					uri: match wallet {
+						Some(w) => format!("{}/wallet/{w}", uri.trim_end_matches('/')),
+						None => uri,
+					},
+				})
+			},
+		};
+
+		Ok(s)
+	}
+}
+
+/// HTTP client of the node, authenticating each request with the current credentials.
+///
+/// The cookie file is read again on every request, since the node rewrites it on each restart.
+#[derive(Debug)]
+pub struct NodeClient {
+	client: RClient,
+	auth: NodeAuth,
+}
+impl NodeClient {
+	fn authorization(&self) -> Result<HeaderValue> {
+		let mut auth =
+			HeaderValue::from_str(&format!("Basic {}", STANDARD.encode(self.auth.credentials()?)))
+				.map_err(|_| ServiceError::FailToExtractItem {
+					item: "credentials",
+					src: "btc.api".into(),
+				})?;
+
+		auth.set_sensitive(true);
+
+		Ok(auth)
+	}
+}
+impl Http for NodeClient {
+	async fn get<U>(&self, uri: U) -> Result<Bytes>
+	where
+		U: IntoUrl,
+	{
+		let u = uri.as_str();
+
+		tracing::info!("GET {u}");
+
+		Ok(self
+			.client
+			.get(uri)
+			.header(AUTHORIZATION, self.authorization()?)
+			.send()
+			.await?
+			.bytes()
+			.await?)
+	}
+
+	async fn post<U, B>(&self, uri: U, body: B) -> Result<Bytes>
+	where
+		U: IntoUrl,
+		B: Into<Body>,
+	{
+		let u = uri.as_str();
+
+		tracing::info!("POST {u}");
+
+		Ok(self
+			.client
+			.post(uri)
+			.header(AUTHORIZATION, self.authorization()?)
+			.body(body)
+			.send()
+			.await?
+			.bytes()
+			.await?)
+	}
+}
+impl BtcSource for Source {
+	// All the backends return 25 transactions per page.
+	const PAGE_SIZE: usize = 25;
+
+	async fn get_addr_txs_chain<S, S1>(&self, address: S, after: Option<S1>) -> Result<Vec<Tx>>
+	where
+		S: AsRef<str>,
+		S1: AsRef<str>,
+	{
+		match self {
+			Self::Mempool(a) => a.get_addr_txs_chain(address, after).await,
+			Self::Esplora(a) => a.get_addr_txs_chain(address, after).await,
+			Self::Node(a) => a.get_addr_txs_chain(address, after).await,
+		}
+	}
+
+	async fn get_addr_txs_chain_since<S, S1>(
+		&self,
+		address: S,
+		since: BlockNumber,
+		after: Option<S1>,
+	) -> Result<Vec<Tx>>
+	where
+		S: AsRef<str>,
+		S1: AsRef<str>,
+	{
+		match self {
+			Self::Mempool(a) => a.get_addr_txs_chain_since(address, since, after).await,
+			Self::Esplora(a) => a.get_addr_txs_chain_since(address, since, after).await,
+			Self::Node(a) => a.get_addr_txs_chain_since(address, since, after).await,
+		}
+	}
+
+	async fn get_utxos<S>(&self, address: S) -> Result<Vec<Utxo>>
+	where
+		S: AsRef<str>,
+	{
+		match self {
+			Self::Mempool(a) => a.get_utxos(address).await,
+			Self::Esplora(a) => a.get_utxos(address).await,
+			Self::Node(a) => a.get_utxos(address).await,
+		}
+	}
+
+	async fn get_tip_height(&self) -> Result<BlockNumber> {
+		match self {
+			Self::Mempool(a) => a.get_tip_height().await,
+			Self::Esplora(a) => a.get_tip_height().await,
+			Self::Node(a) => a.get_tip_height().await,
+		}
+	}
+
+	async fn get_block_hash(&self, height: BlockNumber) -> Result<String> {
+		match self {
+			Self::Mempool(a) => a.get_block_hash(height).await,
+			Self::Esplora(a) => a.get_block_hash(height).await,
+			Self::Node(a) => a.get_block_hash(height).await,
+		}
+	}
+
+	async fn get_block_header(&self, hash: &str) -> Result<Header> {
+		match self {
+			Self::Mempool(a) => a.get_block_header(hash).await,
+			Self::Esplora(a) => a.get_block_header(hash).await,
+			Self::Node(a) => a.get_block_header(hash).await,
+		}
+	}
+
+	async fn get_tx_block(&self, txid: &str) -> Result<Option<(BlockNumber, String)>> {
+		match self {
+			Self::Mempool(a) => a.get_tx_block(txid).await,
+			Self::Esplora(a) => a.get_tx_block(txid).await,
+			Self::Node(a) => a.get_tx_block(txid).await,
+		}
+	}
+
+	async fn get_double_spend(&self, tx: &Transaction) -> Result<Option<(String, BlockNumber)>> {
+		match self {
+			Self::Mempool(a) => a.get_double_spend(tx).await,
+			Self::Esplora(a) => a.get_double_spend(tx).await,
+			Self::Node(a) => a.get_double_spend(tx).await,
+		}
+	}
+
+	async fn get_spv_proof(&self, txid: &str, block_hash: &str) -> Result<SpvProof> {
+		match self {
+			Self::Mempool(a) => a.get_spv_proof(txid, block_hash).await,
+			Self::Esplora(a) => a.get_spv_proof(txid, block_hash).await,
+			Self::Node(a) => a.get_spv_proof(txid, block_hash).await,
+		}
+	}
+
+	async fn get_recommended_fee(&self) -> Result<Fees> {
+		match self {
+			Self::Mempool(a) => a.get_recommended_fee().await,
+			Self::Esplora(a) => a.get_recommended_fee().await,
+			Self::Node(a) => a.get_recommended_fee().await,
+		}
+	}
+
+	async fn broadcast<S>(&self, tx_hex: S) -> Result<String>
+	where
+		S: Into<String>,
+	{
+		match self {
+			Self::Mempool(a) => a.broadcast(tx_hex).await,
+			Self::Esplora(a) => a.broadcast(tx_hex).await,
+			Self::Node(a) => a.broadcast(tx_hex).await,
+		}
+	}
+
+	async fn submit_package(&self, txs_hex: Vec<String>) -> Result<()> {
+		match self {
+			Self::Mempool(a) => a.submit_package(txs_hex).await,
+			Self::Esplora(a) => a.submit_package(txs_hex).await,
+			Self::Node(a) => a.submit_package(txs_hex).await,
+		}
+	}
+}
+#[test]
+fn node_client_should_reread_cookie() {
+	let cookie = std::env::temp_dir().join(format!("btc-cookie-{}", std::process::id()));
+
+	std::fs::write(&cookie, "__cookie__:1\n").unwrap();
+
+	let Source::Node(api) = Source::new(
+		ApiConf::Node(NodeConf {
+			uri: "http://127.0.0.1:18443".into(),
+			wallet: None,
+			auth: NodeAuth::Cookie { cookie: cookie.clone() },
+		}),
+		Network::Regtest,
+		"test",
+	)
+	.unwrap() else {
+		unreachable!()
+	};
+	let basic = |c: &str| format!("Basic {}", STANDARD.encode(c));
+
+	assert_eq!(api.http.authorization().unwrap(), basic("__cookie__:1"));
+
+	// The node has been restarted.
+	std::fs::write(&cookie, "__cookie__:2\n").unwrap();
+
+	assert_eq!(api.http.authorization().unwrap(), basic("__cookie__:2"));
+
+	std::fs::remove_file(&cookie).unwrap();
+
+	assert!(api.http.authorization().is_err());
+}
@@ -0,0 +1,322 @@
+// crates.io
+use bitcoin::{hashes::Hash as _, Transaction, Txid};
+use chrono::Utc;
+// self
+use crate::{
+	chain::evm::{api::*, types::*, *},
+	conf::evm::{Conf, WithdrawalConf},
+	http::Http,
+	prelude::*,
+	sql::{PayoutRecord, Sql, SubmissionRecord, WithdrawalRecord, XRecord},
+	x::Id,
+};
+
+/// Submit the x records and the Bitcoin headers to the contracts of an EVM X chain, and watch the
+/// withdrawals from it.
+#[derive(Debug)]
+pub struct Submitter<H>
+where
+	H: Http,
+{
+	pub id: Id,
+	api: Api<H>,
+	account: Account,
+	contract: Address,
+	max_fee_per_gas: Option<Wei>,
+	pub replace_after: u64,
+	pub withdrawal: Option<WithdrawalConf>,
+}
+impl<H> Submitter<H>
+where
+	H: Http,
+{
+	pub fn new(conf: Conf, http: H) -> Result<Self> {
+		let Conf { id, uri, contract, secret_key, max_fee_per_gas, replace_after, withdrawal } =
+			conf;
+
+		Ok(Self {
+			id: id.into(),
+			api: Api { http, uri },
+			account: Account::from_secret_key(secret_key.trim_start_matches("0x").parse()?),
+			contract: array_bytes::hex2array(contract).map_err(Error::ArrayBytes)?,
+			max_fee_per_gas,
+			replace_after,
+			withdrawal,
+		})
+	}
+
+	// Get the next nonce of the submitter account.
+	pub async fn nonce(&self) -> Result<u64> {
+		self.api.get_transaction_count(&self.account.address).await
+	}
+
+	// Get the nonce of the next submission, following the ones recorded in `sql` which might not
+	// have reached the X chain yet.
+	pub async fn next_nonce<S>(&self, sql: &S) -> Result<u64>
+	where
+		S: Sql,
+	{
+		Ok(self.nonce().await?.max(sql.get_next_nonce(self.id).await?.unwrap_or_default()))
+	}
+
+	// Sign the submission of the record with the given nonce, it's sent by `send` once recorded.
+	//
+	// The bridge contract dedupes the calls by `txid`, a resubmission will be reverted in the gas
+	// estimation.
+	pub async fn submit(&self, xr: &XRecord, nonce: u64) -> Result<SubmissionRecord> {
+		let txid = xr.txid.parse::<Txid>().map_err(BitcoinError::HexToArray)?.to_byte_array();
+		let recipient = array_bytes::hex2bytes(&xr.recipient).map_err(Error::ArrayBytes)?;
+		let data = XCall {
+			txid,
+			amount: xr.amount,
+			target: xr.target.0,
+			recipient: &recipient,
+			// Confirmed records always come with the proof.
+			proof: xr.proof.as_deref().unwrap_or_default(),
+		}
+		.encode();
+
+		self.sign(data, nonce).await
+	}
+
+	// Sign the submission of the raw headers to the light client with the given nonce, it's sent
+	// by `send` once recorded.
+	pub async fn submit_headers(&self, headers: &[u8], nonce: u64) -> Result<SubmissionRecord> {
+		self.sign(HeadersCall { headers }.encode(), nonce).await
+	}
+
+	// Sign the completion of the withdrawal paid by the payout with the given nonce, it's sent by
+	// `send` once recorded.
+	//
+	// The bridge contract completes a withdrawal only once, a resubmission will be reverted in the
+	// gas estimation.
+	pub async fn complete_withdrawal(
+		&self,
+		wr: &WithdrawalRecord,
+		payout: &PayoutRecord,
+		nonce: u64,
+	) -> Result<SubmissionRecord> {
+		let id = array_bytes::hex2array(&wr.withdrawal_id).map_err(Error::ArrayBytes)?;
+		let mut tx = bitcoin::consensus::encode::deserialize_hex::<Transaction>(&payout.tx)
+			.map_err(BitcoinError::from)?;
+
+		// The contract hashes the transaction into the `txid`, which commits to no witness.
+		tx.input.iter_mut().for_each(|i| i.witness.clear());
+
+		let data = CompleteWithdrawalCall {
+			id,
+			tx: &bitcoin::consensus::serialize(&tx),
+			// Paid withdrawals always come with the output.
+			vout: wr.vout.unwrap_or_default(),
+			// Confirmed payouts always come with the proof.
+			proof: payout.proof.as_deref().unwrap_or_default(),
+		}
+		.encode();
+
+		self.sign(data, nonce).await
+	}
+
+	// Get the number of the latest X block.
+	pub async fn block_number(&self) -> Result<u64> {
+		self.api.block_number().await
+	}
+
+	// Get the withdraw events of the bridge contract within the block range, along with their
+	// block numbers and transaction hashes.
+	//
+	// The undecodable events are skipped, they can't be paid anyway.
+	pub async fn get_withdrawals(
+		&self,
+		from_block: u64,
+		to_block: u64,
+	) -> Result<Vec<(u64, String, WithdrawEvent)>> {
+		let mut events = Vec::new();
+
+		for l in
+			self.api.get_logs(&self.contract, &WithdrawEvent::topic(), from_block, to_block).await?
+		{
+			match WithdrawEvent::decode(&l.topics()?, &l.data()?) {
+				Ok(e) => events.push((l.block_number()?, l.transaction_hash, e)),
+				Err(e) => tracing::warn!("skipped the event in {}: {e}", l.transaction_hash),
+			}
+		}
+
+		Ok(events)
+	}
+
+	// Send the signed submission to the X chain.
+	//
+	// Sending it again is harmless, only one transaction of a nonce can ever be executed.
+	pub async fn send(&self, submission: &SubmissionRecord) -> Result<()> {
+		let raw = array_bytes::hex2bytes(&submission.tx).map_err(Error::ArrayBytes)?;
+
+		self.api.send_raw_transaction(&raw).await?;
+
+		Ok(())
+	}
+
+	// Send the recorded submission, it's sent again in the next round on failure.
+	pub async fn send_submission(&self, submission: &SubmissionRecord) {
+		if let Err(e) = self.send(submission).await {
+			tracing::warn!("fail to send the submission {}, retrying later: {e}", submission.hash);
+		}
+	}
+
+	// Get the receipt of the submission `hash` or of any of its replaced versions recorded in
+	// `sql`, along with the hash of the executed one.
+	//
+	// A pending submission is sent again in case it has been dropped or never sent, or replaced at
+	// higher fees once it has been stuck for too long.
+	pub async fn check_submission<S>(
+		&self,
+		sql: &S,
+		hash: String,
+	) -> Result<Option<(String, Receipt)>>
+	where
+		S: Sql,
+	{
+		let versions = sql.get_submission_versions(hash.clone()).await?;
+
+		if versions.is_empty() {
+			return Ok(self.get_receipt(&hash).await?.map(|r| (hash, r)));
+		}
+
+		for v in &versions {
+			if let Some(r) = self.get_receipt(&v.hash).await? {
+				return Ok(Some((v.hash.clone(), r)));
+			}
+		}
+
+		let latest = &versions[0];
+
+		if (Utc::now() - latest.created_at).num_seconds() >= self.replace_after as i64 {
+			match self.replace(latest).await? {
+				Some(replacement) => {
+					sql.replace_submission(latest.hash.clone(), replacement.clone()).await?;
+
+					tracing::warn!(
+						"submission {} replaced by {} at higher fees",
+						latest.hash,
+						replacement.hash
+					);
+
+					self.send_submission(&replacement).await;
+
+					return Ok(None);
+				},
+				None =>
+					tracing::warn!("submission {} is stuck at the maximum fee per gas", latest.hash),
+			}
+		}
+
+		if let Err(e) = self.send(latest).await {
+			tracing::debug!("fail to send the submission {} again: {e}", latest.hash);
+		}
+
+		Ok(None)
+	}
+
+	// Sign the replacement of the stuck submission, the same call of the same nonce at the current
+	// fees, which must be at least 12.5% higher than the replaced ones for the X nodes to accept
+	// it.
+	//
+	// Returns `None` if the fees are capped below.
+	pub async fn replace(&self, submission: &SubmissionRecord) -> Result<Option<SubmissionRecord>> {
+		let bump = |fee: Wei| fee + fee.div_ceil(8);
+		let (max_fee_per_gas, max_priority_fee_per_gas) = self.fees().await?;
+		let max_fee_per_gas = max_fee_per_gas.max(bump(submission.max_fee_per_gas));
+
+		if self.max_fee_per_gas.is_some_and(|cap| max_fee_per_gas > cap) {
+			return Ok(None);
+		}
+
+		let max_priority_fee_per_gas = max_priority_fee_per_gas
+			.max(bump(submission.max_priority_fee_per_gas))
+			.min(max_fee_per_gas);
+
+		Ok(Some(
+			self.sign_with(
+				submission.data.clone(),
+				submission.nonce,
+				submission.gas,
+				max_fee_per_gas,
+				max_priority_fee_per_gas,
+			)
+			.await?,
+		))
+	}
+
+	async fn sign(&self, data: Vec<u8>, nonce: u64) -> Result<SubmissionRecord> {
+		let gas = self.api.estimate_gas(&self.account.address, &self.contract, &data).await?;
+		let (max_fee_per_gas, max_priority_fee_per_gas) = self.fees().await?;
+
+		// 20% margin for the state changes between the estimation and the execution.
+		self.sign_with(data, nonce, gas * 6 / 5, max_fee_per_gas, max_priority_fee_per_gas).await
+	}
+
+	async fn sign_with(
+		&self,
+		data: Vec<u8>,
+		nonce: u64,
+		gas: u64,
+		max_fee_per_gas: Wei,
+		max_priority_fee_per_gas: Wei,
+	) -> Result<SubmissionRecord> {
+		let tx = Eip1559Tx {
+			chain_id: self.api.chain_id().await?,
+			nonce,
+			max_priority_fee_per_gas,
+			max_fee_per_gas,
+			gas,
+			to: self.contract,
+			value: 0,
+			data,
+		};
+		let (raw, hash) = tx.sign(&self.account);
+
+		Ok(SubmissionRecord {
+			target: self.id,
+			nonce,
+			hash: array_bytes::bytes2hex("0x", hash),
+			tx: array_bytes::bytes2hex("", raw),
+			data: tx.data,
+			gas,
+			max_fee_per_gas,
+			max_priority_fee_per_gas,
+			created_at: Utc::now(),
+		})
+	}
+
+	// Get the max fee per gas and the max priority fee per gas of a new transaction.
+	async fn fees(&self) -> Result<(Wei, Wei)> {
+		let max_priority_fee_per_gas = self.api.max_priority_fee_per_gas().await?;
+		// Leave room for the base fee to double.
+		let mut max_fee_per_gas = self.api.base_fee_per_gas().await? * 2 + max_priority_fee_per_gas;
+
+		if let Some(cap) = self.max_fee_per_gas {
+			max_fee_per_gas = max_fee_per_gas.min(cap);
+		}
+
+		Ok((max_fee_per_gas, max_priority_fee_per_gas.min(max_fee_per_gas)))
+	}
+
+	// Get the receipt of a submission, `None` if it's still pending.
+	pub async fn get_receipt(&self, hash: &str) -> Result<Option<Receipt>> {
+		self.api.get_transaction_receipt(hash).await
+	}
+}
+
+/// Whether the error is caused by the contract reverting the call.
+pub fn is_reverted(e: &Error) -> bool {
+	matches!(e, Error::Api(ApiError::JsonRpc { message, .. }) if message.contains("revert"))
+}
+
+/// Whether the contract reverted the call since the record has been relayed already, which no
+/// retry changes.
+pub fn is_relayed(e: &Error) -> bool {
+	matches!(
+		e,
+		Error::Api(ApiError::JsonRpc { message, .. })
+			if message.contains("revert") && message.ends_with("relayed")
+	)
+}
@@ -1,15 +1,45 @@
 // std
-use std::{iter::Iterator, path::Path, sync::Arc};
+use std::{
+	fmt::{Display, Formatter, Result as FmtResult},
+	iter::Iterator,
+	path::Path,
+	sync::Arc,
+};
 // crates.io
 use chrono::{DateTime, Utc};
 use deadpool_sqlite::{Config, Object, Pool, Runtime::Tokio1};
 use rusqlite::{
-	types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef},
-	Connection, OptionalExtension,
+	types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, Type, ValueRef},
+	Connection, OptionalExtension, Row,
 };
 // self
 use crate::{prelude::*, x::*};
 
+// Migrations of the tables created by an older version, the n-th one upgrades the schema from
+// version n, `PRAGMA user_version`, to n + 1.
+//
+// Each one alters a single table, and is skipped if the table didn't exist yet, since it's created
+// at the latest schema then.
+//
+// Version 0 comes without the block hashes, the statuses and the proofs of the records. Their
+// block hashes are filled from the best chain by the relayer, see `get_heights_without_hash`.
+// Version 1 comes without the outputs spent by the consolidations, which are broadcasted again
+// but never replaced.
+const MIGRATIONS: &[(&str, &str)] = &[
+	(
+		"{0}",
+		"ALTER TABLE [{0}] ADD COLUMN block_hash TEXT NOT NULL DEFAULT '';\
+		ALTER TABLE [{0}] ADD COLUMN status TEXT NOT NULL DEFAULT 'detected';\
+		ALTER TABLE [{0}] ADD COLUMN proof BLOB;\
+		INSERT INTO [{0}-history] (record_id, from_status, to_status, reason, created_at) \
+		SELECT id, NULL, status, 'migrated from version 0', created_at FROM [{0}];",
+	),
+	(
+		"{0}-consolidation",
+		"ALTER TABLE [{0}-consolidation] ADD COLUMN prevouts BLOB NOT NULL DEFAULT x'';",
+	),
+];
+
 pub trait Sql
 where
 	Self: X,
@@ -28,24 +58,253 @@ where
 		self.sql().await?.interact(|sql| f(sql)).await.map_err(DeadpoolSqliteError::Interact)?
 	}
 
+	// Create the tables, and migrate the ones of an older schema version.
 	async fn init(&self) -> Result<()> {
-		self.interact(move |sql| {
-			sql.execute(
-				&format!(
-					"CREATE TABLE IF NOT EXISTS [{}] (\
+		self.interact(move |c| {
+			let t = c.unchecked_transaction()?;
+			let version = t.pragma_query_value(None, "user_version", |r| r.get::<_, usize>(0))?;
+			// The tables created below start at the latest version.
+			let existed = MIGRATIONS
+				.iter()
+				.map(|(table, _)| {
+					t.query_row(
+						"SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+						[table.replace("{0}", Self::NAME)],
+						|r| r.get::<_, bool>(0),
+					)
+				})
+				.collect::<rusqlite::Result<Vec<_>>>()?;
+
+			t.execute_batch(&format!(
+				"CREATE TABLE IF NOT EXISTS [{0}] (\
 					id INTEGER PRIMARY KEY AUTOINCREMENT,\
 					block_height INTEGER NOT NULL,\
+					block_hash TEXT NOT NULL,\
 					txid TEXT NOT NULL,\
 					target INTEGER NOT NULL,\
 					recipient TEXT NOT NULL,\
 					amount TEXT NOT NULL,\
+					status TEXT NOT NULL,\
 					hash TEXT,\
+					proof BLOB,\
 					created_at DATETIME NOT NULL,\
 					finished_at DATETIME\
-				)",
+				);\
+				CREATE TABLE IF NOT EXISTS [{0}-history] (\
+					id INTEGER PRIMARY KEY AUTOINCREMENT,\
+					record_id INTEGER NOT NULL,\
+					from_status TEXT,\
+					to_status TEXT NOT NULL,\
+					reason TEXT NOT NULL,\
+					created_at DATETIME NOT NULL\
+				);\
+				CREATE TABLE IF NOT EXISTS [{0}-deposit-address] (\
+					id INTEGER PRIMARY KEY,\
+					address TEXT NOT NULL UNIQUE,\
+					target INTEGER NOT NULL,\
+					recipient TEXT NOT NULL,\
+					created_at DATETIME NOT NULL,\
+					UNIQUE (target, recipient)\
+				);\
+				CREATE TABLE IF NOT EXISTS [{0}-withdrawal] (\
+					id INTEGER PRIMARY KEY AUTOINCREMENT,\
+					target INTEGER NOT NULL,\
+					withdrawal_id TEXT NOT NULL,\
+					x_block INTEGER NOT NULL,\
+					x_hash TEXT NOT NULL,\
+					recipient TEXT NOT NULL,\
+					amount TEXT NOT NULL,\
+					status TEXT NOT NULL,\
+					txid TEXT,\
+					vout INTEGER,\
+					hash TEXT,\
+					reason TEXT,\
+					created_at DATETIME NOT NULL,\
+					finished_at DATETIME,\
+					UNIQUE (target, withdrawal_id),\
+					UNIQUE (txid, vout)\
+				);\
+				CREATE TABLE IF NOT EXISTS [{0}-payout] (\
+					id INTEGER PRIMARY KEY AUTOINCREMENT,\
+					txid TEXT NOT NULL UNIQUE,\
+					origin TEXT NOT NULL,\
+					tx TEXT NOT NULL,\
+					fee_rate INTEGER NOT NULL,\
+					prevouts BLOB NOT NULL,\
+					block_height INTEGER,\
+					block_hash TEXT,\
+					proof BLOB,\
+					created_at DATETIME NOT NULL\
+				);\
+				CREATE TABLE IF NOT EXISTS [{0}-cpfp] (\
+					id INTEGER PRIMARY KEY AUTOINCREMENT,\
+					parent TEXT NOT NULL,\
+					txid TEXT NOT NULL UNIQUE,\
+					tx TEXT NOT NULL,\
+					fee_rate INTEGER NOT NULL,\
+					created_at DATETIME NOT NULL\
+				);\
+				CREATE TABLE IF NOT EXISTS [{0}-consolidation] (\
+					id INTEGER PRIMARY KEY AUTOINCREMENT,\
+					txid TEXT NOT NULL UNIQUE,\
+					tx TEXT NOT NULL,\
+					inputs INTEGER NOT NULL,\
+					amount INTEGER NOT NULL,\
+					fee INTEGER NOT NULL,\
+					fee_rate INTEGER NOT NULL,\
+					prevouts BLOB NOT NULL,\
+					block_height INTEGER,\
+					created_at DATETIME NOT NULL\
+				);\
+				CREATE TABLE IF NOT EXISTS [{0}-submission] (\
+					id INTEGER PRIMARY KEY AUTOINCREMENT,\
+					target INTEGER NOT NULL,\
+					nonce INTEGER NOT NULL,\
+					hash TEXT NOT NULL UNIQUE,\
+					tx TEXT NOT NULL,\
+					data BLOB NOT NULL,\
+					gas INTEGER NOT NULL,\
+					max_fee_per_gas TEXT NOT NULL,\
+					max_priority_fee_per_gas TEXT NOT NULL,\
+					created_at DATETIME NOT NULL\
+				);\
+				CREATE TABLE IF NOT EXISTS [{0}-withdrawal-cursor] (\
+					target INTEGER PRIMARY KEY,\
+					x_block INTEGER NOT NULL\
+				);\
+				CREATE TABLE IF NOT EXISTS [{0}-deposit-cursor] (\
+					address_id INTEGER PRIMARY KEY,\
+					block_height INTEGER NOT NULL\
+				);",
+				Self::NAME
+			))?;
+
+			for (i, ((_, m), existed)) in MIGRATIONS.iter().zip(existed).enumerate().skip(version) {
+				if existed {
+					t.execute_batch(&m.replace("{0}", Self::NAME))?;
+
+					tracing::info!("migrated the database to version {}", i + 1);
+				}
+			}
+
+			t.pragma_update(None, "user_version", MIGRATIONS.len())?;
+			t.commit()?;
+
+			Ok(())
+		})
+		.await
+	}
+
+	// Get the latest record which is neither rejected nor orphaned.
+	async fn get_latest(&self) -> Result<Option<XRecord>> {
+		self.interact(move |sql| {
+			Ok(sql
+				.prepare(&format!(
+					"SELECT {} FROM [{}] WHERE status NOT IN (?1, ?2) ORDER BY id DESC LIMIT 1",
+					XRecord::COLUMNS,
+					Self::NAME,
+				))?
+				.query_row([XStatus::Rejected, XStatus::Orphaned], XRecord::from_row)
+				.optional()?)
+		})
+		.await
+	}
+
+	// Get the records of the Bitcoin transaction, including the rejected ones.
+	async fn get_by_txid(&self, txid: String) -> Result<Vec<XRecord>> {
+		self.interact(move |sql| {
+			Ok(sql
+				.prepare(&format!(
+					"SELECT {} FROM [{}] WHERE txid = ?1 ORDER BY id",
+					XRecord::COLUMNS,
+					Self::NAME,
+				))?
+				.query_map([txid], XRecord::from_row)?
+				.collect::<rusqlite::Result<_>>()?)
+		})
+		.await
+	}
+
+	// Get the records with the status, sorted with oldest first.
+	async fn get_by_status(&self, status: XStatus) -> Result<Vec<XRecord>> {
+		self.interact(move |sql| {
+			Ok(sql
+				.prepare(&format!(
+					"SELECT {} FROM [{}] WHERE status = ?1 ORDER BY id",
+					XRecord::COLUMNS,
+					Self::NAME,
+				))?
+				.query_map([status], XRecord::from_row)?
+				.collect::<rusqlite::Result<_>>()?)
+		})
+		.await
+	}
+
+	// Get the records at and above `block_height` which are neither rejected nor orphaned.
+	async fn get_since(&self, block_height: u64) -> Result<Vec<XRecord>> {
+		self.interact(move |sql| {
+			Ok(sql
+				.prepare(&format!(
+					"SELECT {} FROM [{}] WHERE status NOT IN (?1, ?2) AND block_height >= ?3 \
+					ORDER BY id",
+					XRecord::COLUMNS,
+					Self::NAME,
+				))?
+				.query_map(
+					rusqlite::params![XStatus::Rejected, XStatus::Orphaned, block_height],
+					XRecord::from_row,
+				)?
+				.collect::<rusqlite::Result<_>>()?)
+		})
+		.await
+	}
+
+	// Get the newest block containing records below `block_height`, or the newest one if not set.
+	// Rejected and orphaned records are ignored.
+	async fn get_block_before(&self, block_height: Option<u64>) -> Result<Option<(u64, String)>> {
+		self.interact(move |sql| {
+			let block = sql
+				.prepare(&format!(
+					"SELECT block_height, block_hash FROM [{}] \
+					WHERE status NOT IN (?1, ?2) AND (?3 IS NULL OR block_height < ?3) \
+					ORDER BY block_height DESC LIMIT 1",
+					Self::NAME
+				))?
+				.query_row(
+					rusqlite::params![XStatus::Rejected, XStatus::Orphaned, block_height],
+					|r| Ok((r.get(0)?, r.get(1)?)),
+				)
+				.optional()?;
+
+			Ok(block)
+		})
+		.await
+	}
+
+	// Get the block heights of the records migrated without block hashes.
+	async fn get_heights_without_hash(&self) -> Result<Vec<u64>> {
+		self.interact(move |sql| {
+			Ok(sql
+				.prepare(&format!(
+					"SELECT DISTINCT block_height FROM [{}] WHERE block_hash = '' \
+					ORDER BY block_height",
+					Self::NAME
+				))?
+				.query_map([], |r| r.get(0))?
+				.collect::<rusqlite::Result<_>>()?)
+		})
+		.await
+	}
+
+	// Fill the block hash of the records migrated at the height.
+	async fn fill_block_hash(&self, block_height: u64, block_hash: String) -> Result<()> {
+		self.interact(move |sql| {
+			sql.execute(
+				&format!(
+					"UPDATE [{}] SET block_hash = ?1 WHERE block_height = ?2 AND block_hash = ''",
 					Self::NAME
 				),
-				(),
+				rusqlite::params![block_hash, block_height],
 			)?;
 
 			Ok(())
@@ -53,115 +312,1925 @@ where
 		.await
 	}
 
-	async fn get_latest(&self) -> Result<Option<XRecord>> {
-		let Some((block_height, txid, target, recipient, amount, hash, created_at, finished_at)) =
-			self.interact(move |sql| {
-				let mut stmt = sql
-					.prepare(
-						&format!("SELECT * FROM [{}] ORDER BY id DESC LIMIT 1", Self::NAME,),
-					)?;
-				let maybe_xr = stmt
-					.query_row((), |r| {
-						Ok((
-							r.get(1)?,
-							r.get(2)?,
-							r.get(3)?,
-							r.get(4)?,
-							r.get::<_, String>(5)?,
-							r.get(6)?,
-							r.get(7)?,
-							r.get(8)?,
-						))
+	// Get the status transitions of the record, sorted with oldest first.
+	async fn get_history(&self, id: u64) -> Result<Vec<XTransition>> {
+		self.interact(move |sql| {
+			Ok(sql
+				.prepare(&format!(
+					"SELECT from_status, to_status, reason, created_at FROM [{}-history] \
+					WHERE record_id = ?1 ORDER BY id",
+					Self::NAME,
+				))?
+				.query_map([id], |r| {
+					Ok(XTransition {
+						from: r.get(0)?,
+						to: r.get(1)?,
+						reason: r.get(2)?,
+						created_at: r.get(3)?,
 					})
-					.optional()?;
-
-				Ok(maybe_xr)
-			})
-			.await?
-		else {
-			return Ok(None);
-		};
-		let xr = XRecord {
-			block_height,
-			txid,
-			target,
-			recipient,
-			amount: amount.parse()?,
-			hash,
-			created_at,
-			finished_at,
-		};
-
-		Ok(Some(xr))
+				})?
+				.collect::<rusqlite::Result<_>>()?)
+		})
+		.await
 	}
 
-	async fn insert<I>(&self, mut records: I) -> Result<()>
+	// Get the deposit address bound to the target, or issue a new one at the next index with
+	// `derive`.
+	async fn issue_deposit_address<F>(
+		&self,
+		target: Id,
+		recipient: String,
+		derive: F,
+	) -> Result<DepositAddress>
 	where
-		I: 'static + Send + Iterator<Item = XRecord>,
+		F: 'static + Send + FnOnce(u32) -> Result<String>,
 	{
 		self.interact(move |c| {
+			let tx = c.unchecked_transaction()?;
+			let select = format!(
+				"SELECT {} FROM [{}-deposit-address] WHERE target = ?1 AND recipient = ?2",
+				DepositAddress::COLUMNS,
+				Self::NAME
+			);
+
+			if let Some(a) = tx
+				.query_row(&select, rusqlite::params![target, recipient], DepositAddress::from_row)
+				.optional()?
+			{
+				return Ok(a);
+			}
+
+			let index = tx.query_row(
+				&format!("SELECT COALESCE(MAX(id) + 1, 0) FROM [{}-deposit-address]", Self::NAME),
+				[],
+				|r| r.get(0),
+			)?;
+
+			tx.execute(
+				&format!(
+					"INSERT INTO [{}-deposit-address] (\
+					id,\
+					address,\
+					target,\
+					recipient,\
+					created_at\
+				) VALUES (?1, ?2, ?3, ?4, ?5)",
+					Self::NAME
+				),
+				rusqlite::params![index, derive(index)?, target, recipient, Utc::now()],
+			)?;
+
+			let a = tx.query_row(
+				&select,
+				rusqlite::params![target, recipient],
+				DepositAddress::from_row,
+			)?;
+
+			tx.commit()?;
+
+			Ok(a)
+		})
+		.await
+	}
+
+	// Get all the issued deposit addresses, sorted by their index.
+	async fn get_deposit_addresses(&self) -> Result<Vec<DepositAddress>> {
+		self.interact(move |sql| {
+			Ok(sql
+				.prepare(&format!(
+					"SELECT {} FROM [{}-deposit-address] ORDER BY id",
+					DepositAddress::COLUMNS,
+					Self::NAME,
+				))?
+				.query_map((), DepositAddress::from_row)?
+				.collect::<rusqlite::Result<_>>()?)
+		})
+		.await
+	}
+
+	// Get the tip height the deposit address was last scanned at.
+	async fn get_deposit_cursor(&self, index: u32) -> Result<Option<u64>> {
+		self.interact(move |sql| {
+			Ok(sql
+				.query_row(
+					&format!(
+						"SELECT block_height FROM [{}-deposit-cursor] WHERE address_id = ?1",
+						Self::NAME
+					),
+					[index],
+					|r| r.get(0),
+				)
+				.optional()?)
+		})
+		.await
+	}
+
+	// Move the cursors of the deposit addresses, once their deposits have been inserted.
+	async fn set_deposit_cursors(&self, cursors: Vec<(u32, u64)>) -> Result<()> {
+		self.interact(move |c| {
+			let tx = c.unchecked_transaction()?;
 			let sql = format!(
-				"INSERT OR REPLACE INTO [{}] (\
-				block_height,\
-				txid,\
+				"INSERT INTO [{}-deposit-cursor] (address_id, block_height) VALUES (?1, ?2) \
+				ON CONFLICT (address_id) DO UPDATE SET block_height = ?2",
+				Self::NAME
+			);
+
+			for (index, block_height) in cursors {
+				tx.execute(&sql, rusqlite::params![index, block_height])?;
+			}
+
+			tx.commit()?;
+
+			Ok(())
+		})
+		.await
+	}
+
+	// Get the last X block scanned for the withdrawals of the target.
+	async fn get_withdrawal_cursor(&self, target: Id) -> Result<Option<u64>> {
+		self.interact(move |sql| {
+			Ok(sql
+				.query_row(
+					&format!(
+						"SELECT x_block FROM [{}-withdrawal-cursor] WHERE target = ?1",
+						Self::NAME
+					),
+					[target],
+					|r| r.get(0),
+				)
+				.optional()?)
+		})
+		.await
+	}
+
+	// Insert the withdrawals as detected and move the cursor of the target to `x_block`, at once.
+	//
+	// The withdrawals recorded already are ignored, so a block can be scanned again safely.
+	async fn insert_withdrawals(
+		&self,
+		target: Id,
+		x_block: u64,
+		withdrawals: Vec<WithdrawalRecord>,
+	) -> Result<()> {
+		self.interact(move |c| {
+			let tx = c.unchecked_transaction()?;
+			let sql = format!(
+				"INSERT OR IGNORE INTO [{}-withdrawal] (\
 				target,\
+				withdrawal_id,\
+				x_block,\
+				x_hash,\
 				recipient,\
 				amount,\
-				hash,\
-				created_at,\
-				finished_at\
+				status,\
+				created_at\
 			) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
 				Self::NAME
 			);
 
-			records.try_for_each(|r| {
-				c.execute(
+			for w in withdrawals {
+				tx.execute(
 					&sql,
 					rusqlite::params![
-						r.block_height,
-						r.txid,
-						r.target,
-						r.recipient,
-						r.amount.to_string(),
-						r.hash,
-						r.created_at,
-						r.finished_at
+						target,
+						w.withdrawal_id,
+						w.x_block,
+						w.x_hash,
+						w.recipient,
+						w.amount.to_string(),
+						WithdrawalStatus::Detected,
+						w.created_at
 					],
 				)?;
+			}
 
-				Ok(())
-			})
+			tx.execute(
+				&format!(
+					"INSERT INTO [{}-withdrawal-cursor] (target, x_block) VALUES (?1, ?2) \
+					ON CONFLICT (target) DO UPDATE SET x_block = ?2",
+					Self::NAME
+				),
+				rusqlite::params![target, x_block],
+			)?;
+			tx.commit()?;
+
+			Ok(())
 		})
 		.await
 	}
-}
 
-#[derive(Debug)]
-pub struct XRecord {
-	pub block_height: u64,
-	pub txid: String,
-	pub target: Id,
-	pub recipient: String,
-	pub amount: u128,
-	pub hash: Option<String>,
-	pub created_at: DateTime<Utc>,
-	pub finished_at: Option<DateTime<Utc>>,
-}
+	// Get the withdrawals with the status, sorted with oldest first.
+	async fn get_withdrawals_by_status(
+		&self,
+		status: WithdrawalStatus,
+	) -> Result<Vec<WithdrawalRecord>> {
+		self.interact(move |sql| {
+			Ok(sql
+				.prepare(&format!(
+					"SELECT {} FROM [{}-withdrawal] WHERE status = ?1 ORDER BY id",
+					WithdrawalRecord::COLUMNS,
+					Self::NAME,
+				))?
+				.query_map([status], WithdrawalRecord::from_row)?
+				.collect::<rusqlite::Result<_>>()?)
+		})
+		.await
+	}
 
-impl FromSql for Id {
-	fn column_result(value: ValueRef) -> FromSqlResult<Self> {
-		Ok(Self(value.as_i64()? as _))
+	// Get the withdrawals paid by the Bitcoin transaction, sorted by their outputs.
+	async fn get_withdrawals_by_txid(&self, txid: String) -> Result<Vec<WithdrawalRecord>> {
+		self.interact(move |sql| {
+			Ok(sql
+				.prepare(&format!(
+					"SELECT {} FROM [{}-withdrawal] WHERE txid = ?1 ORDER BY vout",
+					WithdrawalRecord::COLUMNS,
+					Self::NAME,
+				))?
+				.query_map([txid], WithdrawalRecord::from_row)?
+				.collect::<rusqlite::Result<_>>()?)
+		})
+		.await
 	}
-}
-impl ToSql for Id {
-	fn to_sql(&self) -> rusqlite::Result<ToSqlOutput> {
-		Ok(ToSqlOutput::from(self.0))
+
+	// Get the withdrawals of the id on all the X chains.
+	async fn get_withdrawals_by_id(&self, withdrawal_id: String) -> Result<Vec<WithdrawalRecord>> {
+		self.interact(move |sql| {
+			Ok(sql
+				.prepare(&format!(
+					"SELECT {} FROM [{}-withdrawal] WHERE withdrawal_id = ?1 ORDER BY id",
+					WithdrawalRecord::COLUMNS,
+					Self::NAME,
+				))?
+				.query_map([withdrawal_id], WithdrawalRecord::from_row)?
+				.collect::<rusqlite::Result<_>>()?)
+		})
+		.await
 	}
-}
 
-pub fn init<P>(path: P) -> Result<Pool>
-where
-	P: AsRef<Path>,
-{
-	Ok(Config::new(path.as_ref()).create_pool(Tokio1).map_err(DeadpoolSqliteError::Create)?)
+	// Get the payout of the Bitcoin transaction.
+	async fn get_payout(&self, txid: String) -> Result<Option<PayoutRecord>> {
+		self.interact(move |sql| {
+			Ok(sql
+				.query_row(
+					&format!(
+						"SELECT {} FROM [{}-payout] WHERE txid = ?1",
+						PayoutRecord::COLUMNS,
+						Self::NAME,
+					),
+					[txid],
+					PayoutRecord::from_row,
+				)
+				.optional()?)
+		})
+		.await
+	}
+
+	// Get the versions of the payout since the `origin` one, sorted with newest first.
+	async fn get_payout_versions(&self, origin: String) -> Result<Vec<PayoutRecord>> {
+		self.interact(move |sql| {
+			Ok(sql
+				.prepare(&format!(
+					"SELECT {} FROM [{}-payout] WHERE origin = ?1 ORDER BY id DESC",
+					PayoutRecord::COLUMNS,
+					Self::NAME,
+				))?
+				.query_map([origin], PayoutRecord::from_row)?
+				.collect::<rusqlite::Result<_>>()?)
+		})
+		.await
+	}
+
+	// Get the payouts of the paid withdrawals, sorted with oldest first.
+	async fn get_unconfirmed_payouts(&self) -> Result<Vec<PayoutRecord>> {
+		self.interact(move |sql| {
+			Ok(sql
+				.prepare(&format!(
+					"SELECT {} FROM [{1}-payout] WHERE txid IN \
+					(SELECT txid FROM [{1}-withdrawal] WHERE status = ?1) ORDER BY id",
+					PayoutRecord::COLUMNS,
+					Self::NAME,
+				))?
+				.query_map([WithdrawalStatus::Paid], PayoutRecord::from_row)?
+				.collect::<rusqlite::Result<_>>()?)
+		})
+		.await
+	}
+
+	// The payout paying the withdrawals at their outputs has been signed, it must be recorded
+	// before the broadcast so that the withdrawals are never paid twice.
+	async fn pay_withdrawals(&self, payout: PayoutRecord, paid: Vec<(u64, u32)>) -> Result<()> {
+		self.interact(move |c| {
+			let t = c.unchecked_transaction()?;
+
+			insert_payout(&t, Self::NAME, &payout)?;
+
+			for (id, vout) in paid {
+				withdrawal_transition(&t, Self::NAME, id, WithdrawalStatus::Paid)?;
+				t.execute(
+					&format!(
+						"UPDATE [{}-withdrawal] SET txid = ?1, vout = ?2 WHERE id = ?3",
+						Self::NAME
+					),
+					rusqlite::params![payout.txid, vout, id],
+				)?;
+			}
+
+			t.commit()?;
+
+			Ok(())
+		})
+		.await
+	}
+
+	// The payout `txid` is replaced by a version paying the same outputs at a higher fee rate,
+	// which is recorded before the broadcast like the original one.
+	async fn replace_payout(&self, txid: String, replacement: PayoutRecord) -> Result<()> {
+		self.interact(move |c| {
+			let t = c.unchecked_transaction()?;
+
+			insert_payout(&t, Self::NAME, &replacement)?;
+			move_paid_withdrawals(&t, Self::NAME, &txid, &replacement.txid)?;
+			t.commit()?;
+
+			Ok(())
+		})
+		.await
+	}
+
+	// An earlier version `to` of the payout `txid` has been confirmed instead of it.
+	async fn revert_payout(&self, txid: String, to: String) -> Result<()> {
+		self.interact(move |c| move_paid_withdrawals(c, Self::NAME, &txid, &to)).await
+	}
+
+	// The inputs of the payout `txid` have been double spent by a confirmed transaction, so none
+	// of its versions can ever be confirmed, and its withdrawals are paid again.
+	async fn cancel_payout(&self, txid: String) -> Result<()> {
+		self.interact(move |c| {
+			let t = c.unchecked_transaction()?;
+
+			transit_withdrawals_of(
+				&t,
+				Self::NAME,
+				&txid,
+				WithdrawalStatus::Paid,
+				WithdrawalStatus::Detected,
+			)?;
+			t.execute(
+				&format!(
+					"UPDATE [{}-withdrawal] SET txid = NULL, vout = NULL \
+					WHERE txid = ?1 AND status = ?2",
+					Self::NAME
+				),
+				rusqlite::params![txid, WithdrawalStatus::Detected],
+			)?;
+			t.commit()?;
+
+			Ok(())
+		})
+		.await
+	}
+
+	// Get the latest child paying for the payout `parent`, which replaces the earlier ones.
+	async fn get_latest_cpfp(&self, parent: String) -> Result<Option<CpfpRecord>> {
+		self.interact(move |sql| {
+			Ok(sql
+				.query_row(
+					&format!(
+						"SELECT {} FROM [{}-cpfp] WHERE parent = ?1 ORDER BY id DESC LIMIT 1",
+						CpfpRecord::COLUMNS,
+						Self::NAME,
+					),
+					[parent],
+					CpfpRecord::from_row,
+				)
+				.optional()?)
+		})
+		.await
+	}
+
+	// The child paying for the payout has been signed, it's recorded before the broadcast like the
+	// payout.
+	async fn insert_cpfp(&self, cpfp: CpfpRecord) -> Result<()> {
+		self.interact(move |sql| {
+			sql.execute(
+				&format!(
+					"INSERT INTO [{}-cpfp] (parent, txid, tx, fee_rate, created_at) \
+					VALUES (?1, ?2, ?3, ?4, ?5)",
+					Self::NAME
+				),
+				rusqlite::params![cpfp.parent, cpfp.txid, cpfp.tx, cpfp.fee_rate, cpfp.created_at],
+			)?;
+
+			Ok(())
+		})
+		.await
+	}
+
+	// Get the consolidation of the Bitcoin transaction.
+	async fn get_consolidation(&self, txid: String) -> Result<Option<ConsolidationRecord>> {
+		self.interact(move |sql| {
+			Ok(sql
+				.query_row(
+					&format!(
+						"SELECT {} FROM [{}-consolidation] WHERE txid = ?1",
+						ConsolidationRecord::COLUMNS,
+						Self::NAME,
+					),
+					[txid],
+					ConsolidationRecord::from_row,
+				)
+				.optional()?)
+		})
+		.await
+	}
+
+	// Get the latest version of the consolidation not included in a block yet, there is at most
+	// one at a time.
+	async fn get_unconfirmed_consolidation(&self) -> Result<Option<ConsolidationRecord>> {
+		Ok(self.get_unconfirmed_consolidation_versions().await?.into_iter().next())
+	}
+
+	// Get the versions of the consolidation not included in a block yet, sorted with newest
+	// first. The replacements spend the same outputs, so only one of them can be included.
+	async fn get_unconfirmed_consolidation_versions(&self) -> Result<Vec<ConsolidationRecord>> {
+		self.interact(move |sql| {
+			Ok(sql
+				.prepare(&format!(
+					"SELECT {} FROM [{}-consolidation] WHERE block_height IS NULL ORDER BY id DESC",
+					ConsolidationRecord::COLUMNS,
+					Self::NAME,
+				))?
+				.query_map([], ConsolidationRecord::from_row)?
+				.collect::<rusqlite::Result<_>>()?)
+		})
+		.await
+	}
+
+	// The consolidation has been signed, it's recorded before the broadcast like the payouts.
+	//
+	// A replacement is recorded along with the versions it replaces.
+	async fn insert_consolidation(&self, consolidation: ConsolidationRecord) -> Result<()> {
+		self.interact(move |sql| {
+			sql.execute(
+				&format!(
+					"INSERT INTO [{}-consolidation] \
+					(txid, tx, inputs, amount, fee, fee_rate, prevouts, created_at) \
+					VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+					Self::NAME
+				),
+				rusqlite::params![
+					consolidation.txid,
+					consolidation.tx,
+					consolidation.inputs,
+					consolidation.amount,
+					consolidation.fee,
+					consolidation.fee_rate,
+					consolidation.prevouts,
+					consolidation.created_at
+				],
+			)?;
+
+			Ok(())
+		})
+		.await
+	}
+
+	// The version of the consolidation has been included in the block, the other versions are
+	// dropped since they can never be.
+	async fn confirm_consolidation(&self, txid: String, block_height: u64) -> Result<()> {
+		self.interact(move |c| {
+			let t = c.unchecked_transaction()?;
+
+			t.execute(
+				&format!(
+					"UPDATE [{}-consolidation] SET block_height = ?1 WHERE txid = ?2",
+					Self::NAME
+				),
+				rusqlite::params![block_height, txid],
+			)?;
+			t.execute(
+				&format!("DELETE FROM [{}-consolidation] WHERE block_height IS NULL", Self::NAME),
+				[],
+			)?;
+			t.commit()?;
+
+			Ok(())
+		})
+		.await
+	}
+
+	// The inputs of the consolidation not included in a block yet are gone, so none of its
+	// versions can ever be.
+	async fn abandon_consolidation(&self) -> Result<()> {
+		self.interact(move |sql| {
+			sql.execute(
+				&format!("DELETE FROM [{}-consolidation] WHERE block_height IS NULL", Self::NAME),
+				[],
+			)?;
+
+			Ok(())
+		})
+		.await
+	}
+
+	// The payout has reached the required confirmations in the block, and its inclusion has been
+	// proven by the serialized SPV `proof`.
+	async fn confirm_payout(
+		&self,
+		txid: String,
+		block_height: u64,
+		block_hash: String,
+		proof: Vec<u8>,
+	) -> Result<()> {
+		self.interact(move |c| {
+			let t = c.unchecked_transaction()?;
+
+			t.execute(
+				&format!(
+					"UPDATE [{}-payout] SET block_height = ?1, block_hash = ?2, proof = ?3 \
+					WHERE txid = ?4",
+					Self::NAME
+				),
+				rusqlite::params![block_height, block_hash, proof, txid],
+			)?;
+			transit_withdrawals_of(
+				&t,
+				Self::NAME,
+				&txid,
+				WithdrawalStatus::Paid,
+				WithdrawalStatus::Confirmed,
+			)?;
+			t.commit()?;
+
+			Ok(())
+		})
+		.await
+	}
+
+	// The block of the payout has been orphaned before the completions, wait for it to be
+	// confirmed again.
+	async fn unconfirm_payout(&self, txid: String) -> Result<()> {
+		self.interact(move |c| {
+			let t = c.unchecked_transaction()?;
+
+			t.execute(
+				&format!(
+					"UPDATE [{}-payout] SET block_height = NULL, block_hash = NULL, proof = NULL \
+					WHERE txid = ?1",
+					Self::NAME
+				),
+				[&txid],
+			)?;
+			transit_withdrawals_of(
+				&t,
+				Self::NAME,
+				&txid,
+				WithdrawalStatus::Confirmed,
+				WithdrawalStatus::Paid,
+			)?;
+			t.commit()?;
+
+			Ok(())
+		})
+		.await
+	}
+
+	// The completion has been submitted to X in the signed transaction, recorded before it's sent
+	// like the record submissions.
+	async fn submit_withdrawal(&self, id: u64, submission: SubmissionRecord) -> Result<()> {
+		self.interact(move |c| {
+			let t = c.unchecked_transaction()?;
+
+			withdrawal_transition(&t, Self::NAME, id, WithdrawalStatus::Submitted)?;
+			t.execute(
+				&format!("UPDATE [{}-withdrawal] SET hash = ?1 WHERE id = ?2", Self::NAME),
+				rusqlite::params![submission.hash, id],
+			)?;
+			insert_submission(&t, Self::NAME, &submission)?;
+			t.commit()?;
+
+			Ok(())
+		})
+		.await
+	}
+
+	// Replace the stuck submission `hash` by the one of the same nonce, the record or the
+	// withdrawal submitted in it follows the replacement.
+	async fn replace_submission(&self, hash: String, submission: SubmissionRecord) -> Result<()> {
+		self.interact(move |c| {
+			let t = c.unchecked_transaction()?;
+
+			insert_submission(&t, Self::NAME, &submission)?;
+
+			for table in [Self::NAME.to_owned(), format!("{}-withdrawal", Self::NAME)] {
+				t.execute(
+					&format!("UPDATE [{table}] SET hash = ?1 WHERE hash = ?2"),
+					rusqlite::params![submission.hash, hash],
+				)?;
+			}
+
+			t.commit()?;
+
+			Ok(())
+		})
+		.await
+	}
+
+	// Get the versions of the submission `hash` sharing its nonce, sorted with latest first.
+	//
+	// Empty if the submission was sent before they were recorded.
+	async fn get_submission_versions(&self, hash: String) -> Result<Vec<SubmissionRecord>> {
+		self.interact(move |sql| {
+			Ok(sql
+				.prepare(&format!(
+					"SELECT {} FROM [{1}-submission] WHERE (target, nonce) = \
+					(SELECT target, nonce FROM [{1}-submission] WHERE hash = ?1) ORDER BY id DESC",
+					SubmissionRecord::COLUMNS,
+					Self::NAME,
+				))?
+				.query_map([hash], SubmissionRecord::from_row)?
+				.collect::<rusqlite::Result<_>>()?)
+		})
+		.await
+	}
+
+	// Get the nonce following the recorded submissions to the target, some of them might not have
+	// reached the X chain yet.
+	async fn get_next_nonce(&self, target: Id) -> Result<Option<u64>> {
+		self.interact(move |sql| {
+			Ok(sql.query_row(
+				&format!(
+					"SELECT MAX(nonce) + 1 FROM [{}-submission] WHERE target = ?1",
+					Self::NAME
+				),
+				[target],
+				|r| r.get(0),
+			)?)
+		})
+		.await
+	}
+
+	// The completion has been executed successfully on X, or reverted if not `succeeded`, in which
+	// case it's submitted again.
+	async fn finalize_withdrawal(&self, id: u64, succeeded: bool) -> Result<()> {
+		self.interact(move |c| {
+			let t = c.unchecked_transaction()?;
+
+			if succeeded {
+				withdrawal_transition(&t, Self::NAME, id, WithdrawalStatus::Finalized)?;
+				t.execute(
+					&format!(
+						"UPDATE [{}-withdrawal] SET finished_at = ?1 WHERE id = ?2",
+						Self::NAME
+					),
+					rusqlite::params![Utc::now(), id],
+				)?;
+			} else {
+				withdrawal_transition(&t, Self::NAME, id, WithdrawalStatus::Confirmed)?;
+			}
+
+			t.commit()?;
+
+			Ok(())
+		})
+		.await
+	}
+
+	// The withdrawal will never be paid, e.g. its recipient is not a valid address.
+	async fn reject_withdrawal(&self, id: u64, reason: String) -> Result<()> {
+		self.interact(move |c| {
+			let t = c.unchecked_transaction()?;
+
+			withdrawal_transition(&t, Self::NAME, id, WithdrawalStatus::Rejected)?;
+			t.execute(
+				&format!(
+					"UPDATE [{}-withdrawal] SET reason = ?1, finished_at = ?2 WHERE id = ?3",
+					Self::NAME
+				),
+				rusqlite::params![reason, Utc::now(), id],
+			)?;
+			t.commit()?;
+
+			Ok(())
+		})
+		.await
+	}
+
+	// Insert the records as detected.
+	async fn insert<I>(&self, mut records: I) -> Result<()>
+	where
+		I: 'static + Send + Iterator<Item = XRecord>,
+	{
+		self.interact(move |c| {
+			let tx = c.unchecked_transaction()?;
+			let sql = format!(
+				"INSERT INTO [{}] (\
+				block_height,\
+				block_hash,\
+				txid,\
+				target,\
+				recipient,\
+				amount,\
+				status,\
+				hash,\
+				created_at,\
+				finished_at\
+			) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+				Self::NAME
+			);
+
+			records.try_for_each(|r| {
+				tx.execute(
+					&sql,
+					rusqlite::params![
+						r.block_height,
+						r.block_hash,
+						r.txid,
+						r.target,
+						r.recipient,
+						r.amount.to_string(),
+						XStatus::Detected,
+						r.hash,
+						r.created_at,
+						r.finished_at
+					],
+				)?;
+				record_transition(
+					&tx,
+					Self::NAME,
+					tx.last_insert_rowid() as _,
+					None,
+					XStatus::Detected,
+					&format!("detected in block {}", r.block_height),
+				)?;
+
+				Ok::<_, Error>(())
+			})?;
+			tx.commit()?;
+
+			Ok(())
+		})
+		.await
+	}
+
+	// Move the record to the `to` status, the move must be legal.
+	//
+	// Prefer the typed transitions below.
+	async fn transit(&self, id: u64, to: XStatus, reason: String) -> Result<()> {
+		self.interact(move |c| {
+			let tx = c.unchecked_transaction()?;
+
+			record_transit(&tx, Self::NAME, id, to, &reason)?;
+			tx.commit()?;
+
+			Ok(())
+		})
+		.await
+	}
+
+	// The transaction has reached the required confirmations, and its inclusion has been proven
+	// by the serialized SPV `proof`.
+	async fn confirm(&self, id: u64, confirmations: u32, proof: Vec<u8>) -> Result<()> {
+		self.interact(move |c| {
+			let t = c.unchecked_transaction()?;
+
+			record_transit(
+				&t,
+				Self::NAME,
+				id,
+				XStatus::Confirmed,
+				&format!("reached {confirmations} confirmations"),
+			)?;
+			t.execute(
+				&format!("UPDATE [{}] SET proof = ?1 WHERE id = ?2", Self::NAME),
+				rusqlite::params![proof, id],
+			)?;
+			t.commit()?;
+
+			Ok(())
+		})
+		.await
+	}
+
+	// The record has been submitted to X in the signed transaction, recorded before it's sent so
+	// that the record is never submitted twice, even across restarts.
+	async fn submit(&self, id: u64, submission: SubmissionRecord) -> Result<()> {
+		self.interact(move |c| {
+			let t = c.unchecked_transaction()?;
+
+			record_transit(
+				&t,
+				Self::NAME,
+				id,
+				XStatus::Submitted,
+				&format!("submitted in {}", submission.hash),
+			)?;
+			t.execute(
+				&format!("UPDATE [{}] SET hash = ?1 WHERE id = ?2", Self::NAME),
+				rusqlite::params![submission.hash, id],
+			)?;
+			insert_submission(&t, Self::NAME, &submission)?;
+			t.commit()?;
+
+			Ok(())
+		})
+		.await
+	}
+
+	// The submission has been executed successfully on X.
+	async fn finalize(&self, id: u64) -> Result<()> {
+		self.transit(id, XStatus::Finalized, "execution succeeded".into()).await
+	}
+
+	// The record will never be relayed, e.g. its block has been orphaned.
+	async fn reject(&self, id: u64, reason: String) -> Result<()> {
+		self.transit(id, XStatus::Rejected, reason).await
+	}
+
+	// The relayed record's block has been orphaned by a deep reorg.
+	async fn orphan(&self, id: u64, reason: String) -> Result<()> {
+		self.transit(id, XStatus::Orphaned, reason).await
+	}
+
+	// The deposit has been sent back to the user in Bitcoin transaction `txid`.
+	async fn refund(&self, id: u64, txid: String) -> Result<()> {
+		self.transit(id, XStatus::Refunded, format!("refunded in {txid}")).await
+	}
+}
+
+fn record_transition(
+	sql: &Connection,
+	name: &str,
+	id: u64,
+	from: Option<XStatus>,
+	to: XStatus,
+	reason: &str,
+) -> Result<()> {
+	sql.execute(
+		&format!(
+			"INSERT INTO [{name}-history] (\
+			record_id,\
+			from_status,\
+			to_status,\
+			reason,\
+			created_at\
+		) VALUES (?1, ?2, ?3, ?4, ?5)"
+		),
+		rusqlite::params![id, from, to, reason, Utc::now()],
+	)?;
+
+	Ok(())
+}
+
+// Move the record to the `to` status, the move must be legal.
+fn record_transit(sql: &Connection, name: &str, id: u64, to: XStatus, reason: &str) -> Result<()> {
+	let from = sql
+		.query_row(&format!("SELECT status FROM [{name}] WHERE id = ?1"), [id], |r| {
+			r.get::<_, XStatus>(0)
+		})
+		.optional()?
+		.ok_or(SqlError::RecordNotFound { id })?;
+
+	if !from.can_transit_to(to) {
+		Err(SqlError::IllegalTransition { id, from, to })?;
+	}
+
+	sql.execute(
+		&format!("UPDATE [{name}] SET status = ?1 WHERE id = ?2"),
+		rusqlite::params![to, id],
+	)?;
+
+	if to.is_final() {
+		sql.execute(
+			&format!("UPDATE [{name}] SET finished_at = ?1 WHERE id = ?2"),
+			rusqlite::params![Utc::now(), id],
+		)?;
+	}
+
+	record_transition(sql, name, id, Some(from), to, reason)
+}
+
+/// Record the signed submission into the `{name}-submission` table, before it's sent.
+pub fn insert_submission(
+	sql: &Connection,
+	name: &str,
+	submission: &SubmissionRecord,
+) -> Result<()> {
+	sql.execute(
+		&format!(
+			"INSERT INTO [{name}-submission] (\
+			target,\
+			nonce,\
+			hash,\
+			tx,\
+			data,\
+			gas,\
+			max_fee_per_gas,\
+			max_priority_fee_per_gas,\
+			created_at\
+		) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"
+		),
+		rusqlite::params![
+			submission.target,
+			submission.nonce,
+			submission.hash,
+			submission.tx,
+			submission.data,
+			submission.gas,
+			submission.max_fee_per_gas.to_string(),
+			submission.max_priority_fee_per_gas.to_string(),
+			submission.created_at
+		],
+	)?;
+
+	Ok(())
+}
+
+fn insert_payout(sql: &Connection, name: &str, payout: &PayoutRecord) -> Result<()> {
+	sql.execute(
+		&format!(
+			"INSERT INTO [{name}-payout] (txid, origin, tx, fee_rate, prevouts, created_at) \
+			VALUES (?1, ?2, ?3, ?4, ?5, ?6)"
+		),
+		rusqlite::params![
+			payout.txid,
+			payout.origin,
+			payout.tx,
+			payout.fee_rate,
+			payout.prevouts,
+			payout.created_at
+		],
+	)?;
+
+	Ok(())
+}
+
+// Point the paid withdrawals of the payout `from` to its other version `to`, whose outputs are the
+// same.
+fn move_paid_withdrawals(sql: &Connection, name: &str, from: &str, to: &str) -> Result<()> {
+	sql.execute(
+		&format!("UPDATE [{name}-withdrawal] SET txid = ?1 WHERE txid = ?2 AND status = ?3"),
+		rusqlite::params![to, from, WithdrawalStatus::Paid],
+	)?;
+
+	Ok(())
+}
+
+// Move the withdrawals of the payout in the `from` status to the `to` status.
+fn transit_withdrawals_of(
+	sql: &Connection,
+	name: &str,
+	txid: &str,
+	from: WithdrawalStatus,
+	to: WithdrawalStatus,
+) -> Result<()> {
+	let ids = sql
+		.prepare(&format!("SELECT id FROM [{name}-withdrawal] WHERE txid = ?1 AND status = ?2"))?
+		.query_map(rusqlite::params![txid, from], |r| r.get(0))?
+		.collect::<rusqlite::Result<Vec<u64>>>()?;
+
+	for id in ids {
+		withdrawal_transition(sql, name, id, to)?;
+	}
+
+	Ok(())
+}
+
+fn withdrawal_transition(
+	sql: &Connection,
+	name: &str,
+	id: u64,
+	to: WithdrawalStatus,
+) -> Result<()> {
+	let from = sql
+		.query_row(&format!("SELECT status FROM [{name}-withdrawal] WHERE id = ?1"), [id], |r| {
+			r.get::<_, WithdrawalStatus>(0)
+		})
+		.optional()?
+		.ok_or(SqlError::RecordNotFound { id })?;
+
+	if !from.can_transit_to(to) {
+		Err(SqlError::IllegalWithdrawalTransition { id, from, to })?;
+	}
+
+	sql.execute(
+		&format!("UPDATE [{name}-withdrawal] SET status = ?1 WHERE id = ?2"),
+		rusqlite::params![to, id],
+	)?;
+
+	Ok(())
+}
+
+#[derive(Debug)]
+pub struct XRecord {
+	// Assigned by the database, `0` before insertion.
+	pub id: u64,
+	pub block_height: u64,
+	pub block_hash: String,
+	pub txid: String,
+	pub target: Id,
+	pub recipient: String,
+	pub amount: u128,
+	pub status: XStatus,
+	pub hash: Option<String>,
+	// Serialized SPV proof, set once confirmed.
+	pub proof: Option<Vec<u8>>,
+	pub created_at: DateTime<Utc>,
+	pub finished_at: Option<DateTime<Utc>>,
+}
+impl XRecord {
+	const COLUMNS: &'static str = "id, block_height, block_hash, txid, target, recipient, amount, \
+		status, hash, proof, created_at, finished_at";
+
+	fn from_row(r: &Row) -> rusqlite::Result<Self> {
+		Ok(Self {
+			id: r.get(0)?,
+			block_height: r.get(1)?,
+			block_hash: r.get(2)?,
+			txid: r.get(3)?,
+			target: r.get(4)?,
+			recipient: r.get(5)?,
+			amount: r.get::<_, String>(6)?.parse().map_err(|e| {
+				rusqlite::Error::FromSqlConversionFailure(6, Type::Text, Box::new(e))
+			})?,
+			status: r.get(7)?,
+			hash: r.get(8)?,
+			proof: r.get(9)?,
+			created_at: r.get(10)?,
+			finished_at: r.get(11)?,
+		})
+	}
+}
+
+/// Deposit address bound to an X target, see [`deposit`](crate::chain::btc::deposit).
+#[cfg_attr(test, derive(PartialEq))]
+#[derive(Debug)]
+pub struct DepositAddress {
+	// Index of the derived key.
+	pub index: u32,
+	pub address: String,
+	pub target: Id,
+	pub recipient: String,
+	pub created_at: DateTime<Utc>,
+}
+impl DepositAddress {
+	const COLUMNS: &'static str = "id, address, target, recipient, created_at";
+
+	fn from_row(r: &Row) -> rusqlite::Result<Self> {
+		Ok(Self {
+			index: r.get(0)?,
+			address: r.get(1)?,
+			target: r.get(2)?,
+			recipient: r.get(3)?,
+			created_at: r.get(4)?,
+		})
+	}
+}
+
+/// Withdrawal from an X chain, paid from the vault.
+#[derive(Debug)]
+pub struct WithdrawalRecord {
+	// Assigned by the database, `0` before insertion.
+	pub id: u64,
+	// X chain of the withdrawal.
+	pub target: Id,
+	// Assigned by the bridge contract, in hex format.
+	pub withdrawal_id: String,
+	pub x_block: u64,
+	pub x_hash: String,
+	pub recipient: String,
+	pub amount: u128,
+	pub status: WithdrawalStatus,
+	// Payout transaction and the output paying the recipient, set once paid.
+	pub txid: Option<String>,
+	pub vout: Option<u32>,
+	// X transaction of the completion, set once submitted.
+	pub hash: Option<String>,
+	pub reason: Option<String>,
+	pub created_at: DateTime<Utc>,
+	pub finished_at: Option<DateTime<Utc>>,
+}
+impl WithdrawalRecord {
+	const COLUMNS: &'static str = "id, target, withdrawal_id, x_block, x_hash, recipient, amount, \
+		status, txid, vout, hash, reason, created_at, finished_at";
+
+	fn from_row(r: &Row) -> rusqlite::Result<Self> {
+		Ok(Self {
+			id: r.get(0)?,
+			target: r.get(1)?,
+			withdrawal_id: r.get(2)?,
+			x_block: r.get(3)?,
+			x_hash: r.get(4)?,
+			recipient: r.get(5)?,
+			amount: r.get::<_, String>(6)?.parse().map_err(|e| {
+				rusqlite::Error::FromSqlConversionFailure(6, Type::Text, Box::new(e))
+			})?,
+			status: r.get(7)?,
+			txid: r.get(8)?,
+			vout: r.get(9)?,
+			hash: r.get(10)?,
+			reason: r.get(11)?,
+			created_at: r.get(12)?,
+			finished_at: r.get(13)?,
+		})
+	}
+
+	#[cfg(test)]
+	pub fn for_test(withdrawal_id: &str, amount: u128) -> Self {
+		Self {
+			id: 0,
+			target: Id(1),
+			withdrawal_id: withdrawal_id.into(),
+			x_block: 10,
+			x_hash: "0x02".into(),
+			recipient: crate::chain::btc::fixture::RECIPIENT.into(),
+			amount,
+			status: WithdrawalStatus::Detected,
+			txid: None,
+			vout: None,
+			hash: None,
+			reason: None,
+			created_at: Utc::now(),
+			finished_at: None,
+		}
+	}
+}
+
+/// Transaction paying a batch of withdrawals from the vault.
+#[derive(Debug)]
+pub struct PayoutRecord {
+	pub txid: String,
+	// First version of the payout, the replacements spend the same outputs at higher fee rates.
+	pub origin: String,
+	// Signed transaction in hex, broadcasted again until confirmed.
+	pub tx: String,
+	// In satoshis per virtual byte.
+	pub fee_rate: u64,
+	// Consensus encoded outputs spent by the transaction.
+	pub prevouts: Vec<u8>,
+	// Block of the transaction and its serialized SPV proof, set once confirmed.
+	pub block_height: Option<u64>,
+	pub block_hash: Option<String>,
+	pub proof: Option<Vec<u8>>,
+	pub created_at: DateTime<Utc>,
+}
+impl PayoutRecord {
+	const COLUMNS: &'static str =
+		"txid, origin, tx, fee_rate, prevouts, block_height, block_hash, proof, created_at";
+
+	fn from_row(r: &Row) -> rusqlite::Result<Self> {
+		Ok(Self {
+			txid: r.get(0)?,
+			origin: r.get(1)?,
+			tx: r.get(2)?,
+			fee_rate: r.get(3)?,
+			prevouts: r.get(4)?,
+			block_height: r.get(5)?,
+			block_hash: r.get(6)?,
+			proof: r.get(7)?,
+			created_at: r.get(8)?,
+		})
+	}
+}
+
+/// Child spending the change of a stuck payout, paying for both.
+#[derive(Debug)]
+pub struct CpfpRecord {
+	pub parent: String,
+	pub txid: String,
+	// Signed transaction in hex, broadcasted again along with the parent until confirmed.
+	pub tx: String,
+	// Fee rate of the package of the parent and the child, in satoshis per virtual byte.
+	pub fee_rate: u64,
+	pub created_at: DateTime<Utc>,
+}
+impl CpfpRecord {
+	const COLUMNS: &'static str = "parent, txid, tx, fee_rate, created_at";
+
+	fn from_row(r: &Row) -> rusqlite::Result<Self> {
+		Ok(Self {
+			parent: r.get(0)?,
+			txid: r.get(1)?,
+			tx: r.get(2)?,
+			fee_rate: r.get(3)?,
+			created_at: r.get(4)?,
+		})
+	}
+}
+
+/// Signed X transaction submitting a record or a withdrawal completion.
+///
+/// A stuck submission is replaced by another version of the same nonce at higher fees, so that
+/// only one of them can ever be executed.
+#[derive(Clone, Debug)]
+pub struct SubmissionRecord {
+	pub target: Id,
+	pub nonce: u64,
+	pub hash: String,
+	// Signed transaction in hex, sent again until executed.
+	pub tx: String,
+	// Call data and gas limit, kept by the replacements.
+	pub data: Vec<u8>,
+	pub gas: u64,
+	// In wei.
+	pub max_fee_per_gas: u128,
+	pub max_priority_fee_per_gas: u128,
+	pub created_at: DateTime<Utc>,
+}
+impl SubmissionRecord {
+	const COLUMNS: &'static str = "target, nonce, hash, tx, data, gas, max_fee_per_gas, \
+		max_priority_fee_per_gas, created_at";
+
+	fn from_row(r: &Row) -> rusqlite::Result<Self> {
+		let wei = |i| {
+			r.get::<_, String>(i)?
+				.parse()
+				.map_err(|e| rusqlite::Error::FromSqlConversionFailure(i, Type::Text, Box::new(e)))
+		};
+
+		Ok(Self {
+			target: r.get(0)?,
+			nonce: r.get(1)?,
+			hash: r.get(2)?,
+			tx: r.get(3)?,
+			data: r.get(4)?,
+			gas: r.get(5)?,
+			max_fee_per_gas: wei(6)?,
+			max_priority_fee_per_gas: wei(7)?,
+			created_at: r.get(8)?,
+		})
+	}
+
+	#[cfg(test)]
+	pub fn for_test(hash: &str, nonce: u64) -> Self {
+		Self {
+			target: Id(1),
+			nonce,
+			hash: hash.into(),
+			tx: "00".into(),
+			data: Vec::new(),
+			gas: 21_000,
+			max_fee_per_gas: 2,
+			max_priority_fee_per_gas: 1,
+			created_at: Utc::now(),
+		}
+	}
+}
+
+/// Transaction merging the small vault UTXOs, kept for accounting.
+#[derive(Debug)]
+pub struct ConsolidationRecord {
+	pub txid: String,
+	// Signed transaction in hex, broadcasted again until included.
+	pub tx: String,
+	// Number and total value of the merged UTXOs.
+	pub inputs: u64,
+	pub amount: u64,
+	pub fee: u64,
+	// In satoshis per virtual byte.
+	pub fee_rate: u64,
+	// Consensus encoded outputs spent by the transaction, empty for the ones of schema version 1.
+	pub prevouts: Vec<u8>,
+	pub block_height: Option<u64>,
+	pub created_at: DateTime<Utc>,
+}
+impl ConsolidationRecord {
+	const COLUMNS: &'static str =
+		"txid, tx, inputs, amount, fee, fee_rate, prevouts, block_height, created_at";
+
+	fn from_row(r: &Row) -> rusqlite::Result<Self> {
+		Ok(Self {
+			txid: r.get(0)?,
+			tx: r.get(1)?,
+			inputs: r.get(2)?,
+			amount: r.get(3)?,
+			fee: r.get(4)?,
+			fee_rate: r.get(5)?,
+			prevouts: r.get(6)?,
+			block_height: r.get(7)?,
+			created_at: r.get(8)?,
+		})
+	}
+}
+
+/// Lifecycle of a withdrawal.
+///
+/// ```text
+/// Detected <-> Paid <-> Confirmed <-> Submitted -> Finalized
+///    |
+///    +-> Rejected
+/// ```
+///
+/// A paid withdrawal is never rejected, its payout is only ever broadcasted again or replaced by
+/// fee, unless its inputs are double spent, then it's paid again. The withdrawals of a payout are
+/// paid and confirmed together.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WithdrawalStatus {
+	// Seen on X with enough confirmations, ready to be paid.
+	Detected,
+	// Payout signed and broadcasted, without enough confirmations.
+	Paid,
+	// Payout has enough confirmations, ready to be completed.
+	Confirmed,
+	// Completion submitted to X, waiting for the execution.
+	Submitted,
+	// Completion executed on X.
+	Finalized,
+	// Will never be paid.
+	Rejected,
+}
+impl WithdrawalStatus {
+	pub fn as_str(self) -> &'static str {
+		match self {
+			Self::Detected => "detected",
+			Self::Paid => "paid",
+			Self::Confirmed => "confirmed",
+			Self::Submitted => "submitted",
+			Self::Finalized => "finalized",
+			Self::Rejected => "rejected",
+		}
+	}
+
+	pub fn can_transit_to(self, to: Self) -> bool {
+		matches!(
+			(self, to),
+			(Self::Detected, Self::Paid | Self::Rejected)
+				| (Self::Paid, Self::Detected | Self::Confirmed)
+				| (Self::Confirmed, Self::Paid | Self::Submitted)
+				| (Self::Submitted, Self::Confirmed | Self::Finalized)
+		)
+	}
+}
+impl Display for WithdrawalStatus {
+	fn fmt(&self, f: &mut Formatter) -> FmtResult {
+		f.write_str(self.as_str())
+	}
+}
+impl FromSql for WithdrawalStatus {
+	fn column_result(value: ValueRef) -> FromSqlResult<Self> {
+		match value.as_str()? {
+			"detected" => Ok(Self::Detected),
+			"paid" => Ok(Self::Paid),
+			"confirmed" => Ok(Self::Confirmed),
+			"submitted" => Ok(Self::Submitted),
+			"finalized" => Ok(Self::Finalized),
+			"rejected" => Ok(Self::Rejected),
+			s => Err(FromSqlError::Other(format!("invalid status {s}").into())),
+		}
+	}
+}
+impl ToSql for WithdrawalStatus {
+	fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+		Ok(ToSqlOutput::from(self.as_str()))
+	}
+}
+
+/// Lifecycle of a record.
+///
+/// ```text
+/// Detected -> Confirmed -> Submitted -> Finalized
+///    |            |            |   |        |
+///    |            |            |   +--------+-> Orphaned
+///    |            |            |
+///    +------------+------------+-> Rejected -> Refunded
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XStatus {
+	// Seen on Bitcoin, without enough confirmations.
+	Detected,
+	// Has enough confirmations, ready to be submitted.
+	Confirmed,
+	// Submitted to X, waiting for the execution.
+	Submitted,
+	// Executed on X.
+	Finalized,
+	// Will never be relayed.
+	Rejected,
+	// Sent back to the user on Bitcoin.
+	Refunded,
+	// Relayed to X, but its block has been orphaned by a reorg deeper than the required
+	// confirmations, to be reconciled manually.
+	Orphaned,
+}
+impl XStatus {
+	pub fn as_str(self) -> &'static str {
+		match self {
+			Self::Detected => "detected",
+			Self::Confirmed => "confirmed",
+			Self::Submitted => "submitted",
+			Self::Finalized => "finalized",
+			Self::Rejected => "rejected",
+			Self::Refunded => "refunded",
+			Self::Orphaned => "orphaned",
+		}
+	}
+
+	pub fn can_transit_to(self, to: Self) -> bool {
+		matches!(
+			(self, to),
+			(Self::Detected, Self::Confirmed | Self::Rejected)
+				| (Self::Confirmed, Self::Submitted | Self::Rejected)
+				| (Self::Submitted, Self::Finalized | Self::Rejected)
+				| (Self::Rejected, Self::Refunded)
+				| (Self::Submitted | Self::Finalized, Self::Orphaned)
+		)
+	}
+
+	pub fn is_final(self) -> bool {
+		matches!(self, Self::Finalized | Self::Refunded | Self::Orphaned)
+	}
+}
+impl Display for XStatus {
+	fn fmt(&self, f: &mut Formatter) -> FmtResult {
+		f.write_str(self.as_str())
+	}
+}
+impl FromSql for XStatus {
+	fn column_result(value: ValueRef) -> FromSqlResult<Self> {
+		match value.as_str()? {
+			"detected" => Ok(Self::Detected),
+			"confirmed" => Ok(Self::Confirmed),
+			"submitted" => Ok(Self::Submitted),
+			"finalized" => Ok(Self::Finalized),
+			"rejected" => Ok(Self::Rejected),
+			"refunded" => Ok(Self::Refunded),
+			"orphaned" => Ok(Self::Orphaned),
+			s => Err(FromSqlError::Other(format!("invalid status {s}").into())),
+		}
+	}
+}
+impl ToSql for XStatus {
+	fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+		Ok(ToSqlOutput::from(self.as_str()))
+	}
+}
+
+#[derive(Debug)]
+pub struct XTransition {
+	pub from: Option<XStatus>,
+	pub to: XStatus,
+	pub reason: String,
+	pub created_at: DateTime<Utc>,
+}
+
+impl FromSql for Id {
+	fn column_result(value: ValueRef) -> FromSqlResult<Self> {
+		Ok(Self(value.as_i64()? as _))
+	}
+}
+impl ToSql for Id {
+	fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+		Ok(ToSqlOutput::from(self.0))
+	}
+}
+
+pub fn init<P>(path: P) -> Result<Pool>
+where
+	P: AsRef<Path>,
+{
+	Ok(Config::new(path.as_ref()).create_pool(Tokio1).map_err(DeadpoolSqliteError::Create)?)
+}
+
+#[cfg(test)]
+struct Records(Arc<Pool>);
+#[cfg(test)]
+impl Records {
+	// Path of a new database file for the test, opened by `Records::open`.
+	fn path_for_test(name: &str) -> std::path::PathBuf {
+		let p = std::env::temp_dir().join(format!("helix-sql-{name}-{}.db3", std::process::id()));
+		let _ = std::fs::remove_file(&p);
+
+		p
+	}
+
+	fn open(path: &Path) -> Self {
+		Self(Arc::new(init(path).unwrap()))
+	}
+}
+#[cfg(test)]
+impl X for Records {
+	const NAME: &'static str = "test";
+}
+#[cfg(test)]
+impl Sql for Records {
+	async fn pool(&self) -> &Arc<Pool> {
+		&self.0
+	}
+}
+
+#[test]
+fn x_record_transitions_should_work() {
+	let p = Records::path_for_test("x-record");
+	let rt = tokio::runtime::Runtime::new().unwrap();
+
+	rt.block_on(async {
+		let r = Records::open(&p);
+
+		r.init().await.unwrap();
+		r.insert(
+			[XRecord {
+				id: 0,
+				block_height: 1,
+				block_hash: "0".repeat(64),
+				txid: "1".repeat(64),
+				target: Id(1),
+				recipient: "0x".into(),
+				amount: 1_000,
+				status: XStatus::Detected,
+				hash: None,
+				proof: None,
+				created_at: Utc::now(),
+				finished_at: None,
+			}]
+			.into_iter(),
+		)
+		.await
+		.unwrap();
+
+		let xr = r.get_latest().await.unwrap().unwrap();
+
+		assert_eq!(xr.status, XStatus::Detected);
+		assert!(matches!(
+			r.finalize(xr.id).await,
+			Err(Error::Sql(SqlError::IllegalTransition {
+				from: XStatus::Detected,
+				to: XStatus::Finalized,
+				..
+			}))
+		));
+		assert!(matches!(
+			r.confirm(u64::MAX >> 1, 1, Vec::new()).await,
+			Err(Error::Sql(SqlError::RecordNotFound { .. }))
+		));
+
+		r.confirm(xr.id, 6, vec![1]).await.unwrap();
+		r.submit(xr.id, SubmissionRecord::for_test("0x1", 7)).await.unwrap();
+
+		assert_eq!(r.get_next_nonce(Id(1)).await.unwrap(), Some(8));
+		assert_eq!(r.get_next_nonce(Id(2)).await.unwrap(), None);
+
+		// Replaced twice, the record follows the latest version.
+		r.replace_submission("0x1".into(), SubmissionRecord::for_test("0x2", 7)).await.unwrap();
+		r.replace_submission("0x2".into(), SubmissionRecord::for_test("0x3", 7)).await.unwrap();
+
+		assert_eq!(
+			r.get_submission_versions("0x1".into())
+				.await
+				.unwrap()
+				.into_iter()
+				.map(|s| s.hash)
+				.collect::<Vec<_>>(),
+			["0x3", "0x2", "0x1"]
+		);
+		assert!(r.get_submission_versions("0x4".into()).await.unwrap().is_empty());
+
+		r.finalize(xr.id).await.unwrap();
+
+		let xr = r.get_by_txid("1".repeat(64)).await.unwrap().remove(0);
+
+		assert_eq!(xr.status, XStatus::Finalized);
+		assert_eq!(xr.hash.as_deref(), Some("0x3"));
+		assert_eq!(xr.proof, Some(vec![1]));
+		assert!(xr.finished_at.is_some());
+		assert!(r.reject(xr.id, "".into()).await.is_err());
+		assert!(r.refund(xr.id, "2".repeat(64)).await.is_err());
+		assert_eq!(
+			r.get_history(xr.id)
+				.await
+				.unwrap()
+				.into_iter()
+				.map(|t| (t.from, t.to, t.reason))
+				.collect::<Vec<_>>(),
+			[
+				(None, XStatus::Detected, "detected in block 1".into()),
+				(Some(XStatus::Detected), XStatus::Confirmed, "reached 6 confirmations".into()),
+				(Some(XStatus::Confirmed), XStatus::Submitted, "submitted in 0x1".into()),
+				(Some(XStatus::Submitted), XStatus::Finalized, "execution succeeded".into()),
+			]
+		);
+
+		// Only the rejected records are refunded.
+		r.insert([XRecord { id: 0, txid: "2".repeat(64), finished_at: None, ..xr }].into_iter())
+			.await
+			.unwrap();
+
+		let xr = r.get_latest().await.unwrap().unwrap();
+
+		assert!(r.refund(xr.id, "3".repeat(64)).await.is_err());
+
+		r.reject(xr.id, "invalid recipient".into()).await.unwrap();
+		r.refund(xr.id, "3".repeat(64)).await.unwrap();
+
+		let xr = r.get_by_txid("2".repeat(64)).await.unwrap().remove(0);
+
+		assert_eq!(xr.status, XStatus::Refunded);
+		assert!(xr.finished_at.is_some());
+
+		r.0.close();
+	});
+}
+#[test]
+fn deposit_addresses_should_work() {
+	let p = Records::path_for_test("deposit");
+	let rt = tokio::runtime::Runtime::new().unwrap();
+
+	rt.block_on(async {
+		let r = Records::open(&p);
+		let issue = |target: u32, recipient: &str| {
+			r.issue_deposit_address(Id(target), recipient.into(), |i| Ok(format!("addr-{i}")))
+		};
+
+		r.init().await.unwrap();
+
+		let a = issue(1, "0x01").await.unwrap();
+
+		assert_eq!((a.index, a.address.as_str()), (0, "addr-0"));
+		// The same target gets the same address.
+		assert_eq!(issue(1, "0x01").await.unwrap(), a);
+		assert_eq!(issue(2, "0x01").await.unwrap().index, 1);
+		assert_eq!(issue(1, "0x02").await.unwrap().index, 2);
+		assert!(r
+			.issue_deposit_address(Id(3), "0x01".into(), |_| Ok("addr-0".into()))
+			.await
+			.is_err());
+		assert_eq!(
+			r.get_deposit_addresses()
+				.await
+				.unwrap()
+				.into_iter()
+				.map(|a| (a.index, a.target, a.recipient))
+				.collect::<Vec<_>>(),
+			[(0, Id(1), "0x01".into()), (1, Id(2), "0x01".into()), (2, Id(1), "0x02".into())]
+		);
+		assert_eq!(r.get_deposit_cursor(0).await.unwrap(), None);
+
+		r.set_deposit_cursors(vec![(0, 100), (2, 100)]).await.unwrap();
+		r.set_deposit_cursors(vec![(0, 101)]).await.unwrap();
+
+		assert_eq!(r.get_deposit_cursor(0).await.unwrap(), Some(101));
+		assert_eq!(r.get_deposit_cursor(1).await.unwrap(), None);
+		assert_eq!(r.get_deposit_cursor(2).await.unwrap(), Some(100));
+
+		r.0.close();
+	});
+}
+#[test]
+fn withdrawal_transitions_should_work() {
+	let p = Records::path_for_test("withdrawal");
+	let rt = tokio::runtime::Runtime::new().unwrap();
+
+	rt.block_on(async {
+		let r = Records::open(&p);
+		let withdrawal = || WithdrawalRecord::for_test("0x01", 1_000);
+		let payout = |txid: &str, origin: &str| PayoutRecord {
+			txid: txid.into(),
+			origin: origin.into(),
+			tx: "00".into(),
+			fee_rate: 1,
+			prevouts: Vec::new(),
+			block_height: None,
+			block_hash: None,
+			proof: None,
+			created_at: Utc::now(),
+		};
+
+		r.init().await.unwrap();
+
+		assert_eq!(r.get_withdrawal_cursor(Id(1)).await.unwrap(), None);
+
+		r.insert_withdrawals(Id(1), 10, vec![withdrawal()]).await.unwrap();
+		// Scanned again.
+		r.insert_withdrawals(Id(1), 20, vec![withdrawal()]).await.unwrap();
+		// Same id on another X chain.
+		r.insert_withdrawals(Id(2), 10, vec![withdrawal()]).await.unwrap();
+
+		assert_eq!(r.get_withdrawal_cursor(Id(1)).await.unwrap(), Some(20));
+
+		let wrs = r.get_withdrawals_by_id("0x01".into()).await.unwrap();
+
+		assert_eq!(wrs.len(), 2);
+
+		let id = wrs[0].id;
+
+		assert!(matches!(
+			r.submit_withdrawal(id, SubmissionRecord::for_test("0x03", 0)).await,
+			Err(Error::Sql(SqlError::IllegalWithdrawalTransition {
+				from: WithdrawalStatus::Detected,
+				to: WithdrawalStatus::Submitted,
+				..
+			}))
+		));
+		// Paid together or not at all.
+		assert!(r
+			.pay_withdrawals(
+				payout(&"1".repeat(64), &"1".repeat(64)),
+				vec![(id, 0), (u64::MAX >> 1, 1)]
+			)
+			.await
+			.is_err());
+		assert!(r.get_payout("1".repeat(64)).await.unwrap().is_none());
+
+		r.pay_withdrawals(payout(&"1".repeat(64), &"1".repeat(64)), vec![(id, 0), (wrs[1].id, 1)])
+			.await
+			.unwrap();
+
+		// A paid withdrawal is never rejected.
+		assert!(r.reject_withdrawal(id, "".into()).await.is_err());
+
+		// Replaced twice, and the original is confirmed after all.
+		r.replace_payout("1".repeat(64), payout(&"2".repeat(64), &"1".repeat(64))).await.unwrap();
+		r.replace_payout("2".repeat(64), payout(&"3".repeat(64), &"1".repeat(64))).await.unwrap();
+
+		assert_eq!(
+			r.get_payout_versions("1".repeat(64))
+				.await
+				.unwrap()
+				.into_iter()
+				.map(|p| p.txid)
+				.collect::<Vec<_>>(),
+			["3".repeat(64), "2".repeat(64), "1".repeat(64)]
+		);
+		assert_eq!(r.get_unconfirmed_payouts().await.unwrap()[0].txid, "3".repeat(64));
+		assert_eq!(r.get_withdrawals_by_txid("3".repeat(64)).await.unwrap().len(), 2);
+
+		r.revert_payout("3".repeat(64), "1".repeat(64)).await.unwrap();
+
+		assert!(r.get_withdrawals_by_txid("3".repeat(64)).await.unwrap().is_empty());
+		assert_eq!(r.get_unconfirmed_payouts().await.unwrap()[0].txid, "1".repeat(64));
+
+		r.confirm_payout("1".repeat(64), 1, "0".repeat(64), vec![1]).await.unwrap();
+
+		assert!(r.get_unconfirmed_payouts().await.unwrap().is_empty());
+
+		r.submit_withdrawal(wrs[1].id, SubmissionRecord::for_test("0x03", 0)).await.unwrap();
+		r.unconfirm_payout("1".repeat(64)).await.unwrap();
+
+		let payout = r.get_payout("1".repeat(64)).await.unwrap().unwrap();
+
+		assert_eq!((payout.block_hash, payout.proof), (None, None));
+		assert_eq!(
+			r.get_withdrawals_by_txid("1".repeat(64))
+				.await
+				.unwrap()
+				.into_iter()
+				.map(|w| (w.vout, w.status))
+				.collect::<Vec<_>>(),
+			[(Some(0), WithdrawalStatus::Paid), (Some(1), WithdrawalStatus::Submitted)]
+		);
+
+		r.confirm_payout("1".repeat(64), 2, "0".repeat(64), vec![2]).await.unwrap();
+		r.submit_withdrawal(id, SubmissionRecord::for_test("0x04", 1)).await.unwrap();
+		r.finalize_withdrawal(id, false).await.unwrap();
+		r.submit_withdrawal(id, SubmissionRecord::for_test("0x05", 2)).await.unwrap();
+		r.finalize_withdrawal(id, true).await.unwrap();
+
+		let wr = r.get_withdrawals_by_txid("1".repeat(64)).await.unwrap().remove(0);
+		let payout = r.get_payout("1".repeat(64)).await.unwrap().unwrap();
+
+		assert_eq!(wr.status, WithdrawalStatus::Finalized);
+		assert_eq!((payout.block_height, payout.proof), (Some(2), Some(vec![2])));
+		assert_eq!(wr.hash.as_deref(), Some("0x05"));
+		assert!(wr.finished_at.is_some());
+
+		// Another X chain.
+		r.insert_withdrawals(Id(2), 20, vec![WithdrawalRecord::for_test("0x05", 1_000)])
+			.await
+			.unwrap();
+
+		let wr = r.get_withdrawals_by_id("0x05".into()).await.unwrap().remove(0);
+
+		r.reject_withdrawal(wr.id, "invalid recipient".into()).await.unwrap();
+
+		assert!(r.get_withdrawals_by_status(WithdrawalStatus::Detected).await.unwrap().is_empty());
+
+		r.0.close();
+	});
+}
+#[test]
+fn baseline_schema_should_be_migrated() {
+	let p = Records::path_for_test("migration");
+	let rt = tokio::runtime::Runtime::new().unwrap();
+
+	// The schema and a record of the first version.
+	{
+		let c = Connection::open(&p).unwrap();
+
+		c.execute_batch(
+			"CREATE TABLE [test] (\
+				id INTEGER PRIMARY KEY AUTOINCREMENT,\
+				block_height INTEGER NOT NULL,\
+				txid TEXT NOT NULL,\
+				target INTEGER NOT NULL,\
+				recipient TEXT NOT NULL,\
+				amount TEXT NOT NULL,\
+				hash TEXT,\
+				created_at DATETIME NOT NULL,\
+				finished_at DATETIME\
+			);",
+		)
+		.unwrap();
+		c.execute(
+			"INSERT INTO [test] (block_height, txid, target, recipient, amount, created_at) \
+			VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+			rusqlite::params![7, "1".repeat(64), 1, "0x01", "1000", Utc::now()],
+		)
+		.unwrap();
+	}
+
+	rt.block_on(async {
+		let r = Records::open(&p);
+
+		r.init().await.unwrap();
+		// Migrated once only.
+		r.init().await.unwrap();
+
+		let version = r
+			.interact(|sql| {
+				Ok(sql.pragma_query_value(None, "user_version", |r| r.get::<_, usize>(0))?)
+			})
+			.await
+			.unwrap();
+
+		assert_eq!(version, MIGRATIONS.len());
+
+		let xr = r.get_latest().await.unwrap().unwrap();
+
+		assert_eq!((xr.block_height, xr.amount, xr.status), (7, 1_000, XStatus::Detected));
+		assert_eq!((xr.block_hash.as_str(), xr.proof), ("", None));
+		assert_eq!(r.get_history(xr.id).await.unwrap().len(), 1);
+		assert_eq!(r.get_heights_without_hash().await.unwrap(), [7]);
+
+		r.fill_block_hash(7, "0".repeat(64)).await.unwrap();
+
+		assert!(r.get_heights_without_hash().await.unwrap().is_empty());
+		assert_eq!(r.get_latest().await.unwrap().unwrap().block_hash, "0".repeat(64));
+
+		// Works as a new one.
+		r.confirm(xr.id, 6, vec![1]).await.unwrap();
+
+		assert_eq!(r.get_by_status(XStatus::Confirmed).await.unwrap().len(), 1);
+
+		r.0.close();
+	});
+
+	// A new database starts at the latest version.
+	let p = Records::path_for_test("new");
+
+	rt.block_on(async {
+		let r = Records::open(&p);
+
+		r.init().await.unwrap();
+
+		let version = r
+			.interact(|sql| {
+				Ok(sql.pragma_query_value(None, "user_version", |r| r.get::<_, usize>(0))?)
+			})
+			.await
+			.unwrap();
+
+		assert_eq!(version, MIGRATIONS.len());
+
+		r.0.close();
+	});
+
+	// A consolidation of version 1, without its spent outputs.
+	let p = Records::path_for_test("v1");
+
+	{
+		let c = Connection::open(&p).unwrap();
+
+		c.execute_batch(
+			"CREATE TABLE [test-consolidation] (\
+				id INTEGER PRIMARY KEY AUTOINCREMENT,\
+				txid TEXT NOT NULL UNIQUE,\
+				tx TEXT NOT NULL,\
+				inputs INTEGER NOT NULL,\
+				amount INTEGER NOT NULL,\
+				fee INTEGER NOT NULL,\
+				fee_rate INTEGER NOT NULL,\
+				block_height INTEGER,\
+				created_at DATETIME NOT NULL\
+			);\
+			PRAGMA user_version = 1;",
+		)
+		.unwrap();
+		c.execute(
+			"INSERT INTO [test-consolidation] (txid, tx, inputs, amount, fee, fee_rate, created_at) \
+			VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+			rusqlite::params!["1".repeat(64), "00", 2, 1_000, 100, 1, Utc::now()],
+		)
+		.unwrap();
+	}
+
+	rt.block_on(async {
+		let r = Records::open(&p);
+
+		r.init().await.unwrap();
+
+		let c = r.get_unconfirmed_consolidation().await.unwrap().unwrap();
+
+		assert_eq!((c.txid, c.prevouts), ("1".repeat(64), Vec::new()));
+
+		r.0.close();
+	});
 }
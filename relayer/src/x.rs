@@ -1,5 +1,9 @@
 // crates.io
-use bitcoin::script::PushBytesBuf;
+use bitcoin::{
+	hashes::{sha256d, Hash},
+	script::PushBytesBuf,
+};
+#[cfg(test)] use proptest::prelude::*;
 // self
 use crate::prelude::*;
 
@@ -16,10 +20,6 @@ pub enum XEntity {
 	Address32([u8; 32]),
 }
 impl XEntity {
-	const LENGTH_MARKER_SIZE: usize = 1;
-
-	// TODO
-	#[allow(unused)]
 	pub fn from_bytes<S>(s: S) -> Result<Self>
 	where
 		S: AsRef<[u8]>,
@@ -41,25 +41,14 @@ impl XEntity {
 		}
 	}
 
-	// TODO
-	#[allow(unused)]
 	fn encode(&self) -> Vec<u8> {
-		let mut v = Vec::new();
+		let a = self.as_bytes();
+		let mut v = Vec::with_capacity(1 + a.len());
 
-		match self {
-			XEntity::Address20(a) => {
-				v.push(a.len() as _);
-				v.extend_from_slice(a);
-
-				v
-			},
-			XEntity::Address32(a) => {
-				v.push(a.len() as _);
-				v.extend_from_slice(a);
-
-				v
-			},
-		}
+		v.push(a.len() as _);
+		v.extend_from_slice(a);
+
+		v
 	}
 
 	fn decode<S>(s: S) -> Result<Self>
@@ -67,22 +56,13 @@ impl XEntity {
 		S: AsRef<[u8]>,
 	{
 		let s = s.as_ref();
+		let (&marker, bytes) = s.split_first().ok_or(XError::PayloadTruncated(s.len()))?;
 
-		debug_assert_eq!(Self::LENGTH_MARKER_SIZE, 1);
-
-		let e = match s[0] {
-			20 => XEntity::Address20(
-				array_bytes::slice2array(&s[Self::LENGTH_MARKER_SIZE..])
-					.map_err(Error::ArrayBytes)?,
-			),
-			32 => XEntity::Address32(
-				array_bytes::slice2array(&s[Self::LENGTH_MARKER_SIZE..])
-					.map_err(Error::ArrayBytes)?,
-			),
-			_ => Err(XError::EntitySizeInvalid(s[0] as _))?,
-		};
+		if marker as usize != bytes.len() {
+			Err(XError::EntityLengthMismatch { marker: marker as _, actual: bytes.len() })?;
+		}
 
-		Ok(e)
+		Self::from_bytes(bytes)
 	}
 }
 impl From<[u8; 20]> for XEntity {
@@ -138,15 +118,22 @@ impl From<[u8; 4]> for Id {
 
 /// Data structure composition:
 ///
-/// `[0..4](id) ++ [0..1](length) ++ [u8; 75](bytes)`
+/// `[0..3](magic) ++ [0..1](version) ++ [..](payload) ++ [0..4](checksum)`
+///
+/// 1. `magic` (3 bytes):
+///    - The protocol magic `HLX`.
+/// 2. `version` (1 byte):
+///    - Version of the payload format, currently `1`.
+/// 3. `payload`:
+///    - Version 1: `[0..4](id) ++ [0..1](length) ++ [u8; 64](bytes)`.
+///      - `id` (4 bytes): a 4-byte field for a unique identifier, in little endian.
+///      - `length` (1 byte): a 1-byte field indicating the length of the `bytes` field.
+///      - `bytes` (up to 64 bytes): the actual length is specified by the `length` field.
+/// 4. `checksum` (4 bytes):
+///    - The first 4 bytes of the double SHA-256 of all the preceding bytes.
 ///
-/// 1. `id` (4 bytes):
-///    - A 4-byte field for a unique identifier.
-/// 2. `length` (1 byte):
-///    - A 1-byte field indicating the length of the `bytes` field.
-/// 3. `bytes` (up to 75 bytes):
-///    - An array of up to 75 bytes for storing data.
-///    - The actual length is specified by the `length` field.
+/// The legacy format is the bare version 1 payload without the envelope, it can only be decoded
+/// if explicitly accepted.
 #[cfg_attr(test, derive(PartialEq))]
 #[derive(Debug)]
 pub struct XTarget {
@@ -154,52 +141,89 @@ pub struct XTarget {
 	pub entity: XEntity,
 }
 impl XTarget {
-	pub fn encode(&self) -> Result<PushBytesBuf> {
-		let XTarget { id, entity } = self;
-		let mut buf = PushBytesBuf::new();
+	const CHECKSUM_SIZE: usize = 4;
+	const MAGIC: [u8; 3] = *b"HLX";
+	const VERSION: u8 = 1;
 
-		// These are safe because the total size of `XTarget` is always less than 80.
-		buf.extend_from_slice(&id.encode()).unwrap();
-
-		let entity = entity.as_bytes();
+	pub fn encode(&self) -> Result<PushBytesBuf> {
+		let mut v = Self::MAGIC.to_vec();
 
-		buf.push(entity.len() as u8).unwrap();
-		buf.extend_from_slice(entity).unwrap();
+		v.push(Self::VERSION);
+		v.extend_from_slice(&self.encode_payload());
+		v.extend_from_slice(&Self::checksum(&v));
 
-		Ok(buf)
+		// This is safe because the total size of `XTarget` is always less than 80.
+		Ok(v.try_into().unwrap())
 	}
 
-	pub fn decode<S>(s: S) -> Result<Self>
+	pub fn decode<S>(s: S, accept_legacy: bool) -> Result<Self>
 	where
 		S: AsRef<[u8]>,
 	{
 		let s = s.as_ref();
-		let id = Id::decode(&s[..Id::SIZE])?;
-		let entity = XEntity::decode(&s[Id::SIZE..])?;
+		let Some(s) = s.strip_prefix(&Self::MAGIC) else {
+			if accept_legacy {
+				return Self::decode_payload(s);
+			}
+
+			Err(XError::MagicMismatch)?
+		};
+		let (&version, s) = s.split_first().ok_or(XError::PayloadTruncated(0))?;
+
+		if version != Self::VERSION {
+			Err(XError::VersionUnsupported(version))?;
+		}
+		let i =
+			s.len().checked_sub(Self::CHECKSUM_SIZE).ok_or(XError::PayloadTruncated(s.len()))?;
+		let (payload, checksum) = s.split_at(i);
+		let mut v = Self::MAGIC.to_vec();
+
+		v.push(version);
+		v.extend_from_slice(payload);
+
+		if Self::checksum(&v) != checksum {
+			Err(XError::ChecksumMismatch)?;
+		}
+
+		Self::decode_payload(payload)
+	}
+
+	fn encode_payload(&self) -> Vec<u8> {
+		let XTarget { id, entity } = self;
+		let mut v = id.encode().to_vec();
+
+		v.extend_from_slice(&entity.encode());
+
+		v
+	}
+
+	fn decode_payload(s: &[u8]) -> Result<Self> {
+		let (id, entity) = s.split_at_checked(Id::SIZE).ok_or(XError::PayloadTruncated(s.len()))?;
+		let id = Id::decode(id)?;
+		let entity = XEntity::decode(entity)?;
 
 		Ok(Self { id, entity })
 	}
+
+	fn checksum(s: &[u8]) -> [u8; Self::CHECKSUM_SIZE] {
+		let h = sha256d::Hash::hash(s).to_byte_array();
+
+		[h[0], h[1], h[2], h[3]]
+	}
 }
 #[test]
 fn x_target_codec_should_work() {
+	// Version 1.
 	[
 		(
 			Id(2020),
-			['x' as u8; 20].as_slice(),
-			[
-				228, 7, 0, 0, 20, 120, 120, 120, 120, 120, 120, 120, 120, 120, 120, 120, 120, 120,
-				120, 120, 120, 120, 120, 120, 120,
-			]
-			.as_slice(),
+			[b'x'; 20].as_slice(),
+			"484c5801e407000014787878787878787878787878787878787878787833bef522",
 		),
 		(
 			Id(3232),
-			&['x' as u8; 32],
-			&[
-				160, 12, 0, 0, 32, 120, 120, 120, 120, 120, 120, 120, 120, 120, 120, 120, 120, 120,
-				120, 120, 120, 120, 120, 120, 120, 120, 120, 120, 120, 120, 120, 120, 120, 120,
-				120, 120, 120,
-			],
+			&[b'x'; 32],
+			"484c5801a00c000020787878787878787878787878787878787878787878787878787878787878787884fd4210",
 		),
 	]
 	.iter()
@@ -208,10 +232,103 @@ fn x_target_codec_should_work() {
 		let encoded = xt.encode().unwrap();
 		let encoded = encoded.as_bytes();
 
-		assert_eq!(encoded, expected_encoded);
+		assert_eq!(array_bytes::bytes2hex("", encoded), expected_encoded);
+		assert_eq!(XTarget::decode(encoded, false).unwrap(), xt);
+		assert_eq!(XTarget::decode(encoded, true).unwrap(), xt);
+	});
 
-		let decoded = XTarget::decode(encoded).unwrap();
+	// Legacy.
+	[
+		(Id(2020), [b'x'; 20].as_slice(), "e4070000147878787878787878787878787878787878787878"),
+		(
+			Id(3232),
+			&[b'x'; 32],
+			"a00c0000207878787878787878787878787878787878787878787878787878787878787878",
+		),
+	]
+	.iter()
+	.for_each(|&(id, entity, encoded)| {
+		let xt = XTarget { id, entity: XEntity::from_bytes(entity).unwrap() };
+		let encoded = array_bytes::hex2bytes_unchecked(encoded);
 
-		assert_eq!(xt, decoded);
+		assert_eq!(xt.encode_payload(), encoded);
+		assert_eq!(XTarget::decode(&encoded, true).unwrap(), xt);
+		assert!(matches!(XTarget::decode(&encoded, false), Err(Error::X(XError::MagicMismatch))));
 	});
+
+	let xt = XTarget { id: Id(2020), entity: XEntity::Address20([b'x'; 20]) };
+	let encoded = xt.encode().unwrap().as_bytes().to_vec();
+	let mut corrupted = encoded.clone();
+
+	corrupted[10] ^= 1;
+
+	assert!(matches!(XTarget::decode(&corrupted, false), Err(Error::X(XError::ChecksumMismatch))));
+
+	let mut corrupted = encoded;
+
+	corrupted[3] = 2;
+
+	assert!(matches!(
+		XTarget::decode(&corrupted, false),
+		Err(Error::X(XError::VersionUnsupported(2)))
+	));
+}
+#[test]
+fn x_target_decode_should_reject_malformed() {
+	let xt = XTarget { id: Id(2020), entity: XEntity::Address20([b'x'; 20]) };
+	let encoded = xt.encode().unwrap().as_bytes().to_vec();
+	let legacy = xt.encode_payload();
+
+	// Every truncation, including the empty and the 0..4 bytes ones.
+	(0..encoded.len()).for_each(|i| assert!(XTarget::decode(&encoded[..i], true).is_err()));
+	(0..legacy.len()).for_each(|i| assert!(XTarget::decode(&legacy[..i], true).is_err()));
+
+	assert!(matches!(XTarget::decode(b"HLX", true), Err(Error::X(XError::PayloadTruncated(0)))));
+	assert!(matches!(
+		XTarget::decode(&legacy[..Id::SIZE], true),
+		Err(Error::X(XError::PayloadTruncated(0)))
+	));
+
+	let mut mismatched = legacy.clone();
+
+	mismatched[Id::SIZE] = 32;
+
+	assert!(matches!(
+		XTarget::decode(&mismatched, true),
+		Err(Error::X(XError::EntityLengthMismatch { marker: 32, actual: 20 }))
+	));
+
+	let mut extended = legacy;
+
+	extended.push(b'x');
+
+	assert!(matches!(
+		XTarget::decode(&extended, true),
+		Err(Error::X(XError::EntityLengthMismatch { marker: 20, actual: 21 }))
+	));
+}
+#[cfg(test)]
+proptest! {
+	#[test]
+	fn x_target_roundtrip_should_work(
+		id: u32,
+		entity in prop_oneof![
+			any::<[u8; 20]>().prop_map(XEntity::Address20),
+			any::<[u8; 32]>().prop_map(XEntity::Address32),
+		],
+	) {
+		let xt = XTarget { id: Id(id), entity };
+		let encoded = xt.encode().unwrap();
+
+		prop_assert_eq!(&XTarget::decode(encoded.as_bytes(), false).unwrap(), &xt);
+		prop_assert_eq!(&XTarget::decode_payload(&xt.encode_payload()).unwrap(), &xt);
+	}
+
+	#[test]
+	fn x_target_decode_should_not_panic(s in prop::collection::vec(any::<u8>(), 0..80)) {
+		let enveloped = [XTarget::MAGIC.as_slice(), &[XTarget::VERSION], &s].concat();
+
+		let _ = XTarget::decode(&s, true);
+		let _ = XTarget::decode(&enveloped, false);
+	}
 }